@@ -0,0 +1,232 @@
+# German. Every message is translated; a test checks this catalog against the code.
+
+## Errors and warnings
+
+error = Fehler: { $message }
+error-open-device = Gerät konnte nicht geöffnet werden: { $error }
+error-interrupted = Abgebrochen
+error-not-confirmed = Warnung: Das Gerät hat { $command } nicht bestätigt
+error-lamp-not-confirmed = Warnung: { $serial } hat { $command } nicht bestätigt
+error-lamp-failed = Warnung: { $serial } fehlgeschlagen: { $error }
+error-lamp-open = Warnung: { $serial } konnte nicht geöffnet werden: { $error }
+error-unknown-group = Unbekannte oder leere Gruppe `{ $name }`
+error-unreachable = Nicht erreichbar: { $serials }
+error-no-serial = Die Lampe meldet keine Seriennummer
+error-no-config-dir = Kein Konfigurationsverzeichnis gefunden
+error-read-file = { $path } konnte nicht gelesen werden: { $error }
+error-invalid-time = ungültige Uhrzeit `{ $text }`: { $error }
+error-invalid-brightness = ungültige Helligkeit `{ $text }`: erwartet Prozent wie 50 oder Lumen wie 250lm
+error-raw-lumens = --raw erwartet die eigene Helligkeitsstufe der Lampe, etwa 200, keine Lumen
+error-lumens-clamped = Warnung: { $lumens } lm liegt außerhalb des Bereichs der { $model }, verwende { $level } lm
+error-invalid-temperature = ungültige Farbtemperatur `{ $text }`: erwartet Kelvin wie 4000 oder 5600K, Mired wie 250mired oder eines von { $presets }
+error-temperature-outside = Farbtemperatur { $kelvin }K liegt außerhalb von { $min }-{ $max }K; verwende einen Wert in diesem Bereich oder eines von { $presets }
+error-unknown-scene = Unbekannte Szene `{ $name }`; gespeicherte Szenen: { $names }
+error-device-not-found = Keine Litra-Lampe gefunden
+error-serial-not-found = Keine Litra-Lampe mit der Seriennummer { $serial } gefunden
+error-no-match = Keine angeschlossene Lampe passt zu `{ $selector }`; `devices` listet sie auf
+error-permission-denied = Keine Berechtigung, die Lampe unter { $path } zu öffnen. Diese Zeile in /etc/udev/rules.d/50-litra-glow.rules eintragen und die Lampe neu einstecken: { $rule }
+error-read-timeout = Die Lampe hat nicht rechtzeitig geantwortet
+error-toggle-unread = Ein-/Aus-Zustand der Lampe nicht lesbar, daher nicht umgeschaltet
+error-toggle-unconfirmed = Die Lampe hat das { $on ->
+        [yes] Einschalten
+       *[no] Ausschalten
+    } nicht bestätigt
+error-state-unread = Von der Lampe nicht lesbar: { $fields }; nichts wurde gespeichert
+error-write-short = Nur { $written } von { $expected } Bytes haben die Lampe erreicht
+error-hid = HID-Fehler: { $error }
+error-io = E/A-Fehler: { $error }
+error-trace = Trace-Fehler: { $error }
+error-brightness-range = Helligkeit { $level } liegt außerhalb des Bereichs { $min }-{ $max } der { $model }
+error-temperature-range = Farbtemperatur { $kelvin }K liegt außerhalb des Bereichs { $min }-{ $max }K der { $model }
+device-disconnected = Lampe getrennt
+sync-lamp-lost = { $lamp } wird nicht mehr synchronisiert: { $error }
+error-retrying = { $error }, neuer Versuch in { $seconds } s
+
+## Confirmation prompts
+
+confirm-prompt = { $question } [j/N]
+confirm-yes = j, ja, y, yes
+nothing-changed = Nichts geändert
+
+## Scenes, alarm, history and calibration
+
+scene-line = { $name }: { $brightness } % { $temperature } K
+scenes-none = keine
+preset-saved = { $name } gespeichert: { $power ->
+        [on] an
+       *[off] aus
+    }, { $brightness } % { $temperature } K
+alarm-summary = { $armed ->
+        [yes] Aktiv
+       *[no] Inaktiv
+    } um { $time } an { $days }, Anstieg auf { $brightness } % { $temperature } K in { $minutes } min, verpasst: { $missed }
+alarm-next = Nächster Start: { $time }
+history-exported = { $count } Einträge nach { $path } exportiert
+calibration = { $serial }: Farbtemperatur { $temperature } K, Helligkeit { $brightness } %
+
+## Devices
+
+devices-none = Keine unterstützte Litra-Lampe gefunden
+devices-line = { $index }: { $model }, Seriennummer { $serial }, { $path }
+devices-no-serial = unbekannt
+devices-supported = Unterstützte Modelle, in der Reihenfolge der Suche:
+devices-supported-line = { $id } { $model }
+
+## On-time
+
+stats-none = Noch keine Betriebszeit erfasst
+stats-line = { $serial }: { $hours } h
+stats-not-recorded = Keine Betriebszeit für { $serial } erfasst
+stats-target-lamp = Lampe { $serial }
+stats-target-all = allen Lampen
+stats-reset-question = Betriebszeit von { $target } zurücksetzen?
+stats-reset-done = Betriebszeit von { $target } zurückgesetzt
+
+## Import
+
+import-add = Szene { $name } hinzufügen: { $brightness } % { $temperature } K
+import-replace = Szene { $name } ersetzen: { $brightness } % { $temperature } K
+import-not-imported = Nicht importiert:
+import-nothing = Nichts zu importieren
+import-dry-run = Probelauf; mit --apply werden die Szenen gespeichert
+import-done = { $count ->
+        [one] Eine Szene importiert
+       *[other] { $count } Szenen importiert
+    }
+import-not-json = Keine JSON-Datei: { $error }
+import-line = Zeile { $line }
+import-nested = nur `Schlüssel: Wert`-Einträge auf oberster Ebene werden gelesen
+import-out-of-range = `{ $value }` liegt außerhalb des gültigen Bereichs
+import-camera = litra-glow überwacht die Kamera nicht
+import-lamp-selector = litra-glow nutzt die erste gefundene Lampe; Gruppen wählen Lampen per Seriennummer
+import-device-type = nur die Litra Glow wird unterstützt
+import-verbose = stattdessen RUST_LOG oder --trace verwenden
+import-unknown-field = unbekanntes Feld
+import-no-light = setzt weder Helligkeit noch Farbtemperatur
+import-no-light-settings = keine Lichteinstellungen gefunden
+import-needs-temperature = eine Szene braucht neben der Helligkeit auch eine Farbtemperatur
+import-needs-brightness = eine Szene braucht neben der Farbtemperatur auch eine Helligkeit
+
+## Window, popup and tray
+
+connecting = Verbinde...
+footer-on-time = Lampe insgesamt { $hours } h an
+footer-paused = Automatiken pausiert
+footer-serial = Seriennummer { $serial }
+footer-firmware = Firmware { $firmware }
+footer-paused-for = Automatiken noch { $minutes } min pausiert
+announce-connected = Lampe verbunden
+announce-power-on = Lampe an
+announce-power-off = Lampe aus
+announce-brightness = Helligkeit { $percent } Prozent
+announce-temperature = Farbtemperatur { $kelvin } Kelvin
+osd-power = Ein/Aus
+osd-on = An
+osd-off = Aus
+osd-brightness = Helligkeit
+osd-temperature = Farbtemperatur
+history-span = { $hours } h
+tray-show = Anzeigen
+tray-scene = Szene: { $name }
+tray-scene-key = Szene: { $name } ({ $key })
+tray-cycle-brightness = Helligkeit weiterschalten
+tray-cycle-temperature = Farbtemperatur weiterschalten
+tray-alarm-toggle = Wecker: ein/aus
+tray-alarm-skip = Wecker: nächsten überspringen
+tray-alarm-dismiss = Wecker: beenden
+tray-focus-toggle = Fokus folgen: ein/aus
+tray-sync-toggle = Alle Lampen synchron: ein/aus
+tray-pause-hour = Automatiken 1 h pausieren
+tray-pause = Automatiken bis auf Weiteres pausieren
+tray-resume = Automatiken fortsetzen
+tray-quit = Beenden
+
+## Command-line help
+
+help = Steuerung für die Logitech Litra Glow
+help--after = Kombinierte Einstellungen werden in dieser Reihenfolge angewendet: Helligkeit, Farbtemperatur, dann Ein/Aus. Jeder Schreibvorgang wird durch Zurücklesen bestätigt, bevor der nächste folgt.
+help-on = Lampe einschalten
+help-off = Lampe ausschalten
+help-toggle = Lampe ein- oder ausschalten
+help-brightness = Helligkeit setzen (Prozent), oder in Lumen wie 250lm
+help-perceptual = Helligkeitsprozente als wahrgenommene Helligkeit verstehen, sodass 50 % halb so hell wirken
+help-raw = --brightness als eigene Stufe der Lampe nehmen (z. B. 20-250 bei einer Glow) und sie im Status als raw_brightness ausgeben; Kalibrierung und Helligkeitsuntergrenze entfallen
+help-min-floor = Helligkeit nie unter diesen Prozentwert setzen, um Flackern am unteren Ende zu vermeiden; --off funktioniert weiterhin
+help-temperature = Farbtemperatur setzen (2700-6500), in Mired wie 250mired oder als Voreinstellung: warm, soft, neutral, cool, daylight
+help-temperature-percent = Farbtemperatur als Prozentwert von der wärmsten (0) bis zur kühlsten (100) setzen
+help-cycle-brightness = Helligkeit auf den nächsten Wert der Liste schalten
+help-cycle-temperature = Farbtemperatur auf den nächsten Wert der Liste schalten
+help-brightness-up = Helligkeit um so viele Prozent vom aktuellen Wert der Lampe erhöhen und das Ergebnis ausgeben
+help-brightness-down = Helligkeit um so viele Prozent vom aktuellen Wert der Lampe verringern und das Ergebnis ausgeben
+help-and-on = Mit --brightness-up die Lampe auch einschalten, wenn sie aus ist
+help-warmer = Farbtemperatur um so viele Kelvin (Standard ein Schritt, 100) vom aktuellen Wert der Lampe senken und das Ergebnis ausgeben
+help-cooler = Farbtemperatur um so viele Kelvin (Standard ein Schritt, 100) vom aktuellen Wert der Lampe erhöhen und das Ergebnis ausgeben
+help-preset = Eine gespeicherte Szene anwenden, wie von `scene list` aufgelistet
+help-save-preset = Ein-/Aus-Zustand, Helligkeit und Farbtemperatur der Lampe nach den übrigen Einstellungen als Szene speichern
+help-fade = Helligkeit und Farbtemperatur über diese Zeit zu den neuen Werten überblenden, z. B. 500ms oder 2s
+help-status = Aktuellen Zustand der Lampe als JSON anzeigen
+help-json = Fehler auf stderr als JSON-Objekt mit Meldung und Art ausgeben
+help-verbose = Bei --status auch den Namen der Lampe abfragen
+help-off-on-exit = Lampe ausschalten, wenn das Fenster geschlossen oder über das Tray beendet wird
+help-group = Auf alle Lampen einer Gruppe anwenden, Helligkeit je Lampe skaliert
+help-all = Auf jede angeschlossene Lampe anwenden; ohne weitere Optionen steuert das Fenster alle
+help-device = Die Lampe mit dieser Seriennummer, diesem HID-Pfad oder Index aus `devices` statt der ersten gefundenen verwenden
+help-serial = Die Lampe mit dieser Seriennummer statt der ersten gefundenen verwenden
+help-wait = So viele Sekunden warten, bis die Lampe angeschlossen ist, bevor der Befehl ausgeführt wird; 0 wartet unbegrenzt
+help-timeout = Wie lange --status auf die Antwort der Lampe wartet
+help-trace-out = Gesamten Geräteverkehr an eine JSONL-Trace-Datei anhängen; mit --all oder --group eine Datei je Lampe, nach ihrer Seriennummer benannt
+help-replay = Geräteverkehr aus einem Trace abspielen statt echte Hardware zu nutzen
+help-dry-run = Lampe simulieren und die Frames protokollieren, die geschrieben würden
+help-trace = Ausführliche Diagnose mit Zeitmessung auf stderr ausgeben
+help-dump-unknown = Jede nicht verstandene Meldung der Lampe als Hex-Zeile ausgeben, bis zum Abbruch
+help-lang = Sprache für Meldungen und Hilfe, z. B. de; sonst die Systemsprache
+help-cmd-on = Lampe einschalten
+help-cmd-off = Lampe ausschalten
+help-cmd-toggle = Lampe ein- oder ausschalten
+help-cmd-set = Helligkeit und Farbtemperatur setzen, in dieser Reihenfolge und danach Ein/Aus
+help-cmd-set-brightness = Helligkeit (Prozent), oder in Lumen wie 250lm
+help-cmd-set-temperature = Farbtemperatur (2700-6500), in Mired wie 250mired oder als Voreinstellung: warm, soft, neutral, cool, daylight
+help-cmd-set-temperature-percent = Farbtemperatur als Prozentwert von der wärmsten (0) bis zur kühlsten (100)
+help-cmd-set-on = Lampe auch einschalten
+help-cmd-set-off = Lampe auch ausschalten
+help-cmd-set-fade = Helligkeit und Farbtemperatur über diese Zeit zu den neuen Werten überblenden, z. B. 500ms oder 2s
+help-cmd-get = Einen Wert der Lampe ausgeben
+help-cmd-status = Aktuellen Zustand der Lampe als JSON anzeigen
+help-cmd-status-verbose = Auch den Namen der Lampe abfragen
+help-cmd-preset = Eine gespeicherte Szene anwenden, wie von `scene list` aufgelistet
+help-cmd-preset-brightness = Helligkeit (Prozent), oder in Lumen wie 250lm
+help-cmd-preset-temperature = Farbtemperatur (2700-6500), in Mired wie 250mired oder als Voreinstellung: warm, soft, neutral, cool, daylight
+help-cmd-preset-temperature-percent = Farbtemperatur als Prozentwert von der wärmsten (0) bis zur kühlsten (100)
+help-cmd-completions = Ein Skript zur Shell-Vervollständigung ausgeben, z. B. für ~/.bash_completion
+help-cmd-schema = Das JSON-Schema aller JSON-Ausgaben ausgeben
+help-cmd-doctor = Gerät, Berechtigungen und Umgebung prüfen
+help-cmd-devices = Angeschlossene Lampen auflisten
+help-cmd-scene = Szenen speichern, auflisten und anwenden
+help-cmd-scene-cmd-apply = Zu einer gespeicherten Szene überblenden
+help-cmd-scene-cmd-apply-fade = Überblendzeit, z. B. 500ms oder 2s
+help-cmd-scene-cmd-set = Szene speichern und eine gleichnamige ersetzen
+help-cmd-scene-cmd-set-brightness = Helligkeit (Prozent)
+help-cmd-scene-cmd-set-temperature = Farbtemperatur (2700-6500)
+help-cmd-scene-cmd-list = Gespeicherte Szenen auflisten
+help-cmd-alarm = Sonnenaufgangswecker einstellen
+help-cmd-alarm-cmd-show = Weckereinstellungen und nächsten Start anzeigen
+help-cmd-alarm-cmd-set = Weckereinstellungen ändern und den Wecker aktivieren
+help-cmd-alarm-cmd-set-days = z. B. sat,sun
+help-cmd-alarm-cmd-set-brightness = Zielhelligkeit (Prozent)
+help-cmd-alarm-cmd-set-temperature = Ziel-Farbtemperatur
+help-cmd-alarm-cmd-set-missed = Was geschieht, wenn der Start im Ruhezustand verpasst wurde
+help-cmd-alarm-cmd-arm = Wecker aktivieren
+help-cmd-alarm-cmd-disarm = Wecker deaktivieren
+help-cmd-alarm-cmd-skip-next = Den nächsten geplanten Start überspringen
+help-cmd-history = Mit dem aufgezeichneten Verlauf arbeiten
+help-cmd-history-cmd-export = Verlauf als CSV-Datei schreiben
+help-cmd-stats = Betriebszeiten anzeigen oder zurücksetzen
+help-cmd-stats-cmd-show = Anzeigen, wie lange jede bekannte Lampe an war
+help-cmd-stats-cmd-reset = Betriebszeit einer Lampe oder aller Lampen auf null setzen
+help-cmd-stats-cmd-reset-serial = Seriennummer der Lampe; ohne Angabe alle Lampen
+help-cmd-stats-cmd-reset-yes = Nicht nachfragen
+help-cmd-import = Szenen aus den Einstellungen eines anderen Litra-Programms importieren
+help-cmd-import-apply = Szenen speichern; ohne diese Option wird nur die Zusammenfassung gezeigt
+help-cmd-calibrate = Kalibrierung der verbundenen Lampe anzeigen oder ändern
+help-cmd-calibrate-temperature-offset = Wird zu jeder an diese Lampe geschriebenen Farbtemperatur addiert
+help-cmd-calibrate-brightness-offset = Wird zu jeder an diese Lampe geschriebenen Helligkeit addiert
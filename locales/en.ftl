@@ -0,0 +1,241 @@
+# Base catalog. Every message used in the code must be defined here; other
+# catalogs fall back to these per message.
+#
+# Command-line help is translated with ids made of `help-` and the command
+# path, each subcommand marked by `cmd-`: e.g. `help-cmd-scene-cmd-apply`
+# for a subcommand and `help-cmd-scene-cmd-apply-fade` for one of its
+# options, `help` for the program and `help--after` for the text after the
+# options. The English help also lives in the clap attributes; a test keeps
+# the two the same.
+
+## Errors and warnings
+
+error = Error: { $message }
+error-open-device = Failed to open device: { $error }
+error-interrupted = Interrupted
+error-not-confirmed = Warning: device did not confirm { $command }
+error-lamp-not-confirmed = Warning: { $serial } did not confirm { $command }
+error-lamp-failed = Warning: { $serial } failed: { $error }
+error-lamp-open = Warning: failed to open { $serial }: { $error }
+error-unknown-group = Unknown or empty group `{ $name }`
+error-unreachable = Could not reach { $serials }
+error-no-serial = The lamp reports no serial number
+error-no-config-dir = No config directory found
+error-read-file = Failed to read { $path }: { $error }
+error-invalid-time = invalid time `{ $text }`: { $error }
+error-invalid-brightness = invalid brightness `{ $text }`: expected a percentage like 50 or lumens like 250lm
+error-raw-lumens = --raw takes the lamp's own brightness level, such as 200, not lumens
+error-lumens-clamped = Warning: { $lumens } lm is outside the { $model } range, using { $level } lm
+error-invalid-temperature = invalid temperature `{ $text }`: expected kelvin like 4000 or 5600K, mireds like 250mired or one of { $presets }
+error-temperature-outside = temperature { $kelvin }K is outside { $min }-{ $max }K; use a value in that range or one of { $presets }
+error-unknown-scene = Unknown scene `{ $name }`; saved scenes: { $names }
+error-device-not-found = Litra device not found
+error-serial-not-found = No Litra device with serial { $serial } found
+error-no-match = No connected lamp matches `{ $selector }`; run `devices` to list them
+error-permission-denied = No permission to open the lamp at { $path }. Add this line to /etc/udev/rules.d/50-litra-glow.rules, then replug the lamp: { $rule }
+error-read-timeout = Lamp did not answer in time
+error-toggle-unread = Could not read whether the lamp is on, so it was not toggled
+error-toggle-unconfirmed = Lamp did not confirm turning { $on ->
+        [yes] on
+       *[no] off
+    }
+error-state-unread = Could not read the lamp's { $fields }; nothing was saved
+error-write-short = Only { $written } of { $expected } bytes reached the lamp
+error-hid = HID error: { $error }
+error-io = I/O error: { $error }
+error-trace = Trace error: { $error }
+error-brightness-range = Brightness { $level } is outside the { $model } range { $min }-{ $max }
+error-temperature-range = Temperature { $kelvin }K is outside the { $model } range { $min }-{ $max }K
+device-disconnected = Device disconnected
+sync-lamp-lost = Stopped syncing { $lamp }: { $error }
+error-retrying = { $error }, retrying in { $seconds }s
+
+## Confirmation prompts
+
+confirm-prompt = { $question } [y/N]
+# Answers taken as yes, separated by commas.
+confirm-yes = y, yes
+nothing-changed = Nothing changed
+
+## Scenes, alarm, history and calibration
+
+scene-line = { $name }: { $brightness }% { $temperature }K
+scenes-none = none
+preset-saved = Saved { $name }: { $power ->
+        [on] on
+       *[off] off
+    }, { $brightness }% { $temperature }K
+alarm-summary = { $armed ->
+        [yes] Armed
+       *[no] Disarmed
+    } at { $time } on { $days }, ramp to { $brightness }% { $temperature }K over { $minutes }m, missed: { $missed }
+alarm-next = Next start: { $time }
+history-exported = Exported { $count } entries to { $path }
+calibration = { $serial }: temperature { $temperature }K, brightness { $brightness }%
+
+## Devices
+
+devices-none = No supported Litra lamp found
+devices-line = { $index }: { $model }, serial { $serial }, { $path }
+devices-no-serial = unknown
+devices-supported = Supported models, in the order they are probed:
+devices-supported-line = { $id } { $model }
+
+## On-time
+
+stats-none = No on-time recorded yet
+stats-line = { $serial }: { $hours } h
+stats-not-recorded = No on-time recorded for { $serial }
+stats-target-lamp = lamp { $serial }
+stats-target-all = all lamps
+stats-reset-question = Reset the on-time of { $target }?
+stats-reset-done = Reset the on-time of { $target }
+
+## Import
+
+import-add = add scene { $name }: { $brightness }% { $temperature }K
+import-replace = replace scene { $name }: { $brightness }% { $temperature }K
+import-not-imported = Not imported:
+import-nothing = Nothing to import
+import-dry-run = Dry run; pass --apply to write these scenes
+import-done = { $count ->
+        [one] Imported one scene
+       *[other] Imported { $count } scenes
+    }
+import-not-json = Not a JSON file: { $error }
+import-line = line { $line }
+import-nested = only top-level `key: value` entries are read
+import-out-of-range = `{ $value }` is out of range
+import-camera = litra-glow does not watch the camera
+import-lamp-selector = litra-glow uses the first lamp found; groups select lamps by serial
+import-device-type = only the Litra Glow is supported
+import-verbose = use RUST_LOG or --trace instead
+import-unknown-field = unknown field
+import-no-light = sets neither brightness nor temperature
+import-no-light-settings = no light settings found
+import-needs-temperature = a scene needs a temperature as well as a brightness
+import-needs-brightness = a scene needs a brightness as well as a temperature
+
+## Window, popup and tray
+
+connecting = Connecting...
+footer-on-time = Lamp on for { $hours } h in total
+footer-paused = Automations paused
+footer-serial = Serial { $serial }
+footer-firmware = Firmware { $firmware }
+footer-paused-for = Automations paused for { $minutes } more min
+announce-connected = Lamp connected
+announce-power-on = Lamp on
+announce-power-off = Lamp off
+announce-brightness = Brightness { $percent } percent
+announce-temperature = Color temperature { $kelvin } Kelvin
+osd-power = Power
+osd-on = On
+osd-off = Off
+osd-brightness = Brightness
+osd-temperature = Temperature
+history-span = { $hours } h
+tray-show = Show
+tray-scene = Scene: { $name }
+tray-scene-key = Scene: { $name } ({ $key })
+tray-cycle-brightness = Cycle brightness
+tray-cycle-temperature = Cycle temperature
+tray-alarm-toggle = Alarm: arm/disarm
+tray-alarm-skip = Alarm: skip next
+tray-alarm-dismiss = Alarm: dismiss
+tray-focus-toggle = Focus follow: on/off
+tray-sync-toggle = Sync all lamps: on/off
+tray-pause-hour = Pause automations for 1h
+tray-pause = Pause automations until resumed
+tray-resume = Resume automations
+tray-quit = Quit
+
+## Command-line help
+
+help = Logitech Litra Glow controller
+help--after = Combined settings are applied in order: brightness, temperature, then power. Each write is confirmed by reading the value back before the next is sent.
+help-on = Turn the lamp on
+help-off = Turn the lamp off
+help-toggle = Toggle lamp power
+help-brightness = Set brightness (percentage), or in lumens as in 250lm
+help-perceptual = Treat brightness percentages as perceived brightness, so 50% looks like half
+help-raw = Take --brightness as the lamp's own level (e.g. 20-250 on a Glow) and add it to status as raw_brightness; calibration and the brightness floor are left out
+help-min-floor = Never set brightness below this percentage, to avoid flicker at the low end; --off still works
+help-temperature = Set color temperature (2700-6500), in mireds as in 250mired, or by preset: warm, soft, neutral, cool, daylight
+help-temperature-percent = Set color temperature as a percentage from warmest (0) to coolest (100)
+help-cycle-brightness = Step brightness to the next value in the cycle list
+help-cycle-temperature = Step color temperature to the next value in the cycle list
+help-brightness-up = Raise brightness by this many percent from the lamp's current value and print the result
+help-brightness-down = Lower brightness by this many percent from the lamp's current value and print the result
+help-and-on = With --brightness-up, also turn the lamp on if it is off
+help-warmer = Lower color temperature by this many kelvin (default one step, 100) from the lamp's current value and print the result
+help-cooler = Raise color temperature by this many kelvin (default one step, 100) from the lamp's current value and print the result
+help-preset = Apply a saved scene, as listed by `scene list`, before the other settings
+help-save-preset = Save the lamp's power, brightness and temperature as a scene, after any other settings
+help-fade = Fade brightness and temperature to their new values over this time, e.g. 500ms or 2s
+help-status = Show current lamp status
+help-json = Report errors on stderr as a JSON object with the message and its kind
+help-verbose = Also ask the lamp for its name in --status
+help-off-on-exit = Turn the lamp off when the window is closed or quit from the tray
+help-group = Apply to every lamp of a group, scaling brightness by each lamp's ratio
+help-all = Apply to every connected lamp; without other flags, the window drives them all
+help-device = Use the lamp with this serial number, HID path or index, as listed by `devices`, instead of the first found
+help-serial = Use the lamp with this serial number instead of the first found
+help-wait = Wait up to this long for the lamp to be connected before running the command; 0 waits forever
+help-timeout = How long --status waits for the lamp to answer
+help-trace-out = Append all device traffic to a JSONL trace file; with --all or --group, one file per lamp named after its serial number
+help-replay = Replay device traffic from a trace instead of using real hardware
+help-dry-run = Simulate the lamp and log the frames that would be written
+help-trace = Print verbose diagnostics with span timings to stderr
+help-dump-unknown = Print every report from the lamp that is not understood as a hex line, until interrupted
+help-lang = Language of messages and help, e.g. de; defaults to the system language
+help-cmd-on = Turn the lamp on
+help-cmd-off = Turn the lamp off
+help-cmd-toggle = Toggle lamp power
+help-cmd-set = Set brightness and color temperature, applied in that order and then power
+help-cmd-set-brightness = Brightness (percentage), or in lumens as in 250lm
+help-cmd-set-temperature = Color temperature (2700-6500), in mireds as in 250mired, or by preset: warm, soft, neutral, cool, daylight
+help-cmd-set-temperature-percent = Color temperature as a percentage from warmest (0) to coolest (100)
+help-cmd-set-on = Also turn the lamp on
+help-cmd-set-off = Also turn the lamp off
+help-cmd-set-fade = Fade brightness and temperature to their new values over this time, e.g. 500ms or 2s
+help-cmd-get = Print one value the lamp reports
+help-cmd-status = Show current lamp status as JSON
+help-cmd-status-verbose = Also ask the lamp for its name
+help-cmd-preset = Apply a saved scene, as listed by `scene list`
+help-cmd-preset-brightness = Brightness (percentage), or in lumens as in 250lm
+help-cmd-preset-temperature = Color temperature (2700-6500), in mireds as in 250mired, or by preset: warm, soft, neutral, cool, daylight
+help-cmd-preset-temperature-percent = Color temperature as a percentage from warmest (0) to coolest (100)
+help-cmd-completions = Print a shell completion script, e.g. for ~/.bash_completion
+help-cmd-schema = Print the JSON Schema of all JSON output
+help-cmd-doctor = Check the device, permissions and environment
+help-cmd-devices = List the connected lamps
+help-cmd-scene = Save, list and apply scenes
+help-cmd-scene-cmd-apply = Fade to a saved scene
+help-cmd-scene-cmd-apply-fade = Crossfade time, e.g. 500ms or 2s
+help-cmd-scene-cmd-set = Save a scene, replacing one with the same name
+help-cmd-scene-cmd-set-brightness = Brightness (percentage)
+help-cmd-scene-cmd-set-temperature = Color temperature (2700-6500)
+help-cmd-scene-cmd-list = List saved scenes
+help-cmd-alarm = Configure the sunrise wake-up alarm
+help-cmd-alarm-cmd-show = Show the alarm settings and the next start time
+help-cmd-alarm-cmd-set = Change the alarm settings and arm it
+help-cmd-alarm-cmd-set-days = e.g. sat,sun
+help-cmd-alarm-cmd-set-brightness = Target brightness (percentage)
+help-cmd-alarm-cmd-set-temperature = Target color temperature
+help-cmd-alarm-cmd-set-missed = What to do when the start was missed during sleep
+help-cmd-alarm-cmd-arm = Arm the alarm
+help-cmd-alarm-cmd-disarm = Disarm the alarm
+help-cmd-alarm-cmd-skip-next = Skip the next scheduled start
+help-cmd-history = Work with the recorded lamp history
+help-cmd-history-cmd-export = Write the recorded history to a CSV file
+help-cmd-stats = Show or reset the lamp on-time counters
+help-cmd-stats-cmd-show = Show how long each known lamp has been on
+help-cmd-stats-cmd-reset = Zero the on-time of one lamp, or of all lamps
+help-cmd-stats-cmd-reset-serial = Serial number of the lamp; all lamps when omitted
+help-cmd-stats-cmd-reset-yes = Do not ask for confirmation
+help-cmd-import = Import scenes from another Litra tool's settings
+help-cmd-import-apply = Write the scenes; without this only the summary is shown
+help-cmd-calibrate = Show or change the calibration offsets of the connected lamp
+help-cmd-calibrate-temperature-offset = Added to every color temperature written to this lamp
+help-cmd-calibrate-brightness-offset = Added to every brightness written to this lamp
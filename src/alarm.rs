@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+
+/// Minimum step spacing for a wake-up ramp, so a long fade does not write
+/// to the lamp more often than it changes visibly.
+pub const RAMP_STEP_INTERVAL: Duration = Duration::from_secs(10);
+/// How late a start may be noticed and still count as on time.
+const ON_TIME_TOLERANCE: Duration = Duration::from_secs(90);
+/// Shortest ramp used when a late alarm is compressed.
+const MIN_COMPRESSED_RAMP: Duration = Duration::from_secs(60);
+
+/// What to do when the start time passed while the machine was asleep.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MissedAlarm {
+    /// Start late and finish the ramp at its original end time.
+    #[default]
+    Compress,
+    /// Do nothing until the next scheduled day.
+    Skip,
+}
+
+/// Sunrise alarm: at `time` on each of `days` the lamp turns on at its
+/// warmest, dimmest setting and ramps to the target over `ramp_seconds`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlarmConfig {
+    pub armed: bool,
+    pub time: NaiveTime,
+    pub days: Vec<Weekday>,
+    pub ramp_seconds: u64,
+    /// Target brightness in percent.
+    pub brightness: u8,
+    pub temperature: u16,
+    pub missed: MissedAlarm,
+    /// A scheduled date that should not fire, set by "skip next".
+    pub skip_date: Option<NaiveDate>,
+}
+
+impl Default for AlarmConfig {
+    fn default() -> Self {
+        Self {
+            armed: false,
+            time: NaiveTime::from_hms_opt(7, 0, 0).expect("valid time"),
+            days: vec![Weekday::Sat, Weekday::Sun],
+            ramp_seconds: 20 * 60,
+            brightness: 100,
+            temperature: 5000,
+            missed: MissedAlarm::default(),
+            skip_date: None,
+        }
+    }
+}
+
+/// A ramp that should start now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Firing {
+    pub scheduled: DateTime<Local>,
+    /// How long the ramp should take from now.
+    pub duration: Duration,
+}
+
+impl AlarmConfig {
+    pub fn ramp(&self) -> Duration {
+        Duration::from_secs(self.ramp_seconds)
+    }
+
+    /// Local start time on `date`, if the alarm is scheduled that day.
+    fn start_on(&self, date: NaiveDate) -> Option<DateTime<Local>> {
+        if !self.days.contains(&date.weekday()) || self.skip_date == Some(date) {
+            return None;
+        }
+        date.and_time(self.time)
+            .and_local_timezone(Local)
+            .earliest()
+    }
+
+    /// The next start strictly after `after`, looking one week ahead.
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        if !self.armed {
+            return None;
+        }
+        (0..=7)
+            .filter_map(|offset| after.date_naive().checked_add_days(Days::new(offset)))
+            .filter_map(|date| self.start_on(date))
+            .find(|start| *start > after)
+    }
+
+    /// The ramp to run if a start time fell in `(last, now]`. A start noticed
+    /// late, e.g. after suspend, is compressed or skipped per `missed`.
+    pub fn due(&self, last: DateTime<Local>, now: DateTime<Local>) -> Option<Firing> {
+        if !self.armed || now <= last {
+            return None;
+        }
+        let days_back = (now.date_naive() - last.date_naive())
+            .num_days()
+            .clamp(0, 7) as u64;
+        let scheduled = (0..=days_back)
+            .filter_map(|offset| now.date_naive().checked_sub_days(Days::new(offset)))
+            .filter_map(|date| self.start_on(date))
+            .find(|start| *start > last && *start <= now)?;
+
+        let late = (now - scheduled).to_std().unwrap_or_default();
+        if late <= ON_TIME_TOLERANCE {
+            return Some(Firing {
+                scheduled,
+                duration: self.ramp(),
+            });
+        }
+        match self.missed {
+            MissedAlarm::Skip => None,
+            MissedAlarm::Compress => {
+                let remaining = self.ramp().checked_sub(late)?;
+                Some(Firing {
+                    scheduled,
+                    duration: remaining.max(MIN_COMPRESSED_RAMP),
+                })
+            }
+        }
+    }
+}
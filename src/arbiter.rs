@@ -0,0 +1,464 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+/// Who asked for a state change. Manual changes always win; automation
+/// rules are ranked by their position in [`ArbiterConfig::automation_order`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Manual,
+    Automation(String),
+}
+
+impl Source {
+    pub fn automation(rule: &str) -> Self {
+        Source::Automation(rule.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Setting {
+    Power,
+    Brightness,
+    Temperature,
+}
+
+/// A global pause of all automation rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pause {
+    Until(DateTime<Local>),
+    /// Until resumed by hand.
+    Indefinite,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArbiterConfig {
+    /// Automation rules from highest to lowest priority. Unlisted rules rank
+    /// below all listed ones.
+    pub automation_order: Vec<String>,
+    /// Minimum time between two power flips when the second is automated.
+    pub min_power_interval_secs: u64,
+    /// This many automated power flips within `oscillation_window_secs`
+    /// counts as two rules fighting.
+    pub oscillation_flips: usize,
+    pub oscillation_window_secs: u64,
+    /// Stored so a pause survives a restart; an expired one is ignored.
+    pub pause: Option<Pause>,
+}
+
+impl Default for ArbiterConfig {
+    fn default() -> Self {
+        Self {
+            automation_order: vec![
+                "alarm".to_string(),
+                "focus".to_string(),
+                "battery".to_string(),
+                "curve".to_string(),
+            ],
+            min_power_interval_secs: 5,
+            oscillation_flips: 6,
+            oscillation_window_secs: 60,
+            pause: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Apply,
+    /// The rule was suspended after oscillating.
+    Suspended,
+    /// All automations are paused.
+    Paused,
+    /// A higher-priority source changed this setting too recently.
+    Outranked,
+    /// The previous power flip was too recent.
+    RateLimited,
+}
+
+/// Two rules caught flipping power back and forth; `suspended` lost.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Oscillation {
+    pub suspended: String,
+    pub winner: String,
+}
+
+impl std::fmt::Display for Oscillation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Automations \"{}\" and \"{}\" keep overriding each other; \"{}\" is paused",
+            self.winner, self.suspended, self.suspended
+        )
+    }
+}
+
+/// Decides whether a state change may be applied, given recent changes by
+/// other sources. Pure bookkeeping: the caller supplies the time.
+pub struct Arbiter {
+    config: ArbiterConfig,
+    last_change: HashMap<Setting, (Source, Instant)>,
+    flips: VecDeque<(Instant, String)>,
+    suspended: HashSet<String>,
+    warning: Option<Oscillation>,
+    pause: Option<Pause>,
+}
+
+impl Arbiter {
+    pub fn new(config: ArbiterConfig) -> Self {
+        Self {
+            pause: config.pause,
+            config,
+            last_change: HashMap::new(),
+            flips: VecDeque::new(),
+            suspended: HashSet::new(),
+            warning: None,
+        }
+    }
+
+    /// Lower is stronger; manual changes rank 0.
+    fn rank(&self, source: &Source) -> usize {
+        match source {
+            Source::Manual => 0,
+            Source::Automation(rule) => {
+                1 + self
+                    .config
+                    .automation_order
+                    .iter()
+                    .position(|r| r == rule)
+                    .unwrap_or(self.config.automation_order.len())
+            }
+        }
+    }
+
+    /// Checks a change of `setting` by `source` and, when allowed, records it.
+    pub fn check(&mut self, source: &Source, setting: Setting, now: Instant) -> Verdict {
+        let Source::Automation(rule) = source else {
+            self.last_change.insert(setting, (source.clone(), now));
+            return Verdict::Apply;
+        };
+        if self.pause.is_some() {
+            return Verdict::Paused;
+        }
+        if self.suspended.contains(rule) {
+            return Verdict::Suspended;
+        }
+        let hold = Duration::from_secs(self.config.min_power_interval_secs);
+        if let Some((previous, at)) = self.last_change.get(&setting)
+            && now.duration_since(*at) < hold
+        {
+            if self.rank(previous) < self.rank(source) {
+                return Verdict::Outranked;
+            }
+            if setting == Setting::Power {
+                return Verdict::RateLimited;
+            }
+        }
+
+        if setting == Setting::Power {
+            self.flips.push_back((now, rule.clone()));
+            if self.detect_oscillation(now) && self.suspended.contains(rule) {
+                return Verdict::Suspended;
+            }
+        }
+        self.last_change.insert(setting, (source.clone(), now));
+        Verdict::Apply
+    }
+
+    /// Masks all automation rules until `pause` ends; `None` resumes them.
+    /// Manual changes are never affected.
+    pub fn set_pause(&mut self, pause: Option<Pause>) {
+        self.pause = pause;
+    }
+
+    /// Ends a timed pause that has run out by `now`. Returns whether it did.
+    pub fn expire_pause(&mut self, now: DateTime<Local>) -> bool {
+        match self.pause {
+            Some(Pause::Until(until)) if until <= now => {
+                self.pause = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Who last changed `setting`.
+    pub fn last_source(&self, setting: Setting) -> Option<&Source> {
+        self.last_change.get(&setting).map(|(source, _)| source)
+    }
+
+    /// When `setting` was last changed, if that change was manual.
+    pub fn last_manual(&self, setting: Setting) -> Option<Instant> {
+        match self.last_change.get(&setting) {
+            Some((Source::Manual, at)) => Some(*at),
+            _ => None,
+        }
+    }
+
+    /// Suspends the weaker of the two rules flipping power most often once
+    /// the flip rate crosses the threshold. Returns whether one was suspended.
+    fn detect_oscillation(&mut self, now: Instant) -> bool {
+        let window = Duration::from_secs(self.config.oscillation_window_secs);
+        while let Some((at, _)) = self.flips.front()
+            && now.duration_since(*at) > window
+        {
+            self.flips.pop_front();
+        }
+        if self.flips.len() < self.config.oscillation_flips {
+            return false;
+        }
+
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for (_, rule) in &self.flips {
+            *counts.entry(rule.as_str()).or_default() += 1;
+        }
+        let mut rules: Vec<(&str, usize)> = counts.into_iter().collect();
+        rules.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        let [(first, _), (second, _), ..] = rules[..] else {
+            return false;
+        };
+        let (winner, loser) =
+            if self.rank(&Source::automation(first)) <= self.rank(&Source::automation(second)) {
+                (first.to_string(), second.to_string())
+            } else {
+                (second.to_string(), first.to_string())
+            };
+
+        self.flips.retain(|(_, rule)| *rule != loser);
+        self.suspended.insert(loser.clone());
+        self.warning = Some(Oscillation {
+            suspended: loser,
+            winner,
+        });
+        true
+    }
+
+    /// The oscillation detected since the last call, if any.
+    pub fn take_warning(&mut self) -> Option<Oscillation> {
+        self.warning.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(start: Instant, secs: u64) -> Instant {
+        start + Duration::from_secs(secs)
+    }
+
+    /// Runs `(second, source, setting)` steps and collects the verdicts.
+    fn script(arbiter: &mut Arbiter, steps: &[(u64, &str, Setting)]) -> Vec<Verdict> {
+        let start = Instant::now();
+        steps
+            .iter()
+            .map(|&(at, source, setting)| {
+                let source = match source {
+                    "manual" => Source::Manual,
+                    rule => Source::automation(rule),
+                };
+                arbiter.check(&source, setting, secs(start, at))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn manual_changes_always_apply() {
+        let mut arbiter = Arbiter::new(ArbiterConfig::default());
+        let verdicts = script(
+            &mut arbiter,
+            &[
+                (0, "alarm", Setting::Power),
+                (0, "manual", Setting::Power),
+                (0, "manual", Setting::Power),
+                (1, "manual", Setting::Brightness),
+            ],
+        );
+        assert_eq!(verdicts, [Verdict::Apply; 4]);
+        assert_eq!(arbiter.last_source(Setting::Power), Some(&Source::Manual));
+        assert!(arbiter.last_manual(Setting::Brightness).is_some());
+    }
+
+    #[test]
+    fn a_recent_stronger_change_outranks_automation() {
+        let mut arbiter = Arbiter::new(ArbiterConfig::default());
+        let verdicts = script(
+            &mut arbiter,
+            &[
+                (0, "manual", Setting::Brightness),
+                (1, "curve", Setting::Brightness),
+                (5, "curve", Setting::Brightness),
+                (6, "alarm", Setting::Brightness),
+                (7, "curve", Setting::Brightness),
+                (7, "alarm", Setting::Brightness),
+                (8, "unlisted", Setting::Brightness),
+                (8, "curve", Setting::Temperature),
+            ],
+        );
+        assert_eq!(
+            verdicts,
+            [
+                Verdict::Apply,
+                Verdict::Outranked,
+                Verdict::Apply,
+                Verdict::Apply,
+                Verdict::Outranked,
+                Verdict::Apply,
+                Verdict::Outranked,
+                Verdict::Apply,
+            ]
+        );
+        assert_eq!(
+            arbiter.last_source(Setting::Brightness),
+            Some(&Source::automation("alarm"))
+        );
+    }
+
+    #[test]
+    fn the_configured_order_ranks_automations() {
+        let config = ArbiterConfig {
+            automation_order: vec!["curve".to_string(), "alarm".to_string()],
+            ..ArbiterConfig::default()
+        };
+        let mut arbiter = Arbiter::new(config);
+        let verdicts = script(
+            &mut arbiter,
+            &[
+                (0, "curve", Setting::Temperature),
+                (1, "alarm", Setting::Temperature),
+            ],
+        );
+        assert_eq!(verdicts, [Verdict::Apply, Verdict::Outranked]);
+    }
+
+    #[test]
+    fn automated_power_flips_are_rate_limited() {
+        let mut arbiter = Arbiter::new(ArbiterConfig::default());
+        let verdicts = script(
+            &mut arbiter,
+            &[
+                (0, "alarm", Setting::Power),
+                (1, "alarm", Setting::Power),
+                (4, "alarm", Setting::Power),
+                (5, "alarm", Setting::Power),
+                (6, "manual", Setting::Power),
+                (7, "alarm", Setting::Power),
+            ],
+        );
+        assert_eq!(
+            verdicts,
+            [
+                Verdict::Apply,
+                Verdict::RateLimited,
+                Verdict::RateLimited,
+                Verdict::Apply,
+                Verdict::Apply,
+                Verdict::Outranked,
+            ]
+        );
+    }
+
+    #[test]
+    fn two_rules_fighting_suspends_the_weaker() {
+        let mut arbiter = Arbiter::new(ArbiterConfig::default());
+        let fight: Vec<_> = (0..6)
+            .map(|flip| {
+                let rule = if flip % 2 == 0 { "focus" } else { "battery" };
+                (flip * 5, rule, Setting::Power)
+            })
+            .collect();
+        let verdicts = script(&mut arbiter, &fight);
+        assert_eq!(verdicts[..5], [Verdict::Apply; 5]);
+        assert_eq!(verdicts[5], Verdict::Suspended);
+
+        let warning = arbiter.take_warning().unwrap();
+        assert_eq!(
+            warning,
+            Oscillation {
+                suspended: "battery".to_string(),
+                winner: "focus".to_string(),
+            }
+        );
+        let text = warning.to_string();
+        assert!(text.contains("focus") && text.contains("battery"), "{text}");
+        assert_eq!(arbiter.take_warning(), None);
+
+        let verdicts = script(
+            &mut arbiter,
+            &[
+                (100, "battery", Setting::Power),
+                (100, "battery", Setting::Brightness),
+                (100, "focus", Setting::Power),
+            ],
+        );
+        assert_eq!(
+            verdicts,
+            [Verdict::Suspended, Verdict::Suspended, Verdict::Apply]
+        );
+    }
+
+    #[test]
+    fn flips_spread_beyond_the_window_are_not_a_fight() {
+        let mut arbiter = Arbiter::new(ArbiterConfig::default());
+        let slow: Vec<_> = (0..12)
+            .map(|flip| {
+                let rule = if flip % 2 == 0 { "focus" } else { "battery" };
+                (flip * 15, rule, Setting::Power)
+            })
+            .collect();
+        let verdicts = script(&mut arbiter, &slow);
+        assert!(
+            verdicts.iter().all(|v| *v == Verdict::Apply),
+            "{verdicts:?}"
+        );
+        assert_eq!(arbiter.take_warning(), None);
+    }
+
+    #[test]
+    fn one_rule_flipping_alone_is_not_suspended() {
+        let mut arbiter = Arbiter::new(ArbiterConfig::default());
+        let alone: Vec<_> = (0..8)
+            .map(|flip| (flip * 5, "focus", Setting::Power))
+            .collect();
+        let verdicts = script(&mut arbiter, &alone);
+        assert!(
+            verdicts.iter().all(|v| *v == Verdict::Apply),
+            "{verdicts:?}"
+        );
+        assert_eq!(arbiter.take_warning(), None);
+    }
+
+    #[test]
+    fn a_pause_masks_automations_until_it_ends() {
+        let mut arbiter = Arbiter::new(ArbiterConfig::default());
+        arbiter.set_pause(Some(Pause::Indefinite));
+        let verdicts = script(
+            &mut arbiter,
+            &[(0, "alarm", Setting::Power), (0, "manual", Setting::Power)],
+        );
+        assert_eq!(verdicts, [Verdict::Paused, Verdict::Apply]);
+        assert!(!arbiter.expire_pause(Local::now()));
+
+        let now = Local::now();
+        arbiter.set_pause(Some(Pause::Until(now + chrono::Duration::minutes(30))));
+        assert!(!arbiter.expire_pause(now));
+        assert!(arbiter.expire_pause(now + chrono::Duration::minutes(30)));
+        let verdicts = script(&mut arbiter, &[(10, "alarm", Setting::Brightness)]);
+        assert_eq!(verdicts, [Verdict::Apply]);
+    }
+
+    #[test]
+    fn a_stored_pause_survives_a_restart() {
+        let config = ArbiterConfig {
+            pause: Some(Pause::Indefinite),
+            ..ArbiterConfig::default()
+        };
+        let mut arbiter = Arbiter::new(config);
+        let verdicts = script(&mut arbiter, &[(0, "curve", Setting::Temperature)]);
+        assert_eq!(verdicts, [Verdict::Paused]);
+    }
+}
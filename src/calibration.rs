@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+
+use crate::convert::snap_temperature;
+use crate::protocol::{Command, Model};
+
+/// Offsets that make one lamp match another. The app and the CLI speak in
+/// nominal values; these are added just before a value is written and taken
+/// off again when the lamp reports it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Calibration {
+    pub temperature_offset_k: i16,
+    /// Percent of the brightness range.
+    pub brightness_offset_percent: i8,
+}
+
+impl Calibration {
+    pub fn is_identity(&self) -> bool {
+        self.temperature_offset_k == 0 && self.brightness_offset_percent == 0
+    }
+
+    fn brightness_offset(&self, model: Model) -> i32 {
+        let range = (model.max_brightness() - model.min_brightness()) as f32;
+        (self.brightness_offset_percent as f32 * range / 100.0).round() as i32
+    }
+
+    /// The level written to a `model` lamp for a nominal `level`, within
+    /// its limits.
+    pub fn effective_brightness(&self, level: u16, model: Model) -> u16 {
+        shift_brightness(level, self.brightness_offset(model), model)
+    }
+
+    /// The kelvin written to the lamp for a nominal `kelvin`, snapped to the
+    /// device grid.
+    pub fn effective_temperature(&self, kelvin: u16) -> u16 {
+        shift_temperature(kelvin, self.temperature_offset_k as i32)
+    }
+
+    /// The nominal level for one the lamp reported. Where writing clamped
+    /// the value, this is the nearest nominal level that maps back to it.
+    pub fn nominal_brightness(&self, level: u16, model: Model) -> u16 {
+        shift_brightness(level, -self.brightness_offset(model), model)
+    }
+
+    pub fn nominal_temperature(&self, kelvin: u16) -> u16 {
+        shift_temperature(kelvin, -(self.temperature_offset_k as i32))
+    }
+
+    /// Applies the offsets to a set command for a `model` lamp; other
+    /// commands pass through.
+    pub fn calibrate(&self, cmd: Command, model: Model) -> Command {
+        match cmd {
+            Command::SetBrightness(level) => {
+                Command::set_brightness_clamped(self.effective_brightness(level, model), model)
+            }
+            Command::SetTemperature(kelvin) => {
+                Command::set_temperature_clamped(self.effective_temperature(kelvin), model)
+            }
+            other => other,
+        }
+    }
+}
+
+impl std::fmt::Display for Calibration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "temperature {:+}K, brightness {:+}%",
+            self.temperature_offset_k, self.brightness_offset_percent
+        )
+    }
+}
+
+fn shift_brightness(level: u16, offset: i32, model: Model) -> u16 {
+    (level as i32 + offset).clamp(model.min_brightness() as i32, model.max_brightness() as i32)
+        as u16
+}
+
+fn shift_temperature(kelvin: u16, offset: i32) -> u16 {
+    snap_temperature((kelvin as i32 + offset).clamp(0, u16::MAX as i32) as u16)
+}
@@ -0,0 +1,264 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    senders: usize,
+    receiver_alive: bool,
+}
+
+#[derive(Debug)]
+pub enum SendError<T> {
+    Full(T),
+    Disconnected(T),
+}
+
+impl<T> std::fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendError::Full(_) => write!(f, "queue is full"),
+            SendError::Disconnected(_) => write!(f, "receiver is gone"),
+        }
+    }
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Creates a bounded multi-producer queue whose senders choose how to behave
+/// when it is full: wait, replace stale items, or give up.
+pub fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            items: VecDeque::with_capacity(capacity),
+            senders: 1,
+            receiver_alive: true,
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+        capacity: capacity.max(1),
+    });
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared },
+    )
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T> Sender<T> {
+    fn push(&self, mut state: MutexGuard<'_, State<T>>, item: T) -> Result<(), SendError<T>> {
+        if state.items.len() >= self.shared.capacity {
+            return Err(SendError::Full(item));
+        }
+        state.items.push_back(item);
+        drop(state);
+        self.shared.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for room in the queue.
+    pub fn send_timeout(&self, item: T, timeout: Duration) -> Result<(), SendError<T>> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.lock();
+        loop {
+            if !state.receiver_alive {
+                return Err(SendError::Disconnected(item));
+            }
+            if state.items.len() < self.shared.capacity {
+                return self.push(state, item);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(SendError::Full(item));
+            }
+            state = self
+                .shared
+                .not_full
+                .wait_timeout(state, remaining)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    /// Drops every queued item that `item` makes obsolete, then enqueues it.
+    /// `obsolete` is called with the queued item and the new one.
+    pub fn send_latest(
+        &self,
+        item: T,
+        obsolete: impl Fn(&T, &T) -> bool,
+    ) -> Result<(), SendError<T>> {
+        let mut state = self.shared.lock();
+        if !state.receiver_alive {
+            return Err(SendError::Disconnected(item));
+        }
+        state.items.retain(|queued| !obsolete(queued, &item));
+        self.push(state, item)
+    }
+
+    /// Enqueues `item`, dropping the items it makes obsolete only when the
+    /// queue is full.
+    pub fn send_collapsing(
+        &self,
+        item: T,
+        obsolete: impl Fn(&T, &T) -> bool,
+    ) -> Result<(), SendError<T>> {
+        let mut state = self.shared.lock();
+        if !state.receiver_alive {
+            return Err(SendError::Disconnected(item));
+        }
+        if state.items.len() >= self.shared.capacity {
+            state.items.retain(|queued| !obsolete(queued, &item));
+        }
+        self.push(state, item)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.shared.not_empty.notify_all();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    fn pop(&self, mut state: MutexGuard<'_, State<T>>) -> Option<T> {
+        let item = state.items.pop_front()?;
+        drop(state);
+        self.shared.not_full.notify_one();
+        Some(item)
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let state = self.shared.lock();
+        let disconnected = state.senders == 0;
+        match self.pop(state) {
+            Some(item) => Ok(item),
+            None if disconnected => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.lock();
+        loop {
+            if !state.items.is_empty() {
+                return self.pop(state).ok_or(RecvTimeoutError::Timeout);
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = self
+                .shared
+                .not_empty
+                .wait_timeout(state, remaining)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    pub fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.try_recv().ok())
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.lock().receiver_alive = false;
+        self.shared.not_full.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn same_kind(queued: &(char, u32), item: &(char, u32)) -> bool {
+        queued.0 == item.0
+    }
+
+    #[test]
+    fn send_latest_replaces_obsolete_items() {
+        let (tx, rx) = bounded(2);
+        for value in 0..100 {
+            tx.send_latest(('b', value), same_kind).unwrap();
+            tx.send_latest(('t', value), same_kind).unwrap();
+        }
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [('b', 99), ('t', 99)]);
+    }
+
+    #[test]
+    fn send_timeout_gives_up_when_full() {
+        let (tx, rx) = bounded(1);
+        tx.send_timeout(1, Duration::ZERO).unwrap();
+        let started = Instant::now();
+        let refused = tx.send_timeout(2, Duration::from_millis(20));
+        assert!(matches!(refused, Err(SendError::Full(2))));
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert_eq!(rx.try_recv(), Ok(1));
+        tx.send_timeout(3, Duration::ZERO).unwrap();
+        assert_eq!(rx.try_recv(), Ok(3));
+    }
+
+    #[test]
+    fn send_collapsing_drops_obsolete_items_only_when_full() {
+        let (tx, rx) = bounded(3);
+        for value in 0..3 {
+            tx.send_collapsing(('b', value), same_kind).unwrap();
+        }
+        tx.send_collapsing(('t', 0), same_kind).unwrap_err();
+        tx.send_collapsing(('b', 3), same_kind).unwrap();
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [('b', 3)]);
+    }
+
+    #[test]
+    fn senders_see_a_dropped_receiver() {
+        let (tx, rx) = bounded(1);
+        drop(rx);
+        assert!(matches!(
+            tx.send_timeout(1, Duration::ZERO),
+            Err(SendError::Disconnected(1))
+        ));
+        let (tx, rx) = bounded::<u32>(1);
+        drop(tx);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::alarm::AlarmConfig;
+use crate::arbiter::ArbiterConfig;
+use crate::calibration::Calibration;
+use crate::convert::BrightnessScale;
+use crate::curve::TemperatureCurve;
+use crate::cycle::CycleConfig;
+use crate::focus::FocusConfig;
+use crate::group::Group;
+use crate::history::HistoryConfig;
+use crate::hotkey::HotkeyConfig;
+use crate::power_source::BatteryConfig;
+use crate::protocol::DEFAULT_GAMMA;
+use crate::scenes::SceneConfig;
+use crate::storage;
+use crate::transport::HidBackend;
+
+const CONFIG_FILE: &str = "settings.json";
+
+/// User settings. Unknown or missing fields fall back to their defaults so
+/// older files keep loading.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub alarm: AlarmConfig,
+    pub arbitration: ArbiterConfig,
+    pub cycle: CycleConfig,
+    /// How scenes are applied. The scenes, which `--preset` applies too,
+    /// are kept in `scenes.json` by [`crate::scenes::SceneStore`].
+    pub scenes: SceneConfig,
+    pub temperature_curve: TemperatureCurve,
+    pub osd: OsdConfig,
+    pub refresh: RefreshConfig,
+    pub battery: BatteryConfig,
+    pub focus: FocusConfig,
+    /// Per-lamp settings and counters keyed by serial number.
+    pub devices: BTreeMap<String, DeviceConfig>,
+    pub groups: BTreeMap<String, Group>,
+    /// Drive every connected lamp along with the one the window controls.
+    pub sync_lamps: bool,
+    /// Turn the lamp off when the app quits, but not when the window is
+    /// only hidden to the tray.
+    pub off_on_exit: bool,
+    pub brightness: BrightnessConfig,
+    pub hotkeys: HotkeyConfig,
+    pub history: HistoryConfig,
+    /// Language of messages, e.g. `de`. Empty follows the system.
+    pub language: String,
+    /// HID backend expected, e.g. `libusb`. Empty accepts the one built
+    /// in; any other is rejected on load, since hidapi picks the backend at
+    /// build time.
+    pub hid_backend: String,
+}
+
+impl Config {
+    /// The calibration for the lamp with `serial`; none for unknown lamps.
+    pub fn calibration(&self, serial: Option<&str>) -> Calibration {
+        serial
+            .and_then(|serial| self.devices.get(serial))
+            .map(|device| device.calibration)
+            .unwrap_or_default()
+    }
+
+    /// How long the lamp with `serial` has been on, in seconds.
+    pub fn on_seconds(&self, serial: Option<&str>) -> u64 {
+        serial
+            .and_then(|serial| self.devices.get(serial))
+            .map_or(0, |device| device.on_seconds)
+    }
+}
+
+/// Settings and counters of one lamp.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceConfig {
+    #[serde(flatten)]
+    pub calibration: Calibration,
+    /// Total time the lamp has been seen on by the app.
+    pub on_seconds: u64,
+}
+
+impl DeviceConfig {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Background re-reads of the lamp state while connected and idle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RefreshConfig {
+    /// Minutes between re-reads; 0 turns them off.
+    pub interval_minutes: u64,
+    /// Idle seconds before the lamp is asked for its power state to check
+    /// it is still there; 0 turns this off.
+    pub keepalive_seconds: u64,
+    /// Longest wait in milliseconds for a lamp report between rounds of the
+    /// device loop while idle. Higher values wake the CPU less often but
+    /// let commands wait as long.
+    pub poll_ms: u64,
+    /// Write the last known state back to the lamp after the system resumes
+    /// or the lamp reconnects, instead of adopting its values.
+    pub restore_on_reconnect: bool,
+}
+
+impl Default for RefreshConfig {
+    fn default() -> Self {
+        Self {
+            interval_minutes: 5,
+            keepalive_seconds: 10,
+            poll_ms: 30,
+            restore_on_reconnect: false,
+        }
+    }
+}
+
+impl RefreshConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_minutes * 60)
+    }
+
+    pub fn keepalive(&self) -> Duration {
+        Duration::from_secs(self.keepalive_seconds)
+    }
+
+    pub fn poll(&self) -> Duration {
+        Duration::from_millis(self.poll_ms)
+    }
+}
+
+/// How brightness percentages map to lamp levels.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BrightnessConfig {
+    /// Treat percentages as perceived brightness, so 50% looks like half,
+    /// instead of as a linear share of the lamp's range.
+    pub perceptual: bool,
+    /// Exponent of the perceptual curve.
+    pub gamma: f32,
+    /// Lowest brightness the app writes, as a linear percentage of the
+    /// lamp's range, for lamps that flicker near their minimum. Turning the
+    /// lamp off is not affected.
+    pub min_floor: u8,
+}
+
+impl Default for BrightnessConfig {
+    fn default() -> Self {
+        Self {
+            perceptual: false,
+            gamma: DEFAULT_GAMMA,
+            min_floor: 0,
+        }
+    }
+}
+
+impl BrightnessConfig {
+    /// The scale to use; `perceptual` turns the perceptual curve on even
+    /// when the settings leave it off. A gamma that is not a positive
+    /// number falls back to the default.
+    pub fn scale(&self, perceptual: bool) -> BrightnessScale {
+        if !(self.perceptual || perceptual) {
+            return BrightnessScale::Linear;
+        }
+        let gamma = if self.gamma.is_finite() && self.gamma > 0.0 {
+            self.gamma
+        } else {
+            DEFAULT_GAMMA
+        };
+        BrightnessScale::Perceptual(gamma)
+    }
+
+    /// The brightness floor in percent; `min_floor` from the command line
+    /// replaces the saved one.
+    pub fn floor(&self, min_floor: Option<u8>) -> u8 {
+        min_floor.unwrap_or(self.min_floor).min(100)
+    }
+}
+
+/// The popup shown when the lamp's own buttons change a setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OsdConfig {
+    pub enabled: bool,
+}
+
+impl Default for OsdConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// Reads and writes [`Config`] as a single JSON file.
+pub struct ConfigStore {
+    path: PathBuf,
+}
+
+impl ConfigStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// The settings file in the user's config directory, if one can be
+    /// determined.
+    pub fn open_default() -> Option<Self> {
+        storage::config_dir().map(|dir| Self::new(dir.join(CONFIG_FILE)))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the settings. A missing file yields the defaults; settings
+    /// this build cannot honour are an error.
+    pub fn load(&self) -> io::Result<Config> {
+        let config: Config = match fs::read(&self.path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(e),
+        };
+        HidBackend::current()
+            .check_setting(&config.hid_backend)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(config)
+    }
+
+    pub fn save(&self, config: &Config) -> io::Result<()> {
+        let data = serde_json::to_vec_pretty(config).expect("config always serializes");
+        storage::atomic_write(&self.path, &data)
+    }
+
+    /// Loads the settings, lets `change` edit them and saves the result.
+    pub fn update(&self, change: impl FnOnce(&mut Config)) -> io::Result<Config> {
+        let mut config = self.load()?;
+        change(&mut config);
+        self.save(&config)?;
+        Ok(config)
+    }
+}
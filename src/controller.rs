@@ -0,0 +1,327 @@
+use std::cell::{Cell, RefCell};
+use std::time::{Duration, Instant};
+
+use tracing::debug_span;
+
+use crate::calibration::Calibration;
+use crate::convert::{brightness_to_percent, percent_to_brightness};
+use crate::protocol::{Command, Model, Response, Trigger};
+use crate::usb::{DeviceInfo, Error, Identity, LitraDevice};
+
+pub const CONFIRM_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// Lamp state gathered from query replies; `None` for values not yet reported.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Snapshot {
+    pub power: Option<bool>,
+    pub brightness: Option<u16>,
+    pub temperature: Option<u16>,
+}
+
+impl Snapshot {
+    pub fn update(&mut self, response: Response) {
+        match response {
+            Response::Power(on, _) => self.power = Some(on),
+            Response::Brightness(level, _) => self.brightness = Some(level),
+            Response::Temperature(kelvin, _) => self.temperature = Some(kelvin),
+            Response::FeatureIndex(_)
+            | Response::Firmware(_)
+            | Response::Serial(_)
+            | Response::NameLength(_)
+            | Response::NamePart(_)
+            | Response::Unknown { .. } => {}
+        }
+    }
+}
+
+/// Device operations shared by the headless CLI and the GUI worker.
+///
+/// Values going in and out are nominal: the calibration is applied to every
+/// set command and taken off every report.
+pub struct Controller {
+    device: LitraDevice,
+    calibration: Calibration,
+    /// Lowest brightness written, in percent of the range.
+    floor: u8,
+    /// Last nominal brightness and temperature written.
+    written: Cell<(Option<u16>, Option<u16>)>,
+    /// Other lamps that receive every set command sent to this one.
+    followers: RefCell<Vec<Controller>>,
+    /// Followers dropped after a failed write, with their error.
+    lost: RefCell<Vec<(String, Error)>>,
+}
+
+impl Controller {
+    pub fn new(device: LitraDevice) -> Self {
+        Self {
+            device,
+            calibration: Calibration::default(),
+            floor: 0,
+            written: Cell::new((None, None)),
+            followers: RefCell::new(Vec::new()),
+            lost: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Mirrors every set command to `followers`, with brightness carried
+    /// over as a percentage of each lamp's range. Reads still come from
+    /// this lamp alone, so the reported state does not flap between lamps.
+    pub fn set_followers(&self, followers: Vec<Controller>) {
+        self.lost.borrow_mut().clear();
+        *self.followers.borrow_mut() = followers;
+    }
+
+    /// Brings every follower to the known values of `snapshot`.
+    pub fn align_followers(&self, snapshot: Snapshot) {
+        let commands = [
+            snapshot.power.map(Command::SetPower),
+            snapshot.brightness.map(Command::SetBrightness),
+            snapshot.temperature.map(Command::SetTemperature),
+        ];
+        for cmd in commands.into_iter().flatten() {
+            self.mirror(cmd);
+        }
+    }
+
+    /// Followers dropped since the last call, named as in [`label`](Self::label),
+    /// with the error that dropped them.
+    pub fn take_lost(&self) -> Vec<(String, Error)> {
+        self.lost.take()
+    }
+
+    /// The lamp's serial number, else its HID path, else its model name.
+    pub fn label(&self) -> String {
+        self.serial_number()
+            .or_else(|| self.device_info().map(|info| info.path.clone()))
+            .unwrap_or_else(|| self.model().name().to_string())
+    }
+
+    pub fn with_calibration(mut self, calibration: Calibration) -> Self {
+        self.calibration = calibration;
+        self
+    }
+
+    pub fn calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    /// Raises every brightness written below `percent` of the range to it.
+    pub fn with_floor(mut self, percent: u8) -> Self {
+        self.floor = percent;
+        self
+    }
+
+    /// The lowest level written to this lamp.
+    pub fn floor_level(&self) -> u16 {
+        percent_to_brightness(self.floor, self.model())
+    }
+
+    pub fn serial_number(&self) -> Option<String> {
+        self.device.serial_number()
+    }
+
+    pub fn device_info(&self) -> Option<&DeviceInfo> {
+        self.device.device_info()
+    }
+
+    /// The lamp's name, see [`LitraDevice::name`].
+    pub fn name(&self) -> Result<Option<String>, Error> {
+        self.device.name(CONFIRM_TIMEOUT)
+    }
+
+    /// The lamp's serial number and firmware version, see
+    /// [`LitraDevice::info`].
+    pub fn info(&self) -> Result<Identity, Error> {
+        self.device.info(CONFIRM_TIMEOUT)
+    }
+
+    /// The lamp's model, which sets its brightness limits.
+    pub fn model(&self) -> Model {
+        self.device.model()
+    }
+
+    pub fn send(&self, cmd: Command) -> Result<(), Error> {
+        self.device.send(self.to_device(cmd))?;
+        self.mirror(cmd);
+        Ok(())
+    }
+
+    /// Sends a set command to every follower. A follower whose write fails
+    /// is dropped and kept for [`take_lost`](Self::take_lost); this lamp is
+    /// not affected.
+    fn mirror(&self, cmd: Command) {
+        let model = self.model();
+        let mut lost = Vec::new();
+        self.followers.borrow_mut().retain(|follower| {
+            let cmd = match cmd {
+                Command::SetBrightness(level) => Command::SetBrightness(percent_to_brightness(
+                    brightness_to_percent(level, model),
+                    follower.model(),
+                )),
+                Command::SetPower(_) | Command::SetTemperature(_) => cmd,
+                _ => return true,
+            };
+            match follower.send(cmd) {
+                Ok(()) => true,
+                Err(e) => {
+                    lost.push((follower.label(), e));
+                    false
+                }
+            }
+        });
+        self.lost.borrow_mut().extend(lost);
+    }
+
+    pub fn try_read(&self) -> Result<Option<Response>, Error> {
+        Ok(self.device.try_read()?.map(|r| self.nominal(r)))
+    }
+
+    /// Waits up to `timeout` for the next report from the lamp.
+    pub fn read_blocking(&self, timeout: Duration) -> Result<Option<Response>, Error> {
+        Ok(self.device.read_blocking(timeout)?.map(|r| self.nominal(r)))
+    }
+
+    /// Takes a reply off the ones the lamp owes, see [`LitraDevice::settle`].
+    pub fn settle(&self, response: &Response) -> bool {
+        self.device.settle(response)
+    }
+
+    /// Sends a query and waits up to `timeout` for its answer.
+    pub fn query(&self, cmd: Command, timeout: Duration) -> Result<Option<Response>, Error> {
+        Ok(self.device.query(cmd, timeout)?.map(|r| self.nominal(r)))
+    }
+
+    /// Discards queued reports until none arrives or `timeout` elapses.
+    /// Returns how many were dropped.
+    pub fn drain(&self, timeout: Duration) -> Result<usize, Error> {
+        self.device.drain(timeout)
+    }
+
+    /// Queries power, brightness and temperature one after another, moving
+    /// on as soon as each answer arrives. Values not reported before
+    /// `timeout` elapses are left unset.
+    pub fn read_state(&self, timeout: Duration) -> Result<Snapshot, Error> {
+        Ok(self.nominal_snapshot(self.read_device_state(timeout)?))
+    }
+
+    /// Like [`Controller::read_state`], but with the values the lamp
+    /// actually runs at, calibration included.
+    pub fn read_device_state(&self, timeout: Duration) -> Result<Snapshot, Error> {
+        let _span = debug_span!("read_state").entered();
+        let deadline = Instant::now() + timeout;
+        let mut snapshot = Snapshot::default();
+        for query in [
+            Command::GetPower,
+            Command::GetBrightness,
+            Command::GetTemperature,
+        ] {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            if let Some(response) = self.device.query(query, remaining)? {
+                snapshot.update(response);
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// Applies a set command and waits for the lamp to report the new value.
+    pub fn apply(&self, cmd: Command) -> Result<bool, Error> {
+        let _span = debug_span!("apply", command = ?cmd).entered();
+        let confirmed = self
+            .device
+            .set_confirmed(self.to_device(cmd), CONFIRM_TIMEOUT)?;
+        self.mirror(cmd);
+        Ok(confirmed)
+    }
+
+    /// Applies set commands in order, each confirmed before the next is
+    /// sent. Returns the ones the lamp did not confirm.
+    pub fn apply_all(&self, cmds: &[Command]) -> Result<Vec<Command>, Error> {
+        let _span = debug_span!("apply_all", commands = cmds.len()).entered();
+        let device: Vec<Command> = cmds.iter().map(|&cmd| self.to_device(cmd)).collect();
+        let failed = self.device.send_all(&device, Some(CONFIRM_TIMEOUT))?;
+        for &cmd in cmds {
+            self.mirror(cmd);
+        }
+        Ok(cmds
+            .iter()
+            .zip(&device)
+            .filter(|(_, sent)| failed.contains(sent))
+            .map(|(&cmd, _)| cmd)
+            .collect())
+    }
+
+    /// Converts device values to nominal ones.
+    pub fn nominal_snapshot(&self, snapshot: Snapshot) -> Snapshot {
+        let mut nominal = Snapshot {
+            power: snapshot.power,
+            ..Snapshot::default()
+        };
+        if let Some(level) = snapshot.brightness {
+            nominal.update(self.nominal(Response::Brightness(level, Trigger::Reply)));
+        }
+        if let Some(kelvin) = snapshot.temperature {
+            nominal.update(self.nominal(Response::Temperature(kelvin, Trigger::Reply)));
+        }
+        nominal
+    }
+
+    fn to_device(&self, cmd: Command) -> Command {
+        let (brightness, temperature) = self.written.get();
+        let cmd = match cmd {
+            Command::SetBrightness(level) => Command::SetBrightness(level.max(self.floor_level())),
+            other => other,
+        };
+        match cmd {
+            Command::SetBrightness(level) => self.written.set((Some(level), temperature)),
+            Command::SetTemperature(kelvin) => self.written.set((brightness, Some(kelvin))),
+            _ => {}
+        }
+        self.calibration.calibrate(cmd, self.model())
+    }
+
+    /// Takes the calibration off a report. A value matching the last write
+    /// reads back as written, even where the write was clamped. Reports
+    /// outside the device limits are left alone so they still look wrong.
+    fn nominal(&self, response: Response) -> Response {
+        let model = self.model();
+        if !response.is_plausible(model) {
+            return response;
+        }
+        let (brightness, temperature) = self.written.get();
+        let calibration = &self.calibration;
+        match response {
+            Response::Brightness(level, trigger) => Response::Brightness(
+                brightness
+                    .filter(|&nominal| calibration.effective_brightness(nominal, model) == level)
+                    .unwrap_or_else(|| calibration.nominal_brightness(level, model)),
+                trigger,
+            ),
+            Response::Temperature(kelvin, trigger) => Response::Temperature(
+                temperature
+                    .filter(|&nominal| calibration.effective_temperature(nominal) == kelvin)
+                    .unwrap_or_else(|| calibration.nominal_temperature(kelvin)),
+                trigger,
+            ),
+            other => other,
+        }
+    }
+
+    /// Flips the lamp's power from the state it reports, see
+    /// [`LitraDevice::toggle_power`], and turns the followers the same way.
+    pub fn toggle_power(&self) -> Result<bool, Error> {
+        let on = self.device.toggle_power(CONFIRM_TIMEOUT)?;
+        self.mirror(Command::SetPower(on));
+        Ok(on)
+    }
+
+    /// Reads the current power state and returns the opposite, if known.
+    pub fn toggled_power(&self) -> Result<Option<bool>, Error> {
+        match self.query(Command::GetPower, CONFIRM_TIMEOUT)? {
+            Some(Response::Power(on, _)) => Ok(Some(!on)),
+            _ => Ok(None),
+        }
+    }
+}
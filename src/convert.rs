@@ -0,0 +1,328 @@
+use std::time::Duration;
+
+use crate::protocol::{Model, RoundMode, perceptual_to_raw, raw_to_perceptual};
+
+/// Maps a slider value to a brightness level of `model`.
+///
+/// Non-finite input never panics: NaN maps to the minimum, infinities saturate.
+pub fn clamp_brightness(value: f32, model: Model) -> u16 {
+    if value.is_nan() {
+        return model.min_brightness();
+    }
+    let value = value
+        .round()
+        .clamp(model.min_brightness() as f32, model.max_brightness() as f32);
+    value as u16
+}
+
+/// How brightness percentages, and the brightness slider, map to the levels
+/// of a lamp.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BrightnessScale {
+    /// A percentage is that share of the model's range, and the slider runs
+    /// over the levels themselves.
+    #[default]
+    Linear,
+    /// A percentage is how bright the lamp looks, mapped through this gamma,
+    /// and the slider runs from 0 to 100.
+    Perceptual(f32),
+}
+
+impl BrightnessScale {
+    /// The brightness slider's limits for a `model` lamp.
+    pub fn slider_range(self, model: Model) -> (f32, f32) {
+        match self {
+            BrightnessScale::Linear => {
+                (model.min_brightness() as f32, model.max_brightness() as f32)
+            }
+            BrightnessScale::Perceptual(_) => (0.0, 100.0),
+        }
+    }
+
+    /// The level of a `model` lamp for a slider value.
+    pub fn slider_to_level(self, value: f32, model: Model) -> u16 {
+        match self {
+            BrightnessScale::Linear => clamp_brightness(value, model),
+            BrightnessScale::Perceptual(gamma) => {
+                perceptual_to_raw(value, &model.capabilities(), gamma)
+            }
+        }
+    }
+
+    /// Where the slider shows `level` of a `model` lamp.
+    pub fn level_to_slider(self, level: u16, model: Model) -> f32 {
+        match self {
+            BrightnessScale::Linear => level as f32,
+            BrightnessScale::Perceptual(gamma) => {
+                raw_to_perceptual(level, &model.capabilities(), gamma)
+            }
+        }
+    }
+
+    /// The level of a `model` lamp for a percentage from the command line.
+    pub fn percent_to_level(self, percent: u8, model: Model) -> u16 {
+        match self {
+            BrightnessScale::Linear => percent_to_brightness(percent, model),
+            BrightnessScale::Perceptual(gamma) => {
+                perceptual_to_raw(percent as f32, &model.capabilities(), gamma)
+            }
+        }
+    }
+
+    /// The percentage shown for `level` of a `model` lamp.
+    pub fn level_to_percent(self, level: u16, model: Model) -> u8 {
+        match self {
+            BrightnessScale::Linear => brightness_to_percent(level, model),
+            BrightnessScale::Perceptual(gamma) => {
+                raw_to_perceptual(level, &model.capabilities(), gamma).round() as u8
+            }
+        }
+    }
+}
+
+/// Maps a slider value to a temperature of `model`, snapped to the nearest step.
+///
+/// The result is always inside the device limits and on the step grid.
+pub fn clamp_temperature(value: f32, model: Model) -> u16 {
+    let capabilities = model.capabilities();
+    if value.is_nan() {
+        return capabilities.min_temperature;
+    }
+    let value = value.round().clamp(
+        capabilities.min_temperature as f32,
+        capabilities.max_temperature as f32,
+    );
+    capabilities.snap_temperature(value as u16, RoundMode::Nearest)
+}
+
+/// Snaps a kelvin value onto the step grid, rounding half up.
+pub fn snap_temperature(kelvin: u16) -> u16 {
+    round_temperature(kelvin, RoundMode::Nearest)
+}
+
+/// Snaps a kelvin value onto the step grid, rounding as `mode` says. For
+/// values not tied to one lamp; every model shares one temperature range.
+pub fn round_temperature(kelvin: u16, mode: RoundMode) -> u16 {
+    Model::default()
+        .capabilities()
+        .snap_temperature(kelvin, mode)
+}
+
+/// Converts a percentage (clamped to 0-100) to a brightness level of `model`.
+///
+/// Rounds to nearest so that `brightness_to_percent(percent_to_brightness(p)) == p`.
+pub fn percent_to_brightness(percent: u8, model: Model) -> u16 {
+    let percent = percent.min(100) as u32;
+    let range = brightness_range(model) as u32;
+    model.min_brightness() + ((percent * range + 50) / 100) as u16
+}
+
+/// Converts a brightness level of `model` to a percentage in 0-100.
+///
+/// Levels reported outside the device limits are clamped rather than underflowing.
+pub fn brightness_to_percent(level: u16, model: Model) -> u8 {
+    let offset =
+        level.clamp(model.min_brightness(), model.max_brightness()) - model.min_brightness();
+    let range = brightness_range(model) as u32;
+    ((offset as u32 * 100 + range / 2) / range) as u8
+}
+
+fn brightness_range(model: Model) -> u16 {
+    model.max_brightness() - model.min_brightness()
+}
+
+/// Scales a master brightness percentage by a group member's `ratio`.
+///
+/// The result is clamped to 0-100 for this member alone, so one lamp hitting
+/// its limit never changes the others.
+pub fn scale_percent(percent: u8, ratio: f32) -> u8 {
+    let scaled = percent.min(100) as f32 * ratio;
+    if scaled.is_nan() {
+        return 0;
+    }
+    scaled.round().clamp(0.0, 100.0) as u8
+}
+
+/// Parses a duration such as `500ms`, `2s`, `1.5s` or `20m`. A bare number
+/// is taken as seconds.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let value: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration `{text}`"))?;
+    let seconds = match unit.trim() {
+        "ms" => value / 1000.0,
+        "" | "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        other => {
+            return Err(format!(
+                "unknown duration unit `{other}`, use ms, s, m or h"
+            ));
+        }
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("invalid duration `{text}`"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{MAX_TEMPERATURE, MIN_TEMPERATURE, TEMPERATURE_STEP};
+    use proptest::prelude::*;
+
+    fn model() -> impl Strategy<Value = Model> {
+        prop::sample::select(Model::ALL.to_vec())
+    }
+
+    /// Any f32, NaN and the infinities included.
+    fn any_f32() -> impl Strategy<Value = f32> {
+        prop_oneof![
+            prop::num::f32::ANY,
+            Just(f32::NAN),
+            Just(f32::INFINITY),
+            Just(f32::NEG_INFINITY),
+        ]
+    }
+
+    fn on_grid(kelvin: u16) -> bool {
+        (MIN_TEMPERATURE..=MAX_TEMPERATURE).contains(&kelvin)
+            && (kelvin - MIN_TEMPERATURE).is_multiple_of(TEMPERATURE_STEP)
+    }
+
+    proptest! {
+        #[test]
+        fn percent_round_trips(model in model(), percent in 0u8..=100) {
+            prop_assert_eq!(
+                brightness_to_percent(percent_to_brightness(percent, model), model),
+                percent
+            );
+        }
+
+        #[test]
+        fn level_round_trips_within_half_a_percent(model in model(), level: u16) {
+            let clamped = level.clamp(model.min_brightness(), model.max_brightness());
+            let back = percent_to_brightness(brightness_to_percent(level, model), model);
+            let tolerance = brightness_range(model).div_ceil(200);
+            prop_assert!(back.abs_diff(clamped) <= tolerance, "{} -> {}", clamped, back);
+        }
+
+        #[test]
+        fn percent_to_brightness_is_monotonic(model in model(), a: u8, b: u8) {
+            let (low, high) = (a.min(b), a.max(b));
+            let level = percent_to_brightness(high, model);
+            prop_assert!(percent_to_brightness(low, model) <= level);
+            prop_assert!((model.min_brightness()..=model.max_brightness()).contains(&level));
+        }
+
+        #[test]
+        fn brightness_to_percent_is_monotonic(model in model(), a: u16, b: u16) {
+            let (low, high) = (a.min(b), a.max(b));
+            prop_assert!(brightness_to_percent(low, model) <= brightness_to_percent(high, model));
+        }
+
+        #[test]
+        fn brightness_to_percent_stays_in_range(model in model(), level: u16) {
+            prop_assert!(brightness_to_percent(level, model) <= 100);
+        }
+
+        #[test]
+        fn clamp_brightness_stays_in_limits(model in model(), value in any_f32()) {
+            let level = clamp_brightness(value, model);
+            prop_assert!((model.min_brightness()..=model.max_brightness()).contains(&level));
+        }
+
+        #[test]
+        fn clamp_brightness_is_monotonic(model in model(), a in -1e6f32..1e6, b in -1e6f32..1e6) {
+            let (low, high) = (a.min(b), a.max(b));
+            prop_assert!(clamp_brightness(low, model) <= clamp_brightness(high, model));
+        }
+
+        #[test]
+        fn clamp_temperature_lands_on_grid(model in model(), value in any_f32()) {
+            prop_assert!(on_grid(clamp_temperature(value, model)));
+        }
+
+        #[test]
+        fn clamp_temperature_is_monotonic(model in model(), a in -1e6f32..1e6, b in -1e6f32..1e6) {
+            let (low, high) = (a.min(b), a.max(b));
+            prop_assert!(clamp_temperature(low, model) <= clamp_temperature(high, model));
+        }
+
+        #[test]
+        fn snap_temperature_lands_on_grid(kelvin: u16) {
+            let snapped = snap_temperature(kelvin);
+            prop_assert!(on_grid(snapped));
+            if (MIN_TEMPERATURE..=MAX_TEMPERATURE).contains(&kelvin) {
+                prop_assert!(snapped.abs_diff(kelvin) <= TEMPERATURE_STEP / 2);
+            }
+        }
+
+        #[test]
+        fn snap_temperature_is_monotonic(a: u16, b: u16) {
+            let (low, high) = (a.min(b), a.max(b));
+            prop_assert!(snap_temperature(low) <= snap_temperature(high));
+        }
+
+        #[test]
+        fn round_temperature_brackets_the_value(kelvin in MIN_TEMPERATURE..=MAX_TEMPERATURE) {
+            let down = round_temperature(kelvin, RoundMode::Down);
+            let up = round_temperature(kelvin, RoundMode::Up);
+            prop_assert!(on_grid(down) && on_grid(up));
+            prop_assert!(down <= kelvin && kelvin <= up);
+            prop_assert!(up - down <= TEMPERATURE_STEP);
+        }
+
+        #[test]
+        fn scale_percent_stays_in_range(percent: u8, ratio in any_f32()) {
+            prop_assert!(scale_percent(percent, ratio) <= 100);
+        }
+
+        #[test]
+        fn perceptual_scale_stays_in_limits(model in model(), percent: u8, value in any_f32()) {
+            let scale = BrightnessScale::Perceptual(crate::protocol::DEFAULT_GAMMA);
+            for level in [scale.percent_to_level(percent, model), scale.slider_to_level(value, model)] {
+                prop_assert!((model.min_brightness()..=model.max_brightness()).contains(&level));
+            }
+        }
+    }
+
+    #[test]
+    fn every_percent_reads_back_unchanged() {
+        for model in Model::ALL {
+            for percent in 0..=100 {
+                let level = percent_to_brightness(percent, model);
+                assert_eq!(
+                    brightness_to_percent(level, model),
+                    percent,
+                    "{model} {percent}%"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn levels_outside_the_range_do_not_underflow() {
+        for model in Model::ALL {
+            for level in [0, 1, model.min_brightness() - 1, model.min_brightness()] {
+                assert_eq!(brightness_to_percent(level, model), 0, "{model} {level}");
+            }
+            for level in [model.max_brightness(), model.max_brightness() + 1, u16::MAX] {
+                assert_eq!(brightness_to_percent(level, model), 100, "{model} {level}");
+            }
+        }
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("2"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_duration("20m"), Ok(Duration::from_secs(1200)));
+        assert!(parse_duration("2d").is_err());
+        assert!(parse_duration("1e400").is_err());
+    }
+}
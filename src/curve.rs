@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use chrono::{NaiveTime, Timelike};
+use serde::{Deserialize, Serialize};
+
+use crate::convert::snap_temperature;
+
+const DAY_SECONDS: f64 = 86_400.0;
+
+/// A daily color temperature curve through user-defined points, applied
+/// while the lamp is on. Empty means off.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TemperatureCurve {
+    /// `(time of day, kelvin)` pairs in any order.
+    pub points: Vec<(NaiveTime, u16)>,
+    /// How long a manual temperature change pauses the curve.
+    pub hold_minutes: u64,
+    /// Each update fades over this many seconds.
+    pub fade_seconds: u64,
+}
+
+impl Default for TemperatureCurve {
+    fn default() -> Self {
+        Self {
+            points: Vec::new(),
+            hold_minutes: 60,
+            fade_seconds: 30,
+        }
+    }
+}
+
+impl TemperatureCurve {
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn hold(&self) -> Duration {
+        Duration::from_secs(self.hold_minutes * 60)
+    }
+
+    pub fn fade(&self) -> Duration {
+        Duration::from_secs(self.fade_seconds)
+    }
+
+    /// The snapped temperature at `time`, or `None` for an empty curve.
+    ///
+    /// The points are joined by a cubic Hermite spline that wraps around
+    /// midnight. Tangents are the harmonic mean of the neighbouring slopes,
+    /// and zero at local extremes, so the curve never overshoots: between
+    /// two points it stays within their range.
+    pub fn at(&self, time: NaiveTime) -> Option<u16> {
+        let mut knots: Vec<(f64, f64)> = self
+            .points
+            .iter()
+            .map(|&(time, kelvin)| (seconds(time), snap_temperature(kelvin) as f64))
+            .collect();
+        knots.sort_by(|a, b| a.0.total_cmp(&b.0));
+        knots.dedup_by(|later, earlier| later.0 == earlier.0);
+
+        let n = knots.len();
+        let &(_, first) = knots.first()?;
+        if n == 1 {
+            return Some(first as u16);
+        }
+
+        // Segment k runs from knot k to knot k + 1, the last one wrapping
+        // to the first knot of the next day.
+        let width = |k: usize| (knots[(k + 1) % n].0 - knots[k].0).rem_euclid(DAY_SECONDS);
+        let slope = |k: usize| (knots[(k + 1) % n].1 - knots[k].1) / width(k);
+        let tangent = |k: usize| {
+            let (before, after) = (slope((k + n - 1) % n), slope(k));
+            if before * after <= 0.0 {
+                0.0
+            } else {
+                2.0 * before * after / (before + after)
+            }
+        };
+
+        let now = seconds(time);
+        let k = knots
+            .iter()
+            .rposition(|&(start, _)| start <= now)
+            .unwrap_or(n - 1);
+        let h = width(k);
+        let u = (now - knots[k].0).rem_euclid(DAY_SECONDS) / h;
+        let (y0, y1) = (knots[k].1, knots[(k + 1) % n].1);
+        let (m0, m1) = (tangent(k), tangent((k + 1) % n));
+
+        let u2 = u * u;
+        let u3 = u2 * u;
+        let kelvin = (2.0 * u3 - 3.0 * u2 + 1.0) * y0
+            + (u3 - 2.0 * u2 + u) * h * m0
+            + (-2.0 * u3 + 3.0 * u2) * y1
+            + (u3 - u2) * h * m1;
+        Some(snap_temperature(kelvin.round() as u16))
+    }
+}
+
+fn seconds(time: NaiveTime) -> f64 {
+    time.num_seconds_from_midnight() as f64
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+use crate::convert::{percent_to_brightness, snap_temperature};
+use crate::protocol::Model;
+
+/// Values stepped through by the cycle actions, in order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CycleConfig {
+    /// Kelvin values for `cycle_temperature`.
+    pub temperatures: Vec<u16>,
+    /// Brightness percentages for `cycle_brightness`.
+    pub brightness: Vec<u8>,
+    /// Fade to the next value over this many milliseconds; 0 jumps.
+    pub fade_ms: u64,
+}
+
+impl Default for CycleConfig {
+    fn default() -> Self {
+        Self {
+            temperatures: vec![3200, 4500, 5600],
+            brightness: vec![25, 50, 75, 100],
+            fade_ms: 0,
+        }
+    }
+}
+
+impl CycleConfig {
+    /// The temperature after the list entry nearest to `current`.
+    pub fn next_temperature(&self, current: u16) -> Option<u16> {
+        let levels: Vec<u16> = self
+            .temperatures
+            .iter()
+            .map(|&kelvin| snap_temperature(kelvin))
+            .collect();
+        next_after_nearest(&levels, current)
+    }
+
+    /// The brightness level of a `model` lamp after the list entry nearest
+    /// to `current`.
+    pub fn next_brightness(&self, current: u16, model: Model) -> Option<u16> {
+        let levels: Vec<u16> = self
+            .brightness
+            .iter()
+            .map(|&percent| percent_to_brightness(percent, model))
+            .collect();
+        next_after_nearest(&levels, current)
+    }
+}
+
+/// Finds the entry closest to `current` and returns the one after it,
+/// wrapping at the end. The position comes from the value alone, so a change
+/// made on the lamp itself never leaves the cycle out of step.
+fn next_after_nearest(list: &[u16], current: u16) -> Option<u16> {
+    let nearest = list
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, value)| value.abs_diff(current))?
+        .0;
+    Some(list[(nearest + 1) % list.len()])
+}
@@ -0,0 +1,535 @@
+use std::fs;
+use std::io;
+use std::time::Duration;
+
+use hidapi::HidApi;
+
+use crate::config::{Config, ConfigStore};
+use crate::journal::{Intent, Journal};
+use crate::protocol::{Command, Model, VENDOR_ID, is_supported};
+use crate::schema::{Check, CheckOutcome, DoctorReport};
+use crate::transport::HidBackend;
+use crate::usb::{Error, LitraDevice};
+
+const ROUND_TRIP_TIMEOUT: Duration = Duration::from_millis(500);
+
+const PERMISSION_HINT: &str =
+    "Install the udev rule from the README's Linux setup section, then replug the lamp";
+const BUSY_HINT: &str =
+    "Close other apps that control the lamp, such as Logitech G HUB or another litra-glow";
+
+/// A Litra device found during enumeration.
+struct Found {
+    product_id: u16,
+    path: String,
+}
+
+impl Found {
+    fn is_supported(&self) -> bool {
+        Model::from_product_id(self.product_id).is_some()
+    }
+}
+
+impl Check {
+    fn new(name: &str, outcome: CheckOutcome, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            outcome,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn hint(mut self, hint: &str) -> Self {
+        self.hint = Some(hint.to_string());
+        self
+    }
+}
+
+/// Runs every check in order, opening the lamp with `serial` if given.
+/// Checks that depend on an earlier failure are reported as skipped.
+pub fn run(serial: Option<&str>) -> DoctorReport {
+    let store = ConfigStore::open_default();
+    let mut checks = vec![
+        check_versions(),
+        check_hid_backend(HidBackend::current(), store.as_ref()),
+    ];
+
+    let found = match enumerate() {
+        Ok(found) => found,
+        Err(e) => {
+            checks.push(
+                Check::new(
+                    "hid",
+                    CheckOutcome::Fail,
+                    format!("HID API unavailable: {e}"),
+                )
+                .hint("Check that the HID system libraries are installed"),
+            );
+            checks.push(check_config(store.as_ref()));
+            checks.push(check_journal(Journal::open_default().as_ref()));
+            return DoctorReport::new(checks);
+        }
+    };
+    checks.push(check_enumeration(&found));
+    if cfg!(target_os = "linux") {
+        checks.extend(found.iter().map(check_node_permissions));
+    }
+
+    if let Some(lamp) = found.iter().find(|f| f.is_supported()) {
+        let opened = match serial {
+            Some(serial) => LitraDevice::open_serial(serial),
+            None => LitraDevice::open_path(&lamp.path),
+        };
+        match opened {
+            Ok(device) => {
+                checks.push(Check::new("open", CheckOutcome::Pass, "device opened"));
+                checks.push(check_round_trip(&device));
+                checks.push(check_calibration(&device));
+            }
+            Err(e) => {
+                checks.push(check_open_error(&e));
+                checks.push(Check::new(
+                    "round-trip",
+                    CheckOutcome::Skip,
+                    "device not open",
+                ));
+                checks.push(Check::new(
+                    "calibration",
+                    CheckOutcome::Skip,
+                    "device not open",
+                ));
+            }
+        }
+    } else {
+        checks.push(Check::new(
+            "open",
+            CheckOutcome::Skip,
+            "no supported device",
+        ));
+        checks.push(Check::new(
+            "round-trip",
+            CheckOutcome::Skip,
+            "no supported device",
+        ));
+        checks.push(Check::new(
+            "calibration",
+            CheckOutcome::Skip,
+            "no supported device",
+        ));
+    }
+
+    checks.push(check_config(store.as_ref()));
+    checks.push(check_journal(Journal::open_default().as_ref()));
+    DoctorReport::new(checks)
+}
+
+/// Prints the report as aligned `[PASS] name: detail` lines with hints below.
+pub fn print_text(report: &DoctorReport) {
+    for check in &report.checks {
+        let label = match check.outcome {
+            CheckOutcome::Pass => "PASS",
+            CheckOutcome::Warn => "WARN",
+            CheckOutcome::Fail => "FAIL",
+            CheckOutcome::Skip => "SKIP",
+        };
+        println!("[{}] {}: {}", label, check.name, check.detail);
+        if let Some(hint) = &check.hint {
+            println!("       hint: {}", hint);
+        }
+    }
+}
+
+fn check_versions() -> Check {
+    Check::new(
+        "versions",
+        CheckOutcome::Pass,
+        format!(
+            "litra-glow {}, {} HID backend, {}/{}",
+            env!("CARGO_PKG_VERSION"),
+            HidBackend::current(),
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        ),
+    )
+}
+
+/// Names the HID backend built in and checks that `hid_backend` in the
+/// settings agrees, since the app refuses to start when it does not.
+fn check_hid_backend(backend: HidBackend, store: Option<&ConfigStore>) -> Check {
+    // Read past the check `ConfigStore::load` makes, to report it here.
+    let setting = store
+        .and_then(|store| fs::read(store.path()).ok())
+        .and_then(|data| serde_json::from_slice::<Config>(&data).ok())
+        .map(|config| config.hid_backend)
+        .unwrap_or_default();
+    match backend.check_setting(&setting) {
+        Ok(()) if setting.trim().is_empty() => Check::new(
+            "hid-backend",
+            CheckOutcome::Pass,
+            format!("{backend}, built in"),
+        ),
+        Ok(()) => Check::new(
+            "hid-backend",
+            CheckOutcome::Pass,
+            format!("{backend}, as set by hid_backend"),
+        ),
+        Err(e) => Check::new("hid-backend", CheckOutcome::Fail, e)
+            .hint("Remove hid_backend from the settings to use the backend built in"),
+    }
+}
+
+fn enumerate() -> Result<Vec<Found>, hidapi::HidError> {
+    let api = HidApi::new()?;
+    Ok(api
+        .device_list()
+        .filter(|d| is_supported(d.vendor_id(), d.product_id()))
+        .map(|d| Found {
+            product_id: d.product_id(),
+            path: d.path().to_string_lossy().into_owned(),
+        })
+        .collect())
+}
+
+fn check_enumeration(found: &[Found]) -> Check {
+    let ids: Vec<String> = found
+        .iter()
+        .map(|f| format!("{:04x}:{:04x}", VENDOR_ID, f.product_id))
+        .collect();
+    if found.iter().any(Found::is_supported) {
+        Check::new("enumeration", CheckOutcome::Pass, ids.join(", "))
+    } else {
+        Check::new("enumeration", CheckOutcome::Fail, "no Litra device found")
+            .hint("Connect the lamp directly, or try another USB port or cable")
+    }
+}
+
+/// Checks that the device node can be opened for writing by this user,
+/// which is what the udev rule grants.
+fn check_node_permissions(found: &Found) -> Check {
+    let name = "permissions";
+    if !found.path.starts_with("/dev/") {
+        return Check::new(
+            name,
+            CheckOutcome::Skip,
+            format!("{} is not a node", found.path),
+        );
+    }
+    match fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&found.path)
+    {
+        Ok(_) => Check::new(
+            name,
+            CheckOutcome::Pass,
+            format!("{} is writable", found.path),
+        ),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Check::new(
+            name,
+            CheckOutcome::Fail,
+            format!("{} is not writable by this user", found.path),
+        )
+        .hint(PERMISSION_HINT),
+        Err(e) => Check::new(name, CheckOutcome::Warn, format!("{}: {}", found.path, e)),
+    }
+}
+
+fn check_open_error(error: &Error) -> Check {
+    let detail = error.to_string();
+    let message = detail.to_lowercase();
+    if matches!(error, Error::DeviceNotFound(Some(_))) {
+        Check::new("open", CheckOutcome::Fail, detail)
+            .hint("Run `litra-glow devices` to see the connected lamps")
+    } else if matches!(error, Error::DeviceNotFound(None)) {
+        Check::new(
+            "open",
+            CheckOutcome::Fail,
+            "device disappeared before opening",
+        )
+        .hint("Replug the lamp and run doctor again")
+    } else if matches!(error, Error::PermissionDenied(..))
+        || message.contains("permission")
+        || message.contains("access")
+    {
+        Check::new("open", CheckOutcome::Fail, detail).hint(PERMISSION_HINT)
+    } else if message.contains("busy") || message.contains("exclusive") {
+        Check::new("open", CheckOutcome::Fail, detail).hint(BUSY_HINT)
+    } else {
+        Check::new("open", CheckOutcome::Fail, detail)
+            .hint("Run with --trace --status for a detailed log")
+    }
+}
+
+fn check_round_trip(device: &LitraDevice) -> Check {
+    match device.query(Command::GetPower, ROUND_TRIP_TIMEOUT) {
+        Ok(Some(response)) => Check::new(
+            "round-trip",
+            CheckOutcome::Pass,
+            format!("power query answered: {:?}", response),
+        ),
+        Ok(None) => Check::new(
+            "round-trip",
+            CheckOutcome::Fail,
+            format!("no answer to a power query within {:?}", ROUND_TRIP_TIMEOUT),
+        )
+        .hint(BUSY_HINT),
+        Err(e) => Check::new("round-trip", CheckOutcome::Fail, e.to_string()).hint(BUSY_HINT),
+    }
+}
+
+/// Reports the offsets applied to the connected lamp, so a tint that differs
+/// from the nominal value is never a mystery.
+fn check_calibration(device: &LitraDevice) -> Check {
+    let Some(serial) = device.serial_number() else {
+        return Check::new(
+            "calibration",
+            CheckOutcome::Skip,
+            "the lamp reports no serial number",
+        );
+    };
+    let Some(store) = ConfigStore::open_default() else {
+        return Check::new(
+            "calibration",
+            CheckOutcome::Warn,
+            "no config directory found",
+        );
+    };
+    match store.load() {
+        Ok(config) => {
+            let calibration = config.calibration(Some(&serial));
+            let detail = if calibration.is_identity() {
+                format!("{serial}: no offsets")
+            } else {
+                format!("{serial}: {calibration}")
+            };
+            Check::new("calibration", CheckOutcome::Pass, detail)
+        }
+        Err(e) => Check::new(
+            "calibration",
+            CheckOutcome::Warn,
+            format!("{}: {e}", store.path().display()),
+        ),
+    }
+}
+
+/// Checks that the settings file parses, since the app refuses to start
+/// with one that does not.
+fn check_config(store: Option<&ConfigStore>) -> Check {
+    let Some(store) = store else {
+        return Check::new("config", CheckOutcome::Warn, "no config directory found");
+    };
+    let path = store.path().display();
+    if !store.path().exists() {
+        return Check::new(
+            "config",
+            CheckOutcome::Pass,
+            format!("{path}: not created yet, using defaults"),
+        );
+    }
+    match store.load() {
+        Ok(_) => Check::new("config", CheckOutcome::Pass, format!("{path}: valid")),
+        Err(e) => Check::new("config", CheckOutcome::Fail, format!("{path}: {e}"))
+            .hint("Fix the file, or delete it to start over with the defaults"),
+    }
+}
+
+fn check_journal(journal: Option<&Journal>) -> Check {
+    let Some(journal) = journal else {
+        return Check::new("journal", CheckOutcome::Warn, "no config directory found");
+    };
+    let path = journal.path().display();
+    match fs::read(journal.path()) {
+        Ok(data) => match serde_json::from_slice::<Intent>(&data) {
+            Ok(intent) => Check::new(
+                "journal",
+                CheckOutcome::Pass,
+                format!("{path}: pending {:?}", intent.commands()),
+            ),
+            Err(e) => Check::new("journal", CheckOutcome::Warn, format!("{path}: {e}"))
+                .hint("Delete the file; it is rewritten when needed"),
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Check::new("journal", CheckOutcome::Pass, "no pending settings")
+        }
+        Err(e) => Check::new("journal", CheckOutcome::Warn, format!("{path}: {e}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::FakeLitra;
+    use crate::usb::{DeviceOptions, TransportKind};
+    use std::path::PathBuf;
+
+    /// A path of its own for each test, starting absent.
+    fn temp_file(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("litra-glow-doctor-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn open(fake: &FakeLitra) -> LitraDevice {
+        LitraDevice::open(&DeviceOptions {
+            transport: TransportKind::Fake(fake.clone()),
+            ..DeviceOptions::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn versions_name_the_app_version() {
+        let check = check_versions();
+        assert_eq!(check.outcome, CheckOutcome::Pass);
+        assert!(check.detail.contains(env!("CARGO_PKG_VERSION")));
+    }
+
+    #[test]
+    fn enumeration_needs_a_supported_lamp() {
+        let check = check_enumeration(&[]);
+        assert_eq!(check.outcome, CheckOutcome::Fail);
+        assert!(check.hint.is_some());
+
+        let unsupported = Found {
+            product_id: 0xffff,
+            path: "unsupported".to_string(),
+        };
+        let check = check_enumeration(std::slice::from_ref(&unsupported));
+        assert_eq!(check.outcome, CheckOutcome::Fail);
+
+        let glow = Found {
+            product_id: 0xc900,
+            path: "glow".to_string(),
+        };
+        let check = check_enumeration(&[unsupported, glow]);
+        assert_eq!(check.outcome, CheckOutcome::Pass);
+        assert!(check.detail.contains("046d:c900"), "{}", check.detail);
+    }
+
+    #[test]
+    fn permissions_are_checked_on_device_nodes_only() {
+        let found = |path: &str| Found {
+            product_id: 0xc900,
+            path: path.to_string(),
+        };
+        let check = check_node_permissions(&found("1-2:1.0"));
+        assert_eq!(check.outcome, CheckOutcome::Skip);
+        let check = check_node_permissions(&found("/dev/litra-glow-missing"));
+        assert_eq!(check.outcome, CheckOutcome::Warn);
+        if cfg!(target_os = "linux") {
+            let check = check_node_permissions(&found("/dev/null"));
+            assert_eq!(check.outcome, CheckOutcome::Pass);
+        }
+    }
+
+    #[test]
+    fn open_errors_get_their_own_hints() {
+        let hint = |error: Error| {
+            let check = check_open_error(&error);
+            assert_eq!(check.outcome, CheckOutcome::Fail);
+            check.hint.unwrap()
+        };
+        assert!(hint(Error::DeviceNotFound(Some("X".to_string()))).contains("devices"));
+        assert!(hint(Error::DeviceNotFound(None)).contains("Replug"));
+        assert_eq!(
+            hint(Error::PermissionDenied(
+                "/dev/hidraw0".to_string(),
+                Model::Glow
+            )),
+            PERMISSION_HINT
+        );
+        let busy = io::Error::other("Device or resource busy");
+        assert_eq!(hint(Error::Io(busy)), BUSY_HINT);
+        assert!(hint(Error::Disconnected).contains("--trace"));
+    }
+
+    #[test]
+    fn round_trip_needs_an_answer() {
+        let fake = FakeLitra::new(Model::Glow);
+        assert_eq!(check_round_trip(&open(&fake)).outcome, CheckOutcome::Pass);
+        fake.set_silent(true);
+        let check = check_round_trip(&open(&fake));
+        assert_eq!(check.outcome, CheckOutcome::Fail);
+        assert_eq!(check.hint.as_deref(), Some(BUSY_HINT));
+    }
+
+    #[test]
+    fn calibration_needs_a_serial() {
+        let fake = FakeLitra::new(Model::Glow);
+        assert_eq!(check_calibration(&open(&fake)).outcome, CheckOutcome::Skip);
+        let fake = FakeLitra::new(Model::Glow).with_serial("DOCTOR01");
+        let check = check_calibration(&open(&fake));
+        assert_eq!(check.outcome, CheckOutcome::Pass);
+        assert_eq!(check.detail, "DOCTOR01: no offsets");
+    }
+
+    #[test]
+    fn hid_backend_names_the_backend_and_checks_the_setting() {
+        let check = check_hid_backend(HidBackend::Hidraw, None);
+        assert_eq!(check.outcome, CheckOutcome::Pass);
+        assert_eq!(check.detail, "hidraw, built in");
+
+        let store = ConfigStore::new(temp_file("backend.json"));
+        let with = |backend: &str| Config {
+            hid_backend: backend.to_string(),
+            ..Config::default()
+        };
+        store.save(&with("hidraw")).unwrap();
+        let check = check_hid_backend(HidBackend::Hidraw, Some(&store));
+        assert_eq!(check.outcome, CheckOutcome::Pass);
+        assert_eq!(check.detail, "hidraw, as set by hid_backend");
+
+        store.save(&with("libusb")).unwrap();
+        let check = check_hid_backend(HidBackend::Hidraw, Some(&store));
+        assert_eq!(check.outcome, CheckOutcome::Fail);
+        assert!(check.detail.contains("linux-libusb"), "{}", check.detail);
+        assert!(check.hint.is_some());
+        store.save(&with("winusb")).unwrap();
+        assert_eq!(check_config(Some(&store)).outcome, CheckOutcome::Fail);
+        fs::remove_file(store.path()).unwrap();
+    }
+
+    #[test]
+    fn config_must_parse() {
+        assert_eq!(check_config(None).outcome, CheckOutcome::Warn);
+        let store = ConfigStore::new(temp_file("settings.json"));
+        assert_eq!(check_config(Some(&store)).outcome, CheckOutcome::Pass);
+        store.save(&Config::default()).unwrap();
+        assert_eq!(check_config(Some(&store)).outcome, CheckOutcome::Pass);
+        fs::write(store.path(), "{ not json").unwrap();
+        let check = check_config(Some(&store));
+        assert_eq!(check.outcome, CheckOutcome::Fail);
+        assert!(check.hint.is_some());
+        fs::remove_file(store.path()).unwrap();
+    }
+
+    #[test]
+    fn journal_lists_pending_settings() {
+        assert_eq!(check_journal(None).outcome, CheckOutcome::Warn);
+        let journal = Journal::new(temp_file("pending.json"));
+        let check = check_journal(Some(&journal));
+        assert_eq!(check.outcome, CheckOutcome::Pass);
+        assert_eq!(check.detail, "no pending settings");
+
+        let intent = Intent {
+            brightness: Some(200),
+            ..Intent::default()
+        };
+        journal.store(&intent).unwrap();
+        let check = check_journal(Some(&journal));
+        assert_eq!(check.outcome, CheckOutcome::Pass);
+        assert!(
+            check.detail.contains("SetBrightness(200)"),
+            "{}",
+            check.detail
+        );
+
+        fs::write(journal.path(), "garbage").unwrap();
+        let check = check_journal(Some(&journal));
+        assert_eq!(check.outcome, CheckOutcome::Warn);
+        assert!(check.hint.is_some());
+        journal.clear().unwrap();
+    }
+}
@@ -0,0 +1,257 @@
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Turns the lamp on while one of `apps` has focus.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FocusConfig {
+    pub enabled: bool,
+    /// Process names (e.g. `obs64.exe` or `zoom`) or window title fragments,
+    /// compared ignoring case.
+    pub apps: Vec<String>,
+    /// How long after a listed app loses focus the lamp turns off.
+    pub off_delay_seconds: u64,
+    /// Leave the lamp on when it was already on before the app got focus,
+    /// instead of always turning it off.
+    pub restore_previous: bool,
+}
+
+impl Default for FocusConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            apps: Vec::new(),
+            off_delay_seconds: 5,
+            restore_previous: true,
+        }
+    }
+}
+
+impl FocusConfig {
+    pub fn off_delay(&self) -> Duration {
+        Duration::from_secs(self.off_delay_seconds)
+    }
+}
+
+/// The foreground window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FocusedApp {
+    pub process: Option<String>,
+    pub title: String,
+}
+
+impl FocusedApp {
+    /// Whether an entry equals the process name, with or without `.exe`,
+    /// or appears in the window title.
+    pub fn matches(&self, apps: &[String]) -> bool {
+        let process = self.process.as_deref().map(str::to_lowercase);
+        let process = process
+            .as_deref()
+            .map(|name| name.strip_suffix(".exe").unwrap_or(name));
+        let title = self.title.to_lowercase();
+        apps.iter().map(|app| app.to_lowercase()).any(|app| {
+            let app = app.strip_suffix(".exe").unwrap_or(&app);
+            process == Some(app) || (!app.is_empty() && title.contains(app))
+        })
+    }
+}
+
+/// Polls the foreground window on a background thread and calls `changed`
+/// each time focus moves onto or away from one of `apps`. The thread stops
+/// when `changed` returns false, or right away on systems where the
+/// foreground window cannot be read.
+pub fn watch(apps: Vec<String>, mut changed: impl FnMut(bool) -> bool + Send + 'static) {
+    let spawned = thread::Builder::new()
+        .name("focus-watch".to_string())
+        .spawn(move || {
+            let Some(foreground) = platform::Foreground::open() else {
+                warn!("Focus follow unavailable: cannot read the foreground window");
+                return;
+            };
+            let mut last = None;
+            loop {
+                let app = foreground.current();
+                let focused = app.as_ref().is_some_and(|app| app.matches(&apps));
+                if last != Some(focused) {
+                    debug!("Foreground {:?}, listed: {}", app, focused);
+                    last = Some(focused);
+                    if !changed(focused) {
+                        return;
+                    }
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+    if let Err(e) = spawned {
+        warn!("Failed to start focus watcher: {}", e);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::fs;
+
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt as _, GetPropertyReply, Window};
+    use x11rb::rust_connection::RustConnection;
+
+    use super::FocusedApp;
+
+    /// Reads `_NET_ACTIVE_WINDOW` from the root window. Errors come back as
+    /// values, so a window closing between two requests is harmless.
+    pub struct Foreground {
+        conn: RustConnection,
+        root: Window,
+        active_window: Atom,
+        pid: Atom,
+        name: Atom,
+        utf8_string: Atom,
+    }
+
+    impl Foreground {
+        pub fn open() -> Option<Self> {
+            let (conn, screen) = x11rb::connect(None).ok()?;
+            let root = conn.setup().roots.get(screen)?.root;
+            let atom = |name: &[u8]| {
+                conn.intern_atom(false, name)
+                    .ok()?
+                    .reply()
+                    .ok()
+                    .map(|reply| reply.atom)
+            };
+            let active_window = atom(b"_NET_ACTIVE_WINDOW")?;
+            let pid = atom(b"_NET_WM_PID")?;
+            let name = atom(b"_NET_WM_NAME")?;
+            let utf8_string = atom(b"UTF8_STRING")?;
+            Some(Self {
+                conn,
+                root,
+                active_window,
+                pid,
+                name,
+                utf8_string,
+            })
+        }
+
+        fn property(
+            &self,
+            window: Window,
+            property: Atom,
+            kind: Atom,
+            length: u32,
+        ) -> Option<GetPropertyReply> {
+            self.conn
+                .get_property(false, window, property, kind, 0, length)
+                .ok()?
+                .reply()
+                .ok()
+        }
+
+        pub fn current(&self) -> Option<FocusedApp> {
+            let window = self
+                .property(self.root, self.active_window, AtomEnum::WINDOW.into(), 1)?
+                .value32()?
+                .next()
+                .filter(|&window| window != 0)?;
+            let process = self
+                .property(window, self.pid, AtomEnum::CARDINAL.into(), 1)
+                .and_then(|reply| reply.value32()?.next())
+                .and_then(|pid| fs::read_to_string(format!("/proc/{pid}/comm")).ok())
+                .map(|name| name.trim().to_string());
+            let title = self
+                .property(window, self.name, self.utf8_string, 1024)
+                .map(|reply| String::from_utf8_lossy(&reply.value).into_owned())
+                .unwrap_or_default();
+            Some(FocusedApp { process, title })
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::FocusedApp;
+
+    #[link(name = "user32")]
+    unsafe extern "system" {
+        fn GetForegroundWindow() -> isize;
+        fn GetWindowTextW(hwnd: isize, text: *mut u16, max_count: i32) -> i32;
+        fn GetWindowThreadProcessId(hwnd: isize, process_id: *mut u32) -> u32;
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn OpenProcess(access: u32, inherit_handle: i32, process_id: u32) -> isize;
+        fn QueryFullProcessImageNameW(
+            process: isize,
+            flags: u32,
+            name: *mut u16,
+            size: *mut u32,
+        ) -> i32;
+        fn CloseHandle(handle: isize) -> i32;
+    }
+
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+
+    pub struct Foreground;
+
+    impl Foreground {
+        pub fn open() -> Option<Self> {
+            Some(Foreground)
+        }
+
+        pub fn current(&self) -> Option<FocusedApp> {
+            let hwnd = unsafe { GetForegroundWindow() };
+            if hwnd == 0 {
+                return None;
+            }
+            let mut text = [0u16; 512];
+            let len = unsafe { GetWindowTextW(hwnd, text.as_mut_ptr(), text.len() as i32) };
+            let title = String::from_utf16_lossy(&text[..len.max(0) as usize]);
+
+            let mut pid = 0;
+            unsafe { GetWindowThreadProcessId(hwnd, &mut pid) };
+            Some(FocusedApp {
+                process: process_name(pid),
+                title,
+            })
+        }
+    }
+
+    fn process_name(pid: u32) -> Option<String> {
+        let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid) };
+        if process == 0 {
+            return None;
+        }
+        let mut path = [0u16; 1024];
+        let mut size = path.len() as u32;
+        let ok = unsafe { QueryFullProcessImageNameW(process, 0, path.as_mut_ptr(), &mut size) };
+        unsafe { CloseHandle(process) };
+        if ok == 0 {
+            return None;
+        }
+        let path = String::from_utf16_lossy(&path[..size as usize]);
+        path.rsplit('\\').next().map(str::to_string)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod platform {
+    use super::FocusedApp;
+
+    pub struct Foreground;
+
+    impl Foreground {
+        pub fn open() -> Option<Self> {
+            None
+        }
+
+        pub fn current(&self) -> Option<FocusedApp> {
+            None
+        }
+    }
+}
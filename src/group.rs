@@ -0,0 +1,12 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Lamps that move together, such as a key, fill and rim light.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Group {
+    /// Brightness ratio by lamp serial number. A member at 0.4 runs at 40%
+    /// of the group's master brightness.
+    pub members: BTreeMap<String, f32>,
+}
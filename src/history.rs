@@ -0,0 +1,235 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::storage;
+
+const CSV_FILE: &str = "history.csv";
+const CSV_HEADER: &str = "time,power,brightness,temperature,origin\n";
+/// About a day of busy use.
+const RING_CAPACITY: usize = 4096;
+/// Changes closer together than this from the same origin, such as the
+/// steps of a fade or a slider drag, are kept as one entry.
+const MERGE_WINDOW: Duration = Duration::from_secs(2);
+
+/// Recording of lamp state changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// Also append changes to `history.csv` in the config directory.
+    pub csv: bool,
+    /// Size at which the CSV file is rotated; one older file is kept.
+    pub max_csv_kb: u64,
+    /// How far back the history window looks.
+    pub view_hours: u64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            csv: false,
+            max_csv_kb: 512,
+            view_hours: 12,
+        }
+    }
+}
+
+impl HistoryConfig {
+    pub fn view_span(&self) -> Duration {
+        Duration::from_secs(self.view_hours * 3600)
+    }
+}
+
+/// What caused a change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Origin {
+    /// The window, tray or CLI.
+    Manual,
+    /// The lamp's buttons, or other software seen through a re-read.
+    Hardware,
+    Automation,
+}
+
+impl Origin {
+    pub fn name(self) -> &'static str {
+        match self {
+            Origin::Manual => "manual",
+            Origin::Hardware => "hardware",
+            Origin::Automation => "automation",
+        }
+    }
+}
+
+/// Lamp state after a change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub at: DateTime<Local>,
+    pub power: bool,
+    /// Brightness as a percentage, so entries of different models compare.
+    pub brightness: u8,
+    pub temperature: u16,
+    pub origin: Origin,
+}
+
+impl Entry {
+    fn same_state(&self, other: &Entry) -> bool {
+        (self.power, self.brightness, self.temperature)
+            == (other.power, other.brightness, other.temperature)
+    }
+
+    /// One CSV line.
+    fn to_csv(self) -> String {
+        format!(
+            "{},{},{},{},{}\n",
+            self.at.to_rfc3339(),
+            self.power as u8,
+            self.brightness,
+            self.temperature,
+            self.origin.name()
+        )
+    }
+}
+
+/// The most recent changes in memory, optionally mirrored to a CSV file.
+pub struct History {
+    entries: VecDeque<Entry>,
+    log: Option<CsvLog>,
+    /// Whether the newest entry may still be merged and is not written yet.
+    unwritten: bool,
+}
+
+impl History {
+    pub fn new(config: &HistoryConfig) -> Self {
+        let log = config
+            .csv
+            .then(|| csv_path().map(|path| CsvLog::new(path, config.max_csv_kb * 1024)))
+            .flatten();
+        Self {
+            entries: VecDeque::new(),
+            log,
+            unwritten: false,
+        }
+    }
+
+    /// Adds `entry` unless the state did not change. It replaces the newest
+    /// entry when both come from the same origin within the merge window.
+    pub fn record(&mut self, entry: Entry) {
+        if let Some(last) = self.entries.back_mut() {
+            if last.same_state(&entry) {
+                return;
+            }
+            let recent = (entry.at - last.at).to_std().unwrap_or_default() < MERGE_WINDOW;
+            if recent && last.origin == entry.origin {
+                *last = entry;
+                self.unwritten = true;
+                return;
+            }
+        }
+        self.flush();
+        if self.entries.len() == RING_CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+        self.unwritten = true;
+    }
+
+    /// The last recorded state.
+    pub fn last(&self) -> Option<&Entry> {
+        self.entries.back()
+    }
+
+    /// Entries from `since` on, preceded by the one in effect at `since`.
+    pub fn since(&self, since: DateTime<Local>) -> Vec<Entry> {
+        let start = self
+            .entries
+            .iter()
+            .rposition(|entry| entry.at <= since)
+            .unwrap_or(0);
+        self.entries.iter().skip(start).copied().collect()
+    }
+
+    /// Writes the newest entry to the CSV file if it has not been yet.
+    pub fn flush(&mut self) {
+        if !self.unwritten {
+            return;
+        }
+        self.unwritten = false;
+        if let (Some(log), Some(entry)) = (&mut self.log, self.entries.back())
+            && let Err(e) = log.append(entry)
+        {
+            warn!("Failed to write history: {}", e);
+            self.log = None;
+        }
+    }
+}
+
+/// The CSV history file, if there is a config directory.
+pub fn csv_path() -> Option<PathBuf> {
+    storage::config_dir().map(|dir| dir.join(CSV_FILE))
+}
+
+/// The file `path` is rotated to.
+fn rotated_path(path: &Path) -> PathBuf {
+    path.with_extension("csv.1")
+}
+
+/// Appends entries to a CSV file, moving it aside once it grows past
+/// `max_bytes`.
+struct CsvLog {
+    path: PathBuf,
+    max_bytes: u64,
+}
+
+impl CsvLog {
+    fn new(path: PathBuf, max_bytes: u64) -> Self {
+        Self { path, max_bytes }
+    }
+
+    fn append(&mut self, entry: &Entry) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let size = fs::metadata(&self.path).map(|meta| meta.len()).unwrap_or(0);
+        if size >= self.max_bytes {
+            fs::rename(&self.path, rotated_path(&self.path))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        if file.metadata()?.len() == 0 {
+            file.write_all(CSV_HEADER.as_bytes())?;
+        }
+        file.write_all(entry.to_csv().as_bytes())
+    }
+}
+
+/// Copies the recorded history, oldest first, into `out` as one CSV file.
+pub fn export(out: &Path) -> io::Result<usize> {
+    let path = csv_path().ok_or_else(|| io::Error::other("no config directory found"))?;
+    let mut lines = Vec::new();
+    for file in [rotated_path(&path), path] {
+        match fs::read_to_string(&file) {
+            Ok(text) => lines.extend(
+                text.lines()
+                    .filter(|line| !line.is_empty() && *line != CSV_HEADER.trim_end())
+                    .map(str::to_string),
+            ),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let mut file = File::create(out)?;
+    file.write_all(CSV_HEADER.as_bytes())?;
+    for line in &lines {
+        writeln!(file, "{line}")?;
+    }
+    Ok(lines.len())
+}
@@ -0,0 +1,673 @@
+use std::fmt;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use device_query::{DeviceQuery, DeviceState, Keycode};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+/// Fast enough to catch a quick tap, which lasts around 50ms.
+const POLL_INTERVAL: Duration = Duration::from_millis(15);
+/// How often the scene hotkeys look for added, renamed or removed scenes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+/// Scenes past the ninth get no automatic hotkey.
+const PRESET_KEYS: [Keycode; 9] = [
+    Keycode::Key1,
+    Keycode::Key2,
+    Keycode::Key3,
+    Keycode::Key4,
+    Keycode::Key5,
+    Keycode::Key6,
+    Keycode::Key7,
+    Keycode::Key8,
+    Keycode::Key9,
+];
+
+/// Global key bindings. Keys are only observed, never swallowed, so they
+/// still reach the focused app.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HotkeyConfig {
+    /// Binding that toggles power, such as `double:RControl`. Empty means
+    /// none.
+    pub toggle_power: String,
+    /// Longest time from the first press to the second of a double press.
+    pub double_press_ms: u64,
+    /// Modifiers, such as `ctrl+alt`, that with 1 to 9 apply the first nine
+    /// scenes in the order `scene list` shows them. Empty means none.
+    pub auto_preset_keys: String,
+}
+
+impl Default for HotkeyConfig {
+    fn default() -> Self {
+        Self {
+            toggle_power: String::new(),
+            double_press_ms: 350,
+            auto_preset_keys: String::new(),
+        }
+    }
+}
+
+impl HotkeyConfig {
+    pub fn double_press_interval(&self) -> Duration {
+        Duration::from_millis(self.double_press_ms)
+    }
+}
+
+/// Modifier keys held for a chord. Either side counts, and Option and
+/// Command count as Alt and Meta.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub meta: bool,
+}
+
+impl Modifiers {
+    const CTRL: [Keycode; 2] = [Keycode::LControl, Keycode::RControl];
+    const ALT: [Keycode; 4] = [
+        Keycode::LAlt,
+        Keycode::RAlt,
+        Keycode::LOption,
+        Keycode::ROption,
+    ];
+    const SHIFT: [Keycode; 2] = [Keycode::LShift, Keycode::RShift];
+    const META: [Keycode; 4] = [
+        Keycode::LMeta,
+        Keycode::RMeta,
+        Keycode::Command,
+        Keycode::RCommand,
+    ];
+
+    /// The modifiers among the held `keys`.
+    fn held(keys: &[Keycode]) -> Self {
+        let any = |sides: &[Keycode]| keys.iter().any(|key| sides.contains(key));
+        Self {
+            ctrl: any(&Self::CTRL),
+            alt: any(&Self::ALT),
+            shift: any(&Self::SHIFT),
+            meta: any(&Self::META),
+        }
+    }
+
+    fn is_modifier(key: Keycode) -> bool {
+        [&Self::CTRL[..], &Self::ALT, &Self::SHIFT, &Self::META]
+            .iter()
+            .any(|sides| sides.contains(&key))
+    }
+}
+
+impl FromStr for Modifiers {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = Modifiers::default();
+        for name in text.split('+').map(str::trim) {
+            let held = match name.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => &mut modifiers.ctrl,
+                "alt" | "option" => &mut modifiers.alt,
+                "shift" => &mut modifiers.shift,
+                "meta" | "super" | "win" | "cmd" => &mut modifiers.meta,
+                _ => return Err(format!("unknown modifier `{name}` in `{}`", text.trim())),
+            };
+            *held = true;
+        }
+        Ok(modifiers)
+    }
+}
+
+impl fmt::Display for Modifiers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = [
+            (self.ctrl, "Ctrl"),
+            (self.alt, "Alt"),
+            (self.shift, "Shift"),
+            (self.meta, "Meta"),
+        ];
+        let held: Vec<&str> = names
+            .into_iter()
+            .filter_map(|(held, name)| held.then_some(name))
+            .collect();
+        f.write_str(&held.join("+"))
+    }
+}
+
+/// A parsed binding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    /// The key pressed twice in quick succession, e.g. `double:RControl`.
+    Double(Keycode),
+    /// The key pressed while exactly these modifiers are held, e.g.
+    /// `ctrl+alt+1`.
+    Chord(Modifiers, Keycode),
+}
+
+impl FromStr for Binding {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let key = |name: &str| {
+            let name = name.trim();
+            // Digits and letters may be written as on the key cap.
+            let spelled = match name.len() {
+                1 if name.as_bytes()[0].is_ascii_digit() => format!("Key{name}"),
+                1 => name.to_ascii_uppercase(),
+                _ => name.to_string(),
+            };
+            Keycode::from_str(&spelled).map_err(|_| format!("unknown key `{name}` in `{text}`"))
+        };
+        match (text.split_once(':'), text.rsplit_once('+')) {
+            (Some(("double", name)), _) => key(name).map(Binding::Double),
+            (None, Some((modifiers, name))) => Ok(Binding::Chord(modifiers.parse()?, key(name)?)),
+            _ => Err(format!(
+                "unsupported binding `{text}`, use double:<key> or <modifiers>+<key>"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Double(key) => write!(f, "double:{key}"),
+            Binding::Chord(modifiers, key) => {
+                let key = key.to_string();
+                let cap = key
+                    .strip_prefix("Key")
+                    .filter(|digit| digit.len() == 1)
+                    .unwrap_or(&key);
+                write!(f, "{modifiers}+{cap}")
+            }
+        }
+    }
+}
+
+/// The scene hotkeys in effect for some scenes.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PresetKeys {
+    /// Scene names with their chords.
+    pub bound: Vec<(String, Binding)>,
+    /// Scenes whose chord is taken by an explicit binding, which wins.
+    pub collisions: Vec<(String, Binding)>,
+}
+
+impl PresetKeys {
+    /// Binds `auto_preset_keys` plus 1 to 9 to the first nine of `scenes`,
+    /// leaving out chords that `toggle_power` already uses.
+    pub fn new(config: &HotkeyConfig, scenes: &[String]) -> Result<Self, String> {
+        let mut keys = PresetKeys::default();
+        if config.auto_preset_keys.trim().is_empty() {
+            return Ok(keys);
+        }
+        let modifiers: Modifiers = config.auto_preset_keys.parse()?;
+        let explicit: Option<Binding> = config.toggle_power.parse().ok();
+        for (name, key) in scenes.iter().zip(PRESET_KEYS) {
+            let binding = Binding::Chord(modifiers, key);
+            let taken = if explicit == Some(binding) {
+                &mut keys.collisions
+            } else {
+                &mut keys.bound
+            };
+            taken.push((name.clone(), binding));
+        }
+        Ok(keys)
+    }
+
+    /// The chord bound to the scene called `name`.
+    #[cfg_attr(not(feature = "tray"), allow(dead_code))]
+    pub fn get(&self, name: &str) -> Option<Binding> {
+        self.bound
+            .iter()
+            .find(|(scene, _)| scene == name)
+            .map(|&(_, binding)| binding)
+    }
+
+    fn report(&self) {
+        for (name, binding) in &self.collisions {
+            warn!(
+                "Hotkey {} is bound to toggle_power, so scene {:?} gets none",
+                binding, name
+            );
+        }
+        for (name, binding) in &self.bound {
+            debug!("Hotkey {} applies scene {:?}", binding, name);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    /// First press, held since the given time.
+    Down(Instant),
+    /// Released after the first press at the given time.
+    Up(Instant),
+    /// Ignoring the key until it and every other key are released, after a
+    /// chord or a completed double press.
+    Spent,
+}
+
+/// Detects two presses of one key within `max_interval`, fed with the set of
+/// held keys at each poll.
+///
+/// Polling the held keys rather than key events means auto-repeat never
+/// shows up: a held key is one press. Any other key held at the same time
+/// cancels, so chords such as Ctrl+C followed by another Ctrl tap do not
+/// count.
+#[derive(Debug)]
+pub struct DoublePress<K> {
+    key: K,
+    max_interval: Duration,
+    state: State,
+    down: bool,
+}
+
+impl<K: PartialEq> DoublePress<K> {
+    pub fn new(key: K, max_interval: Duration) -> Self {
+        Self {
+            key,
+            max_interval,
+            state: State::Idle,
+            down: false,
+        }
+    }
+
+    /// Takes the keys held at `now` and returns whether this completed a
+    /// double press.
+    pub fn update(&mut self, now: Instant, keys: &[K]) -> bool {
+        let down = keys.contains(&self.key);
+        let pressed = down && !self.down;
+        let released = !down && self.down;
+        self.down = down;
+
+        if keys.iter().any(|key| *key != self.key) {
+            self.state = State::Spent;
+            return false;
+        }
+        let within = |at: Instant| now.duration_since(at) <= self.max_interval;
+        if let State::Up(at) = self.state
+            && pressed
+            && within(at)
+        {
+            self.state = State::Spent;
+            return true;
+        }
+        self.state = match self.state {
+            State::Spent if down => State::Spent,
+            State::Idle if pressed => State::Down(now),
+            State::Spent | State::Idle => State::Idle,
+            State::Down(at) if released && within(at) => State::Up(at),
+            State::Down(_) if released => State::Idle,
+            State::Down(at) => State::Down(at),
+            State::Up(_) if pressed => State::Down(now),
+            State::Up(at) if within(at) => State::Up(at),
+            State::Up(_) => State::Idle,
+        };
+        false
+    }
+}
+
+/// Detects the key of a chord going down while exactly its modifiers, and
+/// no other key, are held. A held chord fires once.
+#[derive(Debug)]
+pub struct ChordPress {
+    modifiers: Modifiers,
+    key: Keycode,
+    down: bool,
+}
+
+impl ChordPress {
+    pub fn new(modifiers: Modifiers, key: Keycode) -> Self {
+        Self {
+            modifiers,
+            key,
+            down: false,
+        }
+    }
+
+    /// Takes the keys held now and returns whether this completed the chord.
+    pub fn update(&mut self, keys: &[Keycode]) -> bool {
+        let down = keys.contains(&self.key);
+        let pressed = down && !self.down;
+        self.down = down;
+        pressed
+            && Modifiers::held(keys) == self.modifiers
+            && keys
+                .iter()
+                .all(|&key| key == self.key || Modifiers::is_modifier(key))
+    }
+}
+
+/// Watches the held keys for one binding.
+enum Detector {
+    Double(DoublePress<Keycode>),
+    Chord(ChordPress),
+}
+
+impl Detector {
+    fn new(binding: Binding, max_interval: Duration) -> Self {
+        match binding {
+            Binding::Double(key) => Detector::Double(DoublePress::new(key, max_interval)),
+            Binding::Chord(modifiers, key) => Detector::Chord(ChordPress::new(modifiers, key)),
+        }
+    }
+
+    fn update(&mut self, now: Instant, keys: &[Keycode]) -> bool {
+        match self {
+            Detector::Double(detector) => detector.update(now, keys),
+            Detector::Chord(detector) => detector.update(keys),
+        }
+    }
+}
+
+/// Polls the keyboard on a background thread and calls `fired` each time
+/// `binding` is triggered. The thread stops when `fired` returns false.
+pub fn watch(
+    binding: Binding,
+    max_interval: Duration,
+    mut fired: impl FnMut() -> bool + Send + 'static,
+) {
+    let spawned = thread::Builder::new()
+        .name("hotkey-watch".to_string())
+        .spawn(move || {
+            let keyboard = DeviceState::new();
+            let mut detector = Detector::new(binding, max_interval);
+            loop {
+                if detector.update(Instant::now(), &keyboard.get_keys()) {
+                    debug!("Hotkey {} triggered", binding);
+                    if !fired() {
+                        return;
+                    }
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+    if let Err(e) = spawned {
+        warn!("Failed to start hotkey watcher: {}", e);
+    }
+}
+
+/// Polls the keyboard on a background thread for the scene hotkeys of
+/// `config` and calls `fired` with the scene of each chord pressed. The
+/// chords follow the names `scenes` returns, asked again every
+/// [`RELOAD_INTERVAL`], so scenes saved while running get theirs. The
+/// thread stops when `fired` returns false.
+pub fn watch_presets(
+    config: HotkeyConfig,
+    mut scenes: impl FnMut() -> Vec<String> + Send + 'static,
+    mut fired: impl FnMut(&str) -> bool + Send + 'static,
+) {
+    let spawned = thread::Builder::new()
+        .name("preset-hotkeys".to_string())
+        .spawn(move || {
+            let keyboard = DeviceState::new();
+            let mut names = Vec::new();
+            let mut detectors = Vec::new();
+            let mut reloaded: Option<Instant> = None;
+            loop {
+                if reloaded.is_none_or(|at| at.elapsed() >= RELOAD_INTERVAL) {
+                    reloaded = Some(Instant::now());
+                    let current = scenes();
+                    if current != names {
+                        let keys = match PresetKeys::new(&config, &current) {
+                            Ok(keys) => keys,
+                            Err(e) => {
+                                warn!("Ignoring scene hotkeys: {}", e);
+                                return;
+                            }
+                        };
+                        keys.report();
+                        detectors = keys
+                            .bound
+                            .into_iter()
+                            .map(|(name, binding)| {
+                                (name, Detector::new(binding, config.double_press_interval()))
+                            })
+                            .collect::<Vec<_>>();
+                        names = current;
+                    }
+                }
+                let (now, keys) = (Instant::now(), keyboard.get_keys());
+                for (name, detector) in &mut detectors {
+                    if detector.update(now, &keys) {
+                        debug!("Scene hotkey for {:?} triggered", name);
+                        if !fired(name) {
+                            return;
+                        }
+                    }
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+    if let Err(e) = spawned {
+        warn!("Failed to start scene hotkey watcher: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CTRL_ALT: Modifiers = Modifiers {
+        ctrl: true,
+        alt: true,
+        shift: false,
+        meta: false,
+    };
+
+    fn config(auto: &str, toggle_power: &str) -> HotkeyConfig {
+        HotkeyConfig {
+            toggle_power: toggle_power.to_string(),
+            auto_preset_keys: auto.to_string(),
+            ..HotkeyConfig::default()
+        }
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn bindings_parse_and_print() {
+        for (text, binding, shown) in [
+            (
+                "double:RControl",
+                Binding::Double(Keycode::RControl),
+                "double:RControl",
+            ),
+            (
+                "ctrl+alt+1",
+                Binding::Chord(CTRL_ALT, Keycode::Key1),
+                "Ctrl+Alt+1",
+            ),
+            (
+                " Control + Option + Key1 ",
+                Binding::Chord(CTRL_ALT, Keycode::Key1),
+                "Ctrl+Alt+1",
+            ),
+            (
+                "shift+meta+p",
+                Binding::Chord(
+                    Modifiers {
+                        shift: true,
+                        meta: true,
+                        ..Modifiers::default()
+                    },
+                    Keycode::P,
+                ),
+                "Shift+Meta+P",
+            ),
+        ] {
+            assert_eq!(text.parse::<Binding>(), Ok(binding), "{text}");
+            assert_eq!(binding.to_string(), shown);
+        }
+        for text in [
+            "RControl",
+            "triple:F1",
+            "hyper+1",
+            "ctrl+Nope",
+            "double:Nope",
+        ] {
+            assert!(text.parse::<Binding>().is_err(), "{text}");
+        }
+    }
+
+    /// Feeds `(millisecond, held keys)` polls to a detector for `'k'` with
+    /// the default interval and returns the times it fired at.
+    fn double_presses(polls: &[(u64, &[char])]) -> Vec<u64> {
+        let mut detector = DoublePress::new('k', Duration::from_millis(350));
+        let start = Instant::now();
+        polls
+            .iter()
+            .filter(|(ms, keys)| detector.update(start + Duration::from_millis(*ms), keys))
+            .map(|(ms, _)| *ms)
+            .collect()
+    }
+
+    #[test]
+    fn double_press_fires_on_the_second_press() {
+        let polls: &[(u64, &[char])] = &[(0, &['k']), (50, &[]), (100, &['k']), (150, &[])];
+        assert_eq!(double_presses(polls), [100]);
+        let polls: &[(u64, &[char])] = &[(0, &['k']), (300, &[]), (350, &['k'])];
+        assert_eq!(double_presses(polls), [350]);
+    }
+
+    #[test]
+    fn slow_presses_start_over() {
+        let polls: &[(u64, &[char])] = &[
+            (0, &['k']),
+            (50, &[]),
+            (400, &['k']),
+            (450, &[]),
+            (500, &['k']),
+        ];
+        assert_eq!(double_presses(polls), [500]);
+    }
+
+    #[test]
+    fn a_held_key_is_one_press() {
+        let held: Vec<(u64, &[char])> = (0..20).map(|poll| (poll * 30, &['k'][..])).collect();
+        assert!(double_presses(&held).is_empty());
+
+        let polls: &[(u64, &[char])] = &[
+            (0, &['k']),
+            (200, &['k']),
+            (500, &[]),
+            (550, &['k']),
+            (600, &[]),
+            (650, &['k']),
+        ];
+        assert_eq!(double_presses(polls), [650]);
+    }
+
+    #[test]
+    fn other_keys_cancel_until_everything_is_released() {
+        let polls: &[(u64, &[char])] = &[(0, &['k']), (50, &[]), (80, &['k', 'c'])];
+        assert!(double_presses(polls).is_empty());
+
+        let polls: &[(u64, &[char])] = &[
+            (0, &['k', 'c']),
+            (50, &['k']),
+            (100, &[]),
+            (150, &['k']),
+            (200, &[]),
+            (250, &['k']),
+        ];
+        assert_eq!(double_presses(polls), [250]);
+
+        let polls: &[(u64, &[char])] = &[(0, &['k']), (30, &[]), (60, &['x']), (90, &['k'])];
+        assert!(double_presses(polls).is_empty());
+    }
+
+    #[test]
+    fn a_triple_press_fires_once() {
+        let polls: &[(u64, &[char])] = &[
+            (0, &['k']),
+            (50, &[]),
+            (100, &['k']),
+            (150, &[]),
+            (200, &['k']),
+            (250, &[]),
+        ];
+        assert_eq!(double_presses(polls), [100]);
+    }
+
+    #[test]
+    fn chord_fires_once_per_press_with_exactly_its_modifiers() {
+        let mut chord = ChordPress::new(CTRL_ALT, Keycode::Key1);
+        assert!(!chord.update(&[Keycode::LControl, Keycode::LAlt]));
+        assert!(chord.update(&[Keycode::LControl, Keycode::LAlt, Keycode::Key1]));
+        // Held, or auto-repeating.
+        assert!(!chord.update(&[Keycode::LControl, Keycode::LAlt, Keycode::Key1]));
+        assert!(!chord.update(&[Keycode::LControl, Keycode::LAlt]));
+        assert!(chord.update(&[Keycode::RControl, Keycode::RAlt, Keycode::Key1]));
+        assert!(!chord.update(&[]));
+
+        // Too few or too many modifiers, or another key along.
+        assert!(!chord.update(&[Keycode::LControl, Keycode::Key1]));
+        assert!(!chord.update(&[]));
+        let shifted = [
+            Keycode::LControl,
+            Keycode::LAlt,
+            Keycode::LShift,
+            Keycode::Key1,
+        ];
+        assert!(!chord.update(&shifted));
+        assert!(!chord.update(&[]));
+        let along = [
+            Keycode::LControl,
+            Keycode::LAlt,
+            Keycode::Key2,
+            Keycode::Key1,
+        ];
+        assert!(!chord.update(&along));
+    }
+
+    #[test]
+    fn scenes_get_one_to_nine_in_order() {
+        let scenes: Vec<String> = (1..=11).map(|n| format!("scene{n:02}")).collect();
+        let keys = PresetKeys::new(&config("ctrl+alt", ""), &scenes).unwrap();
+        assert_eq!(keys.bound.len(), 9);
+        assert!(keys.collisions.is_empty());
+        assert_eq!(
+            keys.get("scene01"),
+            Some(Binding::Chord(CTRL_ALT, Keycode::Key1))
+        );
+        assert_eq!(
+            keys.get("scene09"),
+            Some(Binding::Chord(CTRL_ALT, Keycode::Key9))
+        );
+        assert_eq!(keys.get("scene10"), None);
+    }
+
+    #[test]
+    fn explicit_binding_wins_a_collision() {
+        let scenes = names(&["focus", "interview", "night"]);
+        let keys = PresetKeys::new(&config("ctrl+alt", "ctrl+alt+2"), &scenes).unwrap();
+        let chord = |key| Binding::Chord(CTRL_ALT, key);
+        assert_eq!(
+            keys.bound,
+            [
+                ("focus".to_string(), chord(Keycode::Key1)),
+                ("night".to_string(), chord(Keycode::Key3)),
+            ]
+        );
+        assert_eq!(
+            keys.collisions,
+            [("interview".to_string(), chord(Keycode::Key2))]
+        );
+    }
+
+    #[test]
+    fn no_auto_keys_binds_nothing() {
+        let scenes = names(&["focus"]);
+        assert_eq!(
+            PresetKeys::new(&config("", ""), &scenes),
+            Ok(PresetKeys::default())
+        );
+        assert!(PresetKeys::new(&config("ctrl+hyper", ""), &scenes).is_err());
+    }
+}
@@ -0,0 +1,46 @@
+use crate::transport;
+use crate::usb::{DeviceOptions, Error};
+
+/// Notices the lamp being plugged in or out by refreshing the shared device
+/// list, which is much cheaper than opening the lamp and quiet in the log.
+pub struct Hotplug {
+    serial: Option<String>,
+    path: Option<String>,
+    selector: Option<String>,
+}
+
+impl Hotplug {
+    /// Watches for the lamp `options` would open. Fails when the HID context
+    /// cannot be created.
+    pub fn new(options: &DeviceOptions) -> Result<Self, Error> {
+        transport::with_api(|_| Ok(()))?;
+        Ok(Self {
+            serial: options.serial.clone(),
+            path: options.path.clone(),
+            selector: options.selector.clone(),
+        })
+    }
+
+    /// Whether that lamp is plugged in now. A lamp reporting an empty serial
+    /// matches when the serial asked for is its path, as when opening.
+    pub fn check(&mut self) -> Result<(), Error> {
+        if let Some(selector) = &self.selector {
+            return transport::select(selector).map(|_| ());
+        }
+        let present = transport::with_api(|api| {
+            Ok(transport::lamps(api).any(|d| {
+                let path = d.path().to_string_lossy();
+                let serial = d.serial_number().filter(|serial| !serial.is_empty());
+                self.path.as_deref().is_none_or(|wanted| path == wanted)
+                    && self.serial.as_deref().is_none_or(|wanted| {
+                        serial.map_or(path == wanted, |serial| serial == wanted)
+                    })
+            }))
+        })?;
+        if present {
+            Ok(())
+        } else {
+            Err(Error::DeviceNotFound(self.serial.clone()))
+        }
+    }
+}
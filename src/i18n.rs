@@ -0,0 +1,314 @@
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use tracing::warn;
+use unic_langid::LanguageIdentifier;
+
+/// Environment variable that picks the language, like `--lang`.
+pub const LANG_ENV: &str = "LITRA_GLOW_LANG";
+const BASE_LOCALE: &str = "en";
+
+/// Message catalogs compiled into the binary. The first is the base every
+/// message must exist in; the others may leave messages out.
+const CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.ftl")),
+    ("de", include_str!("../locales/de.ftl")),
+];
+
+/// The chosen locale followed by the base one, for per-message fallback.
+struct Catalog {
+    bundles: Vec<FluentBundle<FluentResource>>,
+}
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+static BASE_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Selects the language for all messages: `lang` if given, else the
+/// system locale. Unknown languages fall back to English. Messages looked
+/// up before this use English.
+pub fn init(lang: Option<&str>) {
+    let requested = lang
+        .map(str::to_string)
+        .or_else(sys_locale::get_locale)
+        .unwrap_or_default();
+    let _ = CATALOG.set(Catalog::new(&requested));
+}
+
+/// Shows every message in English from now on, for runs whose output is
+/// meant for scripts.
+pub fn use_base_locale() {
+    BASE_ONLY.store(true, Ordering::Relaxed);
+}
+
+/// The language asked for with `--lang` or `LITRA_GLOW_LANG`, read before
+/// the command line is parsed so help text can be translated.
+pub fn requested_language(args: &[String]) -> Option<String> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if let Some(lang) = arg.strip_prefix("--lang=") {
+            return Some(lang.to_string());
+        }
+        if arg == "--lang" {
+            return args.next().cloned();
+        }
+    }
+    std::env::var(LANG_ENV).ok().filter(|lang| !lang.is_empty())
+}
+
+impl Catalog {
+    fn new(requested: &str) -> Self {
+        let language = requested
+            .split(['-', '_', '.'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let mut locales = vec![BASE_LOCALE];
+        if let Some((locale, _)) = CATALOGS
+            .iter()
+            .find(|(locale, _)| *locale == language && *locale != BASE_LOCALE)
+        {
+            locales.insert(0, locale);
+        }
+        let bundles = locales
+            .into_iter()
+            .filter_map(|locale| {
+                let (_, source) = CATALOGS.iter().find(|(name, _)| *name == locale)?;
+                bundle(locale, source)
+            })
+            .collect();
+        Self { bundles }
+    }
+
+    fn format(&self, id: &str, args: Option<&FluentArgs>) -> Option<String> {
+        let skip = if BASE_ONLY.load(Ordering::Relaxed) {
+            self.bundles.len().saturating_sub(1)
+        } else {
+            0
+        };
+        self.bundles.iter().skip(skip).find_map(|bundle| {
+            let pattern = bundle.get_message(id)?.value()?;
+            let mut errors = Vec::new();
+            let text = bundle.format_pattern(pattern, args, &mut errors);
+            if !errors.is_empty() {
+                warn!("Errors formatting message {}: {:?}", id, errors);
+            }
+            Some(text.into_owned())
+        })
+    }
+}
+
+fn bundle(locale: &str, source: &str) -> Option<FluentBundle<FluentResource>> {
+    let langid: LanguageIdentifier = locale.parse().ok()?;
+    let resource = FluentResource::try_new(source.to_string())
+        .map_err(|(_, errors)| warn!("Errors in the {} catalog: {:?}", locale, errors))
+        .ok()?;
+    let mut bundle = FluentBundle::new_concurrent(vec![langid]);
+    // Isolation marks would show up as stray characters in a terminal.
+    bundle.set_use_isolating(false);
+    bundle
+        .add_resource(resource)
+        .map_err(|errors| warn!("Duplicate messages in the {} catalog: {:?}", locale, errors))
+        .ok()?;
+    Some(bundle)
+}
+
+fn catalog() -> &'static Catalog {
+    CATALOG.get_or_init(|| Catalog::new(BASE_LOCALE))
+}
+
+/// The message `id` in the chosen language, or English when it has no
+/// translation. A missing id yields the id itself.
+pub fn message(id: &str, args: Option<&FluentArgs>) -> String {
+    catalog().format(id, args).unwrap_or_else(|| id.to_string())
+}
+
+/// The message `id` if any catalog has it, for strings whose English text
+/// lives elsewhere, such as the command-line help.
+pub fn lookup(id: &str) -> Option<String> {
+    catalog().format(id, None)
+}
+
+/// Replaces the help of `command`, its arguments and its subcommands with
+/// translations where the chosen language has them. Ids are `help-` and the
+/// command path, each subcommand marked by `cmd-` so it cannot collide with
+/// an argument of the same name: `help-cmd-scene-cmd-apply` for the about
+/// text and `help-cmd-scene-cmd-apply-fade` for an argument.
+pub fn localize_command(command: clap::Command) -> clap::Command {
+    localize_at(command, "help", &lookup)
+}
+
+/// [`localize_command`] with the catalog of `locale` alone, whatever
+/// language was chosen.
+#[cfg(test)]
+pub fn localize_command_in(command: clap::Command, locale: &str) -> clap::Command {
+    localize_at(command, "help", &|id| catalog_text(locale, id))
+}
+
+/// Id of the about text of subcommand `name` of the command at `path`.
+fn subcommand_path(path: &str, name: &str) -> String {
+    format!("{path}-cmd-{name}")
+}
+
+/// Id of the help of argument `id` of the command at `path`.
+fn argument_path(path: &str, id: &str) -> String {
+    format!("{path}-{}", id.replace('_', "-"))
+}
+
+fn localize_at(
+    mut command: clap::Command,
+    path: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> clap::Command {
+    if let Some(about) = lookup(path) {
+        command = command.about(about);
+    }
+    if let Some(after) = lookup(&format!("{}--after", path)) {
+        command = command.after_help(after);
+    }
+    let ids: Vec<String> = command
+        .get_arguments()
+        .map(|arg| arg.get_id().to_string())
+        .collect();
+    for id in ids {
+        if let Some(help) = lookup(&argument_path(path, &id)) {
+            command = command.mut_arg(&id, |arg| arg.help(help));
+        }
+    }
+    let names: Vec<String> = command
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_string())
+        .collect();
+    for name in names {
+        let path = subcommand_path(path, &name);
+        command = command.mut_subcommand(&name, |sub| localize_at(sub, &path, lookup));
+    }
+    command
+}
+
+/// Every help id [`localize_command`] looks up for `command`, with the
+/// English text it replaces.
+#[cfg(test)]
+pub fn help_ids(command: &clap::Command) -> Vec<(String, String)> {
+    fn walk(command: &clap::Command, path: &str, ids: &mut Vec<(String, String)>) {
+        if let Some(about) = command.get_about() {
+            ids.push((path.to_string(), about.to_string()));
+        }
+        if let Some(after) = command.get_after_help() {
+            ids.push((format!("{path}--after"), after.to_string()));
+        }
+        for arg in command.get_arguments() {
+            if let Some(help) = arg.get_help() {
+                ids.push((argument_path(path, arg.get_id().as_str()), help.to_string()));
+            }
+        }
+        for sub in command.get_subcommands() {
+            walk(sub, &subcommand_path(path, sub.get_name()), ids);
+        }
+    }
+    let mut ids = Vec::new();
+    walk(command, "help", &mut ids);
+    ids
+}
+
+/// The text of `id` in the catalog of `locale` alone, without fallback.
+#[cfg(test)]
+pub fn catalog_text(locale: &str, id: &str) -> Option<String> {
+    let (_, source) = CATALOGS.iter().find(|(name, _)| *name == locale)?;
+    let bundle = bundle(locale, source)?;
+    let pattern = bundle.get_message(id)?.value()?;
+    Some(
+        bundle
+            .format_pattern(pattern, None, &mut Vec::new())
+            .into_owned(),
+    )
+}
+
+/// Every locale with a catalog.
+#[cfg(test)]
+pub fn locales() -> impl Iterator<Item = &'static str> {
+    CATALOGS.iter().map(|(locale, _)| *locale)
+}
+
+/// Looks up a message in the chosen language, with named arguments:
+/// `msg!("error-unknown-scene", name = name)`.
+macro_rules! msg {
+    ($id:literal) => {
+        $crate::i18n::message($id, None)
+    };
+    ($id:literal, $($name:ident = $value:expr),+ $(,)?) => {{
+        let mut args = fluent_bundle::FluentArgs::new();
+        $(args.set(stringify!($name), $value);)+
+        $crate::i18n::message($id, Some(&args))
+    }};
+}
+
+pub(crate) use msg;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every id passed to `msg!` in the sources.
+    fn message_ids() -> Vec<String> {
+        let src = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        let mut ids = Vec::new();
+        for entry in std::fs::read_dir(src).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "rs") {
+                continue;
+            }
+            let source = std::fs::read_to_string(&path).unwrap();
+            for call in source.split("msg!(").skip(1) {
+                let call = call.trim_start();
+                if let Some(id) = call
+                    .strip_prefix('"')
+                    .and_then(|rest| rest.split('"').next())
+                    .filter(|id| id.chars().all(|c| c.is_ascii_lowercase() || c == '-'))
+                {
+                    ids.push(id.to_string());
+                }
+            }
+        }
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
+    #[test]
+    fn every_message_exists_in_every_catalog() {
+        let ids = message_ids();
+        assert!(ids.len() > 50, "found only {} ids", ids.len());
+        let missing: Vec<String> = ids
+            .iter()
+            .flat_map(|id| locales().map(move |locale| (locale, id)))
+            .filter(|(locale, id)| catalog_text(locale, id).is_none())
+            .map(|(locale, id)| format!("{locale}: {id}"))
+            .collect();
+        assert!(missing.is_empty(), "{missing:#?}");
+    }
+
+    #[test]
+    fn catalogs_parse() {
+        for (locale, source) in CATALOGS {
+            assert!(bundle(locale, source).is_some(), "{locale}");
+        }
+    }
+
+    #[test]
+    fn lang_flag_is_read_before_parsing() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            requested_language(&args(&["litra-glow", "--lang", "de", "on"])).as_deref(),
+            Some("de")
+        );
+        assert_eq!(
+            requested_language(&args(&["litra-glow", "--lang=de"])).as_deref(),
+            Some("de")
+        );
+    }
+}
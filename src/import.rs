@@ -0,0 +1,363 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::convert::{brightness_to_percent, snap_temperature};
+use crate::i18n::msg;
+use crate::protocol::Model;
+use crate::scenes::Scene;
+
+/// Scene name used for the light settings of a `litra-autotoggle` config.
+const AUTOTOGGLE_SCENE: &str = "autotoggle";
+
+/// A field of the imported file that has no counterpart here.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unmapped {
+    pub field: String,
+    pub reason: String,
+}
+
+impl Unmapped {
+    fn new(field: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for Unmapped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.reason)
+    }
+}
+
+/// What an import would write, and what it leaves out.
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub scenes: BTreeMap<String, Scene>,
+    pub unmapped: Vec<Unmapped>,
+}
+
+impl Plan {
+    pub fn is_empty(&self) -> bool {
+        self.scenes.is_empty()
+    }
+}
+
+/// Reads a `litra-autotoggle` YAML config. Its brightness and temperature
+/// become the `autotoggle` scene. Camera watching has no equivalent here,
+/// so those fields are reported as unmapped.
+pub fn litra_autotoggle(text: &str) -> Plan {
+    let mut plan = Plan::default();
+    let mut brightness = None;
+    let mut temperature = None;
+    for (line, entry) in text.lines().enumerate() {
+        let trimmed = entry.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed == "---" {
+            continue;
+        }
+        let Some((key, value)) = trimmed
+            .split_once(':')
+            .filter(|_| !entry.starts_with([' ', '\t', '-']))
+        else {
+            plan.unmapped.push(Unmapped::new(
+                msg!("import-line", line = line + 1),
+                msg!("import-nested"),
+            ));
+            continue;
+        };
+        let key = key.trim();
+        let value = yaml_scalar(value);
+        match key {
+            "brightness" => match value.parse::<u8>() {
+                Ok(percent) if percent <= 100 => brightness = Some(percent),
+                _ => plan.unmapped.push(invalid(key, value)),
+            },
+            "temperature" => match value.parse::<u16>() {
+                Ok(kelvin) => temperature = Some(kelvin),
+                _ => plan.unmapped.push(invalid(key, value)),
+            },
+            "video_device" | "delay" | "require_device" => plan
+                .unmapped
+                .push(Unmapped::new(key, msg!("import-camera"))),
+            "serial_number" | "device_path" => plan
+                .unmapped
+                .push(Unmapped::new(key, msg!("import-lamp-selector"))),
+            "device_type" if value.eq_ignore_ascii_case("glow") => {}
+            "device_type" => plan
+                .unmapped
+                .push(Unmapped::new(key, msg!("import-device-type"))),
+            "verbose" => plan
+                .unmapped
+                .push(Unmapped::new(key, msg!("import-verbose"))),
+            _ => plan
+                .unmapped
+                .push(Unmapped::new(key, msg!("import-unknown-field"))),
+        }
+    }
+    add_scene(&mut plan, AUTOTOGGLE_SCENE, brightness, temperature);
+    plan
+}
+
+/// Reads shell aliases that call the `litra` CLI, such as
+/// `alias desk='litra brightness --percentage 70 && litra temperature --value 4000'`.
+/// Each alias setting both brightness and temperature becomes a scene of
+/// the same name.
+pub fn litra_aliases(text: &str) -> Plan {
+    let mut plan = Plan::default();
+    for line in text.lines() {
+        let Some(alias) = line.trim().strip_prefix("alias ") else {
+            continue;
+        };
+        let Some((name, body)) = alias.split_once('=') else {
+            continue;
+        };
+        let name = name.trim();
+        let body = body.trim().trim_matches(['\'', '"']);
+        if !body.split_whitespace().any(|word| word == "litra") {
+            continue;
+        }
+        let (brightness, temperature) = litra_invocations(body);
+        if brightness.is_none() && temperature.is_none() {
+            plan.unmapped.push(Unmapped::new(
+                format!("alias {}", name),
+                msg!("import-no-light"),
+            ));
+            continue;
+        }
+        add_scene(&mut plan, name, brightness, temperature);
+    }
+    plan
+}
+
+/// The brightness percentage and temperature set by the `litra` commands
+/// in one alias. Brightness given in lumens is converted for the model
+/// named by `--device-type`, a Glow when there is none.
+fn litra_invocations(body: &str) -> (Option<u8>, Option<u16>) {
+    let mut brightness = None;
+    let mut temperature = None;
+    for command in body.split(['&', ';', '|']) {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let Some(start) = words.iter().position(|word| *word == "litra") else {
+            continue;
+        };
+        let words = &words[start + 1..];
+        let value = |name: &str| {
+            words
+                .windows(2)
+                .find(|pair| pair[0] == name)
+                .map(|pair| pair[1])
+        };
+        let flag = |name: &str| value(name).and_then(|value| value.parse::<u16>().ok());
+        let model = match value("--device-type") {
+            Some("beam") => Model::Beam,
+            _ => Model::Glow,
+        };
+        match words.first().copied() {
+            Some("brightness") => {
+                brightness = flag("--percentage")
+                    .map(|percent| percent.min(100) as u8)
+                    .or_else(|| flag("--value").map(|lumens| brightness_to_percent(lumens, model)));
+            }
+            Some("temperature") => temperature = flag("--value"),
+            _ => {}
+        }
+    }
+    (brightness, temperature)
+}
+
+/// Reads a Logitech G HUB profile export. G HUB has no documented format,
+/// so this looks for any object holding both a brightness and a
+/// temperature and turns each into a scene named after the nearest
+/// enclosing `name`, or after its position when there is none. Top-level
+/// fields that yielded nothing are reported.
+pub fn ghub_profile(text: &str) -> Result<Plan, String> {
+    let root: Value =
+        serde_json::from_str(text).map_err(|e| msg!("import-not-json", error = e.to_string()))?;
+    let mut plan = Plan::default();
+    match &root {
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                let before = plan.scenes.len();
+                collect_ghub_lights(value, key, None, &mut plan);
+                if plan.scenes.len() == before {
+                    plan.unmapped.push(Unmapped::new(
+                        key.as_str(),
+                        msg!("import-no-light-settings"),
+                    ));
+                }
+            }
+        }
+        _ => collect_ghub_lights(&root, "ghub", None, &mut plan),
+    }
+    Ok(plan)
+}
+
+fn collect_ghub_lights(value: &Value, path: &str, name: Option<&str>, plan: &mut Plan) {
+    match value {
+        Value::Object(fields) => {
+            let name = fields.get("name").and_then(Value::as_str).or(name);
+            let number = |names: &[&str]| {
+                fields
+                    .iter()
+                    .find(|(key, _)| names.iter().any(|name| key.eq_ignore_ascii_case(name)))
+                    .and_then(|(_, value)| value.as_f64())
+            };
+            let brightness = number(&["brightness"]);
+            let temperature = number(&["temperature", "colorTemperature"]);
+            if let (Some(brightness), Some(temperature)) = (brightness, temperature) {
+                add_scene(
+                    plan,
+                    name.unwrap_or(path),
+                    Some(brightness.clamp(0.0, 100.0).round() as u8),
+                    Some(temperature.clamp(0.0, u16::MAX as f64).round() as u16),
+                );
+                return;
+            }
+            for (key, value) in fields {
+                collect_ghub_lights(value, &format!("{}.{}", path, key), name, plan);
+            }
+        }
+        Value::Array(items) => {
+            for (index, value) in items.iter().enumerate() {
+                collect_ghub_lights(value, &format!("{}[{}]", path, index), name, plan);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Adds the scene when both values are known, otherwise notes what is
+/// missing. Temperatures are snapped to the device grid.
+fn add_scene(plan: &mut Plan, name: &str, brightness: Option<u8>, temperature: Option<u16>) {
+    match (brightness, temperature) {
+        (Some(brightness), Some(temperature)) => {
+            plan.scenes.insert(
+                name.to_string(),
+                Scene {
+                    brightness,
+                    temperature: snap_temperature(temperature),
+                    power: None,
+                },
+            );
+        }
+        (Some(_), None) => plan
+            .unmapped
+            .push(Unmapped::new(name, msg!("import-needs-temperature"))),
+        (None, Some(_)) => plan
+            .unmapped
+            .push(Unmapped::new(name, msg!("import-needs-brightness"))),
+        (None, None) => {}
+    }
+}
+
+/// A YAML scalar without quotes or a trailing comment.
+fn yaml_scalar(value: &str) -> &str {
+    let value = value.trim();
+    for quote in ['"', '\''] {
+        if let Some(rest) = value.strip_prefix(quote)
+            && let Some(end) = rest.find(quote)
+        {
+            return &rest[..end];
+        }
+    }
+    value
+        .split_once(" #")
+        .map_or(value, |(value, _)| value)
+        .trim()
+}
+
+fn invalid(key: &str, value: &str) -> Unmapped {
+    Unmapped::new(key, msg!("import-out-of-range", value = value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn fixture(name: &str) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/import")
+            .join(name);
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    fn scene(brightness: u8, temperature: u16) -> Scene {
+        Scene {
+            brightness,
+            temperature: snap_temperature(temperature),
+            power: None,
+        }
+    }
+
+    fn fields(plan: &Plan) -> Vec<&str> {
+        plan.unmapped.iter().map(|u| u.field.as_str()).collect()
+    }
+
+    #[test]
+    fn autotoggle_config_becomes_one_scene() {
+        let plan = litra_autotoggle(&fixture("litra-autotoggle.yml"));
+        assert_eq!(
+            plan.scenes,
+            BTreeMap::from([(AUTOTOGGLE_SCENE.to_string(), scene(70, 4050))])
+        );
+        assert_eq!(
+            fields(&plan),
+            [
+                "serial_number",
+                "video_device",
+                "delay",
+                "require_device",
+                "verbose",
+                "notify",
+                msg!("import-line", line = 12).as_str(),
+            ]
+        );
+        assert!(plan.unmapped.iter().all(|u| !u.reason.is_empty()));
+    }
+
+    #[test]
+    fn autotoggle_values_out_of_range_are_listed() {
+        let plan = litra_autotoggle("brightness: 140\ntemperature: warm\ndevice_type: beam\n");
+        assert!(plan.is_empty());
+        assert_eq!(fields(&plan), ["brightness", "temperature", "device_type"]);
+    }
+
+    #[test]
+    fn aliases_become_scenes_named_after_them() {
+        let plan = litra_aliases(&fixture("litra-aliases.sh"));
+        assert_eq!(
+            plan.scenes,
+            BTreeMap::from([
+                ("desk".to_string(), scene(70, 4000)),
+                (
+                    "beam".to_string(),
+                    scene(brightness_to_percent(200, Model::Beam), 6500)
+                ),
+            ])
+        );
+        assert_eq!(fields(&plan), ["alias lamp-off", "warm"]);
+    }
+
+    #[test]
+    fn ghub_lights_become_scenes_named_after_their_profile() {
+        let plan = ghub_profile(&fixture("ghub-profile.json")).unwrap();
+        assert_eq!(
+            plan.scenes,
+            BTreeMap::from([
+                ("Evening".to_string(), scene(35, 3000)),
+                ("Streaming".to_string(), scene(80, 5000)),
+                ("profiles[2].lighting".to_string(), scene(100, 9000)),
+            ])
+        );
+        assert_eq!(fields(&plan), ["macros", "version"]);
+    }
+
+    #[test]
+    fn ghub_profile_must_be_json() {
+        assert!(ghub_profile(&fixture("litra-autotoggle.yml")).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::protocol::Command;
+use crate::storage;
+
+const JOURNAL_FILE: &str = "pending.json";
+
+/// Settings requested while the lamp was unreachable, so they survive a
+/// restart and can be applied on the next connect. Only the fields that
+/// were actually changed are set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Intent {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub power: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<u16>,
+}
+
+impl Intent {
+    pub fn is_empty(&self) -> bool {
+        self.power.is_none() && self.brightness.is_none() && self.temperature.is_none()
+    }
+
+    /// Whether this intent was recorded for the lamp with `serial`. Intents
+    /// recorded before any lamp was seen apply to whichever connects first.
+    pub fn applies_to(&self, serial: Option<&str>) -> bool {
+        match (self.serial.as_deref(), serial) {
+            (Some(recorded), Some(current)) => recorded == current,
+            _ => true,
+        }
+    }
+
+    /// Set commands restoring this intent, in the CLI's order: brightness,
+    /// temperature, then power.
+    pub fn commands(&self) -> Vec<Command> {
+        let mut commands = Vec::new();
+        commands.extend(self.brightness.map(Command::SetBrightness));
+        commands.extend(self.temperature.map(Command::SetTemperature));
+        commands.extend(self.power.map(Command::SetPower));
+        commands
+    }
+}
+
+/// Single-file store for the pending [`Intent`].
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// The journal in the user's config directory, if one can be determined.
+    pub fn open_default() -> Option<Self> {
+        storage::config_dir().map(|dir| Self::new(dir.join(JOURNAL_FILE)))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the pending intent. A missing or unreadable journal counts as
+    /// empty.
+    pub fn load(&self) -> Intent {
+        match fs::read(&self.path) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                warn!("Ignoring corrupt journal {}: {}", self.path.display(), e);
+                Intent::default()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Intent::default(),
+            Err(e) => {
+                warn!("Failed to read journal {}: {}", self.path.display(), e);
+                Intent::default()
+            }
+        }
+    }
+
+    pub fn store(&self, intent: &Intent) -> io::Result<()> {
+        let data = serde_json::to_vec(intent).expect("intent always serializes");
+        storage::atomic_write(&self.path, &data)
+    }
+
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
@@ -1,25 +1,84 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod alarm;
+mod arbiter;
+mod calibration;
+mod channel;
+mod config;
+mod controller;
+mod convert;
+mod curve;
+mod cycle;
+mod doctor;
+mod focus;
+mod group;
+mod history;
+mod hotkey;
+mod hotplug;
+mod i18n;
+mod import;
+mod journal;
+mod power_source;
 mod protocol;
+mod scenes;
+mod schema;
+mod storage;
+mod throttle;
+mod trace;
+mod transition;
+mod transport;
+mod usage;
 mod usb;
+mod worker;
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
+use std::io::{self, Write};
 #[cfg(windows)]
 use std::os::windows::io::AsRawHandle;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use std::time::{Duration, Instant};
 
-use clap::Parser;
+use alarm::{AlarmConfig, MissedAlarm};
+use arbiter::{Pause, Setting};
+use calibration::Calibration;
+use chrono::{DateTime, Local, NaiveTime, Weekday};
+use clap::builder::{PossibleValue, TypedValueParser};
+use clap::error::{ContextKind, ContextValue, ErrorKind};
+use clap::parser::ValueSource;
+use clap::{
+    ArgGroup, ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum,
+};
+use clap_complete::Shell;
+use config::{Config, ConfigStore};
+use controller::Controller;
+use convert::{
+    BrightnessScale, brightness_to_percent, clamp_temperature, parse_duration,
+    percent_to_brightness, scale_percent, snap_temperature,
+};
 use device_query::{DeviceQuery, DeviceState as DeviceQueryState};
-use log::{debug, error, info, warn};
+use history::Entry;
+use hotplug::Hotplug;
+use i18n::msg;
+use journal::Journal;
 use protocol::{
-    Command, MAX_BRIGHTNESS, MAX_TEMPERATURE, MIN_BRIGHTNESS, MIN_TEMPERATURE, Response,
-    TEMPERATURE_STEP,
+    Command, KNOWN_DEVICES, MAX_TEMPERATURE, MIN_TEMPERATURE, Model, Response, TEMPERATURE_STEP,
+    TemperaturePreset, VENDOR_ID,
 };
+use scenes::{Scene, SceneStore};
 use slint::winit_030::{WinitWindowAccessor, winit};
-use usb::LitraDevice;
+use tracing::{debug, info, warn};
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+use transition::{Fade, Transition};
+use usb::{DeviceOptions, Identity, LitraDevice, TransportKind};
+use worker::{
+    DeviceCommand, DeviceEvent, DeviceId, DeviceState, DeviceWorker, Routed, SystemClock,
+    coalesce_events,
+};
 
 #[cfg(feature = "tray")]
 use std::sync::mpsc as std_mpsc;
@@ -27,86 +86,740 @@ use std::sync::mpsc as std_mpsc;
 slint::include_modules!();
 
 #[derive(Parser)]
-#[command(name = "litra-glow", version, about = "Logitech Litra Glow controller")]
+#[command(
+    name = "litra-glow",
+    version,
+    about = "Logitech Litra Glow controller",
+    after_help = "Combined settings are applied in order: brightness, temperature, then power. \
+                  Each write is confirmed by reading the value back before the next is sent.",
+    // The flags that change or report the lamp, refused next to the
+    // subcommands that stand for them.
+    group(ArgGroup::new("settings").multiple(true).args([
+        "on", "off", "toggle", "brightness", "temperature", "temperature_percent",
+        "cycle_brightness", "cycle_temperature", "brightness_up", "brightness_down",
+        "warmer", "cooler", "preset", "status",
+    ])),
+)]
 struct Cli {
-    #[arg(long, help = "Turn the lamp on")]
+    // The flags for `on`, `off`, `toggle`, `set`, `status` and `preset`
+    // are hidden aliases of those subcommands, kept for existing scripts.
+    #[arg(long, hide = true, conflicts_with_all = ["off", "toggle"], help = "Turn the lamp on")]
     on: bool,
 
-    #[arg(long, help = "Turn the lamp off")]
+    #[arg(
+        long,
+        hide = true,
+        conflicts_with = "toggle",
+        help = "Turn the lamp off"
+    )]
     off: bool,
 
-    #[arg(long, help = "Toggle lamp power")]
+    #[arg(long, hide = true, help = "Toggle lamp power")]
     toggle: bool,
 
-    #[arg(long, value_name = "0-100", help = "Set brightness (percentage)")]
-    brightness: Option<u8>,
+    #[arg(
+        long,
+        hide = true,
+        value_name = "0-100",
+        value_parser = parse_brightness,
+        help = "Set brightness (percentage), or in lumens as in 250lm"
+    )]
+    brightness: Option<BrightnessInput>,
+
+    #[arg(
+        long,
+        conflicts_with = "raw",
+        help = "Treat brightness percentages as perceived brightness, so 50% looks like half"
+    )]
+    perceptual: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Take --brightness as the lamp's own level (e.g. 20-250 on a Glow) and add it to status as raw_brightness; calibration and the brightness floor are left out"
+    )]
+    raw: bool,
+
+    #[arg(
+        long,
+        value_name = "0-100",
+        value_parser = clap::value_parser!(u8).range(0..=100),
+        help = "Never set brightness below this percentage, to avoid flicker at the low end; --off still works"
+    )]
+    min_floor: Option<u8>,
 
     #[arg(
         long,
+        hide = true,
         value_name = "KELVIN",
-        help = "Set color temperature (2700-6500)"
+        value_parser = TemperatureParser,
+        hide_possible_values = true,
+        help = "Set color temperature (2700-6500), in mireds as in 250mired, or by preset: warm, soft, neutral, cool, daylight"
     )]
     temperature: Option<u16>,
 
-    #[arg(long, help = "Show current lamp status")]
+    #[arg(
+        long,
+        hide = true,
+        value_name = "0-100",
+        value_parser = clap::value_parser!(u8).range(0..=100),
+        conflicts_with = "temperature",
+        help = "Set color temperature as a percentage from warmest (0) to coolest (100)"
+    )]
+    temperature_percent: Option<u8>,
+
+    #[arg(
+        long,
+        conflicts_with = "brightness",
+        help = "Step brightness to the next value in the cycle list"
+    )]
+    cycle_brightness: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["temperature", "temperature_percent"],
+        help = "Step color temperature to the next value in the cycle list"
+    )]
+    cycle_temperature: bool,
+
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        value_parser = clap::value_parser!(u8).range(0..=100),
+        conflicts_with_all = ["brightness", "cycle_brightness", "brightness_down", "group", "all"],
+        help = "Raise brightness by this many percent from the lamp's current value and print the result"
+    )]
+    brightness_up: Option<u8>,
+
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        value_parser = clap::value_parser!(u8).range(0..=100),
+        conflicts_with_all = ["brightness", "cycle_brightness", "group", "all"],
+        help = "Lower brightness by this many percent from the lamp's current value and print the result"
+    )]
+    brightness_down: Option<u8>,
+
+    #[arg(
+        long,
+        requires = "brightness_up",
+        conflicts_with_all = ["off", "toggle"],
+        help = "With --brightness-up, also turn the lamp on if it is off"
+    )]
+    and_on: bool,
+
+    #[arg(
+        long,
+        value_name = "KELVIN",
+        num_args = 0..=1,
+        conflicts_with_all = [
+            "temperature", "temperature_percent", "cycle_temperature", "cooler", "group", "all",
+        ],
+        help = "Lower color temperature by this many kelvin (default one step, 100) from the lamp's current value and print the result"
+    )]
+    warmer: Option<Option<u16>>,
+
+    #[arg(
+        long,
+        value_name = "KELVIN",
+        num_args = 0..=1,
+        conflicts_with_all = ["temperature", "temperature_percent", "cycle_temperature", "group", "all"],
+        help = "Raise color temperature by this many kelvin (default one step, 100) from the lamp's current value and print the result"
+    )]
+    cooler: Option<Option<u16>>,
+
+    #[arg(
+        long,
+        hide = true,
+        value_name = "NAME",
+        conflicts_with_all = ["group", "all"],
+        help = "Apply a saved scene, as listed by `scene list`, before the other settings"
+    )]
+    preset: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with_all = ["group", "all"],
+        help = "Save the lamp's power, brightness and temperature as a scene, after any other settings"
+    )]
+    save_preset: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        conflicts_with_all = ["group", "all"],
+        help = "Fade brightness and temperature to their new values over this time, e.g. 500ms or 2s"
+    )]
+    fade: Option<Duration>,
+
+    #[arg(long, hide = true, help = "Show current lamp status")]
     status: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Report errors on stderr as a JSON object with the message and its kind"
+    )]
+    json: bool,
+
+    #[arg(
+        long,
+        hide = true,
+        requires = "status",
+        help = "Also ask the lamp for its name in --status"
+    )]
+    verbose: bool,
+
+    #[arg(
+        long,
+        help = "Turn the lamp off when the window is closed or quit from the tray"
+    )]
+    off_on_exit: bool,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with_all = ["cycle_brightness", "cycle_temperature", "status", "raw"],
+        help = "Apply to every lamp of a group, scaling brightness by each lamp's ratio"
+    )]
+    group: Option<String>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["group", "cycle_brightness", "cycle_temperature", "raw"],
+        help = "Apply to every connected lamp; without other flags, the window drives them all"
+    )]
+    all: bool,
+
+    #[arg(
+        long,
+        value_name = "DEVICE",
+        conflicts_with_all = ["group", "all"],
+        help = "Use the lamp with this serial number, HID path or index, as listed by `devices`, instead of the first found"
+    )]
+    device: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "SERIAL",
+        env = "LITRA_SERIAL",
+        conflicts_with_all = ["group", "device", "all"],
+        help = "Use the lamp with this serial number instead of the first found"
+    )]
+    serial: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        conflicts_with_all = ["group", "all"],
+        help = "Wait up to this long for the lamp to be connected before running the command; 0 waits forever"
+    )]
+    wait: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = 500,
+        help = "How long --status waits for the lamp to answer"
+    )]
+    timeout: u64,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        env = "LITRA_TRACE",
+        help = "Append all device traffic to a JSONL trace file; with --all or --group, one file per lamp named after its serial number"
+    )]
+    trace_out: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Replay device traffic from a trace instead of using real hardware"
+    )]
+    replay: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env = "LITRA_DRY_RUN",
+        conflicts_with = "replay",
+        help = "Simulate the lamp and log the frames that would be written"
+    )]
+    dry_run: bool,
+
+    /// Where device traffic goes unless `--dry-run` or `--replay` says
+    /// otherwise. Not a flag: the real lamp, or one a test hands in.
+    #[arg(skip)]
+    transport: TransportKind,
+
+    #[arg(long, help = "Print verbose diagnostics with span timings to stderr")]
+    trace: bool,
+
+    #[arg(
+        long,
+        hide = true,
+        conflicts_with_all = [
+            "on", "off", "toggle", "brightness", "temperature", "temperature_percent",
+            "cycle_brightness", "cycle_temperature", "brightness_up", "brightness_down",
+            "warmer", "cooler", "preset", "save_preset", "status", "group", "all",
+        ],
+        help = "Print every report from the lamp that is not understood as a hex line, until interrupted"
+    )]
+    dump_unknown: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "LANG",
+        env = i18n::LANG_ENV,
+        help = "Language of messages and help, e.g. de; defaults to the system language"
+    )]
+    lang: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+}
+
+#[derive(Subcommand)]
+enum CliCommand {
+    #[command(about = "Turn the lamp on")]
+    On,
+    #[command(about = "Turn the lamp off")]
+    Off,
+    #[command(about = "Toggle lamp power")]
+    Toggle,
+    #[command(
+        arg_required_else_help = true,
+        about = "Set brightness and color temperature, applied in that order and then power"
+    )]
+    Set {
+        #[command(flatten)]
+        settings: Settings,
+        #[arg(long, conflicts_with = "off", help = "Also turn the lamp on")]
+        on: bool,
+        #[arg(long, help = "Also turn the lamp off")]
+        off: bool,
+        #[arg(
+            long,
+            value_name = "DURATION",
+            value_parser = parse_duration,
+            help = "Fade brightness and temperature to their new values over this time, e.g. 500ms or 2s"
+        )]
+        fade: Option<Duration>,
+    },
+    #[command(about = "Print one value the lamp reports")]
+    Get {
+        #[arg(value_enum)]
+        field: Field,
+    },
+    #[command(about = "Show current lamp status as JSON")]
+    Status {
+        #[arg(long, help = "Also ask the lamp for its name")]
+        verbose: bool,
+    },
+    #[command(about = "Apply a saved scene, as listed by `scene list`")]
+    Preset {
+        name: String,
+        #[command(flatten)]
+        settings: Settings,
+    },
+    #[command(about = "Print a shell completion script, e.g. for ~/.bash_completion")]
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    #[command(hide = true, about = "Print the JSON Schema of all JSON output")]
+    Schema,
+    #[command(about = "Check the device, permissions and environment")]
+    Doctor {
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    #[command(visible_alias = "list", about = "List the connected lamps")]
+    Devices {
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    #[command(subcommand, about = "Save, list and apply scenes")]
+    Scene(SceneCommand),
+    #[command(subcommand, about = "Configure the sunrise wake-up alarm")]
+    Alarm(AlarmCommand),
+    #[command(subcommand, about = "Work with the recorded lamp history")]
+    History(HistoryCommand),
+    #[command(subcommand, about = "Show or reset the lamp on-time counters")]
+    Stats(StatsCommand),
+    #[command(about = "Import scenes from another Litra tool's settings")]
+    Import {
+        #[arg(long, value_enum)]
+        from: ImportFrom,
+        path: PathBuf,
+        #[arg(
+            long,
+            help = "Write the scenes; without this only the summary is shown"
+        )]
+        apply: bool,
+    },
+    #[command(about = "Show or change the calibration offsets of the connected lamp")]
+    Calibrate {
+        #[arg(
+            long,
+            value_name = "KELVIN",
+            allow_hyphen_values = true,
+            value_parser = clap::value_parser!(i16).range(-3800..=3800),
+            help = "Added to every color temperature written to this lamp"
+        )]
+        temperature_offset: Option<i16>,
+        #[arg(
+            long,
+            value_name = "PERCENT",
+            allow_hyphen_values = true,
+            value_parser = clap::value_parser!(i8).range(-100..=100),
+            help = "Added to every brightness written to this lamp"
+        )]
+        brightness_offset: Option<i8>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AlarmCommand {
+    #[command(about = "Show the alarm settings and the next start time")]
+    Show,
+    #[command(about = "Change the alarm settings and arm it")]
+    Set {
+        #[arg(long, value_name = "HH:MM", value_parser = parse_time_of_day)]
+        time: Option<NaiveTime>,
+        #[arg(
+            long,
+            value_name = "DAYS",
+            value_delimiter = ',',
+            help = "e.g. sat,sun"
+        )]
+        days: Option<Vec<Weekday>>,
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        ramp: Option<Duration>,
+        #[arg(long, value_name = "0-100", help = "Target brightness (percentage)")]
+        brightness: Option<u8>,
+        #[arg(long, value_name = "KELVIN", help = "Target color temperature")]
+        temperature: Option<u16>,
+        #[arg(
+            long,
+            value_enum,
+            help = "What to do when the start was missed during sleep"
+        )]
+        missed: Option<MissedAlarm>,
+    },
+    #[command(about = "Arm the alarm")]
+    Arm,
+    #[command(about = "Disarm the alarm")]
+    Disarm,
+    #[command(about = "Skip the next scheduled start")]
+    SkipNext,
+}
+
+#[derive(Subcommand)]
+enum HistoryCommand {
+    #[command(about = "Write the recorded history to a CSV file")]
+    Export { file: PathBuf },
+}
+
+#[derive(Subcommand)]
+enum StatsCommand {
+    #[command(about = "Show how long each known lamp has been on")]
+    Show,
+    #[command(about = "Zero the on-time of one lamp, or of all lamps")]
+    Reset {
+        #[arg(help = "Serial number of the lamp; all lamps when omitted")]
+        serial: Option<String>,
+        #[arg(long, help = "Do not ask for confirmation")]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum SceneCommand {
+    #[command(about = "Fade to a saved scene")]
+    Apply {
+        name: String,
+        #[arg(
+            long,
+            value_name = "DURATION",
+            value_parser = parse_duration,
+            default_value = "0s",
+            help = "Crossfade time, e.g. 500ms or 2s"
+        )]
+        fade: Duration,
+    },
+    #[command(about = "Save a scene, replacing one with the same name")]
+    Set {
+        name: String,
+        #[arg(long, value_name = "0-100", help = "Brightness (percentage)")]
+        brightness: u8,
+        #[arg(long, value_name = "KELVIN", help = "Color temperature (2700-6500)")]
+        temperature: u16,
+    },
+    #[command(about = "List saved scenes")]
+    List,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ImportFrom {
+    /// A litra-autotoggle YAML config
+    LitraAutotoggle,
+    /// A shell file with aliases calling the litra CLI
+    LitraAliases,
+    /// A Logitech G HUB profile export (best effort)
+    GhubProfile,
+}
+
+/// A `--brightness` value. A plain number is a percentage, or the lamp's
+/// own level with `--raw`.
+#[derive(Debug, Clone, Copy)]
+enum BrightnessInput {
+    Percent(u16),
+    Lumens(u16),
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+/// Brightness and temperature for `set`, or over a scene for `preset`.
+#[derive(Args)]
+struct Settings {
+    #[arg(
+        long,
+        value_name = "0-100",
+        value_parser = parse_brightness,
+        help = "Brightness (percentage), or in lumens as in 250lm"
+    )]
+    brightness: Option<BrightnessInput>,
+
+    #[arg(
+        long,
+        value_name = "KELVIN",
+        value_parser = TemperatureParser,
+        hide_possible_values = true,
+        help = "Color temperature (2700-6500), in mireds as in 250mired, or by preset: warm, soft, neutral, cool, daylight"
+    )]
+    temperature: Option<u16>,
+
+    #[arg(
+        long,
+        value_name = "0-100",
+        value_parser = clap::value_parser!(u8).range(0..=100),
+        conflicts_with = "temperature",
+        help = "Color temperature as a percentage from warmest (0) to coolest (100)"
+    )]
+    temperature_percent: Option<u8>,
+}
+
+/// A value `get` prints.
+#[derive(Clone, Copy, ValueEnum)]
+enum Field {
+    /// `on` or `off`.
+    Power,
+    /// Percent, on the `--perceptual` scale if given, or the lamp's own
+    /// level with `--raw`.
+    Brightness,
+    /// Kelvin.
+    Temperature,
 }
 
 impl Cli {
+    /// Folds the `on`, `off`, `toggle`, `set`, `status` and `preset`
+    /// subcommands into the flags they stand for, so both forms run the
+    /// same way. clap checks conflicts only among the arguments of one
+    /// command, so the ones declared on `command` are checked here across
+    /// the subcommand: a folding subcommand is refused next to the
+    /// `settings` group, and next to any flag that conflicts with the flags
+    /// it folds into.
+    fn fold_command(
+        &mut self,
+        command: &clap::Command,
+        matches: &ArgMatches,
+    ) -> Result<(), clap::Error> {
+        let Some((name, sub)) = matches.subcommand() else {
+            return Ok(());
+        };
+        let given = |matches: &ArgMatches, arg: &clap::Arg| {
+            matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+        };
+        let root: Vec<clap::Arg> = command
+            .get_arguments()
+            .filter(|arg| given(matches, arg))
+            .cloned()
+            .collect();
+        let sub_command = command.find_subcommand(name).expect("parsed subcommand");
+        let after: Vec<clap::Arg> = sub_command
+            .get_arguments()
+            .filter(|arg| given(sub, arg))
+            .cloned()
+            .collect();
+        // Whether clap would refuse `prior` with the flag `id`, declared on
+        // either side.
+        let conflict = |prior: &clap::Arg, id: &str| {
+            let declared = |arg: &clap::Arg, other: &str| {
+                command
+                    .get_arg_conflicts_with(arg)
+                    .iter()
+                    .any(|conflict| conflict.get_id() == other)
+            };
+            !prior.is_global_set()
+                && (declared(prior, id)
+                    || command
+                        .get_arguments()
+                        .find(|arg| arg.get_id() == id)
+                        .is_some_and(|arg| declared(arg, prior.get_id().as_str())))
+        };
+        // A global flag after the subcommand escapes clap's own check.
+        for arg in after.iter().filter(|arg| arg.is_global_set()) {
+            if let Some(prior) = root
+                .iter()
+                .find(|prior| conflict(prior, arg.get_id().as_str()))
+            {
+                return Err(argument_conflict(command, arg.to_string(), prior));
+            }
+        }
+        if !matches!(name, "on" | "off" | "toggle" | "set" | "status" | "preset") {
+            return Ok(());
+        }
+        // The subcommand and its arguments, by the flag each stands for.
+        let folded = std::iter::once((name.to_string(), name.to_string())).chain(
+            after
+                .iter()
+                .map(|arg| (arg.get_id().to_string(), arg.to_string())),
+        );
+        let settings: Vec<&str> = command
+            .get_groups()
+            .filter(|group| group.get_id() == "settings")
+            .flat_map(|group| group.get_args())
+            .map(|id| id.as_str())
+            .collect();
+        for (id, shown) in folded {
+            if let Some(prior) = root
+                .iter()
+                .find(|prior| settings.contains(&prior.get_id().as_str()) || conflict(prior, &id))
+            {
+                return Err(argument_conflict(command, shown, prior));
+            }
+        }
+        match self.command.take() {
+            Some(CliCommand::On) => self.on = true,
+            Some(CliCommand::Off) => self.off = true,
+            Some(CliCommand::Toggle) => self.toggle = true,
+            Some(CliCommand::Set {
+                settings,
+                on,
+                off,
+                fade,
+            }) => {
+                self.apply_settings(settings);
+                self.on = on;
+                self.off = off;
+                self.fade = fade.or(self.fade);
+            }
+            Some(CliCommand::Status { verbose }) => {
+                self.status = true;
+                self.verbose = verbose;
+            }
+            Some(CliCommand::Preset { name, settings }) => {
+                self.preset = Some(name);
+                self.apply_settings(settings);
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn apply_settings(&mut self, settings: Settings) {
+        self.brightness = settings.brightness;
+        self.temperature = settings.temperature;
+        self.temperature_percent = settings.temperature_percent;
+    }
+
     fn has_commands(&self) -> bool {
+        self.has_setting_flags() || self.save_preset.is_some()
+    }
+
+    /// Whether a flag that changes or reports the lamp was given. Only
+    /// `--save-preset`, which reads the lamp afterwards, is left out.
+    fn has_setting_flags(&self) -> bool {
         self.on
             || self.off
             || self.toggle
             || self.brightness.is_some()
-            || self.temperature.is_some()
+            || self.temperature().is_some()
+            || self.cycle_brightness
+            || self.cycle_temperature
+            || self.brightness_step().is_some()
+            || self.temperature_shift().is_some()
+            || self.preset.is_some()
             || self.status
     }
-}
 
-#[derive(Debug)]
-enum DeviceCommand {
-    Retry,
-    SetPower(bool),
-    SetBrightness(u16),
-    SetTemperature(u16),
-}
-
-#[derive(Debug)]
-enum DeviceEvent {
-    Connected,
-    Power(bool),
-    Brightness(u16),
-    Temperature(u16),
-    Error(String),
-}
+    /// The requested brightness change in percent, from `--brightness-up`
+    /// or `--brightness-down`.
+    fn brightness_step(&self) -> Option<i16> {
+        self.brightness_up
+            .map(i16::from)
+            .or(self.brightness_down.map(|down| -i16::from(down)))
+    }
 
-const PENDING_TIMEOUT: Duration = Duration::from_millis(300);
-const CENTER_RETRY_DELAY: Duration = Duration::from_millis(16);
-const CENTER_RETRY_LIMIT: u8 = 15;
+    /// The requested temperature change in kelvin, from `--warmer` or
+    /// `--cooler`; one step when no amount is given.
+    fn temperature_shift(&self) -> Option<i32> {
+        let kelvin = |amount: Option<u16>| i32::from(amount.unwrap_or(TEMPERATURE_STEP));
+        self.cooler
+            .map(kelvin)
+            .or(self.warmer.map(|warmer| -kelvin(warmer)))
+    }
 
-#[derive(Debug, Clone, Copy)]
-struct DeviceState {
-    power: bool,
-    brightness: u16,
-    temperature: u16,
-    pending_brightness: Option<Instant>,
-    pending_temperature: Option<Instant>,
-}
+    /// The requested color temperature in kelvin, from `--temperature` or
+    /// `--temperature-percent`.
+    fn temperature(&self) -> Option<u16> {
+        self.temperature.or(self
+            .temperature_percent
+            .map(protocol::temperature_percent_to_kelvin))
+    }
 
-fn clamp_brightness(value: f32) -> u16 {
-    let value = value.round() as i32;
-    let clamped = value.clamp(MIN_BRIGHTNESS as i32, MAX_BRIGHTNESS as i32);
-    clamped as u16
+    fn device_options(&self) -> DeviceOptions {
+        let transport = if self.dry_run {
+            TransportKind::DryRun
+        } else if let Some(path) = &self.replay {
+            TransportKind::Replay(path.clone())
+        } else {
+            self.transport.clone()
+        };
+        DeviceOptions {
+            transport,
+            serial: self.serial.clone(),
+            path: None,
+            selector: self.device.clone(),
+            trace_out: self.trace_out.clone(),
+        }
+    }
 }
 
-fn clamp_temperature(value: f32) -> u16 {
-    let value = value.round() as i32;
-    let clamped = value.clamp(MIN_TEMPERATURE as i32, MAX_TEMPERATURE as i32) as u16;
-    let step = TEMPERATURE_STEP;
-    let half = step / 2;
-    let stepped = ((clamped + half) / step) * step;
-    stepped.clamp(MIN_TEMPERATURE, MAX_TEMPERATURE)
-}
+const SCENE_FADE: Duration = Duration::from_secs(2);
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+const COMMAND_SEND_TIMEOUT: Duration = Duration::from_millis(100);
+const COMMAND_QUEUE_CAPACITY: usize = 8;
+const EVENT_QUEUE_CAPACITY: usize = 32;
+const CENTER_RETRY_DELAY: Duration = Duration::from_millis(16);
+const CENTER_RETRY_LIMIT: u8 = 15;
+/// Must match the title of `OsdWindow` in ui.slint.
+const OSD_TITLE: &str = "Litra Glow OSD";
+const OSD_TIMEOUT: Duration = Duration::from_millis(1200);
+const OSD_FADE: Duration = Duration::from_millis(250);
+/// Distance from the screen corner, in logical pixels.
+const OSD_MARGIN: f64 = 48.0;
+const HISTORY_POLL: Duration = Duration::from_secs(5);
+/// How often `--wait` looks for the lamp again.
+const WAIT_POLL: Duration = Duration::from_millis(250);
 
 fn cursor_position() -> Option<(i32, i32)> {
     let device_state = DeviceQueryState::new();
@@ -211,55 +924,385 @@ fn schedule_center_window(app_weak: slint::Weak<AppWindow>, attempts_left: u8) {
     });
 }
 
-#[cfg(feature = "tray")]
-enum TrayCommand {
-    Show,
-    Quit,
+/// Keeps the OSD from taking focus or appearing in the taskbar. Other
+/// windows are left untouched.
+fn osd_window_attributes(
+    attributes: winit::window::WindowAttributes,
+) -> winit::window::WindowAttributes {
+    if attributes.title != OSD_TITLE {
+        return attributes;
+    }
+    let attributes = attributes.with_active(false);
+    #[cfg(windows)]
+    let attributes = {
+        use winit::platform::windows::WindowAttributesExtWindows;
+        attributes.with_skip_taskbar(true)
+    };
+    attributes
 }
 
-#[cfg(feature = "tray")]
-fn setup_tray() -> Option<(tray_item::TrayItem, std_mpsc::Receiver<TrayCommand>)> {
-    use tray_item::TrayItem;
-
-    let tray_result = TrayItem::new("Litra Glow", tray_item::IconSource::Resource("tray-icon"));
-    let mut tray = match tray_result {
-        Ok(t) => t,
-        Err(e) => {
-            warn!(
-                "Failed to create tray icon: {:?}. Tray functionality disabled.",
-                e
-            );
-            return None;
-        }
+fn place_osd(window: &winit::window::Window) {
+    let Some(monitor) = active_monitor(window) else {
+        return;
     };
+    let position = monitor.position();
+    let size = monitor.size();
+    let window_size = window.outer_size();
+    let margin = (OSD_MARGIN * monitor.scale_factor()) as i32;
+    let x = position.x + size.width as i32 - window_size.width as i32 - margin;
+    let y = position.y + size.height as i32 - window_size.height as i32 - margin;
+    window.set_outer_position(winit::dpi::PhysicalPosition::new(x, y));
+}
 
-    let (tx, rx) = std_mpsc::channel::<TrayCommand>();
-    let tx_quit = tx.clone();
+/// Popup in the corner of the screen showing a value changed with the
+/// lamp's buttons. The window is created on first use and reused, so rapid
+/// presses update it instead of stacking new ones.
+#[derive(Default)]
+struct Osd {
+    window: Option<OsdWindow>,
+    hide_timer: slint::Timer,
+}
 
-    if tray
-        .add_menu_item("Show", move || {
-            let _ = tx.send(TrayCommand::Show);
-        })
-        .is_err()
-    {
-        return None;
+impl Osd {
+    fn show(&mut self, label: &str, value: String, level: f32) {
+        if self.window.is_none() {
+            match OsdWindow::new() {
+                Ok(window) => self.window = Some(window),
+                Err(e) => {
+                    warn!("Failed to create OSD window: {}", e);
+                    return;
+                }
+            }
+        }
+        let Some(window) = &self.window else {
+            return;
+        };
+        window.set_label(label.into());
+        window.set_value(value.into());
+        window.set_level(level);
+        window.set_shown(true);
+        if let Err(e) = window.show() {
+            warn!("Failed to show OSD window: {}", e);
+            return;
+        }
+        window.window().with_winit_window(place_osd);
+
+        let window_weak = window.as_weak();
+        self.hide_timer
+            .start(slint::TimerMode::SingleShot, OSD_TIMEOUT, move || {
+                let Some(window) = window_weak.upgrade() else {
+                    return;
+                };
+                window.set_shown(false);
+                let window_weak = window.as_weak();
+                slint::Timer::single_shot(OSD_FADE, move || {
+                    if let Some(window) = window_weak.upgrade()
+                        && !window.get_shown()
+                    {
+                        let _ = window.hide();
+                    }
+                });
+            });
     }
 
-    if tray
-        .add_menu_item("Quit", move || {
-            let _ = tx_quit.send(TrayCommand::Quit);
-        })
-        .is_err()
-    {
-        return None;
+    /// Shows the app's current value of `setting` on a `model` lamp.
+    fn show_setting(
+        &mut self,
+        app: &AppWindow,
+        setting: Setting,
+        model: Model,
+        scale: BrightnessScale,
+    ) {
+        match setting {
+            Setting::Power => {
+                let (value, level) = if app.get_power() {
+                    (msg!("osd-on"), 1.0)
+                } else {
+                    (msg!("osd-off"), 0.0)
+                };
+                self.show(&msg!("osd-power"), value, level);
+            }
+            Setting::Brightness => {
+                let level = scale.slider_to_level(app.get_brightness(), model);
+                let percent = scale.level_to_percent(level, model);
+                self.show(
+                    &msg!("osd-brightness"),
+                    format!("{}%", percent),
+                    percent as f32 / 100.0,
+                );
+            }
+            Setting::Temperature => {
+                let kelvin = app.get_temperature();
+                let span = (MAX_TEMPERATURE - MIN_TEMPERATURE) as f32;
+                self.show(
+                    &msg!("osd-temperature"),
+                    format!("{}K", clamp_temperature(kelvin, model)),
+                    (kelvin - MIN_TEMPERATURE as f32) / span,
+                );
+            }
+        }
     }
+}
 
-    info!("Tray icon created successfully");
+/// What a screen reader says after `setting` was changed on a `model` lamp.
+fn announcement(app: &AppWindow, setting: Setting, model: Model, scale: BrightnessScale) -> String {
+    match setting {
+        Setting::Power if app.get_power() => msg!("announce-power-on"),
+        Setting::Power => msg!("announce-power-off"),
+        Setting::Brightness => msg!(
+            "announce-brightness",
+            percent =
+                scale.level_to_percent(scale.slider_to_level(app.get_brightness(), model), model)
+        ),
+        Setting::Temperature => msg!(
+            "announce-temperature",
+            kelvin = clamp_temperature(app.get_temperature(), model)
+        ),
+    }
+}
+
+/// Window plotting the recorded changes. While it is shown the worker is
+/// asked for the history every few seconds; while hidden it costs nothing.
+struct HistoryView {
+    window: Option<HistoryWindow>,
+    poll_timer: slint::Timer,
+    span: Duration,
+}
+
+impl HistoryView {
+    fn new(span: Duration) -> Self {
+        Self {
+            window: None,
+            poll_timer: slint::Timer::default(),
+            span,
+        }
+    }
+
+    fn show(&mut self, cmd_tx: &channel::Sender<DeviceCommand>) {
+        if self.window.is_none() {
+            match HistoryWindow::new() {
+                Ok(window) => {
+                    window
+                        .set_span(msg!("history-span", hours = self.span.as_secs() / 3600).into());
+                    self.window = Some(window);
+                }
+                Err(e) => {
+                    warn!("Failed to create history window: {}", e);
+                    return;
+                }
+            }
+        }
+        let Some(window) = &self.window else {
+            return;
+        };
+        if let Err(e) = window.show() {
+            warn!("Failed to show history window: {}", e);
+            return;
+        }
+
+        let window_weak = window.as_weak();
+        let cmd_tx = cmd_tx.clone();
+        let span = self.span;
+        let query = move || {
+            if window_weak
+                .upgrade()
+                .is_some_and(|window| window.window().is_visible())
+            {
+                let _ = cmd_tx.send_latest(
+                    DeviceCommand::QueryHistory(span),
+                    DeviceCommand::replaced_by,
+                );
+            }
+        };
+        query();
+        self.poll_timer
+            .start(slint::TimerMode::Repeated, HISTORY_POLL, query);
+    }
+
+    /// Draws `entries` as step lines in a 1000 x 100 viewbox, brightness
+    /// dropping to zero while the lamp is off, with a marker per change.
+    fn render(&self, entries: &[Entry]) {
+        let Some(window) = &self.window else {
+            return;
+        };
+        if !window.window().is_visible() {
+            return;
+        }
+        let now = Local::now();
+        let start = now - chrono::Duration::from_std(self.span).unwrap_or_default();
+        let span_ms = self.span.as_millis().max(1) as f32;
+        let x = |at: DateTime<Local>| {
+            ((at - start).num_milliseconds().max(0) as f32 / span_ms * 1000.0).min(1000.0)
+        };
+        let brightness = |entry: &Entry| {
+            let percent = if entry.power { entry.brightness } else { 0 };
+            100.0 - percent as f32
+        };
+        let temperature_span = (MAX_TEMPERATURE - MIN_TEMPERATURE) as f32;
+        let temperature = |entry: &Entry| {
+            let offset = entry.temperature.saturating_sub(MIN_TEMPERATURE) as f32;
+            100.0 - offset / temperature_span * 100.0
+        };
+
+        let mut brightness_path = String::new();
+        let mut temperature_path = String::new();
+        let mut brightness_markers = Vec::new();
+        let mut temperature_markers = Vec::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let (x, b, t) = (x(entry.at), brightness(entry), temperature(entry));
+            if i == 0 {
+                brightness_path = format!("M{x:.1},{b:.1}");
+                temperature_path = format!("M{x:.1},{t:.1}");
+            } else {
+                brightness_path += &format!(" H{x:.1} V{b:.1}");
+                temperature_path += &format!(" H{x:.1} V{t:.1}");
+            }
+            if entry.at < start {
+                continue;
+            }
+            let origin = slint::SharedString::from(entry.origin.name());
+            brightness_markers.push(HistoryMarker {
+                x,
+                y: b,
+                origin: origin.clone(),
+            });
+            temperature_markers.push(HistoryMarker { x, y: t, origin });
+        }
+        if !entries.is_empty() {
+            brightness_path += " H1000";
+            temperature_path += " H1000";
+        }
+
+        window.set_brightness_path(brightness_path.into());
+        window.set_temperature_path(temperature_path.into());
+        window.set_brightness_markers(Rc::new(slint::VecModel::from(brightness_markers)).into());
+        window.set_temperature_markers(Rc::new(slint::VecModel::from(temperature_markers)).into());
+    }
+}
+
+#[cfg(feature = "tray")]
+#[derive(Clone)]
+enum TrayCommand {
+    Show,
+    Scene(String),
+    CycleBrightness,
+    CycleTemperature,
+    ToggleAlarm,
+    SkipAlarm,
+    ToggleFocus,
+    ToggleSync,
+    PauseAutomations(Option<Duration>),
+    ResumeAutomations,
+    DismissAlarm,
+    Quit,
+}
+
+#[cfg(feature = "tray")]
+fn setup_tray(
+    hotkeys: &hotkey::HotkeyConfig,
+) -> Option<(tray_item::TrayItem, std_mpsc::Receiver<TrayCommand>)> {
+    use tray_item::TrayItem;
+
+    let tray_result = TrayItem::new("Litra Glow", tray_item::IconSource::Resource("tray-icon"));
+    let mut tray = match tray_result {
+        Ok(t) => t,
+        Err(e) => {
+            warn!(
+                "Failed to create tray icon: {:?}. Tray functionality disabled.",
+                e
+            );
+            return None;
+        }
+    };
+
+    let (tx, rx) = std_mpsc::channel::<TrayCommand>();
+    let tx_quit = tx.clone();
+    let tx_scenes = tx.clone();
+    let tx_items = tx.clone();
+
+    if tray
+        .add_menu_item(&msg!("tray-show"), move || {
+            let _ = tx.send(TrayCommand::Show);
+        })
+        .is_err()
+    {
+        return None;
+    }
+
+    let scene_names = SceneStore::open_default()
+        .and_then(|store| store.load().ok())
+        .map(|scenes| scenes.into_keys().collect::<Vec<_>>())
+        .unwrap_or_default();
+    // The scene hotkeys as they stand at startup.
+    let keys = hotkey::PresetKeys::new(hotkeys, &scene_names).unwrap_or_default();
+    for name in scene_names {
+        let tx_scene = tx_scenes.clone();
+        let label = match keys.get(&name) {
+            Some(key) => msg!(
+                "tray-scene-key",
+                name = name.as_str(),
+                key = key.to_string()
+            ),
+            None => msg!("tray-scene", name = name.as_str()),
+        };
+        if tray
+            .add_menu_item(&label, move || {
+                let _ = tx_scene.send(TrayCommand::Scene(name.clone()));
+            })
+            .is_err()
+        {
+            warn!("Failed to add tray item for scene {}", label);
+        }
+    }
+
+    for (label, command) in [
+        (msg!("tray-cycle-brightness"), TrayCommand::CycleBrightness),
+        (
+            msg!("tray-cycle-temperature"),
+            TrayCommand::CycleTemperature,
+        ),
+        (msg!("tray-alarm-toggle"), TrayCommand::ToggleAlarm),
+        (msg!("tray-alarm-skip"), TrayCommand::SkipAlarm),
+        (msg!("tray-alarm-dismiss"), TrayCommand::DismissAlarm),
+        (msg!("tray-focus-toggle"), TrayCommand::ToggleFocus),
+        (msg!("tray-sync-toggle"), TrayCommand::ToggleSync),
+        (
+            msg!("tray-pause-hour"),
+            TrayCommand::PauseAutomations(Some(Duration::from_secs(60 * 60))),
+        ),
+        (msg!("tray-pause"), TrayCommand::PauseAutomations(None)),
+        (msg!("tray-resume"), TrayCommand::ResumeAutomations),
+    ] {
+        let tx_item = tx_items.clone();
+        if tray
+            .add_menu_item(&label, move || {
+                let _ = tx_item.send(command.clone());
+            })
+            .is_err()
+        {
+            warn!("Failed to add tray item {}", label);
+        }
+    }
+
+    if tray
+        .add_menu_item(&msg!("tray-quit"), move || {
+            let _ = tx_quit.send(TrayCommand::Quit);
+        })
+        .is_err()
+    {
+        return None;
+    }
+
+    info!("Tray icon created successfully");
     Some((tray, rx))
 }
 
 #[cfg(feature = "tray")]
-fn handle_tray_command(cmd: TrayCommand, app_weak: &slint::Weak<AppWindow>) {
+fn handle_tray_command(
+    cmd: TrayCommand,
+    app_weak: &slint::Weak<AppWindow>,
+    cmd_tx: &channel::Sender<DeviceCommand>,
+) {
     if let Some(app) = app_weak.upgrade() {
         match cmd {
             TrayCommand::Show => {
@@ -270,6 +1313,79 @@ fn handle_tray_command(cmd: TrayCommand, app_weak: &slint::Weak<AppWindow>) {
                 });
                 schedule_center_window(app.as_weak(), CENTER_RETRY_LIMIT);
             }
+            TrayCommand::Scene(name) => {
+                let _ = cmd_tx.send_latest(
+                    DeviceCommand::ApplyScene {
+                        name,
+                        duration: SCENE_FADE,
+                    },
+                    DeviceCommand::replaced_by,
+                );
+            }
+            TrayCommand::CycleBrightness => {
+                let _ = cmd_tx.send_timeout(DeviceCommand::CycleBrightness, COMMAND_SEND_TIMEOUT);
+            }
+            TrayCommand::CycleTemperature => {
+                let _ = cmd_tx.send_timeout(DeviceCommand::CycleTemperature, COMMAND_SEND_TIMEOUT);
+            }
+            TrayCommand::ToggleAlarm => {
+                let armed = !app.get_alarm_armed();
+                if let Some(alarm) = update_alarm(|alarm| alarm.armed = armed) {
+                    app.set_alarm_armed(alarm.armed);
+                    let _ = cmd_tx
+                        .send_latest(DeviceCommand::SetAlarm(alarm), DeviceCommand::replaced_by);
+                }
+            }
+            TrayCommand::SkipAlarm => {
+                if let Some(alarm) = update_alarm(skip_next_alarm) {
+                    let _ = cmd_tx
+                        .send_latest(DeviceCommand::SetAlarm(alarm), DeviceCommand::replaced_by);
+                }
+            }
+            TrayCommand::DismissAlarm => {
+                let _ = cmd_tx.send_timeout(DeviceCommand::DismissAlarm, COMMAND_SEND_TIMEOUT);
+            }
+            TrayCommand::PauseAutomations(duration) => {
+                let pause = match duration {
+                    Some(duration) => {
+                        let until =
+                            Local::now() + chrono::Duration::from_std(duration).unwrap_or_default();
+                        Pause::Until(until)
+                    }
+                    None => Pause::Indefinite,
+                };
+                set_automation_pause(Some(pause), cmd_tx);
+            }
+            TrayCommand::ResumeAutomations => set_automation_pause(None, cmd_tx),
+            TrayCommand::ToggleFocus => {
+                let Some(store) = ConfigStore::open_default() else {
+                    return;
+                };
+                match store.update(|config| config.focus.enabled = !config.focus.enabled) {
+                    Ok(config) => {
+                        info!("Focus follow enabled: {}", config.focus.enabled);
+                        let _ = cmd_tx.send_latest(
+                            DeviceCommand::SetFocusFollow(config.focus.enabled),
+                            DeviceCommand::replaced_by,
+                        );
+                    }
+                    Err(e) => warn!("Failed to save focus follow setting: {}", e),
+                }
+            }
+            TrayCommand::ToggleSync => {
+                let Some(store) = ConfigStore::open_default() else {
+                    return;
+                };
+                match store.update(|config| config.sync_lamps = !config.sync_lamps) {
+                    Ok(config) => {
+                        let _ = cmd_tx.send_latest(
+                            DeviceCommand::SetSync(config.sync_lamps),
+                            DeviceCommand::replaced_by,
+                        );
+                    }
+                    Err(e) => warn!("Failed to save lamp sync setting: {}", e),
+                }
+            }
             TrayCommand::Quit => {
                 slint::quit_event_loop().ok();
             }
@@ -277,6 +1393,59 @@ fn handle_tray_command(cmd: TrayCommand, app_weak: &slint::Weak<AppWindow>) {
     }
 }
 
+/// Saves the automation pause so it survives a restart, and hands it to the
+/// worker.
+#[cfg(feature = "tray")]
+fn set_automation_pause(pause: Option<Pause>, cmd_tx: &channel::Sender<DeviceCommand>) {
+    if let Some(store) = ConfigStore::open_default()
+        && let Err(e) = store.update(|config| config.arbitration.pause = pause)
+    {
+        warn!("Failed to save automation pause: {}", e);
+    }
+    let _ = cmd_tx.send_latest(
+        DeviceCommand::PauseAutomations(pause),
+        DeviceCommand::replaced_by,
+    );
+}
+
+/// Footer text: the automation pause while there is one, otherwise the
+/// lamp's total on-time once it is known, followed by the lamp's name,
+/// serial number and firmware version. The name the lamp reports over
+/// HID++ is preferred to the USB product string.
+fn footer_text(
+    pause: Option<Pause>,
+    on_seconds: Option<u64>,
+    product: Option<&str>,
+    identity: &Identity,
+) -> String {
+    let status = match (pause, on_seconds) {
+        (None, None) => None,
+        (None, Some(seconds)) => Some(msg!(
+            "footer-on-time",
+            hours = format!("{:.1}", usage::hours(seconds))
+        )),
+        (Some(Pause::Indefinite), _) => Some(msg!("footer-paused")),
+        (Some(Pause::Until(until)), _) => {
+            let minutes = (until - Local::now()).num_minutes().max(0) + 1;
+            Some(msg!("footer-paused-for", minutes = minutes))
+        }
+    };
+    let serial = identity
+        .serial
+        .as_ref()
+        .map(|serial| msg!("footer-serial", serial = serial.as_str()));
+    let firmware = identity
+        .firmware
+        .as_ref()
+        .map(|firmware| msg!("footer-firmware", firmware = firmware.as_str()));
+    let name = identity.name.as_deref().or(product);
+    [status, name.map(str::to_string), serial, firmware]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" · ")
+}
+
 #[cfg(windows)]
 fn init_cli_console() {
     if std::env::args_os().nth(1).is_none() {
@@ -321,108 +1490,1394 @@ fn init_cli_console() {
     }
 }
 
-fn run_headless(cli: Cli) -> Result<(), String> {
-    let device = LitraDevice::open().map_err(|e| format!("Failed to open device: {}", e))?;
+/// Installs the tracing subscriber, which also collects `log` records from
+/// dependencies. `verbose` enables span timings and trace-level output.
+fn init_tracing(verbose: bool) {
+    if verbose {
+        tracing_subscriber::fmt()
+            .pretty()
+            .with_span_events(FmtSpan::CLOSE)
+            .with_env_filter(EnvFilter::new("info,litra_glow=trace"))
+            .with_writer(std::io::stderr)
+            .init();
+    } else {
+        let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+        tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_writer(std::io::stderr)
+            .init();
+    }
+}
+
+/// Why a CLI run failed: the message to print, and the exit code and kind,
+/// which tell device errors apart.
+#[derive(Debug)]
+struct Failure {
+    message: String,
+    code: i32,
+    kind: &'static str,
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            code: 1,
+            kind: "other",
+        }
+    }
+}
+
+impl From<usb::Error> for Failure {
+    fn from(e: usb::Error) -> Self {
+        Self {
+            message: e.to_string(),
+            code: e.exit_code(),
+            kind: e.kind(),
+        }
+    }
+}
+
+/// Reports `failure` on stderr, as an [`schema::ErrorReport`] when `json`
+/// is set, and exits with its code.
+fn exit_with(json: bool, failure: Failure) -> ! {
+    if json {
+        let report = schema::ErrorReport::new(failure.message, failure.kind);
+        eprintln!("{}", schema::to_json(&report));
+    } else {
+        eprintln!("{}", msg!("error", message = failure.message));
+    }
+    std::process::exit(failure.code);
+}
+
+impl From<Failure> for String {
+    fn from(failure: Failure) -> Self {
+        failure.message
+    }
+}
+
+/// Opens the lamp with the calibration saved for its serial number.
+fn open_controller(cli: &Cli) -> Result<Controller, Failure> {
+    let device = open_device(cli).map_err(|e| Failure {
+        message: msg!("error-open-device", error = e.to_string()),
+        code: e.exit_code(),
+        kind: e.kind(),
+    })?;
+    let controller = Controller::new(device);
+    if cli.raw {
+        return Ok(controller);
+    }
+    let config = load_config()?;
+    let calibration = config.calibration(controller.serial_number().as_deref());
+    Ok(controller
+        .with_calibration(calibration)
+        .with_floor(config.brightness.floor(cli.min_floor)))
+}
+
+/// Opens the lamp, with `--wait` looking for it again until it is
+/// connected or the wait is over. The device list is refreshed on the
+/// shared HID context each time rather than set up anew.
+fn open_device(cli: &Cli) -> Result<LitraDevice, usb::Error> {
+    let options = cli.device_options();
+    let Some(seconds) = cli.wait else {
+        return LitraDevice::open(&options);
+    };
+    let deadline = (seconds > 0).then(|| Instant::now() + Duration::from_secs(seconds));
+    info!("Waiting for the lamp to be connected");
+    loop {
+        match LitraDevice::open(&options) {
+            Err(usb::Error::DeviceNotFound(_) | usb::Error::NoMatch(_))
+                if deadline.is_none_or(|deadline| Instant::now() < deadline) =>
+            {
+                thread::sleep(WAIT_POLL);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Prints the reports the lamp sends that do not parse as anything known,
+/// one hex line each, for adding support for new models and buttons.
+fn run_dump_unknown(cli: &Cli) -> Result<(), Failure> {
+    let controller = open_controller(cli)?;
+    loop {
+        if let Some(Response::Unknown { report }) =
+            controller.read_blocking(Duration::from_secs(1))?
+        {
+            println!("{}", trace::encode_hex(&report));
+        }
+    }
+}
+
+fn run_headless(cli: Cli) -> Result<(), Failure> {
+    if let Some(name) = &cli.group {
+        return run_group(&cli, name);
+    }
+    if cli.all {
+        return run_all(&cli);
+    }
+    let controller = open_controller(&cli)?;
+    let config = load_config()?;
+    if let Some(name) = &cli.preset {
+        apply_preset(&controller, &config, name)?;
+    }
+
+    let model = controller.model();
+    let scale = config.brightness.scale(cli.perceptual);
+    let mut brightness = match cli.brightness {
+        Some(input) if cli.raw => Some(raw_level(input, model)?),
+        input => input.map(|input| brightness_level(input, model, scale, 1.0)),
+    };
+    let mut temperature = cli.temperature().map(snap_temperature);
+    if cli.cycle_brightness || cli.cycle_temperature {
+        (brightness, temperature) = cycle_targets(&cli, &controller, brightness, temperature)?;
+    }
+    let mut stepped = None;
+    if let Some(step) = cli.brightness_step() {
+        let (level, percent) = step_brightness(&cli, &controller, scale, step)?;
+        brightness = Some(level);
+        stepped = Some(percent);
+    }
+    if let Some(shift) = cli.temperature_shift() {
+        temperature = Some(shift_temperature(&cli, &controller, shift)?);
+    }
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let interrupted_handler = Arc::clone(&interrupted);
+    let _ = ctrlc::set_handler(move || interrupted_handler.store(true, Ordering::SeqCst));
+
+    // Ctrl-C during the fade leaves the lamp at the last value written.
+    if let Some(fade) = cli.fade
+        && !fade.is_zero()
+        && (brightness.is_some() || temperature.is_some())
+    {
+        crossfade(
+            &controller,
+            brightness,
+            temperature,
+            fade,
+            scale,
+            cli.timeout,
+            &interrupted,
+        )?;
+    }
+
+    let mut commands = Vec::new();
+    commands.extend(brightness.map(Command::SetBrightness));
+    commands.extend(temperature.map(Command::SetTemperature));
+    // Toggling reads the lamp right before writing, after the other
+    // settings, so power still goes last.
+    let power = if cli.toggle {
+        None
+    } else if cli.on {
+        Some(true)
+    } else if cli.off {
+        Some(false)
+    } else if cli.and_on && controller.toggled_power()? == Some(true) {
+        Some(true)
+    } else {
+        None
+    };
+    commands.extend(power.map(Command::SetPower));
+
+    if interrupted.load(Ordering::SeqCst) {
+        return Err(msg!("error-interrupted").into());
+    }
+    for cmd in controller.apply_all(&commands)? {
+        eprintln!(
+            "{}",
+            msg!("error-not-confirmed", command = format!("{:?}", cmd))
+        );
+    }
+    if cli.toggle {
+        let on = controller.toggle_power()?;
+        debug!("Toggled power to {}", on);
+    }
+    if !cli.status {
+        if let Some(percent) = stepped {
+            println!("{}", percent);
+        }
+        if cli.temperature_shift().is_some()
+            && let Some(kelvin) = temperature
+        {
+            println!("{}", kelvin);
+        }
+    }
+
+    if let Some(name) = &cli.save_preset {
+        let scene = save_preset(&cli, &controller, name)?;
+        if !cli.status {
+            println!(
+                "{}",
+                msg!(
+                    "preset-saved",
+                    name = name.as_str(),
+                    power = if scene.power == Some(true) {
+                        "on"
+                    } else {
+                        "off"
+                    },
+                    brightness = scene.brightness,
+                    temperature = scene.temperature
+                )
+            );
+        }
+    }
+
+    if cli.status {
+        let status = lamp_status(&controller, &load_config()?, &cli)?;
+        println!("{}", schema::to_json(&status));
+    }
+
+    Ok(())
+}
+
+/// Reads the lamp's state and saves it as the scene `name`, replacing one
+/// of the same name. Nothing is saved unless all three values were read.
+fn save_preset(cli: &Cli, controller: &Controller, name: &str) -> Result<Scene, Failure> {
+    let state = controller.read_state(Duration::from_millis(cli.timeout))?;
+    let (Some(power), Some(brightness), Some(temperature)) =
+        (state.power, state.brightness, state.temperature)
+    else {
+        let unread = [
+            ("power", state.power.is_none()),
+            ("brightness", state.brightness.is_none()),
+            ("temperature", state.temperature.is_none()),
+        ]
+        .into_iter()
+        .filter_map(|(field, missing)| missing.then_some(field))
+        .collect::<Vec<_>>()
+        .join(", ");
+        let timeout = Failure::from(usb::Error::ReadTimeout);
+        return Err(Failure {
+            message: msg!("error-state-unread", fields = unread),
+            ..timeout
+        });
+    };
+    let scene = Scene {
+        brightness: brightness_to_percent(brightness, controller.model()),
+        temperature: snap_temperature(temperature),
+        power: Some(power),
+    };
+    let store = SceneStore::open_default().ok_or_else(|| msg!("error-no-config-dir"))?;
+    store.set(name, scene).map_err(|e| e.to_string())?;
+    Ok(scene)
+}
+
+/// Applies the scene called `name` in one go: brightness, temperature,
+/// then power, each confirmed.
+fn apply_preset(controller: &Controller, config: &Config, name: &str) -> Result<(), Failure> {
+    let store = SceneStore::open_default().ok_or_else(|| msg!("error-no-config-dir"))?;
+    let scene = load_scene(&store, name)?;
+    let mut commands = vec![
+        Command::SetBrightness(percent_to_brightness(scene.brightness, controller.model())),
+        Command::SetTemperature(snap_temperature(scene.temperature)),
+    ];
+    commands.extend(scene_power(controller, &scene, config)?.map(Command::SetPower));
+    for cmd in controller.apply_all(&commands)? {
+        eprintln!(
+            "{}",
+            msg!("error-not-confirmed", command = format!("{:?}", cmd))
+        );
+    }
+    Ok(())
+}
+
+/// The level and percentage `step` percent away from the lamp's current
+/// brightness on `scale`, clamped between the brightness floor and 100%.
+fn step_brightness(
+    cli: &Cli,
+    controller: &Controller,
+    scale: BrightnessScale,
+    step: i16,
+) -> Result<(u16, u8), Failure> {
+    let model = controller.model();
+    let timeout = Duration::from_millis(cli.timeout);
+    let Some(Response::Brightness(level, _)) = controller.query(Command::GetBrightness, timeout)?
+    else {
+        return Err(usb::Error::ReadTimeout.into());
+    };
+    let current = scale.level_to_percent(level, model) as i16;
+    let floor = scale.level_to_percent(controller.floor_level(), model) as i16;
+    let percent = (current + step).clamp(floor, 100) as u8;
+    Ok((scale.percent_to_level(percent, model), percent))
+}
+
+/// The lamp's current temperature moved by `shift` kelvin, snapped to the
+/// step grid and clamped to the model's range; reaching either end is not
+/// an error.
+fn shift_temperature(cli: &Cli, controller: &Controller, shift: i32) -> Result<u16, Failure> {
+    let timeout = Duration::from_millis(cli.timeout);
+    let Some(Response::Temperature(kelvin, _)) =
+        controller.query(Command::GetTemperature, timeout)?
+    else {
+        return Err(usb::Error::ReadTimeout.into());
+    };
+    Ok(clamp_temperature(
+        (kelvin as i32 + shift) as f32,
+        controller.model(),
+    ))
+}
+
+/// Prints the one value `field` of the lamp, read with a query.
+fn run_get(cli: &Cli, field: Field) -> Result<(), Failure> {
+    let controller = open_controller(cli)?;
+    let query = match field {
+        Field::Power => Command::GetPower,
+        Field::Brightness => Command::GetBrightness,
+        Field::Temperature => Command::GetTemperature,
+    };
+    let value = match controller.query(query, Duration::from_millis(cli.timeout))? {
+        Some(Response::Power(on, _)) => if on { "on" } else { "off" }.to_string(),
+        Some(Response::Brightness(level, _)) if cli.raw => level.to_string(),
+        Some(Response::Brightness(level, _)) => {
+            let scale = load_config()?.brightness.scale(cli.perceptual);
+            scale
+                .level_to_percent(level, controller.model())
+                .to_string()
+        }
+        Some(Response::Temperature(kelvin, _)) => kelvin.to_string(),
+        _ => return Err(usb::Error::ReadTimeout.into()),
+    };
+    println!("{}", value);
+    Ok(())
+}
+
+/// The `--status` report for `controller`, waiting up to `--timeout` for
+/// the state to be read. The name is only asked for with `--verbose`.
+fn lamp_status(
+    controller: &Controller,
+    config: &Config,
+    cli: &Cli,
+) -> Result<schema::Status, usb::Error> {
+    let model = controller.model();
+    let effective = controller.read_device_state(Duration::from_millis(cli.timeout))?;
+    let nominal = controller.nominal_snapshot(effective);
+    let on_seconds = config.on_seconds(controller.serial_number().as_deref());
+    let percent = |level| brightness_to_percent(level, model);
+    let identity = controller.info()?;
+    let name = if cli.verbose {
+        controller.name()?
+    } else {
+        None
+    };
+    let lumens = effective
+        .brightness
+        .map(|level| protocol::raw_to_lumens(level, &model.capabilities()));
+    Ok(schema::Status::new(
+        model.id(),
+        nominal.power,
+        nominal.brightness.map(percent),
+        nominal.temperature,
+        effective.brightness.map(percent),
+        effective.temperature,
+        usage::hours(on_seconds),
+    )
+    .with_lumens(lumens)
+    .with_temperature_percent(
+        nominal
+            .temperature
+            .map(protocol::kelvin_to_temperature_percent),
+    )
+    .with_identity(identity.serial, identity.firmware)
+    .with_name(name)
+    .with_raw_brightness(effective.brightness.filter(|_| cli.raw)))
+}
+
+/// Applies the CLI settings to every member of the group `name`, with
+/// brightness scaled by each member's ratio.
+fn run_group(cli: &Cli, name: &str) -> Result<(), Failure> {
+    let config = load_config()?;
+    let group = config
+        .groups
+        .get(name)
+        .filter(|group| !group.members.is_empty())
+        .ok_or_else(|| msg!("error-unknown-group", name = name))?;
+    let members = group
+        .members
+        .iter()
+        .map(|(serial, &ratio)| Member {
+            label: serial.clone(),
+            device: LitraDevice::open(&DeviceOptions {
+                serial: Some(serial.clone()),
+                path: None,
+                selector: None,
+                trace_out: cli
+                    .trace_out
+                    .as_deref()
+                    .map(|path| trace::lamp_path(path, serial)),
+                ..cli.device_options()
+            }),
+            ratio,
+        })
+        .collect();
+    apply_to_members(cli, &config, members)
+}
+
+/// Applies the CLI settings to every connected lamp alike.
+fn run_all(cli: &Cli) -> Result<(), Failure> {
+    let config = load_config()?;
+    let usb::OpenedLamps { opened, failed } = LitraDevice::open_all(&cli.device_options())?;
+    if opened.is_empty() && failed.is_empty() {
+        return Err(usb::Error::DeviceNotFound(None).into());
+    }
+    let opened = opened.into_iter().map(|device| Member {
+        label: device
+            .serial_number()
+            .or_else(|| device.device_info().map(|info| info.path.clone()))
+            .unwrap_or_else(|| device.model().name().to_string()),
+        device: Ok(device),
+        ratio: 1.0,
+    });
+    let failed = failed.into_iter().map(|(label, e)| Member {
+        label,
+        device: Err(e),
+        ratio: 1.0,
+    });
+    apply_to_members(cli, &config, opened.chain(failed).collect())
+}
+
+/// A lamp driven by a multi-lamp run, named by `label` in messages.
+struct Member {
+    label: String,
+    /// The opened lamp, or why it could not be opened.
+    device: Result<LitraDevice, usb::Error>,
+    /// Share of the requested brightness this lamp runs at.
+    ratio: f32,
+}
+
+/// Applies the CLI settings to each member. Temperature is the same for
+/// all, and `--toggle` follows the first member so the lamps end up
+/// together. A member that cannot be reached does not stop the others.
+/// With `--status`, prints a JSON array with one status per reachable
+/// member. When any member fails, the run fails with the exit code of the
+/// first member's error.
+fn apply_to_members(cli: &Cli, config: &Config, members: Vec<Member>) -> Result<(), Failure> {
+    let scale = config.brightness.scale(cli.perceptual);
+    let temperature = cli.temperature().map(snap_temperature);
+    let mut power = if cli.on {
+        Some(true)
+    } else if cli.off {
+        Some(false)
+    } else {
+        None
+    };
+
+    let mut failed = Vec::new();
+    let mut cause = None;
+    let mut statuses = Vec::new();
+    for member in members {
+        let controller = match member.device {
+            Ok(device) => {
+                let calibration = config.calibration(device.serial_number().as_deref());
+                Controller::new(device)
+                    .with_calibration(calibration)
+                    .with_floor(config.brightness.floor(cli.min_floor))
+            }
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    msg!(
+                        "error-lamp-open",
+                        serial = member.label.as_str(),
+                        error = e.to_string()
+                    )
+                );
+                failed.push(member.label);
+                cause.get_or_insert(e);
+                continue;
+            }
+        };
+        if cli.toggle && power.is_none() {
+            power = controller.toggled_power()?;
+        }
+        let brightness = cli
+            .brightness
+            .map(|input| brightness_level(input, controller.model(), scale, member.ratio));
+
+        let mut commands = Vec::new();
+        commands.extend(brightness.map(Command::SetBrightness));
+        commands.extend(temperature.map(Command::SetTemperature));
+        commands.extend(power.map(Command::SetPower));
+        let unconfirmed = match controller.apply_all(&commands) {
+            Ok(unconfirmed) => unconfirmed,
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    msg!(
+                        "error-lamp-failed",
+                        serial = member.label.as_str(),
+                        error = e.to_string()
+                    )
+                );
+                failed.push(member.label);
+                cause.get_or_insert(e);
+                continue;
+            }
+        };
+        for cmd in unconfirmed {
+            eprintln!(
+                "{}",
+                msg!(
+                    "error-lamp-not-confirmed",
+                    serial = member.label.as_str(),
+                    command = format!("{:?}", cmd)
+                )
+            );
+        }
+        if cli.status {
+            match lamp_status(&controller, config, cli) {
+                Ok(status) => statuses.push(status),
+                Err(e) => {
+                    eprintln!(
+                        "{}",
+                        msg!(
+                            "error-lamp-failed",
+                            serial = member.label.as_str(),
+                            error = e.to_string()
+                        )
+                    );
+                    failed.push(member.label);
+                    cause.get_or_insert(e);
+                }
+            }
+        }
+    }
+    if cli.status {
+        println!("{}", schema::to_json(&statuses));
+    }
+
+    match cause {
+        None => Ok(()),
+        Some(cause) => Err(Failure {
+            message: msg!("error-unreachable", serials = failed.join(", ")),
+            code: cause.exit_code(),
+            kind: cause.kind(),
+        }),
+    }
+}
+
+/// Reads the saved settings, falling back to the defaults when there is no
+/// config directory.
+fn load_config() -> Result<Config, String> {
+    match ConfigStore::open_default() {
+        Some(store) => store.load().map_err(|e| e.to_string()),
+        None => Ok(Config::default()),
+    }
+}
+
+/// Prints the calibration of the connected lamp, or changes it and
+/// re-applies the current values so the new offsets show right away.
+fn run_calibrate(
+    cli: &Cli,
+    temperature_offset: Option<i16>,
+    brightness_offset: Option<i8>,
+) -> Result<(), String> {
+    let controller = open_controller(cli)?;
+    let serial = controller
+        .serial_number()
+        .ok_or_else(|| msg!("error-no-serial"))?;
+    let mut calibration = controller.calibration();
+    if temperature_offset.is_none() && brightness_offset.is_none() {
+        print_calibration(&serial, calibration);
+        return Ok(());
+    }
+
+    let current = controller
+        .read_state(Duration::from_millis(cli.timeout))
+        .map_err(|e| e.to_string())?;
+    calibration.temperature_offset_k =
+        temperature_offset.unwrap_or(calibration.temperature_offset_k);
+    calibration.brightness_offset_percent =
+        brightness_offset.unwrap_or(calibration.brightness_offset_percent);
+    let store = ConfigStore::open_default().ok_or_else(|| msg!("error-no-config-dir"))?;
+    store
+        .update(|config| {
+            let device = config.devices.entry(serial.clone()).or_default();
+            device.calibration = calibration;
+            if device.is_default() {
+                config.devices.remove(&serial);
+            }
+        })
+        .map_err(|e| e.to_string())?;
+
+    let controller = controller.with_calibration(calibration);
+    let commands = current
+        .brightness
+        .map(Command::SetBrightness)
+        .into_iter()
+        .chain(current.temperature.map(Command::SetTemperature));
+    for cmd in commands {
+        if !controller.apply(cmd).map_err(|e| e.to_string())? {
+            eprintln!(
+                "{}",
+                msg!("error-not-confirmed", command = format!("{:?}", cmd))
+            );
+        }
+    }
+    print_calibration(&serial, calibration);
+    Ok(())
+}
+
+/// Prints what importing `path` would do and, with `apply`, saves the
+/// scenes. Existing scenes of the same name are replaced.
+fn run_import(from: ImportFrom, path: &Path, apply: bool) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| {
+        msg!(
+            "error-read-file",
+            path = path.display().to_string(),
+            error = e.to_string()
+        )
+    })?;
+    let plan = match from {
+        ImportFrom::LitraAutotoggle => import::litra_autotoggle(&text),
+        ImportFrom::LitraAliases => import::litra_aliases(&text),
+        ImportFrom::GhubProfile => import::ghub_profile(&text)?,
+    };
+    let store = SceneStore::open_default().ok_or_else(|| msg!("error-no-config-dir"))?;
+    let existing = store.load().map_err(|e| e.to_string())?;
+
+    for (name, scene) in &plan.scenes {
+        let (name, brightness, temperature) = (name.as_str(), scene.brightness, scene.temperature);
+        let line = if existing.contains_key(name) {
+            msg!(
+                "import-replace",
+                name = name,
+                brightness = brightness,
+                temperature = temperature
+            )
+        } else {
+            msg!(
+                "import-add",
+                name = name,
+                brightness = brightness,
+                temperature = temperature
+            )
+        };
+        println!("{}", line);
+    }
+    if !plan.unmapped.is_empty() {
+        println!("{}", msg!("import-not-imported"));
+        for unmapped in &plan.unmapped {
+            println!("  {}", unmapped);
+        }
+    }
+    if plan.is_empty() {
+        println!("{}", msg!("import-nothing"));
+        return Ok(());
+    }
+    if !apply {
+        println!("{}", msg!("import-dry-run"));
+        return Ok(());
+    }
+    for (name, scene) in &plan.scenes {
+        store.set(name, *scene).map_err(|e| e.to_string())?;
+    }
+    println!("{}", msg!("import-done", count = plan.scenes.len()));
+    Ok(())
+}
+
+/// Prints the connected lamps in `format`, or the supported models when
+/// there are none. Returns whether any lamp was found.
+fn run_devices(format: OutputFormat) -> Result<bool, String> {
+    let devices = LitraDevice::list().map_err(|e| e.to_string())?;
+    if let OutputFormat::Json = format {
+        let devices: Vec<schema::Device> = devices
+            .iter()
+            .enumerate()
+            .map(|(index, device)| {
+                schema::Device::new(
+                    index,
+                    device.model.id(),
+                    device.product.clone(),
+                    device.serial.clone(),
+                    device.path.clone(),
+                )
+            })
+            .collect();
+        println!("{}", schema::to_json(&devices));
+        return Ok(!devices.is_empty());
+    }
+    if devices.is_empty() {
+        println!("{}", msg!("devices-none"));
+        println!("{}", msg!("devices-supported"));
+        for (product_id, model) in KNOWN_DEVICES {
+            let id = format!("{VENDOR_ID:04x}:{product_id:04x}");
+            println!(
+                "{}",
+                msg!("devices-supported-line", id = id, model = model.name())
+            );
+        }
+        return Ok(false);
+    }
+    for (index, device) in devices.into_iter().enumerate() {
+        let serial = device.serial.unwrap_or_else(|| msg!("devices-no-serial"));
+        println!(
+            "{}",
+            msg!(
+                "devices-line",
+                index = index,
+                model = device.model.name(),
+                serial = serial,
+                path = device.path
+            )
+        );
+    }
+    Ok(true)
+}
+
+fn run_stats(command: &StatsCommand) -> Result<(), String> {
+    let store = ConfigStore::open_default().ok_or_else(|| msg!("error-no-config-dir"))?;
+    let config = store.load().map_err(|e| e.to_string())?;
+    match command {
+        StatsCommand::Show => {
+            let counted: Vec<_> = config
+                .devices
+                .iter()
+                .filter(|(_, device)| device.on_seconds > 0)
+                .collect();
+            if counted.is_empty() {
+                println!("{}", msg!("stats-none"));
+            }
+            for (serial, device) in counted {
+                let hours = format!("{:.1}", usage::hours(device.on_seconds));
+                println!(
+                    "{}",
+                    msg!("stats-line", serial = serial.as_str(), hours = hours)
+                );
+            }
+        }
+        StatsCommand::Reset { serial, yes } => {
+            if let Some(serial) = serial
+                && config.on_seconds(Some(serial)) == 0
+            {
+                return Err(msg!("stats-not-recorded", serial = serial.as_str()));
+            }
+            let target = match serial {
+                Some(serial) => msg!("stats-target-lamp", serial = serial.as_str()),
+                None => msg!("stats-target-all"),
+            };
+            if !yes && !confirm(&msg!("stats-reset-question", target = target.as_str()))? {
+                println!("{}", msg!("nothing-changed"));
+                return Ok(());
+            }
+            store
+                .update(|config| {
+                    for (name, device) in config.devices.iter_mut() {
+                        if serial.as_ref().is_none_or(|serial| serial == name) {
+                            device.on_seconds = 0;
+                        }
+                    }
+                    config.devices.retain(|_, device| !device.is_default());
+                })
+                .map_err(|e| e.to_string())?;
+            println!("{}", msg!("stats-reset-done", target = target));
+        }
+    }
+    Ok(())
+}
+
+/// Asks a yes/no question on the terminal; anything but yes is a no.
+fn confirm(question: &str) -> Result<bool, String> {
+    print!("{} ", msg!("confirm-prompt", question = question));
+    io::stdout().flush().map_err(|e| e.to_string())?;
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .map_err(|e| e.to_string())?;
+    let answer = answer.trim().to_lowercase();
+    Ok(msg!("confirm-yes")
+        .split(',')
+        .any(|yes| !answer.is_empty() && yes.trim() == answer))
+}
+
+fn print_calibration(serial: &str, calibration: Calibration) {
+    let line = msg!(
+        "calibration",
+        serial = serial,
+        temperature = format!("{:+}", calibration.temperature_offset_k),
+        brightness = format!("{:+}", calibration.brightness_offset_percent)
+    );
+    println!("{}", line);
+}
+
+/// Replaces the targets of the requested cycle flags with the entry after the
+/// lamp's current value, fading there first when the cycle has a fade time.
+fn cycle_targets(
+    cli: &Cli,
+    controller: &Controller,
+    brightness: Option<u16>,
+    temperature: Option<u16>,
+) -> Result<(Option<u16>, Option<u16>), Failure> {
+    let config = load_config()?;
+    let cycle = config.cycle;
+    let current = controller.read_state(Duration::from_millis(cli.timeout))?;
+    let (Some(from_brightness), Some(from_temperature)) = (current.brightness, current.temperature)
+    else {
+        return Err(usb::Error::ReadTimeout.into());
+    };
+
+    let brightness = if cli.cycle_brightness {
+        cycle.next_brightness(from_brightness, controller.model())
+    } else {
+        brightness
+    };
+    let temperature = if cli.cycle_temperature {
+        cycle.next_temperature(from_temperature)
+    } else {
+        temperature
+    };
+    let fade = Duration::from_millis(cycle.fade_ms);
+    if !fade.is_zero() {
+        crossfade(
+            controller,
+            Some(brightness.unwrap_or(from_brightness)),
+            Some(temperature.unwrap_or(from_temperature)),
+            fade,
+            config.brightness.scale(cli.perceptual),
+            cli.timeout,
+            &AtomicBool::new(false),
+        )?;
+    }
+    Ok((brightness, temperature))
+}
+
+/// Applies `change` to the saved alarm settings and returns the result.
+/// Failures are logged, since callers have nowhere to report them.
+fn update_alarm(change: impl FnOnce(&mut AlarmConfig)) -> Option<AlarmConfig> {
+    let store = ConfigStore::open_default()?;
+    match store.update(|config| change(&mut config.alarm)) {
+        Ok(config) => Some(config.alarm),
+        Err(e) => {
+            warn!("Failed to save alarm settings: {}", e);
+            None
+        }
+    }
+}
+
+fn skip_next_alarm(alarm: &mut AlarmConfig) {
+    alarm.skip_date = alarm
+        .next_after(Local::now())
+        .map(|start| start.date_naive());
+}
+
+/// Parses a brightness percentage, or lumens with an `lm` suffix.
+fn parse_brightness(text: &str) -> Result<BrightnessInput, String> {
+    let invalid = || msg!("error-invalid-brightness", text = text);
+    match text.strip_suffix("lm") {
+        Some(lumens) => lumens
+            .trim()
+            .parse()
+            .map(BrightnessInput::Lumens)
+            .map_err(|_| invalid()),
+        None => text
+            .parse()
+            .map(BrightnessInput::Percent)
+            .map_err(|_| invalid()),
+    }
+}
+
+/// The level of a `model` lamp for `--brightness`, scaled by a group
+/// member's `ratio`. Lumens outside the model's range are clamped with a
+/// warning.
+fn brightness_level(
+    input: BrightnessInput,
+    model: Model,
+    scale: BrightnessScale,
+    ratio: f32,
+) -> u16 {
+    match input {
+        BrightnessInput::Percent(percent) => {
+            scale.percent_to_level(scale_percent(percent.min(100) as u8, ratio), model)
+        }
+        BrightnessInput::Lumens(lumens) => {
+            let lumens = (lumens as f32 * ratio).round().clamp(0.0, u16::MAX as f32) as u16;
+            let level = protocol::lumens_to_raw(lumens, &model.capabilities());
+            if level != lumens {
+                eprintln!(
+                    "{}",
+                    msg!(
+                        "error-lumens-clamped",
+                        lumens = lumens,
+                        model = model.name(),
+                        level = level
+                    )
+                );
+            }
+            level
+        }
+    }
+}
+
+/// The level for `--brightness` with `--raw`, written as given. Levels
+/// outside the model's range are refused rather than clamped.
+fn raw_level(input: BrightnessInput, model: Model) -> Result<u16, Failure> {
+    let BrightnessInput::Percent(level) = input else {
+        return Err(Failure {
+            message: msg!("error-raw-lumens"),
+            code: usb::INVALID_INPUT_EXIT_CODE,
+            kind: "out-of-range",
+        });
+    };
+    if !model.capabilities().brightness_range().contains(&level) {
+        return Err(usb::Error::from(protocol::ProtocolError::Brightness { level, model }).into());
+    }
+    Ok(level)
+}
+
+/// Value parser for temperatures, see [`parse_temperature`]. It also offers
+/// the preset names to shell completion; they are hidden from `--help`,
+/// which would otherwise list them as the only values.
+#[derive(Clone)]
+struct TemperatureParser;
+
+impl TypedValueParser for TemperatureParser {
+    type Value = u16;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&clap::Arg>,
+        value: &std::ffi::OsStr,
+    ) -> Result<u16, clap::Error> {
+        parse_temperature.parse_ref(cmd, arg, value)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(TemperaturePreset::ALL.into_iter().map(|preset| {
+            PossibleValue::new(preset.name()).help(format!("{}K", preset.kelvin()))
+        })))
+    }
+}
+
+/// Parses a color temperature in kelvin, with or without a `K` suffix, in
+/// mireds with a `mired` suffix, or as a preset name. Mireds are converted and snapped onto the kelvin
+/// step grid. Kelvin outside the range every model supports are refused.
+fn parse_temperature(text: &str) -> Result<u16, String> {
+    let invalid = || {
+        msg!(
+            "error-invalid-temperature",
+            text = text,
+            presets = TemperaturePreset::names()
+        )
+    };
+    if let Some(mired) = text.strip_suffix("mired") {
+        let mired = mired.trim().parse().map_err(|_| invalid())?;
+        return Ok(snap_temperature(protocol::mired_to_kelvin(mired)));
+    }
+    let kelvin = text.strip_suffix(['K', 'k']).unwrap_or(text).trim();
+    let Ok(kelvin) = kelvin.parse::<u16>() else {
+        return text
+            .parse::<TemperaturePreset>()
+            .map(TemperaturePreset::kelvin)
+            .map_err(|_| invalid());
+    };
+    if !(MIN_TEMPERATURE..=MAX_TEMPERATURE).contains(&kelvin) {
+        return Err(msg!(
+            "error-temperature-outside",
+            kelvin = kelvin,
+            min = MIN_TEMPERATURE,
+            max = MAX_TEMPERATURE,
+            presets = TemperaturePreset::names()
+        ));
+    }
+    Ok(kelvin)
+}
+
+fn parse_time_of_day(text: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(text, "%H:%M")
+        .map_err(|e| msg!("error-invalid-time", text = text, error = e.to_string()))
+}
 
-    if cli.status {
-        device.send(Command::GetPower).map_err(|e| e.to_string())?;
-        thread::sleep(Duration::from_millis(100));
-        device
-            .send(Command::GetBrightness)
-            .map_err(|e| e.to_string())?;
-        thread::sleep(Duration::from_millis(100));
-        device
-            .send(Command::GetTemperature)
-            .map_err(|e| e.to_string())?;
-
-        let mut power = None;
-        let mut brightness = None;
-        let mut temperature = None;
-
-        for _ in 0..10 {
-            thread::sleep(Duration::from_millis(50));
-            while let Ok(Some(response)) = device.try_read() {
-                match response {
-                    Response::Power(on, _) => power = Some(on),
-                    Response::Brightness(level, _) => brightness = Some(level),
-                    Response::Temperature(temp, _) => temperature = Some(temp),
+fn run_alarm(command: &AlarmCommand) -> Result<(), String> {
+    let store = ConfigStore::open_default().ok_or_else(|| msg!("error-no-config-dir"))?;
+    let config = store
+        .update(|config| {
+            let alarm = &mut config.alarm;
+            match command {
+                AlarmCommand::Show => {}
+                AlarmCommand::Set {
+                    time,
+                    days,
+                    ramp,
+                    brightness,
+                    temperature,
+                    missed,
+                } => {
+                    alarm.time = time.unwrap_or(alarm.time);
+                    alarm.days = days.clone().unwrap_or_else(|| alarm.days.clone());
+                    alarm.ramp_seconds = ramp.map_or(alarm.ramp_seconds, |ramp| ramp.as_secs());
+                    alarm.brightness = brightness.map_or(alarm.brightness, |b| b.min(100));
+                    alarm.temperature = temperature.map_or(alarm.temperature, snap_temperature);
+                    alarm.missed = missed.unwrap_or(alarm.missed);
+                    alarm.armed = true;
                 }
+                AlarmCommand::Arm => alarm.armed = true,
+                AlarmCommand::Disarm => alarm.armed = false,
+                AlarmCommand::SkipNext => skip_next_alarm(alarm),
             }
-            if power.is_some() && brightness.is_some() && temperature.is_some() {
-                break;
-            }
-        }
-
-        let brightness_pct =
-            brightness.map(|b| ((b - MIN_BRIGHTNESS) * 100) / (MAX_BRIGHTNESS - MIN_BRIGHTNESS));
+        })
+        .map_err(|e| e.to_string())?;
+
+    let alarm = &config.alarm;
+    let days: Vec<String> = alarm.days.iter().map(Weekday::to_string).collect();
+    let summary = msg!(
+        "alarm-summary",
+        armed = if alarm.armed { "yes" } else { "no" },
+        time = alarm.time.format("%H:%M").to_string(),
+        days = days.join(","),
+        brightness = alarm.brightness,
+        temperature = alarm.temperature,
+        minutes = alarm.ramp_seconds / 60,
+        missed = format!("{:?}", alarm.missed)
+    );
+    println!("{}", summary);
+    if let Some(next) = alarm.next_after(Local::now()) {
+        let time = next.format("%a %Y-%m-%d %H:%M").to_string();
+        println!("{}", msg!("alarm-next", time = time));
+    }
+    Ok(())
+}
 
-        fn fmt_opt<T: std::fmt::Display>(opt: Option<T>) -> String {
-            opt.map_or("null".to_string(), |v| v.to_string())
-        }
-        println!(
-            "{{\"power\":{},\"brightness\":{},\"temperature\":{}}}",
-            fmt_opt(power),
-            fmt_opt(brightness_pct),
-            fmt_opt(temperature)
-        );
+/// The scene called `name`; the error for an unknown name lists the saved
+/// ones.
+fn load_scene(store: &SceneStore, name: &str) -> Result<Scene, String> {
+    let scenes = store.load().map_err(|e| e.to_string())?;
+    scenes.get(name).copied().ok_or_else(|| {
+        let names = if scenes.is_empty() {
+            msg!("scenes-none")
+        } else {
+            scenes.keys().cloned().collect::<Vec<_>>().join(", ")
+        };
+        msg!("error-unknown-scene", name = name, names = names)
+    })
+}
 
-        return Ok(());
+/// The power to switch to for `scene`: its own, else on when scenes turn
+/// an unlit lamp on and this one reports being off.
+fn scene_power(
+    controller: &Controller,
+    scene: &Scene,
+    config: &Config,
+) -> Result<Option<bool>, usb::Error> {
+    if scene.power.is_some() {
+        return Ok(scene.power);
     }
+    // Toggling an unlit lamp would turn it on.
+    Ok((config.scenes.power_on && controller.toggled_power()? == Some(true)).then_some(true))
+}
 
-    if cli.toggle {
-        device.send(Command::GetPower).map_err(|e| e.to_string())?;
-        thread::sleep(Duration::from_millis(100));
-        if let Ok(Some(Response::Power(on, _))) = device.try_read() {
-            device
-                .send(Command::SetPower(!on))
-                .map_err(|e| e.to_string())?;
+fn run_scene(cli: &Cli, command: &SceneCommand) -> Result<(), String> {
+    let store = SceneStore::open_default().ok_or_else(|| msg!("error-no-config-dir"))?;
+    match command {
+        SceneCommand::Set {
+            name,
+            brightness,
+            temperature,
+        } => {
+            let scene = Scene {
+                brightness: (*brightness).min(100),
+                temperature: snap_temperature(*temperature),
+                power: None,
+            };
+            store.set(name, scene).map_err(|e| e.to_string())
+        }
+        SceneCommand::List => {
+            let scenes = store.load().map_err(|e| e.to_string())?;
+            for (name, scene) in scenes {
+                println!(
+                    "{}",
+                    msg!(
+                        "scene-line",
+                        name = name.as_str(),
+                        brightness = scene.brightness,
+                        temperature = scene.temperature
+                    )
+                );
+            }
+            Ok(())
+        }
+        SceneCommand::Apply { name, fade } => {
+            let scene = load_scene(&store, name)?;
+            let controller = open_controller(cli)?;
+            let config = load_config()?;
+            if let Some(on) =
+                scene_power(&controller, &scene, &config).map_err(|e| e.to_string())?
+            {
+                controller
+                    .apply(Command::SetPower(on))
+                    .map_err(|e| e.to_string())?;
+            }
+            let brightness = percent_to_brightness(scene.brightness, controller.model());
+            let temperature = snap_temperature(scene.temperature);
+            if !fade.is_zero() {
+                crossfade(
+                    &controller,
+                    Some(brightness),
+                    Some(temperature),
+                    *fade,
+                    config.brightness.scale(cli.perceptual),
+                    cli.timeout,
+                    &AtomicBool::new(false),
+                )?;
+            }
+            for cmd in [
+                Command::SetBrightness(brightness),
+                Command::SetTemperature(temperature),
+            ] {
+                if !controller.apply(cmd).map_err(|e| e.to_string())? {
+                    eprintln!(
+                        "{}",
+                        msg!("error-not-confirmed", command = format!("{:?}", cmd))
+                    );
+                }
+            }
+            Ok(())
         }
-    } else if cli.on {
-        device
-            .send(Command::SetPower(true))
-            .map_err(|e| e.to_string())?;
-    } else if cli.off {
-        device
-            .send(Command::SetPower(false))
-            .map_err(|e| e.to_string())?;
     }
+}
 
-    if let Some(percent) = cli.brightness {
-        let level = (percent as u16).clamp(0, 100);
-        let brightness = MIN_BRIGHTNESS + (level * (MAX_BRIGHTNESS - MIN_BRIGHTNESS) / 100);
-        device
-            .send(Command::SetBrightness(brightness))
-            .map_err(|e| e.to_string())?;
+/// Steps brightness and temperature from the lamp's current values towards
+/// the targets, brightness evenly on `scale`, sending both in turn. An
+/// axis without a target, or whose current value cannot be read, is left
+/// out. Stops where it is once `interrupted` is set.
+fn crossfade(
+    controller: &Controller,
+    brightness: Option<u16>,
+    temperature: Option<u16>,
+    duration: Duration,
+    scale: BrightnessScale,
+    timeout_ms: u64,
+    interrupted: &AtomicBool,
+) -> Result<(), String> {
+    let current = controller
+        .read_state(Duration::from_millis(timeout_ms))
+        .map_err(|e| e.to_string())?;
+    let brightness = current.brightness.zip(brightness);
+    let temperature = current.temperature.zip(temperature);
+    let mut transition = Transition::new(
+        Instant::now(),
+        duration,
+        brightness.unwrap_or_default(),
+        temperature.unwrap_or_default(),
+    );
+    if brightness.is_none() {
+        transition.drop_brightness();
     }
-
-    if let Some(temp) = cli.temperature {
-        let temp = temp.clamp(MIN_TEMPERATURE, MAX_TEMPERATURE);
-        device
-            .send(Command::SetTemperature(temp))
-            .map_err(|e| e.to_string())?;
+    if temperature.is_none() {
+        transition.drop_temperature();
+    }
+    if let BrightnessScale::Perceptual(gamma) = scale {
+        transition = transition.with_perceptual(gamma, controller.model().capabilities());
+    }
+    for (at, cmd) in Fade::new(transition) {
+        thread::sleep(at.saturating_duration_since(Instant::now()));
+        if interrupted.load(Ordering::SeqCst) {
+            return Err(msg!("error-interrupted"));
+        }
+        controller.send(cmd).map_err(|e| e.to_string())?;
     }
-
     Ok(())
 }
 
+/// clap's own error for `arg` given with `prior`, which it could not see.
+fn argument_conflict(command: &clap::Command, arg: String, prior: &clap::Arg) -> clap::Error {
+    let mut error = clap::Error::new(ErrorKind::ArgumentConflict).with_cmd(command);
+    error.insert(ContextKind::InvalidArg, ContextValue::String(arg));
+    error.insert(
+        ContextKind::PriorArg,
+        ContextValue::String(prior.to_string()),
+    );
+    error.insert(
+        ContextKind::Usage,
+        ContextValue::StyledStr(command.clone().render_usage()),
+    );
+    error
+}
+
+/// Parses the command line with the localized help and folds the
+/// subcommand into the flags, see [`Cli::fold_command`].
+fn parse_cli<I, T>(args: I) -> Result<Cli, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let mut command = i18n::localize_command(Cli::command());
+    let matches = command.try_get_matches_from_mut(args)?;
+    let mut cli = Cli::from_arg_matches(&matches)?;
+    cli.fold_command(&command, &matches)?;
+    Ok(cli)
+}
+
+/// Prints a command line parse error and exits, with
+/// [`usb::INVALID_INPUT_EXIT_CODE`] rather than clap's 2 so it is not
+/// mistaken for a missing lamp. `--help` and `--version` exit as usual.
+fn exit_usage(error: clap::Error) -> ! {
+    if !error.use_stderr() {
+        error.exit();
+    }
+    let _ = error.print();
+    std::process::exit(usb::INVALID_INPUT_EXIT_CODE);
+}
+
 fn main() -> Result<(), slint::PlatformError> {
     #[cfg(windows)]
     init_cli_console();
 
-    let cli = Cli::parse();
+    let args: Vec<String> = std::env::args_os()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let lang = i18n::requested_language(&args).or_else(|| {
+        load_config()
+            .ok()
+            .map(|config| config.language)
+            .filter(|lang| !lang.is_empty())
+    });
+    i18n::init(lang.as_deref());
+    let cli = parse_cli(&args).unwrap_or_else(|e| exit_usage(e));
+    if cli.status || cli.json {
+        // The JSON error payload is read by scripts.
+        i18n::use_base_locale();
+    }
+    let options = cli.device_options();
+
+    match &cli.command {
+        Some(CliCommand::Completions { shell }) => {
+            let mut command = i18n::localize_command(Cli::command());
+            let name = command.get_name().to_string();
+            clap_complete::generate(*shell, &mut command, name, &mut io::stdout());
+            return Ok(());
+        }
+        Some(CliCommand::Schema) => {
+            println!("{}", schema::document());
+            return Ok(());
+        }
+        Some(CliCommand::Doctor { format }) => {
+            let report = doctor::run(cli.serial.as_deref());
+            match *format {
+                OutputFormat::Text => doctor::print_text(&report),
+                OutputFormat::Json => println!("{}", schema::to_json(&report)),
+            }
+            std::process::exit(if report.passed() { 0 } else { 1 });
+        }
+        Some(CliCommand::Devices { format }) => {
+            match run_devices(*format) {
+                Ok(true) => {}
+                Ok(false) => std::process::exit(usb::Error::DeviceNotFound(None).exit_code()),
+                Err(e) => exit_with(cli.json, e.into()),
+            }
+            return Ok(());
+        }
+        Some(CliCommand::Alarm(command)) => {
+            if let Err(e) = run_alarm(command) {
+                exit_with(cli.json, e.into());
+            }
+            return Ok(());
+        }
+        Some(CliCommand::Scene(command)) => {
+            if cli.trace || cli.dry_run {
+                init_tracing(cli.trace);
+            }
+            if let Err(e) = run_scene(&cli, command) {
+                exit_with(cli.json, e.into());
+            }
+            return Ok(());
+        }
+        Some(CliCommand::History(HistoryCommand::Export { file })) => {
+            match history::export(file) {
+                Ok(count) => println!(
+                    "{}",
+                    msg!(
+                        "history-exported",
+                        count = count,
+                        path = file.display().to_string()
+                    )
+                ),
+                Err(e) => exit_with(cli.json, e.to_string().into()),
+            }
+            return Ok(());
+        }
+        Some(CliCommand::Import { from, path, apply }) => {
+            if let Err(e) = run_import(*from, path, *apply) {
+                exit_with(cli.json, e.into());
+            }
+            return Ok(());
+        }
+        Some(CliCommand::Stats(command)) => {
+            if let Err(e) = run_stats(command) {
+                exit_with(cli.json, e.into());
+            }
+            return Ok(());
+        }
+        Some(CliCommand::Get { field }) => {
+            if cli.trace || cli.dry_run {
+                init_tracing(cli.trace);
+            }
+            if let Err(e) = run_get(&cli, *field) {
+                exit_with(cli.json, e);
+            }
+            return Ok(());
+        }
+        Some(CliCommand::Calibrate {
+            temperature_offset,
+            brightness_offset,
+        }) => {
+            if cli.trace || cli.dry_run {
+                init_tracing(cli.trace);
+            }
+            if let Err(e) = run_calibrate(&cli, *temperature_offset, *brightness_offset) {
+                exit_with(cli.json, e.into());
+            }
+            return Ok(());
+        }
+        // Folded into the flags by `Cli::fold_command`.
+        Some(
+            CliCommand::On
+            | CliCommand::Off
+            | CliCommand::Toggle
+            | CliCommand::Set { .. }
+            | CliCommand::Status { .. }
+            | CliCommand::Preset { .. },
+        )
+        | None => {}
+    }
+
+    if cli.dump_unknown {
+        if cli.trace || cli.dry_run {
+            init_tracing(cli.trace);
+        }
+        if let Err(e) = run_dump_unknown(&cli) {
+            exit_with(cli.json, e);
+        }
+        return Ok(());
+    }
+
+    let headless = cli.has_commands();
+    if cli.trace || cli.dry_run || !headless {
+        init_tracing(cli.trace);
+    }
 
-    if cli.has_commands() {
+    if headless {
+        let (status, json) = (cli.status, cli.json);
         if let Err(e) = run_headless(cli) {
-            eprintln!("Error: {}", e);
-            std::process::exit(1);
+            if status && !json {
+                let report = schema::ErrorReport::new(e.message.as_str(), e.kind);
+                println!("{}", schema::to_json(&report));
+            }
+            exit_with(json, e);
         }
         return Ok(());
     }
 
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
-
     info!("Starting Litra Glow app");
 
+    if let Err(e) = slint::BackendSelector::new()
+        .with_winit_window_attributes_hook(osd_window_attributes)
+        .select()
+    {
+        warn!("Failed to configure window backend: {}", e);
+    }
+
     let app = AppWindow::new()?;
     info!("App window created");
     let app_weak_center = app.as_weak();
@@ -431,66 +2886,225 @@ fn main() -> Result<(), slint::PlatformError> {
     });
 
     let initialized = Rc::new(Cell::new(false));
-
-    app.set_brightness(MIN_BRIGHTNESS as f32);
+    let model = Rc::new(Cell::new(Model::default()));
+    let config = ConfigStore::open_default()
+        .map(|store| {
+            store.load().unwrap_or_else(|e| {
+                warn!("Failed to load {}: {}", store.path().display(), e);
+                Default::default()
+            })
+        })
+        .unwrap_or_default();
+    let brightness_scale = config.brightness.scale(cli.perceptual);
+    let brightness_floor = config.brightness.floor(cli.min_floor);
+
+    set_model_limits(&app, model.get(), brightness_scale, brightness_floor);
+    app.set_brightness(brightness_scale.level_to_slider(
+        percent_to_brightness(brightness_floor, model.get()),
+        model.get(),
+    ));
     app.set_temperature(MIN_TEMPERATURE as f32);
     app.set_power(false);
-    app.set_error("Connecting...".into());
+    app.set_error(msg!("connecting").into());
 
     #[cfg(feature = "tray")]
-    let tray_setup = setup_tray();
+    let tray_setup = setup_tray(&config.hotkeys);
     #[cfg(feature = "tray")]
     let tray_enabled = tray_setup.is_some();
     #[cfg(not(feature = "tray"))]
     let tray_enabled = false;
 
-    let (cmd_tx, cmd_rx) = mpsc::channel();
-    let (evt_tx, evt_rx) = mpsc::channel();
+    let (cmd_tx, cmd_rx) = channel::bounded(COMMAND_QUEUE_CAPACITY);
+    let (evt_tx, evt_rx) = channel::bounded(EVENT_QUEUE_CAPACITY);
 
     let device_state = DeviceState {
         power: false,
-        brightness: MIN_BRIGHTNESS,
+        brightness: model.get().min_brightness(),
         temperature: MIN_TEMPERATURE,
         pending_brightness: None,
         pending_temperature: None,
     };
-    thread::spawn(move || device_loop(cmd_rx, evt_tx, device_state));
+    let (journal, hotplug) = match options.transport {
+        TransportKind::Hid => (
+            Journal::open_default(),
+            Hotplug::new(&options)
+                .map_err(|e| warn!("Hotplug detection unavailable: {}", e))
+                .ok(),
+        ),
+        _ => (None, None),
+    };
+    let mut worker = DeviceWorker::new(
+        Box::new(move || LitraDevice::open(&options)),
+        Box::new(SystemClock),
+        evt_tx,
+        device_state,
+    );
+    if let Some(journal) = journal {
+        worker = worker.with_journal(journal);
+        if let Some(store) = ConfigStore::open_default() {
+            worker = worker.with_usage_store(store);
+        }
+    }
+    if let Some(hotplug) = hotplug {
+        worker = worker.with_hotplug(hotplug);
+    }
+    if let Some(scenes) = SceneStore::open_default() {
+        worker = worker.with_scenes(scenes);
+    }
+    app.set_alarm_armed(config.alarm.armed);
+    let osd_enabled = config.osd.enabled;
+    let off_on_exit = config.off_on_exit || cli.off_on_exit;
+    let mut pause = config.arbitration.pause;
+    let mut on_seconds = None;
+    let mut identity = Identity::default();
+    let mut product = None;
+    // Lamp the controls show; events about any other one are stale.
+    let mut shown: Option<DeviceId> = None;
+    worker = worker
+        .with_alarm(config.alarm)
+        .with_arbiter(config.arbitration)
+        .with_cycle(config.cycle)
+        .with_scene_config(config.scenes)
+        .with_curve(config.temperature_curve)
+        .with_refresh(config.refresh.interval())
+        .with_keepalive(config.refresh.keepalive())
+        .with_poll_interval(config.refresh.poll())
+        .with_restore(config.refresh.restore_on_reconnect)
+        .with_battery(config.battery)
+        .with_focus(config.focus.clone())
+        .with_devices(config.devices)
+        .with_brightness_floor(brightness_floor)
+        .with_sync(config.sync_lamps || cli.all)
+        .with_history(config.history);
+    let worker_handle = thread::spawn(move || worker.run(cmd_rx));
+    if !config.focus.apps.is_empty() {
+        let cmd_tx_focus = cmd_tx.clone();
+        focus::watch(config.focus.apps, move |focused| {
+            !matches!(
+                cmd_tx_focus.send_latest(
+                    DeviceCommand::FocusChanged(focused),
+                    DeviceCommand::replaced_by
+                ),
+                Err(channel::SendError::Disconnected(_))
+            )
+        });
+    }
+    if !config.hotkeys.toggle_power.is_empty() {
+        match config.hotkeys.toggle_power.parse() {
+            Ok(binding) => {
+                let cmd_tx_hotkey = cmd_tx.clone();
+                hotkey::watch(binding, config.hotkeys.double_press_interval(), move || {
+                    !matches!(
+                        cmd_tx_hotkey
+                            .send_timeout(DeviceCommand::TogglePower, COMMAND_SEND_TIMEOUT),
+                        Err(channel::SendError::Disconnected(_))
+                    )
+                });
+            }
+            Err(e) => warn!("Ignoring power hotkey: {}", e),
+        }
+    }
+    if !config.hotkeys.auto_preset_keys.trim().is_empty() {
+        let cmd_tx_presets = cmd_tx.clone();
+        hotkey::watch_presets(
+            config.hotkeys.clone(),
+            || {
+                SceneStore::open_default()
+                    .and_then(|store| store.load().ok())
+                    .map(|scenes| scenes.into_keys().collect())
+                    .unwrap_or_default()
+            },
+            move |name| {
+                !matches!(
+                    cmd_tx_presets.send_latest(
+                        DeviceCommand::ApplyScene {
+                            name: name.to_string(),
+                            duration: SCENE_FADE,
+                        },
+                        DeviceCommand::replaced_by
+                    ),
+                    Err(channel::SendError::Disconnected(_))
+                )
+            },
+        );
+    }
+    let evt_rx = Rc::new(evt_rx);
+
+    if let Err(e) = ctrlc::set_handler(|| {
+        let _ = slint::invoke_from_event_loop(|| {
+            let _ = slint::quit_event_loop();
+        });
+    }) {
+        warn!("Failed to install Ctrl-C handler: {}", e);
+    }
 
     let initialized_brightness = Rc::clone(&initialized);
+    let model_brightness = Rc::clone(&model);
     let cmd_tx_brightness = cmd_tx.clone();
     app.on_brightness_changed(move |value| {
         if !initialized_brightness.get() {
             return;
         }
-        let level = clamp_brightness(value);
-        info!("Brightness changed: {} -> {}", value, level);
-        let _ = cmd_tx_brightness.send(DeviceCommand::SetBrightness(level));
+        let level = brightness_scale.slider_to_level(value, model_brightness.get());
+        debug!("Brightness changed: {} -> {}", value, level);
+        let _ = cmd_tx_brightness.send_latest(
+            DeviceCommand::SetBrightness(level),
+            DeviceCommand::replaced_by,
+        );
     });
 
     let initialized_temperature = Rc::clone(&initialized);
+    let model_temperature = Rc::clone(&model);
     let cmd_tx_temperature = cmd_tx.clone();
     app.on_temperature_changed(move |value| {
         if !initialized_temperature.get() {
             return;
         }
-        let level = clamp_temperature(value);
-        info!("Temperature changed: {} -> {}", value, level);
-        let _ = cmd_tx_temperature.send(DeviceCommand::SetTemperature(level));
+        let level = clamp_temperature(value, model_temperature.get());
+        debug!("Temperature changed: {} -> {}", value, level);
+        let _ = cmd_tx_temperature.send_latest(
+            DeviceCommand::SetTemperature(level),
+            DeviceCommand::replaced_by,
+        );
     });
 
     let initialized_power = Rc::clone(&initialized);
     let cmd_tx_power = cmd_tx.clone();
+    let app_weak_power = app.as_weak();
     app.on_power_toggled(move |on| {
         if !initialized_power.get() {
             return;
         }
         info!("Power toggled: {}", on);
-        let _ = cmd_tx_power.send(DeviceCommand::SetPower(on));
+        if let Err(e) = cmd_tx_power.send_timeout(DeviceCommand::SetPower(on), COMMAND_SEND_TIMEOUT)
+        {
+            warn!("Power command not sent: {}", e);
+            if let Some(app) = app_weak_power.upgrade() {
+                app.set_power(!on);
+            }
+        }
+    });
+
+    let cmd_tx_alarm = cmd_tx.clone();
+    let app_weak_alarm = app.as_weak();
+    app.on_alarm_toggled(move |armed| {
+        info!("Wake-up alarm armed: {}", armed);
+        match update_alarm(|alarm| alarm.armed = armed) {
+            Some(alarm) => {
+                let _ = cmd_tx_alarm
+                    .send_latest(DeviceCommand::SetAlarm(alarm), DeviceCommand::replaced_by);
+            }
+            None => {
+                if let Some(app) = app_weak_alarm.upgrade() {
+                    app.set_alarm_armed(!armed);
+                }
+            }
+        }
     });
 
     let cmd_tx_retry = cmd_tx.clone();
     app.on_retry_connect(move || {
-        let _ = cmd_tx_retry.send(DeviceCommand::Retry);
+        let _ = cmd_tx_retry.send_latest(DeviceCommand::Retry, DeviceCommand::replaced_by);
     });
 
     let app_weak_minimize = app.as_weak();
@@ -514,6 +3128,13 @@ fn main() -> Result<(), slint::PlatformError> {
         let _ = open::that("https://github.com/sponsors/3axap4eHko");
     });
 
+    let history_view = Rc::new(RefCell::new(HistoryView::new(config.history.view_span())));
+    let history_view_show = Rc::clone(&history_view);
+    let cmd_tx_history = cmd_tx.clone();
+    app.on_history(move || {
+        history_view_show.borrow_mut().show(&cmd_tx_history);
+    });
+
     let app_weak_drag = app.as_weak();
     app.on_start_drag(move || {
         let Some(app) = app_weak_drag.upgrade() else {
@@ -525,8 +3146,12 @@ fn main() -> Result<(), slint::PlatformError> {
     });
 
     let app_weak_events = app.as_weak();
+    #[cfg(feature = "tray")]
+    let cmd_tx_tray = cmd_tx.clone();
+    let evt_rx_events = Rc::clone(&evt_rx);
     let initialized_events = Rc::clone(&initialized);
     let init_count = Rc::new(Cell::new(0u8));
+    let mut osd = Osd::default();
     let timer = slint::Timer::default();
     timer.start(
         slint::TimerMode::Repeated,
@@ -539,199 +3164,750 @@ fn main() -> Result<(), slint::PlatformError> {
             #[cfg(feature = "tray")]
             if let Some((ref _tray, ref tray_rx)) = tray_setup {
                 while let Ok(cmd) = tray_rx.try_recv() {
-                    handle_tray_command(cmd, &app_weak_events);
+                    handle_tray_command(cmd, &app_weak_events, &cmd_tx_tray);
                 }
             }
 
-            while let Ok(event) = evt_rx.try_recv() {
+            let footer = footer_text(pause, on_seconds, product.as_deref(), &identity);
+            if app.get_footer() != footer.as_str() {
+                app.set_footer(footer.into());
+            }
+
+            let received: Vec<Routed> = evt_rx_events.try_iter().collect();
+            if received.is_empty() {
+                return;
+            }
+            let received_count = received.len();
+            let events = coalesce_events(received);
+            let mut updates = 0;
+            for Routed { device, event } in events {
+                if let DeviceEvent::Connected(_) = event {
+                    shown = Some(device);
+                } else if event.is_about_lamp() && shown.as_ref().is_some_and(|id| *id != device) {
+                    debug!("Ignoring {:?} from {}", event, device);
+                    continue;
+                }
                 match event {
-                    DeviceEvent::Connected => {
-                        app.set_error("".into());
+                    DeviceEvent::Connected(connected) => {
+                        if model.get() != connected {
+                            info!("Connected to a {}", connected);
+                            model.set(connected);
+                            set_model_limits(&app, connected, brightness_scale, brightness_floor);
+                            updates += 1;
+                        }
+                        if !app.get_error().is_empty() {
+                            app.set_error("".into());
+                            app.set_announcement(msg!("announce-connected").into());
+                            updates += 1;
+                        }
+                    }
+                    DeviceEvent::Disconnected => {
+                        identity = Identity::default();
+                        product = None;
+                        let message = msg!("device-disconnected");
+                        app.set_error(message.as_str().into());
+                        app.set_announcement(message.into());
+                        updates += 1;
                     }
                     DeviceEvent::Power(on) => {
                         info!("UI received power event: {}", on);
-                        app.set_power(on);
+                        if app.get_power() != on {
+                            app.set_power(on);
+                            updates += 1;
+                        }
                         if !initialized_events.get() {
                             init_count.set(init_count.get() + 1);
                         }
                     }
                     DeviceEvent::Brightness(level) => {
-                        app.set_brightness(level as f32);
+                        let model = model.get();
+                        if brightness_scale.slider_to_level(app.get_brightness(), model) != level {
+                            app.set_brightness(brightness_scale.level_to_slider(level, model));
+                            updates += 1;
+                        }
                         if !initialized_events.get() {
                             init_count.set(init_count.get() + 1);
                         }
                     }
                     DeviceEvent::Temperature(level) => {
-                        app.set_temperature(level as f32);
+                        if app.get_temperature() != level as f32 {
+                            app.set_temperature(level as f32);
+                            updates += 1;
+                        }
                         if !initialized_events.get() {
                             init_count.set(init_count.get() + 1);
                         }
                     }
-                    DeviceEvent::Error(message) => {
-                        app.set_error(message.into());
+                    DeviceEvent::Error(message) | DeviceEvent::Warning(message) => {
+                        if app.get_error() != message.as_str() {
+                            app.set_error(message.as_str().into());
+                            app.set_announcement(message.into());
+                            updates += 1;
+                        }
+                    }
+                    DeviceEvent::ButtonPressed(setting) => {
+                        app.set_announcement(
+                            announcement(&app, setting, model.get(), brightness_scale).into(),
+                        );
+                        if osd_enabled {
+                            osd.show_setting(&app, setting, model.get(), brightness_scale);
+                        }
+                    }
+                    DeviceEvent::AutomationsPaused(paused) => pause = paused,
+                    DeviceEvent::OnTime(seconds) => on_seconds = Some(seconds),
+                    DeviceEvent::Identity(reported) => identity = reported,
+                    DeviceEvent::Info(info) => {
+                        info!("Lamp: {:?}", info);
+                        product = info.product;
                     }
+                    DeviceEvent::History(entries) => history_view.borrow().render(&entries),
+                    DeviceEvent::ShutdownComplete => {}
                 }
                 if !initialized_events.get() && init_count.get() >= 2 {
                     info!("Initialization complete");
                     initialized_events.set(true);
                 }
             }
+            debug!(
+                "Applied {} property updates for {} device events",
+                updates, received_count
+            );
         },
     );
 
-    app.run()
+    let result = app.run();
+    shutdown_worker(&cmd_tx, &evt_rx, worker_handle, off_on_exit);
+    result
 }
 
-fn device_loop(
-    cmd_rx: mpsc::Receiver<DeviceCommand>,
-    evt_tx: mpsc::Sender<DeviceEvent>,
-    mut state: DeviceState,
+/// Fits the brightness and temperature sliders to the limits of `model`,
+/// with brightness on `scale` and not below `floor` percent.
+fn set_model_limits(app: &AppWindow, model: Model, scale: BrightnessScale, floor: u8) {
+    let capabilities = model.capabilities();
+    let (brightness_min, brightness_max) = scale.slider_range(model);
+    app.set_brightness_min(brightness_min);
+    app.set_brightness_max(brightness_max);
+    app.set_brightness_floor(scale.level_to_slider(percent_to_brightness(floor, model), model));
+    app.set_temperature_min(capabilities.min_temperature as f32);
+    app.set_temperature_max(capabilities.max_temperature as f32);
+    app.set_temperature_step(capabilities.temperature_step as f32);
+}
+
+/// Asks the worker to run its exit actions, turning the lamp off first when
+/// `power_off` is set, and waits a bounded time for the acknowledgement
+/// before joining it. A worker that is between connections skips the
+/// power-off.
+fn shutdown_worker(
+    cmd_tx: &channel::Sender<DeviceCommand>,
+    evt_rx: &channel::Receiver<Routed>,
+    handle: thread::JoinHandle<()>,
+    power_off: bool,
 ) {
-    info!("Device loop started");
-    let mut device: Option<LitraDevice> = None;
-    let mut last_error: Option<String> = None;
+    if cmd_tx
+        .send_timeout(DeviceCommand::Shutdown { power_off }, SHUTDOWN_TIMEOUT)
+        .is_err()
+    {
+        return;
+    }
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        match evt_rx.recv_timeout(remaining) {
+            Ok(Routed {
+                event: DeviceEvent::ShutdownComplete,
+                ..
+            }) => {
+                let _ = handle.join();
+                info!("Device worker stopped");
+                return;
+            }
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    warn!("Device worker did not acknowledge shutdown in time");
+}
 
-    loop {
-        if device.is_none() {
-            debug!("Trying to open device...");
-            match LitraDevice::open() {
-                Ok(dev) => {
-                    info!("Device connected, querying state...");
-                    if let Err(e) = dev.send(Command::GetPower) {
-                        error!("Failed to send GetPower: {}", e);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Once;
+    use transport::FakeLitra;
+
+    /// Saves a config with the group `desk`, holding the lamp `DESK01`, to
+    /// the test config directory.
+    fn save_config() {
+        static SAVED: Once = Once::new();
+        SAVED.call_once(|| {
+            let mut config = Config::default();
+            config.groups.insert(
+                "desk".to_string(),
+                group::Group {
+                    members: [("DESK01".to_string(), 1.0)].into(),
+                },
+            );
+            ConfigStore::open_default().unwrap().save(&config).unwrap();
+        });
+    }
+
+    /// Parses `args` into a CLI that talks to `fake`.
+    fn fake_cli(fake: &FakeLitra, args: &[&str]) -> Cli {
+        save_config();
+        let mut cli = parse_cli(["litra-glow"].iter().chain(args)).unwrap();
+        cli.transport = TransportKind::Fake(fake.clone());
+        cli
+    }
+
+    /// Runs the headless CLI with `args` against `fake`.
+    fn run(fake: &FakeLitra, args: &[&str]) -> Result<(), Failure> {
+        run_headless(fake_cli(fake, args))
+    }
+
+    #[test]
+    fn an_injected_transport_keeps_the_lamp_selection() {
+        let fake = FakeLitra::new(Model::Glow);
+        let cli = fake_cli(
+            &fake,
+            &["--device", "DESK01", "--trace-out", "lamp.jsonl", "status"],
+        );
+        let options = cli.device_options();
+        assert!(matches!(options.transport, TransportKind::Fake(_)));
+        assert_eq!(options.selector.as_deref(), Some("DESK01"));
+        assert_eq!(options.trace_out, Some(PathBuf::from("lamp.jsonl")));
+
+        let cli = fake_cli(&fake, &["--dry-run", "status"]);
+        assert!(matches!(
+            cli.device_options().transport,
+            TransportKind::DryRun
+        ));
+    }
+
+    fn exit_code(fake: &FakeLitra, args: &[&str]) -> (i32, &'static str) {
+        match run(fake, args) {
+            Ok(()) => (0, "ok"),
+            Err(failure) => (failure.code, failure.kind),
+        }
+    }
+
+    fn unplugged() -> FakeLitra {
+        let fake = FakeLitra::new(Model::Glow).with_serial("DESK01");
+        fake.unplug();
+        fake
+    }
+
+    fn silent() -> FakeLitra {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.set_silent(true);
+        fake
+    }
+
+    #[test]
+    fn success_exits_with_zero() {
+        let fake = FakeLitra::new(Model::Glow);
+        assert_eq!(exit_code(&fake, &["set", "--brightness", "50"]), (0, "ok"));
+        assert_eq!(fake.state().1, 135);
+        assert_eq!(exit_code(&fake, &["on"]), (0, "ok"));
+        assert!(fake.state().0);
+    }
+
+    #[test]
+    fn missing_lamp_exits_with_two() {
+        let expected = (2, "device-not-found");
+        assert_eq!(exit_code(&unplugged(), &["on"]), expected);
+        assert_eq!(exit_code(&unplugged(), &["--all", "--on"]), expected);
+        assert_eq!(
+            exit_code(&unplugged(), &["--group", "desk", "--on"]),
+            expected
+        );
+    }
+
+    #[test]
+    fn combined_settings_apply_in_order_with_slow_acks() {
+        let brightness = percent_to_brightness(80, Model::Glow);
+        for power in [None, Some(true), Some(false)] {
+            for set_brightness in [false, true] {
+                for set_temperature in [false, true] {
+                    let mut args = Vec::new();
+                    match power {
+                        Some(true) => args.push("--on"),
+                        Some(false) => args.push("--off"),
+                        None => {}
                     }
-                    thread::sleep(Duration::from_millis(100));
-                    if let Err(e) = dev.send(Command::GetBrightness) {
-                        error!("Failed to send GetBrightness: {}", e);
+                    if set_brightness {
+                        args.extend(["--brightness", "80"]);
                     }
-                    thread::sleep(Duration::from_millis(100));
-                    if let Err(e) = dev.send(Command::GetTemperature) {
-                        error!("Failed to send GetTemperature: {}", e);
+                    if set_temperature {
+                        args.extend(["--temperature", "3200"]);
                     }
-                    device = Some(dev);
-                    last_error = None;
-                    let _ = evt_tx.send(DeviceEvent::Connected);
-                }
-                Err(err) => {
-                    let message = err.to_string();
-                    if last_error.as_deref() != Some(&message) {
-                        warn!("Device error: {}", message);
-                        let _ = evt_tx.send(DeviceEvent::Error(message.clone()));
-                        last_error = Some(message);
+                    if args.is_empty() {
+                        continue;
                     }
-                    match cmd_rx.recv_timeout(Duration::from_secs(2)) {
-                        Ok(cmd) => {
-                            debug!("Received command while disconnected: {:?}", cmd);
-                            let _ = handle_command(cmd, &mut state, None);
-                        }
-                        Err(mpsc::RecvTimeoutError::Disconnected) => break,
-                        Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    let fake = FakeLitra::new(Model::Glow);
+                    fake.set_state(power != Some(true), 50, 6500);
+                    fake.set_ack_delay(Duration::from_millis(20));
+                    assert_eq!(exit_code(&fake, &args), (0, "ok"), "{args:?}");
+
+                    let mut expected = Vec::new();
+                    if set_brightness {
+                        expected.push(Command::SetBrightness(brightness));
                     }
-                    continue;
+                    if set_temperature {
+                        expected.push(Command::SetTemperature(3200));
+                    }
+                    if let Some(on) = power {
+                        expected.push(Command::SetPower(on));
+                    }
+                    let mut sets = fake.written();
+                    sets.retain(|command| {
+                        matches!(
+                            command,
+                            Command::SetPower(_)
+                                | Command::SetBrightness(_)
+                                | Command::SetTemperature(_)
+                        )
+                    });
+                    sets.dedup();
+                    assert_eq!(sets, expected, "{args:?}");
+
+                    let (on, level, temperature) = fake.state();
+                    assert_eq!(on, power.unwrap_or(true), "{args:?}");
+                    let level_expected = if set_brightness { brightness } else { 50 };
+                    assert_eq!(level, level_expected, "{args:?}");
+                    let temperature_expected = if set_temperature { 3200 } else { 6500 };
+                    assert_eq!(temperature, temperature_expected, "{args:?}");
                 }
             }
         }
+    }
 
-        let mut disconnected = false;
-        if let Some(dev) = device.as_ref() {
-            while let Ok(cmd) = cmd_rx.try_recv() {
-                info!("Received command: {:?}", cmd);
-                if handle_command(cmd, &mut state, Some(dev)).is_err() {
-                    error!("Command failed, device disconnected");
-                    disconnected = true;
-                    break;
-                }
+    #[test]
+    fn a_wedged_lamp_does_not_replay_a_flood_of_commands() {
+        let fake = FakeLitra::new(Model::Glow);
+        // On, so the toggle turns it off and every power-on is from the flood.
+        fake.set_state(true, 100, 4000);
+        let (cmd_tx, cmd_rx) = channel::bounded(COMMAND_QUEUE_CAPACITY);
+        let (evt_tx, evt_rx) = channel::bounded(EVENT_QUEUE_CAPACITY);
+        let state = DeviceState {
+            power: false,
+            brightness: Model::Glow.min_brightness(),
+            temperature: MIN_TEMPERATURE,
+            pending_brightness: None,
+            pending_temperature: None,
+        };
+        let options = DeviceOptions {
+            transport: TransportKind::Fake(fake.clone()),
+            ..DeviceOptions::default()
+        };
+        let worker = DeviceWorker::new(
+            Box::new(move || LitraDevice::open(&options)),
+            Box::new(SystemClock),
+            evt_tx,
+            state,
+        );
+        let handle = thread::spawn(move || worker.run(cmd_rx));
+        let next_event = |wanted: fn(&DeviceEvent) -> bool| loop {
+            let routed = evt_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+            if wanted(&routed.event) {
+                break;
             }
-
-            if !disconnected {
-                match dev.try_read() {
-                    Ok(Some(response)) => {
-                        debug!("Received response: {:?}", response);
-                        match response {
-                            Response::Power(on, _) => {
-                                state.power = on;
-                                info!("Sending power event to UI: {}", on);
-                                let _ = evt_tx.send(DeviceEvent::Power(on));
-                            }
-                            Response::Brightness(level, is_hw) => {
-                                let accept = is_hw
-                                    || match state.pending_brightness {
-                                        Some(t) if t.elapsed() < PENDING_TIMEOUT => false,
-                                        Some(_) => {
-                                            state.pending_brightness = None;
-                                            true
-                                        }
-                                        None => true,
-                                    };
-                                if accept {
-                                    state.brightness = level;
-                                    let _ = evt_tx.send(DeviceEvent::Brightness(level));
-                                }
-                            }
-                            Response::Temperature(level, is_hw) => {
-                                let accept = is_hw
-                                    || match state.pending_temperature {
-                                        Some(t) if t.elapsed() < PENDING_TIMEOUT => false,
-                                        Some(_) => {
-                                            state.pending_temperature = None;
-                                            true
-                                        }
-                                        None => true,
-                                    };
-                                if accept {
-                                    state.temperature = level;
-                                    let _ = evt_tx.send(DeviceEvent::Temperature(level));
-                                }
-                            }
-                        }
-                    }
-                    Ok(None) => {}
-                    Err(e) => {
-                        error!("Read error: {:?}", e);
-                        disconnected = true;
-                    }
-                }
+        };
+        next_event(|event| matches!(event, DeviceEvent::Connected(_)));
+
+        // The lamp stops answering while the worker waits on a toggle.
+        fake.set_silent(true);
+        cmd_tx
+            .send_timeout(DeviceCommand::TogglePower, COMMAND_SEND_TIMEOUT)
+            .unwrap();
+        while !fake.written().contains(&Command::GetPower) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let wedged = fake.written().len();
+
+        for percent in 1..=200 {
+            let level = percent_to_brightness(percent / 2, Model::Glow);
+            let brightness = DeviceCommand::SetBrightness(level);
+            cmd_tx
+                .send_latest(brightness, DeviceCommand::replaced_by)
+                .unwrap();
+            let temperature = DeviceCommand::SetTemperature(2700 + u16::from(percent) * 10);
+            cmd_tx
+                .send_latest(temperature, DeviceCommand::replaced_by)
+                .unwrap();
+        }
+        let mut accepted = 0;
+        let refused = loop {
+            match cmd_tx.send_timeout(DeviceCommand::SetPower(true), COMMAND_SEND_TIMEOUT) {
+                Ok(()) => accepted += 1,
+                Err(err) => break err,
             }
+            assert!(accepted <= COMMAND_QUEUE_CAPACITY);
+        };
+        assert!(matches!(refused, channel::SendError::Full(_)), "{refused}");
+        fake.set_silent(false);
+
+        let shutdown = DeviceCommand::Shutdown { power_off: false };
+        cmd_tx
+            .send_timeout(shutdown, Duration::from_secs(5))
+            .unwrap();
+        next_event(|event| matches!(event, DeviceEvent::ShutdownComplete));
+        handle.join().unwrap();
+
+        let sent = fake.written().split_off(wedged);
+        let count = |wanted: fn(&Command) -> bool| sent.iter().filter(|cmd| wanted(cmd)).count();
+        assert_eq!(count(|cmd| matches!(cmd, Command::SetBrightness(_))), 1);
+        assert_eq!(count(|cmd| matches!(cmd, Command::SetTemperature(_))), 1);
+        assert!(sent.contains(&Command::SetBrightness(percent_to_brightness(
+            100,
+            Model::Glow
+        ))));
+        assert!(sent.contains(&Command::SetTemperature(4700)));
+        assert!(count(|cmd| matches!(cmd, Command::SetPower(true))) <= accepted);
+    }
+
+    #[test]
+    fn unknown_group_is_not_a_device_error() {
+        let fake = FakeLitra::new(Model::Glow);
+        assert_eq!(
+            exit_code(&fake, &["--group", "nowhere", "--on"]),
+            (1, "other")
+        );
+    }
+
+    #[test]
+    fn group_members_trace_to_their_own_files() {
+        let dir = storage::config_dir().unwrap().join("group-trace");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("trace.jsonl");
+        let fake = FakeLitra::new(Model::Glow).with_serial("DESK01");
+        let args = [
+            "--group",
+            "desk",
+            "--on",
+            "--trace-out",
+            path.to_str().unwrap(),
+        ];
+        assert_eq!(exit_code(&fake, &args), (0, "ok"));
+        assert!(!path.exists());
+        let trace = std::fs::read_to_string(dir.join("trace-DESK01.jsonl")).unwrap();
+        assert!(trace.lines().count() > 1, "{trace}");
+    }
+
+    #[test]
+    fn presets_are_the_scenes_the_window_shows() {
+        let fake = FakeLitra::new(Model::Glow);
+        let set = ["set", "--brightness", "40", "--temperature", "3000", "--on"];
+        assert_eq!(exit_code(&fake, &set), (0, "ok"));
+        assert_eq!(exit_code(&fake, &["--save-preset", "evening"]), (0, "ok"));
+        let scene = SceneStore::open_default().unwrap().get("evening").unwrap();
+        assert_eq!(
+            scene,
+            Some(Scene {
+                brightness: 40,
+                temperature: 3000,
+                power: Some(true),
+            })
+        );
+
+        let set = [
+            "set",
+            "--brightness",
+            "90",
+            "--temperature",
+            "6000",
+            "--off",
+        ];
+        assert_eq!(exit_code(&fake, &set), (0, "ok"));
+        let args = ["--preset", "evening", "--brightness", "70"];
+        assert_eq!(exit_code(&fake, &args), (0, "ok"));
+        let (power, brightness, temperature) = fake.state();
+        assert!(power);
+        assert_eq!(brightness, percent_to_brightness(70, Model::Glow));
+        assert_eq!(temperature, 3000);
+
+        let failure = run(&fake, &["--preset", "morning"]).unwrap_err();
+        assert!(failure.message.contains("evening"), "{}", failure.message);
+    }
+
+    /// How long reading the state of `fake` takes with `--timeout 2000`.
+    fn time_state_read(fake: &FakeLitra) -> (Duration, controller::Snapshot) {
+        let cli = fake_cli(fake, &["--timeout", "2000", "status"]);
+        let controller = open_controller(&cli).unwrap();
+        let started = Instant::now();
+        let snapshot = controller
+            .read_device_state(Duration::from_millis(cli.timeout))
+            .unwrap();
+        (started.elapsed(), snapshot)
+    }
+
+    #[test]
+    fn status_returns_as_soon_as_the_lamp_answers() {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.set_state(true, 120, 4000);
+        let (elapsed, snapshot) = time_state_read(&fake);
+        assert_eq!(
+            (snapshot.power, snapshot.brightness, snapshot.temperature),
+            (Some(true), Some(120), Some(4000))
+        );
+        assert!(elapsed < Duration::from_millis(100), "{elapsed:?}");
+
+        let delay = Duration::from_millis(150);
+        fake.delay_ack(Command::GetBrightness, delay);
+        let (elapsed, snapshot) = time_state_read(&fake);
+        assert_eq!(snapshot.brightness, Some(120));
+        assert!(elapsed >= delay, "{elapsed:?}");
+        assert!(elapsed < delay + Duration::from_millis(100), "{elapsed:?}");
+    }
+
+    #[test]
+    fn status_gives_up_at_the_timeout() {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.delay_ack(Command::GetTemperature, Duration::from_secs(5));
+        let started = Instant::now();
+        // The temperature is reported as unread rather than failing.
+        let code = exit_code(&fake, &["--timeout", "200", "status"]);
+        let elapsed = started.elapsed();
+        assert_eq!(code, (0, "ok"));
+        assert!(elapsed >= Duration::from_millis(200), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(800), "{elapsed:?}");
+    }
+
+    #[test]
+    fn perceptual_brightness_looks_like_the_percent_given() {
+        let fake = FakeLitra::new(Model::Glow);
+        let capabilities = Model::Glow.capabilities();
+        let gamma = protocol::DEFAULT_GAMMA;
+
+        run(&fake, &["--perceptual", "--brightness", "50"]).unwrap();
+        let (_, perceptual, _) = fake.state();
+        assert_eq!(
+            perceptual,
+            protocol::perceptual_to_raw(50.0, &capabilities, gamma)
+        );
+        assert!(perceptual < percent_to_brightness(50, Model::Glow));
+
+        for (percent, level) in [
+            ("0", Model::Glow.min_brightness()),
+            ("100", Model::Glow.max_brightness()),
+        ] {
+            run(&fake, &["--perceptual", "--brightness", percent]).unwrap();
+            assert_eq!(fake.state().1, level, "{percent}%");
+        }
+    }
+
+    #[test]
+    fn status_reads_back_the_percent_set() {
+        let fake = FakeLitra::new(Model::Beam);
+        for percent in [1, 49, 50, 51, 99] {
+            run(&fake, &["--brightness", &percent.to_string()]).unwrap();
+            let cli = fake_cli(&fake, &["status"]);
+            let controller = open_controller(&cli).unwrap();
+            let status = lamp_status(&controller, &Config::default(), &cli).unwrap();
+            assert_eq!(status.brightness, Some(percent));
         }
+    }
 
-        if disconnected {
-            warn!("Device disconnected");
-            device = None;
-            let _ = evt_tx.send(DeviceEvent::Error("Device disconnected".to_string()));
+    #[test]
+    fn status_of_a_lamp_matches_the_schema() {
+        let fake = FakeLitra::new(Model::BeamLx).with_serial("2219FE12");
+        for args in [&["status"][..], &["status", "--verbose", "--raw"]] {
+            let cli = fake_cli(&fake, args);
+            let controller = open_controller(&cli).unwrap();
+            let status = lamp_status(&controller, &Config::default(), &cli).unwrap();
+            let json = schema::to_json(&status);
+            schema::assert_conforms::<schema::Status>(&json);
+            assert_eq!(
+                serde_json::from_str::<schema::Status>(&json).unwrap(),
+                status
+            );
+            assert_eq!(status.model, "beam-lx");
+            assert_eq!(status.serial.as_deref(), Some("2219FE12"));
+            assert_eq!(status.raw_brightness.is_some(), cli.raw, "{json}");
         }
+    }
 
-        thread::sleep(Duration::from_millis(30));
+    #[test]
+    fn group_and_all_reach_the_lamp() {
+        let fake = FakeLitra::new(Model::Beam).with_serial("DESK01");
+        assert_eq!(exit_code(&fake, &["--group", "desk", "--on"]), (0, "ok"));
+        assert!(fake.state().0);
+        assert_eq!(exit_code(&fake, &["--all", "--off"]), (0, "ok"));
+        assert!(!fake.state().0);
     }
-}
 
-fn handle_command(
-    cmd: DeviceCommand,
-    state: &mut DeviceState,
-    device: Option<&LitraDevice>,
-) -> Result<(), usb::Error> {
-    match cmd {
-        DeviceCommand::Retry => {}
-        DeviceCommand::SetPower(on) => {
-            state.power = on;
-            if let Some(dev) = device {
-                dev.send(Command::SetPower(on))?;
-            }
+    #[test]
+    fn lamp_that_never_answers_exits_with_five() {
+        assert_eq!(exit_code(&silent(), &["toggle"]), (5, "toggle-failed"));
+        assert_eq!(
+            exit_code(&silent(), &["--save-preset", "late", "--timeout", "50"]),
+            (5, "read-timeout")
+        );
+    }
+
+    #[test]
+    fn save_preset_names_the_values_not_read_and_keeps_the_old_scene() {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.set_state(true, 120, 4000);
+        assert_eq!(exit_code(&fake, &["--save-preset", "kept"]), (0, "ok"));
+        let store = SceneStore::open_default().unwrap();
+        let saved = store.get("kept").unwrap();
+        assert!(saved.is_some());
+
+        fake.set_state(false, 200, 6000);
+        fake.delay_ack(Command::GetTemperature, Duration::from_secs(5));
+        for name in ["kept", "partial"] {
+            let args = ["--timeout", "200", "--save-preset", name];
+            let failure = run(&fake, &args).unwrap_err();
+            assert_eq!((failure.code, failure.kind), (5, "read-timeout"));
+            assert_eq!(
+                failure.message,
+                msg!("error-state-unread", fields = "temperature")
+            );
         }
-        DeviceCommand::SetBrightness(level) => {
-            state.brightness = level;
-            state.pending_brightness = Some(Instant::now());
-            if let Some(dev) = device {
-                dev.send(Command::SetBrightness(level))?;
-            }
+        assert_eq!(store.get("kept").unwrap(), saved);
+        assert_eq!(store.get("partial").unwrap(), None);
+
+        let args = ["--timeout", "50", "--save-preset", "nothing"];
+        let failure = run(&silent(), &args).unwrap_err();
+        assert_eq!(
+            failure.message,
+            msg!(
+                "error-state-unread",
+                fields = "power, brightness, temperature"
+            )
+        );
+        assert_eq!(store.get("nothing").unwrap(), None);
+    }
+
+    #[test]
+    fn out_of_range_raw_level_exits_with_eight() {
+        let fake = FakeLitra::new(Model::Glow);
+        let (code, kind) = exit_code(&fake, &["--raw", "set", "--brightness", "400"]);
+        assert_eq!(code, usb::INVALID_INPUT_EXIT_CODE);
+        assert_eq!(kind, "out-of-range");
+        assert_eq!(fake.written(), []);
+    }
+
+    #[test]
+    fn unparseable_arguments_are_usage_errors() {
+        assert!(parse_cli(["litra-glow", "--brightness", "bright"]).is_err());
+    }
+
+    #[test]
+    fn subcommands_conflict_like_flags() {
+        for args in [
+            &["--on", "set", "--brightness", "10"][..],
+            &["--brightness-up", "5", "on"],
+            &["--group", "desk", "status"],
+            &[
+                "--group",
+                "desk",
+                "set",
+                "--fade",
+                "1s",
+                "--brightness",
+                "10",
+            ],
+            &["--dump-unknown", "toggle"],
+            &["--perceptual", "on", "--raw"],
+            &["--all", "status", "--raw"],
+        ] {
+            let error = parse_cli(["litra-glow"].iter().chain(args))
+                .err()
+                .unwrap_or_else(|| panic!("{args:?} parsed"));
+            assert_eq!(error.kind(), ErrorKind::ArgumentConflict, "{args:?}");
+            assert!(error.to_string().contains("cannot be used with"), "{error}");
+        }
+        for args in [
+            &["--group", "desk", "on"][..],
+            &["--device", "1", "status", "--raw"],
+            &["--raw", "set", "--brightness", "100"],
+            &["--fade", "1s", "set", "--brightness", "10"],
+            &["--json", "scene", "list"],
+        ] {
+            assert!(
+                parse_cli(["litra-glow"].iter().chain(args)).is_ok(),
+                "{args:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn temperature_spellings() {
+        for (text, kelvin) in [
+            ("4000", 4000),
+            ("4000K", 4000),
+            ("4000k", 4000),
+            (" 4000 K", 4000),
+            ("2700", MIN_TEMPERATURE),
+            ("6500K", MAX_TEMPERATURE),
+            ("250mired", 4000),
+            ("warm", 2700),
+            ("Neutral", 4300),
+            ("DAYLIGHT", 6500),
+        ] {
+            assert_eq!(parse_temperature(text), Ok(kelvin), "{text}");
+        }
+        for preset in TemperaturePreset::ALL {
+            assert_eq!(parse_temperature(preset.name()), Ok(preset.kelvin()));
         }
-        DeviceCommand::SetTemperature(level) => {
-            state.temperature = level;
-            state.pending_temperature = Some(Instant::now());
-            if let Some(dev) = device {
-                dev.send(Command::SetTemperature(level))?;
+    }
+
+    #[test]
+    fn rejected_temperatures_list_the_presets() {
+        for text in [
+            "2699", "6501K", "0", "100000", "", "K", "4000KK", "-4000", "4000.5", "lukewarm",
+            "warmK",
+        ] {
+            let error = parse_temperature(text).unwrap_err();
+            assert!(
+                error.contains(&TemperaturePreset::names()),
+                "{text}: {error}"
+            );
+        }
+    }
+
+    #[test]
+    fn temperature_parser_backs_the_cli() {
+        let cli = Cli::try_parse_from(["litra-glow", "--temperature", "cool"]).unwrap();
+        assert_eq!(cli.temperature(), Some(5600));
+        assert!(Cli::try_parse_from(["litra-glow", "--temperature", "9000"]).is_err());
+    }
+
+    #[test]
+    fn completions_generate_for_every_shell_and_language() {
+        for locale in i18n::locales() {
+            for &shell in Shell::value_variants() {
+                let mut command = i18n::localize_command_in(Cli::command(), locale);
+                let mut script = Vec::new();
+                clap_complete::generate(shell, &mut command, "litra-glow", &mut script);
+                let script = String::from_utf8(script).unwrap();
+                assert!(script.contains("litra-glow"), "{locale} {shell}");
+                for word in ["--brightness-up", "scene", "completions"] {
+                    assert!(script.contains(word), "{locale} {shell}: no {word}");
+                }
+                if shell == Shell::Fish {
+                    let about = i18n::catalog_text(locale, "help-cmd-scene").unwrap();
+                    assert!(script.contains(&about), "{locale}: no `{about}`");
+                }
             }
         }
     }
 
-    Ok(())
+    #[test]
+    fn help_ids_exist_in_every_catalog() {
+        let mut missing = Vec::new();
+        for (id, english) in i18n::help_ids(&Cli::command()) {
+            for locale in i18n::locales() {
+                match i18n::catalog_text(locale, &id) {
+                    None => missing.push(format!("{locale}: {id}")),
+                    // The English help lives in the clap attributes too.
+                    Some(text) if locale == "en" && text != english => {
+                        missing.push(format!("en: {id} reads `{text}`, not `{english}`"))
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        assert!(missing.is_empty(), "{missing:#?}");
+    }
 }
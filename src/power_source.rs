@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+
+/// Dimming while running on battery.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatteryConfig {
+    pub enabled: bool,
+    /// Scene applied on battery. When no scene has this name, brightness is
+    /// capped at `brightness` instead.
+    pub scene: String,
+    /// Brightness cap in percent.
+    pub brightness: u8,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            scene: "battery".to_string(),
+            brightness: 40,
+        }
+    }
+}
+
+/// Where the machine is drawing power from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerSource {
+    Ac,
+    Battery,
+}
+
+/// The current power source. `None` on machines without a system battery
+/// and wherever it cannot be determined, so desktops never see a change.
+#[cfg(target_os = "linux")]
+pub fn current() -> Option<PowerSource> {
+    use std::fs;
+
+    let read = |path: std::path::PathBuf| {
+        fs::read_to_string(path)
+            .map(|text| text.trim().to_string())
+            .unwrap_or_default()
+    };
+    let mut has_battery = false;
+    let mut on_ac = false;
+    let mut discharging = false;
+    for entry in fs::read_dir("/sys/class/power_supply").ok()?.flatten() {
+        let dir = entry.path();
+        match read(dir.join("type")).as_str() {
+            // Batteries in mice and keyboards report scope "Device".
+            "Battery" if read(dir.join("scope")) != "Device" => {
+                has_battery = true;
+                discharging |= read(dir.join("status")) == "Discharging";
+            }
+            "Mains" | "USB" => on_ac |= read(dir.join("online")) == "1",
+            _ => {}
+        }
+    }
+
+    if !has_battery {
+        None
+    } else if on_ac || !discharging {
+        Some(PowerSource::Ac)
+    } else {
+        Some(PowerSource::Battery)
+    }
+}
+
+#[cfg(windows)]
+pub fn current() -> Option<PowerSource> {
+    // Only the first two fields are read, but the layout must match.
+    #[repr(C)]
+    #[derive(Default)]
+    #[allow(dead_code)]
+    struct SystemPowerStatus {
+        ac_line_status: u8,
+        battery_flag: u8,
+        battery_life_percent: u8,
+        system_status_flag: u8,
+        battery_life_time: u32,
+        battery_full_life_time: u32,
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetSystemPowerStatus(status: *mut SystemPowerStatus) -> i32;
+    }
+
+    const NO_SYSTEM_BATTERY: u8 = 128;
+
+    let mut status = SystemPowerStatus::default();
+    if unsafe { GetSystemPowerStatus(&mut status) } == 0
+        || status.battery_flag & NO_SYSTEM_BATTERY != 0
+    {
+        return None;
+    }
+    match status.ac_line_status {
+        0 => Some(PowerSource::Battery),
+        1 => Some(PowerSource::Ac),
+        _ => None,
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn current() -> Option<PowerSource> {
+    None
+}
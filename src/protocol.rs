@@ -1,12 +1,335 @@
+use std::ops::RangeInclusive;
+
 pub const VENDOR_ID: u16 = 0x046d;
-pub const PRODUCT_ID: u16 = 0xc900;
+/// Every supported lamp by product ID, in probing order: when several lamps
+/// are connected and none is picked, the one earliest in this table is
+/// opened, so a Glow is preferred over a Beam and a Beam over a Beam LX.
+/// Lamps are also listed in this order.
+pub const KNOWN_DEVICES: [(u16, Model); 3] = [
+    (Model::Glow.product_id(), Model::Glow),
+    (Model::Beam.product_id(), Model::Beam),
+    (Model::BeamLx.product_id(), Model::BeamLx),
+];
+
+/// Whether `vendor_id:product_id` is a lamp listed in [`KNOWN_DEVICES`].
+pub fn is_supported(vendor_id: u16, product_id: u16) -> bool {
+    vendor_id == VENDOR_ID && Model::supported_product_ids().contains(&product_id)
+}
 
-pub const MIN_BRIGHTNESS: u16 = 0x14;
-pub const MAX_BRIGHTNESS: u16 = 0xfa;
+/// Color temperature range and step in kelvin, the same for every model.
 pub const MIN_TEMPERATURE: u16 = 2700;
 pub const MAX_TEMPERATURE: u16 = 6500;
 pub const TEMPERATURE_STEP: u16 = 100;
 
+/// Converts mireds (a million over kelvin) to kelvin, rounding to nearest.
+/// Zero mireds saturate to `u16::MAX`.
+pub fn mired_to_kelvin(mired: u16) -> u16 {
+    reciprocal_million(mired)
+}
+
+/// Converts kelvin to mireds, rounding to nearest. Zero kelvin saturates to
+/// `u16::MAX`.
+pub fn kelvin_to_mired(kelvin: u16) -> u16 {
+    reciprocal_million(kelvin)
+}
+
+fn reciprocal_million(value: u16) -> u16 {
+    let value = value.max(1) as u32;
+    ((1_000_000 + value / 2) / value).min(u16::MAX as u32) as u16
+}
+
+/// Converts a warm-to-cool percentage, as home automation systems give
+/// white temperature, to kelvin on the step grid: 0 is [`MIN_TEMPERATURE`]
+/// and 100, or more, is [`MAX_TEMPERATURE`].
+pub fn temperature_percent_to_kelvin(percent: u8) -> u16 {
+    let span = (MAX_TEMPERATURE - MIN_TEMPERATURE) as u32;
+    let offset = (percent.min(100) as u32 * span + 50) / 100;
+    Model::default()
+        .capabilities()
+        .snap_temperature(MIN_TEMPERATURE + offset as u16, RoundMode::Nearest)
+}
+
+/// The warm-to-cool percentage of `kelvin`, rounding to nearest; the
+/// inverse of [`temperature_percent_to_kelvin`] to within one step.
+pub fn kelvin_to_temperature_percent(kelvin: u16) -> u8 {
+    let span = (MAX_TEMPERATURE - MIN_TEMPERATURE) as u32;
+    let offset = (kelvin.clamp(MIN_TEMPERATURE, MAX_TEMPERATURE) - MIN_TEMPERATURE) as u32;
+    ((offset * 100 + span / 2) / span) as u8
+}
+
+/// A named color temperature, from the warmest to the coolest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemperaturePreset {
+    Warm,
+    Soft,
+    Neutral,
+    Cool,
+    Daylight,
+}
+
+impl TemperaturePreset {
+    pub const ALL: [TemperaturePreset; 5] = [
+        TemperaturePreset::Warm,
+        TemperaturePreset::Soft,
+        TemperaturePreset::Neutral,
+        TemperaturePreset::Cool,
+        TemperaturePreset::Daylight,
+    ];
+
+    /// The preset's temperature, on the step grid of every model.
+    pub const fn kelvin(self) -> u16 {
+        match self {
+            TemperaturePreset::Warm => 2700,
+            TemperaturePreset::Soft => 3400,
+            TemperaturePreset::Neutral => 4300,
+            TemperaturePreset::Cool => 5600,
+            TemperaturePreset::Daylight => 6500,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            TemperaturePreset::Warm => "warm",
+            TemperaturePreset::Soft => "soft",
+            TemperaturePreset::Neutral => "neutral",
+            TemperaturePreset::Cool => "cool",
+            TemperaturePreset::Daylight => "daylight",
+        }
+    }
+
+    /// The preset names with their temperatures, comma separated, for
+    /// error messages: `warm (2700K), soft (3400K), ...`.
+    pub fn names() -> String {
+        Self::ALL
+            .map(|preset| format!("{} ({}K)", preset.name(), preset.kelvin()))
+            .join(", ")
+    }
+}
+
+impl std::fmt::Display for TemperaturePreset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for TemperaturePreset {
+    type Err = String;
+
+    /// Parses a preset name, ignoring case.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        Self::ALL
+            .into_iter()
+            .find(|preset| preset.name().eq_ignore_ascii_case(text))
+            .ok_or_else(|| {
+                format!(
+                    "unknown temperature preset `{text}`, use one of {}",
+                    Self::names()
+                )
+            })
+    }
+}
+
+/// The settings a model accepts. Brightness is in lumens, so
+/// `max_brightness` is also the lamp's light output; temperatures are in
+/// kelvin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub min_brightness: u16,
+    pub max_brightness: u16,
+    pub min_temperature: u16,
+    pub max_temperature: u16,
+    pub temperature_step: u16,
+}
+
+/// Gamma of the perceptual brightness curve when none is configured; close
+/// to how the eye responds to light.
+pub const DEFAULT_GAMMA: f32 = 2.2;
+
+/// The brightness level of a lamp with `capabilities` that looks like
+/// `percent` of its full brightness, raising the share to `gamma`. 0 and 100
+/// give the minimum and maximum level exactly.
+pub fn perceptual_to_raw(percent: f32, capabilities: &Capabilities, gamma: f32) -> u16 {
+    let share = if percent.is_nan() {
+        0.0
+    } else {
+        (percent / 100.0).clamp(0.0, 1.0)
+    };
+    let range = (capabilities.max_brightness - capabilities.min_brightness) as f32;
+    capabilities.min_brightness + (share.powf(gamma) * range).round() as u16
+}
+
+/// The inverse of [`perceptual_to_raw`]: how bright `level` looks, in
+/// percent.
+pub fn raw_to_perceptual(level: u16, capabilities: &Capabilities, gamma: f32) -> f32 {
+    let level = level.clamp(capabilities.min_brightness, capabilities.max_brightness);
+    let range = (capabilities.max_brightness - capabilities.min_brightness) as f32;
+    let share = (level - capabilities.min_brightness) as f32 / range;
+    share.powf(1.0 / gamma) * 100.0
+}
+
+/// The brightness level of a lamp with `capabilities` for `lumens` of light,
+/// clamped to its range. Levels are lumens, so in range this is the same
+/// number.
+pub fn lumens_to_raw(lumens: u16, capabilities: &Capabilities) -> u16 {
+    lumens.clamp(capabilities.min_brightness, capabilities.max_lumens())
+}
+
+/// The light output in lumens of a lamp with `capabilities` at `level`.
+pub fn raw_to_lumens(level: u16, capabilities: &Capabilities) -> u16 {
+    level.clamp(capabilities.min_brightness, capabilities.max_brightness)
+}
+
+/// Which way [`Capabilities::snap_temperature`] rounds a value that falls
+/// between two steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundMode {
+    /// To the closer step; a value halfway between rounds up.
+    Nearest,
+    Down,
+    Up,
+}
+
+impl Capabilities {
+    /// Brightness levels the lamp accepts, in lumens.
+    pub const fn brightness_range(&self) -> RangeInclusive<u16> {
+        self.min_brightness..=self.max_brightness
+    }
+
+    /// Temperatures the lamp accepts, in kelvin.
+    pub const fn temperature_range(&self) -> RangeInclusive<u16> {
+        self.min_temperature..=self.max_temperature
+    }
+
+    /// The lamp's light output at full brightness.
+    pub const fn max_lumens(&self) -> u16 {
+        self.max_brightness
+    }
+
+    /// Clamps `kelvin` to the temperature range and moves it onto the step
+    /// grid, which starts at `min_temperature`.
+    pub fn snap_temperature(&self, kelvin: u16, mode: RoundMode) -> u16 {
+        let step = self.temperature_step;
+        let offset =
+            kelvin.clamp(self.min_temperature, self.max_temperature) - self.min_temperature;
+        let steps = match mode {
+            RoundMode::Nearest => (offset + step / 2) / step,
+            RoundMode::Down => offset / step,
+            RoundMode::Up => offset.div_ceil(step),
+        };
+        (self.min_temperature + steps * step).min(self.max_temperature)
+    }
+}
+
+/// A supported lamp. All models speak the same protocol; they differ in
+/// their brightness range, which is in lumens, and in the feature index
+/// their messages are addressed to. Only the white front light of the
+/// Beam LX is controlled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Model {
+    #[default]
+    Glow,
+    Beam,
+    BeamLx,
+}
+
+impl Model {
+    /// Every supported model, in the probing order of [`KNOWN_DEVICES`].
+    pub const ALL: [Model; 3] = [Model::Glow, Model::Beam, Model::BeamLx];
+
+    /// The model with this product ID; `None` for any other lamp, which
+    /// enumeration skips.
+    pub fn from_product_id(product_id: u16) -> Option<Self> {
+        KNOWN_DEVICES
+            .into_iter()
+            .find_map(|(id, model)| (id == product_id).then_some(model))
+    }
+
+    /// Product IDs of every supported model, in the probing order of
+    /// [`KNOWN_DEVICES`]. Enumeration only looks at lamps with these.
+    pub const fn supported_product_ids() -> &'static [u16] {
+        const IDS: [u16; KNOWN_DEVICES.len()] = {
+            let mut ids = [0; KNOWN_DEVICES.len()];
+            let mut i = 0;
+            while i < ids.len() {
+                ids[i] = KNOWN_DEVICES[i].0;
+                i += 1;
+            }
+            ids
+        };
+        &IDS
+    }
+
+    /// Position of this model in [`KNOWN_DEVICES`]; lower is probed first.
+    pub fn priority(self) -> usize {
+        KNOWN_DEVICES
+            .iter()
+            .position(|&(_, model)| model == self)
+            .unwrap_or(KNOWN_DEVICES.len())
+    }
+
+    pub const fn product_id(self) -> u16 {
+        match self {
+            Model::Glow => 0xc900,
+            Model::Beam => 0xc901,
+            Model::BeamLx => 0xc903,
+        }
+    }
+
+    pub const fn capabilities(self) -> Capabilities {
+        let (min_brightness, max_brightness) = match self {
+            Model::Glow => (20, 250),
+            Model::Beam | Model::BeamLx => (30, 400),
+        };
+        Capabilities {
+            min_brightness,
+            max_brightness,
+            min_temperature: MIN_TEMPERATURE,
+            max_temperature: MAX_TEMPERATURE,
+            temperature_step: TEMPERATURE_STEP,
+        }
+    }
+
+    pub const fn min_brightness(self) -> u16 {
+        self.capabilities().min_brightness
+    }
+
+    pub const fn max_brightness(self) -> u16 {
+        self.capabilities().max_brightness
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Model::Glow => "Litra Glow",
+            Model::Beam => "Litra Beam",
+            Model::BeamLx => "Litra Beam LX",
+        }
+    }
+
+    /// Lowercase name used in JSON output.
+    pub const fn id(self) -> &'static str {
+        match self {
+            Model::Glow => "glow",
+            Model::Beam => "beam",
+            Model::BeamLx => "beam-lx",
+        }
+    }
+
+    /// Third byte of every message to and from this model.
+    pub const fn feature_index(self) -> u8 {
+        match self {
+            Model::Glow | Model::Beam => 0x04,
+            Model::BeamLx => 0x06,
+        }
+    }
+}
+
+impl std::fmt::Display for Model {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
 const SET_POWER: u32 = 0x11FF041C;
 const SET_BRIGHTNESS: u32 = 0x11FF044C;
 const SET_TEMPERATURE: u32 = 0x11FF049C;
@@ -15,7 +338,85 @@ const GET_POWER: u32 = 0x11FF0401;
 const GET_BRIGHTNESS: u32 = 0x11FF0431;
 const GET_TEMPERATURE: u32 = 0x11FF0481;
 
-#[derive(Debug)]
+/// Function bytes of the reports the lamp sends on its own when its
+/// buttons change a setting.
+const BUTTON_POWER: u8 = 0x00;
+const BUTTON_BRIGHTNESS: u8 = 0x10;
+const BUTTON_TEMPERATURE: u8 = 0x20;
+
+/// The function and software id byte of a command header. The lamp
+/// answers queries and acknowledges set commands with the same byte.
+const fn function_byte(header: u32) -> u8 {
+    header.to_be_bytes()[3]
+}
+
+/// HID++ feature reporting firmware versions and the serial number.
+pub const DEVICE_INFORMATION: u16 = 0x0003;
+/// Function and software id bytes of the identity queries. The software
+/// id 0xA keeps their replies apart from the lamp's own reports.
+const ROOT_GET_FEATURE: u8 = 0x0A;
+const GET_FIRMWARE: u8 = 0x1A;
+const GET_SERIAL: u8 = 0x2A;
+/// HID++ feature reporting the device's marketing name.
+pub const DEVICE_NAME: u16 = 0x0005;
+/// Function and software id bytes of the name queries. The software id 0xB
+/// keeps them apart from the identity queries, whose function numbers
+/// they share.
+const GET_NAME_LENGTH: u8 = 0x0B;
+const GET_NAME: u8 = 0x1B;
+/// Bytes of the name carried by one reply.
+pub const NAME_PART_LEN: usize = 16;
+/// Feature index of HID++ error replies.
+const ERROR_INDEX: u8 = 0xFF;
+/// HID++ device index of a device attached directly rather than through a
+/// receiver, the second byte of every frame.
+const DEVICE_INDEX: u8 = 0xFF;
+/// HID++ long report ID, the first byte of every frame. hidapi sends it
+/// as the report ID, so frames need no extra prefix.
+const LONG_REPORT: u8 = 0x11;
+
+/// `data` without the zero report ID some backends put in front of the
+/// HID++ report ID.
+fn strip_report_prefix(data: &[u8]) -> &[u8] {
+    match data {
+        [0x00, LONG_REPORT, ..] => &data[1..],
+        _ => data,
+    }
+}
+
+/// Firmware of the lamp's main entity, as reported over HID++.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Firmware {
+    pub prefix: [u8; 3],
+    pub number: u8,
+    pub revision: u8,
+    pub build: u16,
+}
+
+impl std::fmt::Display for Firmware {
+    /// Logitech's notation, e.g. `RQM 40.00.B0012`; the numbers are BCD.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {:02X}.{:02X}.B{:04X}",
+            String::from_utf8_lossy(&self.prefix).trim(),
+            self.number,
+            self.revision,
+            self.build
+        )
+    }
+}
+
+/// Why a command could not be encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// A brightness level outside the model's range.
+    Brightness { level: u16, model: Model },
+    /// A temperature outside the model's range.
+    Temperature { kelvin: u16, model: Model },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Command {
     SetPower(bool),
     SetBrightness(u16),
@@ -23,15 +424,60 @@ pub enum Command {
     GetPower,
     GetBrightness,
     GetTemperature,
+    /// Ask the root feature for the index of a feature.
+    GetFeatureIndex(u16),
+    /// Ask the device information feature at this index for the firmware.
+    GetFirmware(u8),
+    /// Ask the device information feature at this index for the serial.
+    GetSerial(u8),
+    /// Ask the device name feature at this index for the name's length.
+    GetNameLength(u8),
+    /// Ask the device name feature at this index for the part of the name
+    /// starting at this offset.
+    GetName(u8, u8),
 }
 
 impl Command {
-    pub fn to_bytes(&self) -> [u8; 20] {
+    /// A brightness command for a `model` lamp, with `level` clamped to its
+    /// range.
+    pub fn set_brightness_clamped(level: u16, model: Model) -> Command {
+        let capabilities = model.capabilities();
+        Command::SetBrightness(
+            level.clamp(capabilities.min_brightness, capabilities.max_brightness),
+        )
+    }
+
+    /// A temperature command for a `model` lamp, with `kelvin` clamped to
+    /// its range and snapped to the nearest step.
+    pub fn set_temperature_clamped(kelvin: u16, model: Model) -> Command {
+        Command::SetTemperature(
+            model
+                .capabilities()
+                .snap_temperature(kelvin, RoundMode::Nearest),
+        )
+    }
+
+    /// Encodes the command for a `model` lamp. Brightness and temperature
+    /// outside the model's range are refused, since the lamp would ignore
+    /// them without a word.
+    pub fn to_bytes(self, model: Model) -> Result<[u8; 20], ProtocolError> {
+        let capabilities = model.capabilities();
+        match self {
+            Command::SetBrightness(level) if !capabilities.brightness_range().contains(&level) => {
+                return Err(ProtocolError::Brightness { level, model });
+            }
+            Command::SetTemperature(kelvin)
+                if !capabilities.temperature_range().contains(&kelvin) =>
+            {
+                return Err(ProtocolError::Temperature { kelvin, model });
+            }
+            _ => {}
+        }
         let mut buf = [0u8; 20];
         match self {
             Command::SetPower(on) => {
                 buf[0..4].copy_from_slice(&SET_POWER.to_be_bytes());
-                buf[4] = *on as u8;
+                buf[4] = on as u8;
             }
             Command::SetBrightness(level) => {
                 buf[0..4].copy_from_slice(&SET_BRIGHTNESS.to_be_bytes());
@@ -50,38 +496,721 @@ impl Command {
             Command::GetTemperature => {
                 buf[0..4].copy_from_slice(&GET_TEMPERATURE.to_be_bytes());
             }
+            Command::GetFeatureIndex(feature) => {
+                buf[0..4].copy_from_slice(&[LONG_REPORT, DEVICE_INDEX, 0x00, ROOT_GET_FEATURE]);
+                buf[4..6].copy_from_slice(&feature.to_be_bytes());
+                return Ok(buf);
+            }
+            Command::GetFirmware(index) => {
+                // Entity 0 is the main application firmware.
+                buf[0..4].copy_from_slice(&[LONG_REPORT, DEVICE_INDEX, index, GET_FIRMWARE]);
+                return Ok(buf);
+            }
+            Command::GetSerial(index) => {
+                buf[0..4].copy_from_slice(&[LONG_REPORT, DEVICE_INDEX, index, GET_SERIAL]);
+                return Ok(buf);
+            }
+            Command::GetNameLength(index) => {
+                buf[0..4].copy_from_slice(&[LONG_REPORT, DEVICE_INDEX, index, GET_NAME_LENGTH]);
+                return Ok(buf);
+            }
+            Command::GetName(index, offset) => {
+                buf[0..5].copy_from_slice(&[LONG_REPORT, DEVICE_INDEX, index, GET_NAME, offset]);
+                return Ok(buf);
+            }
         }
-        buf
+        buf[2] = model.feature_index();
+        Ok(buf)
     }
+
+    /// The read-back command that reports the value this command sets.
+    pub fn query(&self) -> Command {
+        match self {
+            Command::SetPower(_) | Command::GetPower => Command::GetPower,
+            Command::SetBrightness(_) | Command::GetBrightness => Command::GetBrightness,
+            Command::SetTemperature(_) | Command::GetTemperature => Command::GetTemperature,
+            Command::GetFeatureIndex(_)
+            | Command::GetFirmware(_)
+            | Command::GetSerial(_)
+            | Command::GetNameLength(_)
+            | Command::GetName(..) => *self,
+        }
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let data = strip_report_prefix(data);
+        if data.len() < 6 {
+            return None;
+        }
+        match (data[2], data[3]) {
+            (0x00, ROOT_GET_FEATURE) => {
+                return Some(Command::GetFeatureIndex(u16::from_be_bytes([
+                    data[4], data[5],
+                ])));
+            }
+            (index, GET_FIRMWARE) => return Some(Command::GetFirmware(index)),
+            (index, GET_SERIAL) => return Some(Command::GetSerial(index)),
+            (index, GET_NAME_LENGTH) => return Some(Command::GetNameLength(index)),
+            (index, GET_NAME) => return Some(Command::GetName(index, data[4])),
+            _ => {}
+        }
+        if !Model::ALL
+            .iter()
+            .any(|model| model.feature_index() == data[2])
+        {
+            return None;
+        }
+        // The headers above carry the Glow's feature index.
+        let index = Model::Glow.feature_index();
+        let header = u32::from_be_bytes([data[0], data[1], index, data[3]]);
+        let value = u16::from_be_bytes([data[4], data[5]]);
+        match header {
+            SET_POWER => Some(Command::SetPower(data[4] != 0)),
+            SET_BRIGHTNESS => Some(Command::SetBrightness(value)),
+            SET_TEMPERATURE => Some(Command::SetTemperature(value)),
+            GET_POWER => Some(Command::GetPower),
+            GET_BRIGHTNESS => Some(Command::GetBrightness),
+            GET_TEMPERATURE => Some(Command::GetTemperature),
+            _ => None,
+        }
+    }
+}
+
+/// Why the lamp sent a power, brightness or temperature report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// The lamp's own buttons changed the setting. Holding a button sends
+    /// one report per step.
+    Button,
+    /// An answer to a query, or the acknowledgement of a set command.
+    Reply,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[allow(dead_code)]
 pub enum Response {
-    Power(bool, bool),
-    Brightness(u16, bool),
-    Temperature(u16, bool),
+    Power(bool, Trigger),
+    Brightness(u16, Trigger),
+    Temperature(u16, Trigger),
+    /// Index of the feature asked for; 0 when the device lacks it.
+    FeatureIndex(u8),
+    Firmware(Firmware),
+    /// Serial number as 12 ASCII characters.
+    Serial([u8; 12]),
+    /// Length of the device name in bytes.
+    NameLength(u8),
+    /// Part of the device name, padded with zeros past its end.
+    NamePart([u8; NAME_PART_LEN]),
+    /// A report from the lamp that none of the above covers, kept whole and
+    /// padded with zeros so new models and buttons can be studied.
+    Unknown {
+        report: [u8; 20],
+    },
 }
 
 impl Response {
+    /// Parses a report from any model. The frame must be a HID++ long
+    /// report from the lamp itself and not an error; anything else is
+    /// `None`. Reports it cannot make sense of, including settings from the
+    /// feature index of an unsupported model, come back as
+    /// [`Unknown`](Response::Unknown). A leading zero report ID and however
+    /// many bytes of padding follow do not matter.
     pub fn from_bytes(data: &[u8]) -> Option<Self> {
-        if data.len() < 6 {
+        let data = strip_report_prefix(data);
+        if data.len() < 6
+            || data[0] != LONG_REPORT
+            || data[1] != DEVICE_INDEX
+            || data[2] == ERROR_INDEX
+        {
             return None;
         }
+        match (data[2], data[3]) {
+            (0x00, ROOT_GET_FEATURE) => return Some(Response::FeatureIndex(data[4])),
+            (_, GET_FIRMWARE) if data.len() >= 12 => {
+                return Some(Response::Firmware(Firmware {
+                    prefix: [data[5], data[6], data[7]],
+                    number: data[8],
+                    revision: data[9],
+                    build: u16::from_be_bytes([data[10], data[11]]),
+                }));
+            }
+            (_, GET_SERIAL) if data.len() >= 16 => {
+                let mut serial = [0; 12];
+                serial.copy_from_slice(&data[4..16]);
+                return Some(Response::Serial(serial));
+            }
+            (_, GET_NAME_LENGTH) => return Some(Response::NameLength(data[4])),
+            (_, GET_NAME) if data.len() >= 4 + NAME_PART_LEN => {
+                let mut part = [0; NAME_PART_LEN];
+                part.copy_from_slice(&data[4..4 + NAME_PART_LEN]);
+                return Some(Response::NamePart(part));
+            }
+            _ => {}
+        }
+        if !Model::ALL
+            .iter()
+            .any(|model| model.feature_index() == data[2])
+        {
+            return Some(Response::unknown(data));
+        }
+        let value = u16::from_be_bytes([data[4], data[5]]);
         match data[3] {
-            0x00 => Some(Response::Power(data[4] != 0, true)),
-            0x01 => Some(Response::Power(data[4] != 0, false)),
-            0x10 => Some(Response::Brightness(data[5] as u16, true)),
-            0x31 => Some(Response::Brightness(data[5] as u16, false)),
-            0x20 => {
-                let temp = u16::from_be_bytes([data[4], data[5]]);
-                Some(Response::Temperature(temp, true))
-            }
-            0x81 => {
-                let temp = u16::from_be_bytes([data[4], data[5]]);
-                Some(Response::Temperature(temp, false))
+            BUTTON_POWER => Some(Response::Power(data[4] != 0, Trigger::Button)),
+            BUTTON_BRIGHTNESS => Some(Response::Brightness(value, Trigger::Button)),
+            BUTTON_TEMPERATURE => Some(Response::Temperature(value, Trigger::Button)),
+            function
+                if function == function_byte(GET_POWER) || function == function_byte(SET_POWER) =>
+            {
+                Some(Response::Power(data[4] != 0, Trigger::Reply))
             }
-            _ => None,
+            function
+                if function == function_byte(GET_BRIGHTNESS)
+                    || function == function_byte(SET_BRIGHTNESS) =>
+            {
+                Some(Response::Brightness(value, Trigger::Reply))
+            }
+            function
+                if function == function_byte(GET_TEMPERATURE)
+                    || function == function_byte(SET_TEMPERATURE) =>
+            {
+                Some(Response::Temperature(value, Trigger::Reply))
+            }
+            _ => Some(Response::unknown(data)),
+        }
+    }
+
+    fn unknown(data: &[u8]) -> Self {
+        let mut report = [0; 20];
+        let len = data.len().min(report.len());
+        report[..len].copy_from_slice(&data[..len]);
+        Response::Unknown { report }
+    }
+
+    /// Whether the reported value is within the limits of `model`. Garbage
+    /// frames that happen to parse usually are not.
+    pub fn is_plausible(&self, model: Model) -> bool {
+        match *self {
+            Response::Power(..) => true,
+            Response::Brightness(level, _) => {
+                model.capabilities().brightness_range().contains(&level)
+            }
+            Response::Temperature(kelvin, _) => {
+                model.capabilities().temperature_range().contains(&kelvin)
+            }
+            Response::FeatureIndex(_)
+            | Response::Firmware(_)
+            | Response::Serial(_)
+            | Response::NameLength(_)
+            | Response::NamePart(_)
+            | Response::Unknown { .. } => true,
+        }
+    }
+
+    /// Whether the lamp sent this on its own after a button press, rather
+    /// than in reply to a query.
+    pub fn is_hardware(&self) -> bool {
+        match *self {
+            Response::Power(_, trigger)
+            | Response::Brightness(_, trigger)
+            | Response::Temperature(_, trigger) => trigger == Trigger::Button,
+            Response::FeatureIndex(_)
+            | Response::Firmware(_)
+            | Response::Serial(_)
+            | Response::NameLength(_)
+            | Response::NamePart(_)
+            | Response::Unknown { .. } => false,
+        }
+    }
+
+    /// Encodes the report the way a `model` lamp sends it.
+    pub fn to_bytes(self, model: Model) -> [u8; 20] {
+        let mut buf = [0u8; 20];
+        buf[0..3].copy_from_slice(&[LONG_REPORT, DEVICE_INDEX, model.feature_index()]);
+        match self {
+            Response::Power(on, trigger) => {
+                buf[3] = match trigger {
+                    Trigger::Button => BUTTON_POWER,
+                    Trigger::Reply => function_byte(GET_POWER),
+                };
+                buf[4] = on as u8;
+            }
+            Response::Brightness(level, trigger) => {
+                buf[3] = match trigger {
+                    Trigger::Button => BUTTON_BRIGHTNESS,
+                    Trigger::Reply => function_byte(GET_BRIGHTNESS),
+                };
+                buf[4..6].copy_from_slice(&level.to_be_bytes());
+            }
+            Response::Temperature(kelvin, trigger) => {
+                buf[3] = match trigger {
+                    Trigger::Button => BUTTON_TEMPERATURE,
+                    Trigger::Reply => function_byte(GET_TEMPERATURE),
+                };
+                buf[4..6].copy_from_slice(&kelvin.to_be_bytes());
+            }
+            Response::FeatureIndex(index) => {
+                buf[2..5].copy_from_slice(&[0x00, ROOT_GET_FEATURE, index]);
+            }
+            Response::Firmware(firmware) => {
+                buf[3] = GET_FIRMWARE;
+                buf[5..8].copy_from_slice(&firmware.prefix);
+                buf[8] = firmware.number;
+                buf[9] = firmware.revision;
+                buf[10..12].copy_from_slice(&firmware.build.to_be_bytes());
+            }
+            Response::Serial(serial) => {
+                buf[3] = GET_SERIAL;
+                buf[4..16].copy_from_slice(&serial);
+            }
+            Response::NameLength(length) => {
+                buf[3] = GET_NAME_LENGTH;
+                buf[4] = length;
+            }
+            Response::NamePart(part) => {
+                buf[3] = GET_NAME;
+                buf[4..4 + NAME_PART_LEN].copy_from_slice(&part);
+            }
+            Response::Unknown { report } => buf = report,
+        }
+        buf
+    }
+
+    /// Whether this response answers `cmd`: same setting and, for set
+    /// commands, the requested value.
+    pub fn matches(&self, cmd: &Command) -> bool {
+        match (self, cmd) {
+            (Response::Power(on, _), Command::SetPower(want)) => on == want,
+            (Response::Brightness(level, _), Command::SetBrightness(want)) => level == want,
+            (Response::Temperature(kelvin, _), Command::SetTemperature(want)) => kelvin == want,
+            (Response::Power(..), Command::GetPower)
+            | (Response::Brightness(..), Command::GetBrightness)
+            | (Response::Temperature(..), Command::GetTemperature)
+            | (Response::FeatureIndex(_), Command::GetFeatureIndex(_))
+            | (Response::Firmware(_), Command::GetFirmware(_))
+            | (Response::Serial(_), Command::GetSerial(_))
+            | (Response::NameLength(_), Command::GetNameLength(_))
+            | (Response::NamePart(_), Command::GetName(..)) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn model() -> impl Strategy<Value = Model> {
+        prop::sample::select(Model::ALL.to_vec())
+    }
+
+    fn trigger() -> impl Strategy<Value = Trigger> {
+        prop_oneof![Just(Trigger::Button), Just(Trigger::Reply)]
+    }
+
+    /// Every command a `model` lamp accepts, values within its range.
+    fn command(model: Model) -> impl Strategy<Value = Command> {
+        let capabilities = model.capabilities();
+        prop_oneof![
+            any::<bool>().prop_map(Command::SetPower),
+            capabilities
+                .brightness_range()
+                .prop_map(Command::SetBrightness),
+            capabilities
+                .temperature_range()
+                .prop_map(Command::SetTemperature),
+            Just(Command::GetPower),
+            Just(Command::GetBrightness),
+            Just(Command::GetTemperature),
+            any::<u16>().prop_map(Command::GetFeatureIndex),
+            (1u8..0xFF).prop_map(Command::GetFirmware),
+            (1u8..0xFF).prop_map(Command::GetSerial),
+            (1u8..0xFF).prop_map(Command::GetNameLength),
+            (1u8..0xFF, any::<u8>()).prop_map(|(index, offset)| Command::GetName(index, offset)),
+        ]
+    }
+
+    fn response() -> impl Strategy<Value = Response> {
+        prop_oneof![
+            (any::<bool>(), trigger()).prop_map(|(on, trigger)| Response::Power(on, trigger)),
+            (any::<u16>(), trigger())
+                .prop_map(|(level, trigger)| Response::Brightness(level, trigger)),
+            (any::<u16>(), trigger())
+                .prop_map(|(kelvin, trigger)| Response::Temperature(kelvin, trigger)),
+            any::<u8>().prop_map(Response::FeatureIndex),
+            (any::<[u8; 3]>(), any::<u8>(), any::<u8>(), any::<u16>()).prop_map(
+                |(prefix, number, revision, build)| Response::Firmware(Firmware {
+                    prefix,
+                    number,
+                    revision,
+                    build,
+                })
+            ),
+            any::<[u8; 12]>().prop_map(Response::Serial),
+            any::<u8>().prop_map(Response::NameLength),
+            any::<[u8; NAME_PART_LEN]>().prop_map(Response::NamePart),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn commands_round_trip((model, cmd) in model().prop_flat_map(|model| (Just(model), command(model)))) {
+            let bytes = cmd.to_bytes(model).unwrap();
+            prop_assert_eq!(Command::from_bytes(&bytes), Some(cmd));
+            // With the zero report ID some backends put in front.
+            let prefixed = [&[0x00][..], &bytes].concat();
+            prop_assert_eq!(Command::from_bytes(&prefixed), Some(cmd));
         }
+
+        #[test]
+        fn responses_round_trip(model in model(), response in response()) {
+            let bytes = response.to_bytes(model);
+            prop_assert_eq!(Response::from_bytes(&bytes), Some(response));
+            prop_assert_eq!(Response::from_bytes(&[&[0x00][..], &bytes].concat()), Some(response));
+        }
+
+        #[test]
+        fn responses_answer_their_query(model in model(), response in response()) {
+            let Some(cmd) = (match response {
+                Response::Power(..) => Some(Command::GetPower),
+                Response::Brightness(..) => Some(Command::GetBrightness),
+                Response::Temperature(..) => Some(Command::GetTemperature),
+                Response::FeatureIndex(_) => Some(Command::GetFeatureIndex(DEVICE_NAME)),
+                Response::Firmware(_) => Some(Command::GetFirmware(1)),
+                Response::Serial(_) => Some(Command::GetSerial(1)),
+                Response::NameLength(_) => Some(Command::GetNameLength(2)),
+                Response::NamePart(_) => Some(Command::GetName(2, 0)),
+                Response::Unknown { .. } => None,
+            }) else {
+                return Ok(());
+            };
+            prop_assert!(response.matches(&cmd));
+            prop_assert!(Command::from_bytes(&cmd.to_bytes(model).unwrap()).is_some());
+        }
+
+        #[test]
+        fn from_bytes_never_panics(data in prop::collection::vec(any::<u8>(), 0..64)) {
+            let _ = Command::from_bytes(&data);
+            let _ = Response::from_bytes(&data);
+        }
+
+        #[test]
+        fn out_of_range_values_are_refused(model in model(), value: u16) {
+            let capabilities = model.capabilities();
+            let brightness = Command::SetBrightness(value).to_bytes(model);
+            let in_range = capabilities.brightness_range().contains(&value);
+            prop_assert_eq!(brightness.is_ok(), in_range);
+            let temperature = Command::SetTemperature(value).to_bytes(model);
+            let in_range = capabilities.temperature_range().contains(&value);
+            prop_assert_eq!(temperature.is_ok(), in_range);
+        }
+
+        #[test]
+        fn temperature_percent_round_trips_within_a_step(percent: u8) {
+            let kelvin = temperature_percent_to_kelvin(percent);
+            prop_assert!((MIN_TEMPERATURE..=MAX_TEMPERATURE).contains(&kelvin));
+            prop_assert_eq!(kelvin % TEMPERATURE_STEP, 0);
+            let back = kelvin_to_temperature_percent(kelvin);
+            // One step of the 2700-6500 K span is 100 / 38, under 3 percent.
+            let step = (TEMPERATURE_STEP * 100).div_ceil(MAX_TEMPERATURE - MIN_TEMPERATURE) as u8;
+            prop_assert!(back.abs_diff(percent.min(100)) <= step, "{} -> {} -> {}", percent, kelvin, back);
+            prop_assert_eq!(temperature_percent_to_kelvin(back), kelvin);
+        }
+
+        #[test]
+        fn perceptual_levels_stay_in_range(model in model(), percent in any::<f32>(), gamma in 0.5f32..4.0) {
+            let capabilities = model.capabilities();
+            let level = perceptual_to_raw(percent, &capabilities, gamma);
+            prop_assert!(capabilities.brightness_range().contains(&level));
+        }
+
+        #[test]
+        fn perceptual_to_raw_is_monotonic(model in model(), a in 0f32..=100.0, b in 0f32..=100.0, gamma in 0.5f32..4.0) {
+            let capabilities = model.capabilities();
+            let (low, high) = (a.min(b), a.max(b));
+            prop_assert!(
+                perceptual_to_raw(low, &capabilities, gamma) <= perceptual_to_raw(high, &capabilities, gamma)
+            );
+        }
+
+        #[test]
+        fn perceptual_round_trips_within_a_level(model in model(), level: u16, gamma in 0.5f32..4.0) {
+            let capabilities = model.capabilities();
+            let clamped = level.clamp(capabilities.min_brightness, capabilities.max_brightness);
+            let percent = raw_to_perceptual(level, &capabilities, gamma);
+            prop_assert!((0.0..=100.0).contains(&percent));
+            let back = perceptual_to_raw(percent, &capabilities, gamma);
+            prop_assert!(back.abs_diff(clamped) <= 1, "{} -> {} -> {}", clamped, percent, back);
+        }
+    }
+
+    #[test]
+    fn models_report_their_capabilities() {
+        for (model, brightness) in [
+            (Model::Glow, 20..=250),
+            (Model::Beam, 30..=400),
+            (Model::BeamLx, 30..=400),
+        ] {
+            let capabilities = model.capabilities();
+            assert_eq!(capabilities.brightness_range(), brightness, "{model}");
+            assert_eq!(capabilities.max_lumens(), *brightness.end(), "{model}");
+            assert_eq!(capabilities.temperature_range(), 2700..=6500, "{model}");
+            assert_eq!(capabilities.temperature_step, 100, "{model}");
+        }
+    }
+
+    #[test]
+    fn snapping_keeps_the_temperature_limits() {
+        for model in Model::ALL {
+            let capabilities = model.capabilities();
+            for mode in [RoundMode::Nearest, RoundMode::Down, RoundMode::Up] {
+                for (kelvin, expected) in [
+                    (MIN_TEMPERATURE, MIN_TEMPERATURE),
+                    (MAX_TEMPERATURE, MAX_TEMPERATURE),
+                    (0, MIN_TEMPERATURE),
+                    (MIN_TEMPERATURE - 1, MIN_TEMPERATURE),
+                    (MAX_TEMPERATURE + 1, MAX_TEMPERATURE),
+                    (u16::MAX, MAX_TEMPERATURE),
+                ] {
+                    assert_eq!(
+                        capabilities.snap_temperature(kelvin, mode),
+                        expected,
+                        "{model} {kelvin} {mode:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn snapping_rounds_the_way_asked() {
+        let capabilities = Model::Glow.capabilities();
+        for (kelvin, nearest, down, up) in [
+            (2701, 2700, 2700, 2800),
+            (2749, 2700, 2700, 2800),
+            (2750, 2800, 2700, 2800),
+            (6499, 6500, 6400, 6500),
+        ] {
+            assert_eq!(
+                capabilities.snap_temperature(kelvin, RoundMode::Nearest),
+                nearest
+            );
+            assert_eq!(capabilities.snap_temperature(kelvin, RoundMode::Down), down);
+            assert_eq!(capabilities.snap_temperature(kelvin, RoundMode::Up), up);
+        }
+    }
+
+    #[test]
+    fn product_ids_map_to_their_models() {
+        assert_eq!(Model::supported_product_ids(), [0xc900, 0xc901, 0xc903]);
+        for model in Model::ALL {
+            assert_eq!(Model::from_product_id(model.product_id()), Some(model));
+            assert!(is_supported(VENDOR_ID, model.product_id()), "{model}");
+            assert!(!is_supported(0x046e, model.product_id()), "{model}");
+        }
+        for unknown in [0x0000, 0xc902, 0xc904, 0xffff] {
+            assert_eq!(Model::from_product_id(unknown), None, "{unknown:04x}");
+            assert!(!is_supported(VENDOR_ID, unknown), "{unknown:04x}");
+        }
+    }
+
+    #[test]
+    fn models_display_their_names() {
+        assert_eq!(Model::Glow.to_string(), "Litra Glow");
+        assert_eq!(Model::Beam.to_string(), "Litra Beam");
+        assert_eq!(Model::BeamLx.to_string(), "Litra Beam LX");
+    }
+
+    #[test]
+    fn perceptual_ends_are_exact() {
+        for model in Model::ALL {
+            let capabilities = model.capabilities();
+            for gamma in [1.0, DEFAULT_GAMMA, 3.0] {
+                assert_eq!(
+                    perceptual_to_raw(0.0, &capabilities, gamma),
+                    model.min_brightness()
+                );
+                assert_eq!(
+                    perceptual_to_raw(100.0, &capabilities, gamma),
+                    model.max_brightness()
+                );
+                assert_eq!(
+                    perceptual_to_raw(f32::NAN, &capabilities, gamma),
+                    model.min_brightness()
+                );
+                assert_eq!(
+                    raw_to_perceptual(model.min_brightness(), &capabilities, gamma),
+                    0.0
+                );
+                assert_eq!(
+                    raw_to_perceptual(model.max_brightness(), &capabilities, gamma),
+                    100.0
+                );
+                // Levels reported below the minimum do not underflow.
+                assert_eq!(raw_to_perceptual(0, &capabilities, gamma), 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn perceptual_midpoint_is_dimmer_than_linear() {
+        let capabilities = Model::Glow.capabilities();
+        let linear = perceptual_to_raw(50.0, &capabilities, 1.0);
+        assert_eq!(linear, 135);
+        assert!(perceptual_to_raw(50.0, &capabilities, DEFAULT_GAMMA) < linear);
+    }
+
+    #[test]
+    fn mireds_convert_at_the_temperature_limits() {
+        assert_eq!(kelvin_to_mired(MIN_TEMPERATURE), 370);
+        assert_eq!(kelvin_to_mired(MAX_TEMPERATURE), 154);
+        assert_eq!(mired_to_kelvin(370), 2703);
+        assert_eq!(mired_to_kelvin(154), 6494);
+        assert_eq!(mired_to_kelvin(kelvin_to_mired(4000)), 4000);
+    }
+
+    #[test]
+    fn mireds_round_to_nearest() {
+        // 1e6 / 3000 = 333.3 rounds down, 1e6 / 3100 = 322.6 rounds up.
+        assert_eq!(kelvin_to_mired(3000), 333);
+        assert_eq!(kelvin_to_mired(3100), 323);
+        // 1e6 / 371 = 2695.4 rounds down, 1e6 / 370 = 2702.7 rounds up.
+        assert_eq!(mired_to_kelvin(371), 2695);
+        assert_eq!(mired_to_kelvin(370), 2703);
+        // 1e6 / 128 = 7812.5 rounds half up.
+        assert_eq!(mired_to_kelvin(128), 7813);
+        assert_eq!(kelvin_to_mired(128), 7813);
+        assert_eq!(mired_to_kelvin(0), u16::MAX);
+        assert_eq!(kelvin_to_mired(1), u16::MAX);
+    }
+
+    #[test]
+    fn set_acknowledgements_parse_as_replies() {
+        for model in Model::ALL {
+            for (cmd, expected) in [
+                (
+                    Command::SetPower(true),
+                    Response::Power(true, Trigger::Reply),
+                ),
+                (
+                    Command::SetBrightness(model.max_brightness()),
+                    Response::Brightness(model.max_brightness(), Trigger::Reply),
+                ),
+                (
+                    Command::SetTemperature(MAX_TEMPERATURE),
+                    Response::Temperature(MAX_TEMPERATURE, Trigger::Reply),
+                ),
+            ] {
+                let bytes = cmd.to_bytes(model).unwrap();
+                assert_eq!(
+                    Response::from_bytes(&bytes),
+                    Some(expected),
+                    "{model} {cmd:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn responses_carry_the_model_feature_index() {
+        for model in Model::ALL {
+            let bytes = Response::Brightness(100, Trigger::Reply).to_bytes(model);
+            assert_eq!(bytes[2], model.feature_index(), "{model}");
+        }
+        let bytes = Response::Power(true, Trigger::Button).to_bytes(Model::BeamLx);
+        assert_eq!(
+            bytes[..5],
+            [LONG_REPORT, DEVICE_INDEX, 0x06, BUTTON_POWER, 1]
+        );
+    }
+
+    #[test]
+    fn unknown_reports_are_kept_whole() {
+        let mut report = [0u8; 20];
+        report[..6].copy_from_slice(&[LONG_REPORT, DEVICE_INDEX, 0x04, 0x77, 0x12, 0x34]);
+        let response = Response::from_bytes(&report).unwrap();
+        assert_eq!(response, Response::Unknown { report });
+        assert_eq!(response.to_bytes(Model::Glow), report);
+    }
+
+    #[test]
+    fn errors_and_foreign_frames_are_dropped() {
+        let mut error = GET_POWER.to_be_bytes().to_vec();
+        error[2] = ERROR_INDEX;
+        error.resize(20, 0);
+        assert_eq!(Response::from_bytes(&error), None);
+        assert_eq!(
+            Response::from_bytes(&[0x10, DEVICE_INDEX, 0x04, 0x01, 0, 0]),
+            None
+        );
+        assert_eq!(
+            Response::from_bytes(&[LONG_REPORT, DEVICE_INDEX, 0x04]),
+            None
+        );
+    }
+
+    /// Brightness reports as a Beam sends them at levels above 255, whose
+    /// high byte is set, and from a Beam LX on its own feature index.
+    const BEAM_BRIGHTNESS_FRAMES: [([u8; 20], Response); 4] = [
+        (
+            [
+                0x11, 0xff, 0x04, 0x10, 0x01, 0x90, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            ],
+            Response::Brightness(400, Trigger::Button),
+        ),
+        (
+            [
+                0x11, 0xff, 0x04, 0x31, 0x01, 0x2c, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            ],
+            Response::Brightness(300, Trigger::Reply),
+        ),
+        (
+            [
+                0x11, 0xff, 0x04, 0x4c, 0x01, 0x04, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            ],
+            Response::Brightness(260, Trigger::Reply),
+        ),
+        (
+            [
+                0x11, 0xff, 0x06, 0x10, 0x01, 0x5e, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            ],
+            Response::Brightness(350, Trigger::Button),
+        ),
+    ];
+
+    #[test]
+    fn beam_brightness_keeps_the_high_byte() {
+        for (frame, expected) in BEAM_BRIGHTNESS_FRAMES {
+            let response = Response::from_bytes(&frame);
+            assert_eq!(response, Some(expected), "{frame:02x?}");
+            assert!(response.unwrap().is_plausible(Model::Beam));
+            // hidraw hands the frame over with a zero report ID in front.
+            assert_eq!(
+                Response::from_bytes(&[&[0x00][..], &frame].concat()),
+                Some(expected)
+            );
+        }
+    }
+
+    #[test]
+    fn glow_brightness_is_unchanged() {
+        for level in Model::Glow.min_brightness()..=Model::Glow.max_brightness() {
+            let [high, low] = level.to_be_bytes();
+            assert_eq!(high, 0);
+            for (function, trigger) in [(0x10, Trigger::Button), (0x31, Trigger::Reply)] {
+                let frame = [LONG_REPORT, DEVICE_INDEX, 0x04, function, 0x00, low];
+                assert_eq!(
+                    Response::from_bytes(&frame),
+                    Some(Response::Brightness(u16::from(low), trigger))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn beam_levels_above_the_glow_range_are_implausible_for_a_glow() {
+        let beam = Response::Brightness(400, Trigger::Button);
+        assert!(beam.is_plausible(Model::Beam));
+        assert!(!beam.is_plausible(Model::Glow));
     }
 }
@@ -0,0 +1,73 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage;
+
+const SCENES_FILE: &str = "scenes.json";
+
+/// A named combination of brightness (percent) and temperature (kelvin)
+/// that is faded to as one, also applied with `--preset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scene {
+    pub brightness: u8,
+    pub temperature: u16,
+    /// Power to switch to; without one, [`SceneConfig::power_on`] decides.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub power: Option<bool>,
+}
+
+/// How scenes are applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneConfig {
+    /// Turn the lamp on when a scene is applied while it is off.
+    pub power_on: bool,
+}
+
+impl Default for SceneConfig {
+    fn default() -> Self {
+        Self { power_on: true }
+    }
+}
+
+/// Scenes stored as a single JSON object keyed by name.
+pub struct SceneStore {
+    path: PathBuf,
+}
+
+impl SceneStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// The store in the user's config directory, if one can be determined.
+    pub fn open_default() -> Option<Self> {
+        storage::config_dir().map(|dir| Self::new(dir.join(SCENES_FILE)))
+    }
+
+    /// Reads all scenes. A missing file means no scenes.
+    pub fn load(&self) -> io::Result<BTreeMap<String, Scene>> {
+        match fs::read(&self.path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> io::Result<Option<Scene>> {
+        Ok(self.load()?.get(name).copied())
+    }
+
+    /// Adds or replaces the scene called `name`.
+    pub fn set(&self, name: &str, scene: Scene) -> io::Result<()> {
+        let mut scenes = self.load()?;
+        scenes.insert(name.to_string(), scene);
+        let data = serde_json::to_vec_pretty(&scenes).expect("scenes always serialize");
+        storage::atomic_write(&self.path, &data)
+    }
+}
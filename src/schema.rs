@@ -0,0 +1,510 @@
+use schemars::{JsonSchema, schema_for};
+#[cfg(test)]
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Bump whenever the serialized shape of any type below changes.
+pub const SCHEMA_VERSION: u32 = 11;
+
+/// Lamp state as reported by `--status`, or one entry of the array printed
+/// by `--all --status`. `model` is `glow`, `beam` or `beam-lx`.
+/// Brightness is a percentage of that model's range.
+/// `brightness` and `temperature` are nominal; the `effective_` values are
+/// what the lamp runs at after its calibration offsets. `lumens` is the
+/// light output at the effective brightness. `temperature_percent` is the
+/// nominal temperature from warmest (0) to coolest (100). `on_hours` is the
+/// total on-time counted by the app, to one decimal. `serial` and
+/// `firmware` are what the lamp reports about itself, null if it did not
+/// answer in time. `name` is the lamp's own name, only with `--verbose`.
+/// `raw_brightness` is the lamp's own brightness level, only with `--raw`.
+#[derive(Debug, Serialize, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize, PartialEq))]
+pub struct Status {
+    pub schema_version: u32,
+    pub model: String,
+    pub power: Option<bool>,
+    pub brightness: Option<u8>,
+    pub temperature: Option<u16>,
+    pub temperature_percent: Option<u8>,
+    pub effective_brightness: Option<u8>,
+    pub effective_temperature: Option<u16>,
+    pub lumens: Option<u16>,
+    pub on_hours: f64,
+    pub serial: Option<String>,
+    pub firmware: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_brightness: Option<u16>,
+}
+
+impl Status {
+    pub fn new(
+        model: &str,
+        power: Option<bool>,
+        brightness: Option<u8>,
+        temperature: Option<u16>,
+        effective_brightness: Option<u8>,
+        effective_temperature: Option<u16>,
+        on_hours: f64,
+    ) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            model: model.to_string(),
+            power,
+            brightness,
+            temperature,
+            temperature_percent: None,
+            effective_brightness,
+            effective_temperature,
+            lumens: None,
+            on_hours,
+            serial: None,
+            firmware: None,
+            name: None,
+            raw_brightness: None,
+        }
+    }
+
+    pub fn with_identity(mut self, serial: Option<String>, firmware: Option<String>) -> Self {
+        self.serial = serial;
+        self.firmware = firmware;
+        self
+    }
+
+    pub fn with_lumens(mut self, lumens: Option<u16>) -> Self {
+        self.lumens = lumens;
+        self
+    }
+
+    pub fn with_temperature_percent(mut self, percent: Option<u8>) -> Self {
+        self.temperature_percent = percent;
+        self
+    }
+
+    pub fn with_name(mut self, name: Option<String>) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn with_raw_brightness(mut self, level: Option<u16>) -> Self {
+        self.raw_brightness = level;
+        self
+    }
+}
+
+/// One entry of the array printed by `devices --format json`, in the order
+/// of the text listing. `model` is as in [`Status`]; `product` is the name
+/// the lamp reports, null if none.
+#[derive(Debug, Serialize, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize, PartialEq))]
+pub struct Device {
+    pub schema_version: u32,
+    pub index: usize,
+    pub model: String,
+    pub product: Option<String>,
+    pub serial: Option<String>,
+    pub path: String,
+}
+
+impl Device {
+    pub fn new(
+        index: usize,
+        model: &str,
+        product: Option<String>,
+        serial: Option<String>,
+        path: String,
+    ) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            index,
+            model: model.to_string(),
+            product,
+            serial,
+            path,
+        }
+    }
+}
+
+/// Failure payload emitted in place of a JSON result, and on stderr with
+/// `--json`. `kind` names the failure, e.g. `device-not-found` or
+/// `read-timeout`, and is `other` for failures not about the lamp.
+#[derive(Debug, Serialize, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize, PartialEq))]
+pub struct ErrorReport {
+    pub schema_version: u32,
+    pub error: String,
+    pub kind: String,
+}
+
+impl ErrorReport {
+    pub fn new(error: impl Into<String>, kind: &str) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            error: error.into(),
+            kind: kind.to_string(),
+        }
+    }
+}
+
+/// Result of a single `doctor` check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckOutcome {
+    Pass,
+    Warn,
+    Fail,
+    Skip,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Check {
+    pub name: String,
+    pub outcome: CheckOutcome,
+    pub detail: String,
+    /// What to try next, for checks that did not pass.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+/// Output of `doctor --format json`.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DoctorReport {
+    pub schema_version: u32,
+    pub checks: Vec<Check>,
+}
+
+impl DoctorReport {
+    pub fn new(checks: Vec<Check>) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            checks,
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.outcome != CheckOutcome::Fail)
+    }
+}
+
+pub fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("output types always serialize")
+}
+
+/// JSON Schema document describing every output type, keyed by name.
+pub fn document() -> String {
+    let document = serde_json::json!({
+        "schema_version": SCHEMA_VERSION,
+        "status": schema_for!(Status),
+        "status_all": schema_for!(Vec<Status>),
+        "error": schema_for!(ErrorReport),
+        "doctor": schema_for!(DoctorReport),
+        "devices": schema_for!(Vec<Device>),
+    });
+    serde_json::to_string_pretty(&document).expect("schema always serializes")
+}
+
+/// Panics unless `json` is valid against the schema of `T`. Covers what
+/// the output types use: types, properties, required fields, arrays,
+/// enums and references.
+#[cfg(test)]
+pub fn assert_conforms<T: JsonSchema>(json: &str) {
+    use serde_json::Value;
+
+    fn check(value: &Value, schema: &Value, root: &Value, at: &str) {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/$defs/");
+            return check(value, &root["$defs"][name], root, at);
+        }
+        if let Some(choices) = schema["enum"].as_array() {
+            assert!(choices.contains(value), "{at}: {value} not in {choices:?}");
+        }
+        let types: Vec<&str> = match &schema["type"] {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        let matches = |name: &str| match name {
+            "null" => value.is_null(),
+            "boolean" => value.is_boolean(),
+            "integer" => value.is_u64() || value.is_i64(),
+            "number" => value.is_number(),
+            "string" => value.is_string(),
+            "array" => value.is_array(),
+            "object" => value.is_object(),
+            other => panic!("{at}: unexpected schema type {other}"),
+        };
+        assert!(
+            types.is_empty() || types.iter().any(|name| matches(name)),
+            "{at}: {value} is not {types:?}"
+        );
+        if let Some(minimum) = schema["minimum"].as_f64() {
+            assert!(
+                value.as_f64().is_none_or(|n| n >= minimum),
+                "{at}: {value} < {minimum}"
+            );
+        }
+        if let Some(maximum) = schema["maximum"].as_f64() {
+            assert!(
+                value.as_f64().is_none_or(|n| n <= maximum),
+                "{at}: {value} > {maximum}"
+            );
+        }
+        if let (Value::Array(items), Some(item)) = (value, schema.get("items")) {
+            for (index, element) in items.iter().enumerate() {
+                check(element, item, root, &format!("{at}[{index}]"));
+            }
+        }
+        if let Value::Object(fields) = value {
+            let properties = schema["properties"].as_object();
+            for required in schema["required"].as_array().into_iter().flatten() {
+                let required = required.as_str().unwrap();
+                assert!(fields.contains_key(required), "{at}: missing {required}");
+            }
+            for (name, field) in fields {
+                let property = properties.and_then(|properties| properties.get(name));
+                let property = property.unwrap_or_else(|| panic!("{at}: unknown field {name}"));
+                check(field, property, root, &format!("{at}.{name}"));
+            }
+        }
+    }
+
+    let schema = serde_json::to_value(schema_for!(T)).unwrap();
+    let value: Value = serde_json::from_str(json).unwrap();
+    check(&value, &schema, &schema, "$");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_status() -> Status {
+        Status::new(
+            "beam-lx",
+            Some(true),
+            Some(50),
+            Some(4000),
+            Some(55),
+            Some(4100),
+            12.5,
+        )
+        .with_identity(
+            Some("2219FE12\"A".to_string()),
+            Some("RQM 40.00.B0012".to_string()),
+        )
+        .with_lumens(Some(250))
+        .with_temperature_percent(Some(34))
+        .with_name(Some("Litra Beam LX".to_string()))
+        .with_raw_brightness(Some(120))
+    }
+
+    fn round_trip<T: Serialize + for<'de> Deserialize<'de> + JsonSchema>(value: &T) -> T {
+        let json = to_json(value);
+        assert_conforms::<T>(&json);
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn status_round_trips_against_its_schema() {
+        let full = full_status();
+        assert_eq!(round_trip(&full), full);
+        let bare = Status::new("glow", None, None, None, None, None, 0.0);
+        assert_eq!(round_trip(&bare), bare);
+        let json = to_json(&bare);
+        assert!(
+            !json.contains("raw_brightness") && !json.contains("\"name\""),
+            "{json}"
+        );
+        let all = vec![full, bare];
+        assert_eq!(round_trip(&all), all);
+    }
+
+    #[test]
+    fn devices_round_trip_against_their_schema() {
+        let devices = vec![
+            Device::new(
+                0,
+                "glow",
+                Some("Litra Glow".to_string()),
+                Some("2219FE12".to_string()),
+                "/dev/hidraw3".to_string(),
+            ),
+            Device::new(
+                1,
+                "beam",
+                None,
+                None,
+                r"\\?\HID#VID_046D&PID_C901".to_string(),
+            ),
+        ];
+        assert_eq!(round_trip(&devices), devices);
+        assert_eq!(round_trip(&Vec::<Device>::new()), []);
+    }
+
+    #[test]
+    fn errors_round_trip_against_their_schema() {
+        let error = ErrorReport::new("Litra device not found", "device-not-found");
+        assert_eq!(round_trip(&error), error);
+    }
+
+    #[test]
+    fn schema_check_catches_a_changed_shape() {
+        let renamed = to_json(&full_status()).replace("\"lumens\"", "\"lumen\"");
+        let result = std::panic::catch_unwind(|| assert_conforms::<Status>(&renamed));
+        assert!(result.is_err());
+        let string_power = to_json(&full_status()).replace("\"power\":true", "\"power\":\"on\"");
+        let result = std::panic::catch_unwind(|| assert_conforms::<Status>(&string_power));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn status_snapshots() {
+        assert_eq!(
+            to_json(&full_status()),
+            r#"{"schema_version":11,"model":"beam-lx","power":true,"brightness":50,"temperature":4000,"temperature_percent":34,"effective_brightness":55,"effective_temperature":4100,"lumens":250,"on_hours":12.5,"serial":"2219FE12\"A","firmware":"RQM 40.00.B0012","name":"Litra Beam LX","raw_brightness":120}"#
+        );
+        // A lamp that answered nothing in time.
+        assert_eq!(
+            to_json(&Status::new("glow", None, None, None, None, None, 0.0)),
+            r#"{"schema_version":11,"model":"glow","power":null,"brightness":null,"temperature":null,"temperature_percent":null,"effective_brightness":null,"effective_temperature":null,"lumens":null,"on_hours":0.0,"serial":null,"firmware":null}"#
+        );
+        // Power read, the rest timed out.
+        assert_eq!(
+            to_json(&Status::new(
+                "beam",
+                Some(false),
+                None,
+                None,
+                None,
+                None,
+                1.5
+            )),
+            r#"{"schema_version":11,"model":"beam","power":false,"brightness":null,"temperature":null,"temperature_percent":null,"effective_brightness":null,"effective_temperature":null,"lumens":null,"on_hours":1.5,"serial":null,"firmware":null}"#
+        );
+    }
+
+    #[test]
+    fn device_error_and_doctor_snapshots() {
+        let device = Device::new(
+            0,
+            "glow",
+            None,
+            Some("2219FE12".to_string()),
+            "1-2:1.0".to_string(),
+        );
+        assert_eq!(
+            to_json(&vec![device]),
+            r#"[{"schema_version":11,"index":0,"model":"glow","product":null,"serial":"2219FE12","path":"1-2:1.0"}]"#
+        );
+        assert_eq!(
+            to_json(&ErrorReport::new(
+                "Litra device not found",
+                "device-not-found"
+            )),
+            r#"{"schema_version":11,"error":"Litra device not found","kind":"device-not-found"}"#
+        );
+        let report = DoctorReport::new(vec![
+            Check {
+                name: "device".to_string(),
+                outcome: CheckOutcome::Pass,
+                detail: "Litra Glow".to_string(),
+                hint: None,
+            },
+            Check {
+                name: "udev".to_string(),
+                outcome: CheckOutcome::Fail,
+                detail: "no rule".to_string(),
+                hint: Some("install the rule".to_string()),
+            },
+        ]);
+        assert_eq!(
+            to_json(&report),
+            r#"{"schema_version":11,"checks":[{"name":"device","outcome":"pass","detail":"Litra Glow"},{"name":"udev","outcome":"fail","detail":"no rule","hint":"install the rule"}]}"#
+        );
+    }
+
+    /// The fields of every output type with their JSON types, as the
+    /// schema document gives them.
+    fn shape() -> String {
+        use serde_json::Value;
+
+        fn fields(schema: &Value, root: &Value) -> String {
+            if let Some(reference) = schema["$ref"].as_str() {
+                let name = reference.trim_start_matches("#/$defs/");
+                return fields(&root["$defs"][name], root);
+            }
+            if let Some(items) = schema.get("items") {
+                return format!("[{}]", fields(items, root));
+            }
+            if let Some(choices) = schema["enum"].as_array() {
+                let choices: Vec<&str> = choices.iter().filter_map(Value::as_str).collect();
+                return choices.join("|");
+            }
+            match schema["properties"].as_object() {
+                Some(properties) => {
+                    let required = schema["required"].as_array().cloned().unwrap_or_default();
+                    let fields: Vec<String> = properties
+                        .iter()
+                        .map(|(name, property)| {
+                            let optional = if required.contains(&Value::from(name.as_str())) {
+                                ""
+                            } else {
+                                "?"
+                            };
+                            format!("{name}{optional}: {}", fields(property, root))
+                        })
+                        .collect();
+                    format!("{{{}}}", fields.join(", "))
+                }
+                None => match &schema["type"] {
+                    Value::Array(types) => {
+                        let types: Vec<&str> = types.iter().filter_map(Value::as_str).collect();
+                        types.join("|")
+                    }
+                    other => other.as_str().unwrap_or("any").to_string(),
+                },
+            }
+        }
+
+        let document: Value = serde_json::from_str(&document()).unwrap();
+        ["status", "error", "doctor", "devices"]
+            .iter()
+            .map(|name| format!("{name} {}", fields(&document[name], &document[name])))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// When this fails, the shape of the JSON output changed: bump
+    /// [`SCHEMA_VERSION`] and update the snapshots above along with it.
+    #[test]
+    fn schema_changes_bump_the_version() {
+        assert_eq!(SCHEMA_VERSION, 11);
+        assert_eq!(
+            shape(),
+            concat!(
+                "status {brightness?: integer|null, effective_brightness?: integer|null, ",
+                "effective_temperature?: integer|null, firmware?: string|null, ",
+                "lumens?: integer|null, model: string, name?: string|null, on_hours: number, ",
+                "power?: boolean|null, raw_brightness?: integer|null, schema_version: integer, ",
+                "serial?: string|null, temperature?: integer|null, ",
+                "temperature_percent?: integer|null}\n",
+                "error {error: string, kind: string, schema_version: integer}\n",
+                "doctor {checks: [{detail: string, hint?: string|null, name: string, ",
+                "outcome: pass|warn|fail|skip}], schema_version: integer}\n",
+                "devices [{index: integer, model: string, path: string, ",
+                "product?: string|null, schema_version: integer, serial?: string|null}]",
+            )
+        );
+    }
+
+    #[test]
+    fn document_lists_every_output() {
+        let document: serde_json::Value = serde_json::from_str(&document()).unwrap();
+        assert_eq!(document["schema_version"], SCHEMA_VERSION);
+        for name in ["status", "status_all", "error", "doctor", "devices"] {
+            assert!(document[name].is_object(), "{name}");
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const APP_DIR: &str = "litra-glow";
+
+/// Per-user directory for the app's own files, e.g. `~/.config/litra-glow`.
+#[cfg(not(test))]
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(APP_DIR))
+}
+
+/// Tests keep their files in a directory of their own, never the user's.
+#[cfg(test)]
+pub fn config_dir() -> Option<PathBuf> {
+    Some(std::env::temp_dir().join(format!("{APP_DIR}-test-{}", std::process::id())))
+}
+
+/// Replaces `path` with `contents` so readers see either the old or the new
+/// file, never a partial write.
+pub fn atomic_write(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+    {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)
+}
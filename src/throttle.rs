@@ -0,0 +1,93 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+/// Counts repeated events so a chatty path can log one summary per window
+/// instead of one line per event.
+///
+/// Callers should check `tracing::enabled!` first so nothing is counted when
+/// the summary would be discarded anyway.
+pub struct LogThrottle {
+    window: Duration,
+    window_start: Cell<Option<Instant>>,
+    count: Cell<u32>,
+}
+
+impl LogThrottle {
+    pub const fn new(window: Duration) -> Self {
+        Self {
+            window,
+            window_start: Cell::new(None),
+            count: Cell::new(0),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Records one event. Once the window has elapsed, returns the number of
+    /// events seen in it, including this one, and starts a new window.
+    pub fn hit(&self, now: Instant) -> Option<u32> {
+        let count = self.count.get().saturating_add(1);
+        let start = *self.window_start.get().get_or_insert(now);
+        if now.duration_since(start) < self.window {
+            self.window_start.set(Some(start));
+            self.count.set(count);
+            return None;
+        }
+        self.window_start.set(None);
+        self.count.set(0);
+        Some(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(2);
+
+    #[test]
+    fn counts_hits_until_the_window_elapses() {
+        let throttle = LogThrottle::new(WINDOW);
+        let start = Instant::now();
+        for ms in [0, 10, 500, 1999] {
+            assert_eq!(throttle.hit(start + Duration::from_millis(ms)), None);
+        }
+        assert_eq!(throttle.hit(start + WINDOW), Some(5));
+    }
+
+    #[test]
+    fn a_summary_starts_a_new_window() {
+        let throttle = LogThrottle::new(WINDOW);
+        let start = Instant::now();
+        throttle.hit(start);
+        assert_eq!(throttle.hit(start + WINDOW), Some(2));
+
+        let next = start + WINDOW + Duration::from_millis(100);
+        assert_eq!(throttle.hit(next), None);
+        assert_eq!(throttle.hit(next + WINDOW / 2), None);
+        assert_eq!(throttle.hit(next + WINDOW), Some(3));
+    }
+
+    #[test]
+    fn a_quiet_spell_is_summarized_on_the_next_hit() {
+        let throttle = LogThrottle::new(WINDOW);
+        let start = Instant::now();
+        throttle.hit(start);
+        assert_eq!(throttle.hit(start + Duration::from_secs(60)), Some(2));
+        assert_eq!(
+            throttle.hit(start + Duration::from_secs(61)),
+            None,
+            "the window restarts from the first hit after a summary"
+        );
+    }
+
+    #[test]
+    fn a_zero_window_summarizes_every_hit() {
+        let throttle = LogThrottle::new(Duration::ZERO);
+        let now = Instant::now();
+        assert_eq!(throttle.hit(now), Some(1));
+        assert_eq!(throttle.hit(now), Some(1));
+    }
+}
@@ -0,0 +1,433 @@
+use std::cell::{Cell, RefCell};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::protocol::{Command, Model, Response};
+use crate::transport::Transport;
+use crate::usb::{DeviceInfo, Error};
+
+pub const TRACE_FORMAT: &str = "litra-glow-trace";
+pub const TRACE_VERSION: u32 = 1;
+
+/// First line of every recording session in a trace file.
+#[derive(Debug, Serialize, Deserialize)]
+struct TraceHeader {
+    format: String,
+    version: u32,
+    started_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Out,
+    In,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceFrame {
+    pub t_ms: u64,
+    pub dir: Direction,
+    pub data: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meaning: Option<String>,
+}
+
+impl TraceFrame {
+    fn new(t_ms: u64, dir: Direction, data: &[u8]) -> Self {
+        let meaning = match dir {
+            Direction::Out => Command::from_bytes(data).map(|cmd| format!("{cmd:?}")),
+            Direction::In => Response::from_bytes(data)
+                .filter(|resp| !matches!(resp, Response::Unknown { .. }))
+                .map(|resp| format!("{resp:?}")),
+        };
+        Self {
+            t_ms,
+            dir,
+            data: encode_hex(data),
+            meaning,
+        }
+    }
+}
+
+pub fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn epoch_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// The trace file for one of several lamps traced at once, so their
+/// frames do not interleave: `lamp`, a serial number or index, goes before
+/// the extension, and `trace.jsonl` becomes `trace-DESK01.jsonl`.
+pub fn lamp_path(path: &Path, lamp: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push("-");
+    name.push(lamp.replace(|c: char| !c.is_ascii_alphanumeric(), "_"));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+/// Wraps a transport and appends every frame to a JSONL trace file. Lines
+/// are buffered and flushed whenever a read finds the lamp quiet, and on
+/// drop, so tracing can stay on; a failed write is logged, never fatal.
+pub struct TracingTransport {
+    inner: Box<dyn Transport>,
+    file: RefCell<BufWriter<File>>,
+    start: Instant,
+}
+
+impl TracingTransport {
+    pub fn new(inner: Box<dyn Transport>, path: &Path) -> Result<Self, Error> {
+        let mut file = BufWriter::new(OpenOptions::new().create(true).append(true).open(path)?);
+        let header = TraceHeader {
+            format: TRACE_FORMAT.to_string(),
+            version: TRACE_VERSION,
+            started_ms: epoch_ms(),
+        };
+        writeln!(
+            file,
+            "{}",
+            serde_json::to_string(&header).map_err(trace_error)?
+        )?;
+        info!("Tracing device traffic to {}", path.display());
+        Ok(Self {
+            inner,
+            file: RefCell::new(file),
+            start: Instant::now(),
+        })
+    }
+
+    fn record(&self, dir: Direction, data: &[u8]) {
+        let frame = TraceFrame::new(self.start.elapsed().as_millis() as u64, dir, data);
+        let Ok(line) = serde_json::to_string(&frame) else {
+            return;
+        };
+        if let Err(e) = writeln!(self.file.borrow_mut(), "{line}") {
+            warn!("Failed to write trace frame: {}", e);
+        }
+    }
+}
+
+impl Transport for TracingTransport {
+    fn write(&self, data: &[u8]) -> Result<usize, Error> {
+        let written = self.inner.write(data)?;
+        self.record(Direction::Out, data);
+        Ok(written)
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, Error> {
+        let len = self.inner.read_timeout(buf, timeout_ms)?;
+        if len > 0 {
+            self.record(Direction::In, &buf[..len]);
+        } else if let Err(e) = self.file.borrow_mut().flush() {
+            warn!("Failed to flush trace file: {}", e);
+        }
+        Ok(len)
+    }
+
+    fn serial_number(&self) -> Option<String> {
+        self.inner.serial_number()
+    }
+
+    fn model(&self) -> Model {
+        self.inner.model()
+    }
+
+    fn info(&self) -> Option<DeviceInfo> {
+        self.inner.info()
+    }
+}
+
+struct ReplayFrame {
+    at: Duration,
+    data: Vec<u8>,
+}
+
+/// Plays back inbound frames from a trace with their original relative timing
+/// and checks outbound frames against the recording.
+pub struct ReplayTransport {
+    outbound: Vec<ReplayFrame>,
+    inbound: Vec<ReplayFrame>,
+    next_out: Cell<usize>,
+    next_in: Cell<usize>,
+    start: Instant,
+}
+
+impl ReplayTransport {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut origin_ms = None;
+        let mut session_ms = 0;
+        let mut outbound = Vec::new();
+        let mut inbound = Vec::new();
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let line_no = index + 1;
+            if let Ok(header) = serde_json::from_str::<TraceHeader>(&line) {
+                if header.format != TRACE_FORMAT || header.version != TRACE_VERSION {
+                    return Err(Error::Trace(format!(
+                        "line {line_no}: unsupported trace {} v{}",
+                        header.format, header.version
+                    )));
+                }
+                session_ms = header
+                    .started_ms
+                    .saturating_sub(*origin_ms.get_or_insert(header.started_ms));
+                continue;
+            }
+            if origin_ms.is_none() {
+                return Err(Error::Trace("missing trace header".to_string()));
+            }
+            let frame: TraceFrame = serde_json::from_str(&line)
+                .map_err(|e| Error::Trace(format!("line {line_no}: {e}")))?;
+            let data = decode_hex(&frame.data)
+                .ok_or_else(|| Error::Trace(format!("line {line_no}: invalid hex data")))?;
+            let replay = ReplayFrame {
+                at: Duration::from_millis(session_ms + frame.t_ms),
+                data,
+            };
+            match frame.dir {
+                Direction::Out => outbound.push(replay),
+                Direction::In => inbound.push(replay),
+            }
+        }
+
+        info!(
+            "Replaying {} outbound and {} inbound frames from {}",
+            outbound.len(),
+            inbound.len(),
+            path.display()
+        );
+        Ok(Self {
+            outbound,
+            inbound,
+            next_out: Cell::new(0),
+            next_in: Cell::new(0),
+            start: Instant::now(),
+        })
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn write(&self, data: &[u8]) -> Result<usize, Error> {
+        let index = self.next_out.get();
+        match self.outbound.get(index) {
+            Some(expected) if expected.data == data => {}
+            Some(expected) => warn!(
+                "Replay divergence at outbound frame {}: expected {}, got {}",
+                index,
+                encode_hex(&expected.data),
+                encode_hex(data)
+            ),
+            None => warn!(
+                "Replay divergence: unexpected outbound frame {}",
+                encode_hex(data)
+            ),
+        }
+        self.next_out.set(index + 1);
+        Ok(data.len())
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, Error> {
+        let index = self.next_in.get();
+        let timeout = Duration::from_millis(timeout_ms.max(0) as u64);
+        let Some(frame) = self.inbound.get(index) else {
+            thread::sleep(timeout);
+            return Ok(0);
+        };
+        let wait = frame.at.saturating_sub(self.start.elapsed());
+        if wait > timeout {
+            thread::sleep(timeout);
+            return Ok(0);
+        }
+        thread::sleep(wait);
+        let len = frame.data.len().min(buf.len());
+        buf[..len].copy_from_slice(&frame.data[..len]);
+        self.next_in.set(index + 1);
+        Ok(len)
+    }
+}
+
+fn trace_error(e: serde_json::Error) -> Error {
+    Error::Trace(e.to_string())
+}
+
+#[cfg(test)]
+impl ReplayTransport {
+    /// The recorded inbound frames as the parser reads them today, without
+    /// waiting out their timing.
+    pub fn responses(&self) -> Vec<Option<Response>> {
+        self.inbound
+            .iter()
+            .map(|frame| Response::from_bytes(&frame.data))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Firmware, Trigger};
+    use crate::transport::FakeLitra;
+    use crate::usb::{DeviceOptions, LitraDevice, TransportKind};
+
+    const TIMEOUT: Duration = Duration::from_millis(500);
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    /// Every frame of the fixture `name`, skipping its header.
+    fn frames(name: &str) -> Vec<TraceFrame> {
+        std::fs::read_to_string(fixture(name))
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn each_lamp_gets_its_own_trace() {
+        let path = Path::new("/tmp/traces/trace.jsonl");
+        assert_eq!(
+            lamp_path(path, "DESK01"),
+            Path::new("/tmp/traces/trace-DESK01.jsonl")
+        );
+        assert_eq!(lamp_path(path, "1"), Path::new("/tmp/traces/trace-1.jsonl"));
+        assert_eq!(lamp_path(Path::new("trace"), "a/b"), Path::new("trace-a_b"));
+    }
+
+    #[test]
+    fn fixtures_parse_as_recorded() {
+        for name in ["glow-buttons.trace", "beam-lx-identity.trace"] {
+            for frame in frames(name) {
+                let data = decode_hex(&frame.data).unwrap();
+                let parsed = TraceFrame::new(frame.t_ms, frame.dir, &data);
+                assert_eq!(parsed.meaning, frame.meaning, "{name} at {} ms", frame.t_ms);
+            }
+        }
+    }
+
+    #[test]
+    fn glow_fixture_replays_buttons() {
+        let replay = ReplayTransport::load(&fixture("glow-buttons.trace")).unwrap();
+        assert_eq!(
+            replay.responses(),
+            [
+                Some(Response::Power(true, Trigger::Reply)),
+                Some(Response::Brightness(100, Trigger::Reply)),
+                Some(Response::Temperature(4500, Trigger::Reply)),
+                Some(Response::Brightness(110, Trigger::Button)),
+                Some(Response::Brightness(120, Trigger::Button)),
+                Some(Response::Brightness(130, Trigger::Button)),
+                Some(Response::Temperature(4000, Trigger::Button)),
+                Some(Response::Power(false, Trigger::Button)),
+            ]
+        );
+    }
+
+    #[test]
+    fn beam_lx_fixture_replays_identity() {
+        let replay = ReplayTransport::load(&fixture("beam-lx-identity.trace")).unwrap();
+        let responses = replay.responses();
+        assert_eq!(responses[0], Some(Response::FeatureIndex(2)));
+        assert_eq!(
+            responses[1],
+            Some(Response::Firmware(Firmware {
+                prefix: *b"RQM",
+                number: 0x41,
+                revision: 0x02,
+                build: 0x0015,
+            }))
+        );
+        assert_eq!(responses[2], Some(Response::Serial(*b"2326FE80A1B4")));
+        // The HID++ error reply is dropped, the unknown report kept whole.
+        assert_eq!(responses[3], None);
+        assert_eq!(
+            responses[4],
+            Some(Response::Brightness(300, Trigger::Reply))
+        );
+        assert!(matches!(responses[6], Some(Response::Unknown { .. })));
+    }
+
+    #[test]
+    fn beam_lx_fixture_answers_device_queries() {
+        let device = LitraDevice::open(&DeviceOptions {
+            transport: TransportKind::Replay(fixture("beam-lx-identity.trace")),
+            ..DeviceOptions::default()
+        })
+        .unwrap();
+        let identity = device.info(TIMEOUT).unwrap();
+        assert_eq!(identity.firmware.as_deref(), Some("RQM 41.02.B0015"));
+        assert_eq!(identity.serial.as_deref(), Some("2326FE80A1B4"));
+    }
+
+    #[test]
+    fn recorded_traffic_replays() {
+        let path =
+            std::env::temp_dir().join(format!("litra-glow-trace-{}.trace", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let fake = FakeLitra::new(Model::Glow);
+        fake.push(Response::Brightness(42, Trigger::Button));
+        let device = LitraDevice::open(&DeviceOptions {
+            transport: TransportKind::Fake(fake.clone()),
+            trace_out: Some(path.clone()),
+            ..DeviceOptions::default()
+        })
+        .unwrap();
+        device
+            .set_confirmed(Command::SetPower(true), TIMEOUT)
+            .unwrap();
+        drop(device);
+
+        let replay = ReplayTransport::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            replay.responses(),
+            [
+                Some(Response::Brightness(42, Trigger::Button)),
+                Some(Response::Power(true, Trigger::Reply)),
+            ]
+        );
+        let outbound: Vec<_> = replay
+            .outbound
+            .iter()
+            .map(|frame| Command::from_bytes(&frame.data))
+            .collect();
+        assert_eq!(
+            outbound,
+            [Some(Command::SetPower(true)), Some(Command::GetPower)]
+        );
+    }
+}
@@ -0,0 +1,332 @@
+use std::time::{Duration, Instant};
+
+use crate::convert::round_temperature;
+use crate::protocol::{
+    Capabilities, Command, RoundMode, kelvin_to_mired, mired_to_kelvin, perceptual_to_raw,
+    raw_to_perceptual,
+};
+
+/// Minimum time between two fade steps sent to the lamp.
+pub const STEP_INTERVAL: Duration = Duration::from_millis(50);
+
+/// One interpolated setting: where it started and where it ends.
+#[derive(Debug, Clone, Copy)]
+struct Axis {
+    from: u16,
+    to: u16,
+}
+
+impl Axis {
+    fn at(self, progress: f32) -> u16 {
+        let span = self.to as f32 - self.from as f32;
+        (self.from as f32 + span * progress).round() as u16
+    }
+
+    /// Like [`Axis::at`] for brightness levels, moving evenly in how bright
+    /// the lamp looks instead. The ends are exact.
+    fn at_perceptual(self, progress: f32, capabilities: &Capabilities, gamma: f32) -> u16 {
+        if progress <= 0.0 {
+            return self.from;
+        }
+        if progress >= 1.0 {
+            return self.to;
+        }
+        let from = raw_to_perceptual(self.from, capabilities, gamma);
+        let to = raw_to_perceptual(self.to, capabilities, gamma);
+        perceptual_to_raw(from + (to - from) * progress, capabilities, gamma)
+    }
+}
+
+/// A linear fade of brightness and temperature that share one clock, so
+/// both reach their targets at the same moment. Temperature moves linearly
+/// in mireds, which the eye sees as an even change, rather than in kelvin.
+#[derive(Debug, Clone, Copy)]
+pub struct Transition {
+    start: Instant,
+    duration: Duration,
+    interval: Duration,
+    brightness: Option<Axis>,
+    temperature: Option<Axis>,
+    /// Gamma and limits of the lamp when brightness fades perceptually.
+    perceptual: Option<(f32, Capabilities)>,
+}
+
+/// Values for one step of a transition; `None` for axes not fading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    pub brightness: Option<u16>,
+    pub temperature: Option<u16>,
+}
+
+impl Transition {
+    pub fn new(
+        start: Instant,
+        duration: Duration,
+        brightness: (u16, u16),
+        temperature: (u16, u16),
+    ) -> Self {
+        Self {
+            start,
+            duration,
+            interval: STEP_INTERVAL,
+            brightness: Some(Axis {
+                from: brightness.0,
+                to: brightness.1,
+            }),
+            temperature: Some(Axis {
+                from: kelvin_to_mired(temperature.0),
+                to: kelvin_to_mired(temperature.1),
+            }),
+            perceptual: None,
+        }
+    }
+
+    /// Fades brightness evenly in how bright it looks on a lamp with
+    /// `capabilities`, through `gamma`, rather than evenly in levels.
+    pub fn with_perceptual(mut self, gamma: f32, capabilities: Capabilities) -> Self {
+        self.perceptual = Some((gamma, capabilities));
+        self
+    }
+
+    /// Spaces steps at least `interval` apart instead of [`STEP_INTERVAL`].
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Stops fading brightness, e.g. because it was set by hand. The other
+    /// axis still runs to its target.
+    pub fn drop_brightness(&mut self) {
+        self.brightness = None;
+    }
+
+    /// Stops fading temperature; see [`Transition::drop_brightness`].
+    pub fn drop_temperature(&mut self) {
+        self.temperature = None;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.brightness.is_none() && self.temperature.is_none()
+    }
+
+    pub fn is_done(&self, now: Instant) -> bool {
+        self.is_empty() || now.duration_since(self.start) >= self.duration
+    }
+
+    /// Values the lamp should show at `now`. Temperatures are snapped onto
+    /// the device's step grid, rounding back toward the start so no step
+    /// passes the target; the last step lands on it.
+    pub fn step(&self, now: Instant) -> Step {
+        let progress = if self.duration.is_zero() {
+            1.0
+        } else {
+            (now.duration_since(self.start).as_secs_f32() / self.duration.as_secs_f32()).min(1.0)
+        };
+        Step {
+            brightness: self.brightness.map(|axis| match &self.perceptual {
+                Some((gamma, capabilities)) => axis.at_perceptual(progress, capabilities, *gamma),
+                None => axis.at(progress),
+            }),
+            temperature: self.temperature.map(|axis| {
+                let mode = if progress >= 1.0 {
+                    RoundMode::Nearest
+                } else if axis.from > axis.to {
+                    // Fewer mireds is a higher temperature.
+                    RoundMode::Down
+                } else {
+                    RoundMode::Up
+                };
+                round_temperature(mired_to_kelvin(axis.at(progress)), mode)
+            }),
+        }
+    }
+}
+
+/// The set commands of a [`Transition`] with the moment to send each, one
+/// step per interval from its start. Steps that would repeat the value
+/// sent before are left out; the last step, at the end of the transition,
+/// sets every fading setting to its target. A zero duration is that last
+/// step alone. It lives here rather than in `protocol` because it steps
+/// through a `Transition`, which needs the device's temperature grid.
+#[derive(Debug, Clone)]
+pub struct Fade {
+    transition: Transition,
+    at: Instant,
+    sent: Step,
+    queued: Option<(Instant, Command)>,
+    finished: bool,
+}
+
+impl Fade {
+    pub fn new(transition: Transition) -> Self {
+        Self {
+            at: transition.start,
+            sent: transition.step(transition.start),
+            queued: None,
+            finished: false,
+            transition,
+        }
+    }
+}
+
+impl Iterator for Fade {
+    type Item = (Instant, Command);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(queued) = self.queued.take() {
+            return Some(queued);
+        }
+        while !self.finished {
+            let end = self.transition.start + self.transition.duration;
+            let at = self.at.min(end);
+            let last = at >= end || self.transition.is_done(at);
+            self.finished = last;
+            self.at = at + self.transition.interval;
+            let step = self.transition.step(at);
+            let fresh =
+                |value: Option<u16>, sent: Option<u16>| value.filter(|&v| last || sent != Some(v));
+            let brightness = fresh(step.brightness, self.sent.brightness);
+            let temperature = fresh(step.temperature, self.sent.temperature);
+            self.sent = step;
+            match (
+                brightness.map(|level| (at, Command::SetBrightness(level))),
+                temperature.map(|kelvin| (at, Command::SetTemperature(kelvin))),
+            ) {
+                (Some(first), second) => {
+                    self.queued = second;
+                    return Some(first);
+                }
+                (None, Some(only)) => return Some(only),
+                (None, None) => {}
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{DEFAULT_GAMMA, MAX_TEMPERATURE, MIN_TEMPERATURE, Model};
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    /// Values sent for one setting, each with its time from the start.
+    type Timed = Vec<(Duration, u16)>;
+
+    /// The brightness and temperature commands of `fade`.
+    fn split(start: Instant, fade: Fade) -> (Timed, Timed) {
+        let mut brightness = Vec::new();
+        let mut temperature = Vec::new();
+        for (at, cmd) in fade {
+            match cmd {
+                Command::SetBrightness(level) => brightness.push((at - start, level)),
+                Command::SetTemperature(kelvin) => temperature.push((at - start, kelvin)),
+                other => panic!("unexpected {other:?}"),
+            }
+        }
+        (brightness, temperature)
+    }
+
+    fn is_monotonic(values: &Timed, rising: bool) -> bool {
+        values.windows(2).all(|pair| match rising {
+            true => pair[0].1 <= pair[1].1,
+            false => pair[0].1 >= pair[1].1,
+        })
+    }
+
+    #[test]
+    fn one_step_per_interval_after_the_start() {
+        let start = Instant::now();
+        for (duration, interval, steps) in [(1000, 50, 20), (500, 100, 5), (1030, 100, 11)] {
+            let transition = Transition::new(start, ms(duration), (20, 250), (2700, 6500))
+                .with_interval(ms(interval));
+            let (brightness, _) = split(start, Fade::new(transition));
+            assert_eq!(brightness.len(), steps, "{duration} ms every {interval} ms");
+            let times: Vec<_> = brightness.iter().map(|(at, _)| *at).collect();
+            assert!(
+                times
+                    .windows(2)
+                    .all(|pair| pair[1] - pair[0] <= ms(interval))
+            );
+            assert!(times.windows(2).all(|pair| pair[0] < pair[1]));
+            assert_eq!(times.last(), Some(&ms(duration)));
+        }
+    }
+
+    #[test]
+    fn values_move_one_way_and_end_on_the_target() {
+        let start = Instant::now();
+        let glow = Model::Glow.capabilities();
+        for (brightness, temperature) in [
+            ((20, 250), (MIN_TEMPERATURE, MAX_TEMPERATURE)),
+            ((250, 20), (MAX_TEMPERATURE, MIN_TEMPERATURE)),
+            ((100, 101), (4000, 4100)),
+        ] {
+            for perceptual in [false, true] {
+                let mut transition = Transition::new(start, ms(2000), brightness, temperature);
+                if perceptual {
+                    transition = transition.with_perceptual(DEFAULT_GAMMA, glow);
+                }
+                for interval in [10, 50, 300] {
+                    let fade = Fade::new(transition.with_interval(ms(interval)));
+                    let (levels, kelvins) = split(start, fade);
+                    let case = format!("{brightness:?} {temperature:?} every {interval} ms");
+                    assert!(is_monotonic(&levels, brightness.0 < brightness.1), "{case}");
+                    assert!(
+                        is_monotonic(&kelvins, temperature.0 < temperature.1),
+                        "{case}"
+                    );
+                    assert_eq!(levels.last(), Some(&(ms(2000), brightness.1)), "{case}");
+                    assert_eq!(kelvins.last(), Some(&(ms(2000), temperature.1)), "{case}");
+                    assert!(
+                        kelvins.iter().all(|(_, kelvin)| kelvin % 100 == 0),
+                        "{case}: {kelvins:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn repeated_values_are_not_sent_again() {
+        let start = Instant::now();
+        let transition =
+            Transition::new(start, ms(1000), (100, 102), (4000, 4000)).with_interval(ms(10));
+        let (brightness, temperature) = split(start, Fade::new(transition));
+        let levels: Vec<_> = brightness.iter().map(|(_, level)| *level).collect();
+        assert_eq!(levels, [101, 102, 102]);
+        assert_eq!(temperature, [(ms(1000), 4000)]);
+    }
+
+    #[test]
+    fn zero_duration_is_the_final_step_alone() {
+        let start = Instant::now();
+        for (brightness, temperature) in [((20, 250), (2700, 6500)), ((80, 80), (5000, 5000))] {
+            let transition = Transition::new(start, Duration::ZERO, brightness, temperature);
+            assert_eq!(
+                Fade::new(transition).collect::<Vec<_>>(),
+                [
+                    (start, Command::SetBrightness(brightness.1)),
+                    (start, Command::SetTemperature(temperature.1)),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn dropped_axes_are_left_alone() {
+        let start = Instant::now();
+        let mut transition = Transition::new(start, ms(500), (20, 250), (2700, 6500));
+        transition.drop_temperature();
+        let (brightness, temperature) = split(start, Fade::new(transition));
+        assert!(!brightness.is_empty());
+        assert!(temperature.is_empty());
+    }
+}
@@ -0,0 +1,793 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use hidapi::{DeviceInfo as HidDeviceInfo, HidApi, HidDevice};
+use tracing::{debug, info, warn};
+
+use crate::protocol::{
+    Command, MIN_TEMPERATURE, Model, Response, Trigger, VENDOR_ID, is_supported,
+};
+use crate::usb::{DeviceInfo, Error};
+
+/// Raw frame I/O under [`crate::usb::LitraDevice`]. Implemented by the HID
+/// backend, the `--dry-run` simulation and trace replay, so everything above
+/// it runs the same against a real or scripted lamp.
+pub trait Transport: Send {
+    fn write(&self, data: &[u8]) -> Result<usize, Error>;
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, Error>;
+
+    /// Serial number of the connected lamp, when the transport knows it.
+    fn serial_number(&self) -> Option<String> {
+        None
+    }
+
+    /// Model of the connected lamp. Simulated lamps are Glows.
+    fn model(&self) -> Model {
+        Model::default()
+    }
+
+    /// What the HID layer reported about the lamp when it was opened.
+    fn info(&self) -> Option<DeviceInfo> {
+        None
+    }
+}
+
+#[cfg(all(
+    target_os = "linux",
+    feature = "linux-hidraw",
+    feature = "linux-libusb"
+))]
+compile_error!(
+    "the `linux-hidraw` and `linux-libusb` features are exclusive; \
+     build the libusb backend with `--no-default-features --features tray,linux-libusb`"
+);
+
+/// HID backend compiled into this build. hidapi links exactly one backend,
+/// chosen on Linux by the `linux-hidraw` or `linux-libusb` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HidBackend {
+    Hidraw,
+    Libusb,
+    Native,
+}
+
+impl HidBackend {
+    pub const fn current() -> Self {
+        if cfg!(not(target_os = "linux")) {
+            HidBackend::Native
+        } else if cfg!(feature = "linux-libusb") {
+            HidBackend::Libusb
+        } else {
+            HidBackend::Hidraw
+        }
+    }
+
+    /// The cargo feature selecting the other Linux backend, if any.
+    pub const fn alternative_feature(self) -> Option<&'static str> {
+        match self {
+            HidBackend::Hidraw => Some("linux-libusb"),
+            HidBackend::Libusb => Some("linux-hidraw"),
+            HidBackend::Native => None,
+        }
+    }
+
+    /// Checks the `hid_backend` setting against this build. hidapi cannot
+    /// switch backends at runtime, so asking for another one is an error
+    /// that says how to build it instead of being silently ignored.
+    pub fn check_setting(self, setting: &str) -> Result<(), String> {
+        let setting = setting.trim();
+        if setting.is_empty() || setting.eq_ignore_ascii_case(&self.to_string()) {
+            return Ok(());
+        }
+        match self.alternative_feature() {
+            Some(feature) if setting.eq_ignore_ascii_case(&feature["linux-".len()..]) => {
+                Err(format!(
+                    "hid_backend is \"{setting}\" but this build uses {self}; the backend is \
+                     chosen at build time, rebuild with \
+                     `--no-default-features --features tray,{feature}`"
+                ))
+            }
+            _ => Err(format!(
+                "hid_backend is \"{setting}\" but this build can only use {self}; \
+                 remove the setting or set it to \"{self}\""
+            )),
+        }
+    }
+
+    /// udev rule line granting this user access to `model` through this
+    /// backend.
+    pub fn udev_rule(self, model: Model) -> String {
+        match self {
+            HidBackend::Libusb => format!(
+                r#"SUBSYSTEM=="usb", ATTR{{idVendor}}=="{:04x}", ATTR{{idProduct}}=="{:04x}", MODE="0666""#,
+                VENDOR_ID,
+                model.product_id()
+            ),
+            HidBackend::Hidraw | HidBackend::Native => format!(
+                r#"KERNEL=="hidraw*", ATTRS{{idVendor}}=="{:04x}", ATTRS{{idProduct}}=="{:04x}", MODE="0666""#,
+                VENDOR_ID,
+                model.product_id()
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for HidBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HidBackend::Hidraw => write!(f, "hidraw"),
+            HidBackend::Libusb => write!(f, "libusb"),
+            HidBackend::Native => write!(f, "native"),
+        }
+    }
+}
+
+/// Logitech vendor usage page of the HID++ collection the lamp listens on.
+const HIDPP_USAGE_PAGE: u16 = 0xFF43;
+
+/// The process-wide HID context. Creating one initializes the backend and
+/// enumerates every HID device, which can stall for hundreds of
+/// milliseconds on Windows, so it is created on first use and afterwards
+/// only its device list is refreshed.
+static API: Mutex<Option<HidApi>> = Mutex::new(None);
+
+/// Runs `f` with the shared HID context, its device list refreshed.
+pub fn with_api<T>(f: impl FnOnce(&HidApi) -> Result<T, Error>) -> Result<T, Error> {
+    let mut api = API.lock().unwrap_or_else(PoisonError::into_inner);
+    let api = match &mut *api {
+        Some(api) => {
+            api.refresh_devices()?;
+            api
+        }
+        None => {
+            debug!("Initializing HID API...");
+            api.insert(HidApi::new()?)
+        }
+    };
+    f(api)
+}
+
+pub struct HidTransport {
+    device: HidDevice,
+    serial: Option<String>,
+    model: Model,
+    info: Option<DeviceInfo>,
+}
+
+impl HidTransport {
+    /// Every connected lamp, ordered by model in the probing order of
+    /// [`KNOWN_DEVICES`](crate::protocol::KNOWN_DEVICES), then by path so
+    /// the order is stable. A lamp with several HID interfaces is listed
+    /// once, by its HID++ one.
+    pub fn list() -> Result<Vec<DeviceInfo>, Error> {
+        with_api(|api| Ok(Self::list_from(api)))
+    }
+
+    fn list_from(api: &HidApi) -> Vec<DeviceInfo> {
+        let mut interfaces: Vec<&HidDeviceInfo> = lamps(api).collect();
+        interfaces.sort_by_key(|d| d.usage_page() != HIDPP_USAGE_PAGE);
+        let mut found: Vec<DeviceInfo> = Vec::new();
+        let mut seen: Vec<LampKey> = Vec::new();
+        for interface in interfaces {
+            let (Some(lamp), key) = (describe(interface), LampKey::of(interface)) else {
+                continue;
+            };
+            if !seen.contains(&key) {
+                seen.push(key);
+                found.push(lamp);
+            }
+        }
+        found.sort_by(|a, b| (a.model.priority(), &a.path).cmp(&(b.model.priority(), &b.path)));
+        found.dedup_by(|a, b| a.path == b.path);
+        found
+    }
+
+    /// Opens the lamp at `path`, else the one with `serial`, else the first
+    /// found, trying each supported model in turn. Lamps reporting an empty
+    /// serial, as some do behind hubs, are matched by their path instead.
+    pub fn open(serial: Option<&str>, path: Option<&str>) -> Result<Self, Error> {
+        with_api(|api| Self::open_from(api, serial, path))
+    }
+
+    fn open_from(api: &HidApi, serial: Option<&str>, path: Option<&str>) -> Result<Self, Error> {
+        let backend = HidBackend::current();
+        info!(
+            "Looking for device VID={:04x} PID={:04x?} via {} backend",
+            VENDOR_ID,
+            Model::supported_product_ids(),
+            backend
+        );
+        let not_found = || Error::DeviceNotFound(serial.map(str::to_string));
+        let path = match (path, serial) {
+            (Some(path), _) => Some(path.to_string()),
+            (None, Some(serial)) => Some(find_serial(api, serial).ok_or_else(not_found)?),
+            (None, None) => None,
+        };
+        let opened = match &path {
+            Some(path) => {
+                let model = lamps(api)
+                    .find(|d| d.path().to_string_lossy() == *path)
+                    .and_then(|d| Model::from_product_id(d.product_id()))
+                    .unwrap_or_default();
+                let path = CString::new(path.as_str()).map_err(|_| not_found())?;
+                api.open_path(&path).map(|device| (device, model))
+            }
+            None => open_any(api),
+        };
+        let (device, guessed) = match opened {
+            Ok(opened) => opened,
+            Err(e) => {
+                let present = lamps(api).any(|d| {
+                    path.as_ref()
+                        .is_none_or(|path| d.path().to_string_lossy() == *path)
+                });
+                if !present {
+                    return Err(not_found());
+                }
+                if let Some(denied) = permission_denied(api, path.as_deref(), &e) {
+                    return Err(denied);
+                }
+                if let Some(feature) = backend.alternative_feature() {
+                    warn!(
+                        "Device is present but the {} backend could not open it; \
+                         if another driver holds it, try a build with the `{}` feature",
+                        backend, feature
+                    );
+                }
+                return Err(e.into());
+            }
+        };
+
+        // Ask the opened device what it is, in case the path or the first
+        // product ID that opened led to another model.
+        let info = device.get_device_info().ok().and_then(|d| describe(&d));
+        let model = info.as_ref().map_or(guessed, |info| info.model);
+        info!("{} opened successfully", model);
+        device.set_blocking_mode(false)?;
+        let serial = device
+            .get_serial_number_string()
+            .ok()
+            .flatten()
+            .filter(|serial| !serial.is_empty());
+
+        Ok(Self {
+            device,
+            serial,
+            model,
+            info,
+        })
+    }
+}
+
+/// The HID path of the lamp `selector` picks out of [`HidTransport::list`]:
+/// the one with that serial number or path, else the one at that index.
+/// Fails with [`Error::DeviceNotFound`] when no lamp is connected at all.
+pub fn select(selector: &str) -> Result<String, Error> {
+    let lamps = with_api(|api| Ok(HidTransport::list_from(api)))?;
+    if lamps.is_empty() {
+        return Err(Error::DeviceNotFound(None));
+    }
+    lamps
+        .iter()
+        .find(|lamp| lamp.serial.as_deref() == Some(selector) || lamp.path == selector)
+        .or_else(|| {
+            selector
+                .parse()
+                .ok()
+                .and_then(|index: usize| lamps.get(index))
+        })
+        .map(|lamp| lamp.path.clone())
+        .ok_or_else(|| Error::NoMatch(selector.to_string()))
+}
+
+/// `d` as a [`DeviceInfo`], if it is a supported lamp.
+fn describe(d: &HidDeviceInfo) -> Option<DeviceInfo> {
+    let text = |s: Option<&str>| s.filter(|s| !s.is_empty()).map(str::to_string);
+    Some(DeviceInfo {
+        model: Model::from_product_id(d.product_id())?,
+        path: d.path().to_string_lossy().into_owned(),
+        serial: text(d.serial_number()),
+        manufacturer: text(d.manufacturer_string()),
+        product: text(d.product_string()),
+    })
+}
+
+/// What the HID interfaces of one lamp share and another lamp's do not.
+#[derive(Debug, PartialEq, Eq)]
+enum LampKey {
+    Serial(String),
+    /// For lamps reporting an empty serial, as some do behind hubs: vendor,
+    /// product and the USB port taken from the HID path.
+    Port(u16, u16, String),
+}
+
+impl LampKey {
+    fn of(d: &HidDeviceInfo) -> Self {
+        match d.serial_number().filter(|serial| !serial.is_empty()) {
+            Some(serial) => LampKey::Serial(serial.to_string()),
+            None => LampKey::Port(
+                d.vendor_id(),
+                d.product_id(),
+                usb_port(&d.path().to_string_lossy()),
+            ),
+        }
+    }
+}
+
+/// The part of a HID path before the interface, the same for every
+/// interface of a lamp: `1-2` of the libusb path `1-2:1.0`, and a Windows
+/// path up to `&mi_02`. Other paths, such as `/dev/hidraw3`, name one
+/// interface and are kept whole.
+fn usb_port(path: &str) -> String {
+    if let Some(at) = path.to_ascii_lowercase().find("&mi_") {
+        return path[..at].to_string();
+    }
+    let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit() || c == '.');
+    match path.split_once(':') {
+        Some((port, interface))
+            if digits(interface)
+                && port
+                    .split_once('-')
+                    .is_some_and(|(bus, ports)| digits(bus) && digits(ports)) =>
+        {
+            port.to_string()
+        }
+        _ => path.to_string(),
+    }
+}
+
+/// Every supported lamp in the device list of `api`.
+pub fn lamps(api: &HidApi) -> impl Iterator<Item = &HidDeviceInfo> {
+    api.device_list()
+        .filter(|d| is_supported(d.vendor_id(), d.product_id()))
+}
+
+/// Opens the HID++ interface of the lamp earliest in
+/// [`KNOWN_DEVICES`](crate::protocol::KNOWN_DEVICES). A lamp exposing
+/// several HID collections ignores writes to the others.
+/// Without one, opens the first lamp of the first model that has one
+/// connected, logging which interface that landed on.
+fn open_any(api: &HidApi) -> hidapi::HidResult<(HidDevice, Model)> {
+    if let Some((info, model)) = lamps(api)
+        .filter(|d| d.usage_page() == HIDPP_USAGE_PAGE)
+        .filter_map(|d| Some((d, Model::from_product_id(d.product_id())?)))
+        .min_by_key(|(_, model)| model.priority())
+    {
+        debug!("Opening HID++ interface {}", info.path().to_string_lossy());
+        return api.open_path(info.path()).map(|device| (device, model));
+    }
+    let [first, rest @ ..] = Model::ALL;
+    let opened = api
+        .open(VENDOR_ID, first.product_id())
+        .map(|device| (device, first));
+    let opened = rest.into_iter().fold(opened, |opened, model| {
+        opened.or_else(|_| {
+            api.open(VENDOR_ID, model.product_id())
+                .map(|device| (device, model))
+        })
+    });
+    if let Ok((device, _)) = &opened
+        && let Ok(info) = device.get_device_info()
+    {
+        info!(
+            "No HID++ interface found, opened {} (usage page {:04x}, usage {:04x})",
+            info.path().to_string_lossy(),
+            info.usage_page(),
+            info.usage()
+        );
+    }
+    opened
+}
+
+/// A permission error for the lamp at `path`, or the first lamp, when that
+/// is why opening it failed. Device nodes are checked directly, other paths
+/// by the wording of `error`.
+fn permission_denied(api: &HidApi, path: Option<&str>, error: &hidapi::HidError) -> Option<Error> {
+    let info = lamps(api)
+        .filter(|d| path.is_none_or(|path| d.path().to_string_lossy() == path))
+        .min_by_key(|d| d.usage_page() != HIDPP_USAGE_PAGE)?;
+    let path = info.path().to_string_lossy().into_owned();
+    let denied = if path.starts_with("/dev/") {
+        fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .is_err_and(|e| e.kind() == io::ErrorKind::PermissionDenied)
+    } else {
+        let message = error.to_string().to_lowercase();
+        message.contains("permission") || message.contains("access")
+    };
+    let model = Model::from_product_id(info.product_id())?;
+    denied.then_some(Error::PermissionDenied(path, model))
+}
+
+/// The path of the lamp with `serial`, preferring its HID++ interface. A lamp whose serial reads empty
+/// matches when `serial` is its path, as listed by `devices`.
+fn find_serial(api: &HidApi, serial: &str) -> Option<String> {
+    let path = |d: &HidDeviceInfo| d.path().to_string_lossy().into_owned();
+    lamps(api)
+        .filter(|d| d.serial_number() == Some(serial))
+        .min_by_key(|d| d.usage_page() != HIDPP_USAGE_PAGE)
+        .or_else(|| {
+            lamps(api).find(|d| d.serial_number().is_none_or(str::is_empty) && path(d) == serial)
+        })
+        .map(path)
+}
+
+impl Transport for HidTransport {
+    fn write(&self, data: &[u8]) -> Result<usize, Error> {
+        Ok(self.device.write(data)?)
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, Error> {
+        Ok(self.device.read_timeout(buf, timeout_ms)?)
+    }
+
+    fn serial_number(&self) -> Option<String> {
+        self.serial.clone()
+    }
+
+    fn model(&self) -> Model {
+        self.model
+    }
+
+    fn info(&self) -> Option<DeviceInfo> {
+        self.info.clone()
+    }
+}
+
+/// Simulated lamp for `--dry-run`: logs every frame that would be written,
+/// applies set commands to its own state and answers queries from it.
+pub struct DryRunTransport {
+    state: RefCell<(bool, u16, u16)>,
+    replies: RefCell<VecDeque<[u8; 20]>>,
+}
+
+impl DryRunTransport {
+    pub fn new() -> Self {
+        info!("Dry run: no device will be touched");
+        Self {
+            state: RefCell::new((false, Model::Glow.min_brightness(), MIN_TEMPERATURE)),
+            replies: RefCell::new(VecDeque::new()),
+        }
+    }
+}
+
+impl Transport for DryRunTransport {
+    fn write(&self, data: &[u8]) -> Result<usize, Error> {
+        let cmd = Command::from_bytes(data);
+        info!("Dry run: would write {:02x?} ({:?})", data, cmd);
+        let mut state = self.state.borrow_mut();
+        let reply = match cmd {
+            Some(Command::SetPower(on)) => {
+                state.0 = on;
+                None
+            }
+            Some(Command::SetBrightness(level)) => {
+                state.1 = level;
+                None
+            }
+            Some(Command::SetTemperature(kelvin)) => {
+                state.2 = kelvin;
+                None
+            }
+            Some(Command::GetPower) => Some(Response::Power(state.0, Trigger::Reply)),
+            Some(Command::GetBrightness) => Some(Response::Brightness(state.1, Trigger::Reply)),
+            Some(Command::GetTemperature) => Some(Response::Temperature(state.2, Trigger::Reply)),
+            // The simulated lamp has no device information feature.
+            Some(Command::GetFeatureIndex(_)) => Some(Response::FeatureIndex(0)),
+            Some(
+                Command::GetFirmware(_)
+                | Command::GetSerial(_)
+                | Command::GetNameLength(_)
+                | Command::GetName(..),
+            )
+            | None => None,
+        };
+        if let Some(reply) = reply {
+            self.replies
+                .borrow_mut()
+                .push_back(reply.to_bytes(Model::Glow));
+        }
+        Ok(data.len())
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, Error> {
+        let Some(reply) = self.replies.borrow_mut().pop_front() else {
+            thread::sleep(Duration::from_millis(timeout_ms.max(0) as u64));
+            return Ok(0);
+        };
+        let len = reply.len().min(buf.len());
+        buf[..len].copy_from_slice(&reply[..len]);
+        Ok(len)
+    }
+}
+
+/// Scripted lamp for tests. Answers queries from its own state the way a
+/// `model` lamp would, records every command written to it, and hands out
+/// responses queued with [`push`](Self::push) before its own answers.
+/// Clones share the lamp, so a test keeps one to inspect and steer it while
+/// the device under test owns another.
+#[cfg(test)]
+#[derive(Debug, Clone)]
+pub struct FakeLitra {
+    model: Model,
+    serial: Option<String>,
+    lamp: std::sync::Arc<Mutex<FakeLamp>>,
+}
+
+#[cfg(test)]
+#[derive(Debug)]
+struct FakeLamp {
+    power: bool,
+    brightness: u16,
+    temperature: u16,
+    written: Vec<Command>,
+    /// Frames to read, each with the time it arrives.
+    replies: VecDeque<(std::time::Instant, [u8; 20])>,
+    /// How long the lamp takes to answer a write.
+    ack_delay: Duration,
+    /// Commands answered later than the rest, and how much later.
+    slow_acks: Vec<(Command, Duration)>,
+    /// Queries go unanswered, as from a lamp that stopped listening.
+    silent: bool,
+    /// Reads and writes fail, as once the lamp is unplugged.
+    unplugged: bool,
+}
+
+#[cfg(test)]
+impl FakeLitra {
+    /// Feature indices the fake reports for the identity features.
+    pub const INFORMATION_INDEX: u8 = 0x01;
+    pub const NAME_INDEX: u8 = 0x02;
+    pub const FIRMWARE: crate::protocol::Firmware = crate::protocol::Firmware {
+        prefix: *b"RQM",
+        number: 0x40,
+        revision: 0x00,
+        build: 0x0012,
+    };
+
+    pub fn new(model: Model) -> Self {
+        Self {
+            model,
+            serial: None,
+            lamp: std::sync::Arc::new(Mutex::new(FakeLamp {
+                power: false,
+                brightness: model.min_brightness(),
+                temperature: MIN_TEMPERATURE,
+                written: Vec::new(),
+                replies: VecDeque::new(),
+                ack_delay: Duration::ZERO,
+                slow_acks: Vec::new(),
+                silent: false,
+                unplugged: false,
+            })),
+        }
+    }
+
+    pub fn with_serial(mut self, serial: &str) -> Self {
+        self.serial = Some(serial.to_string());
+        self
+    }
+
+    fn lamp(&self) -> std::sync::MutexGuard<'_, FakeLamp> {
+        self.lamp.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Queues `response` as the next frame read, e.g. a button report.
+    pub fn push(&self, response: Response) {
+        let at = std::time::Instant::now();
+        self.lamp()
+            .replies
+            .push_back((at, response.to_bytes(self.model)));
+    }
+
+    /// Power, brightness and temperature as the lamp holds them.
+    pub fn state(&self) -> (bool, u16, u16) {
+        let lamp = self.lamp();
+        (lamp.power, lamp.brightness, lamp.temperature)
+    }
+
+    pub fn set_state(&self, power: bool, brightness: u16, temperature: u16) {
+        let mut lamp = self.lamp();
+        (lamp.power, lamp.brightness, lamp.temperature) = (power, brightness, temperature);
+    }
+
+    /// Every command written so far, oldest first.
+    pub fn written(&self) -> Vec<Command> {
+        self.lamp().written.clone()
+    }
+
+    pub fn set_silent(&self, silent: bool) {
+        self.lamp().silent = silent;
+    }
+
+    /// Delays every answer by `delay`, as from a lamp still busy with the
+    /// previous command.
+    pub fn set_ack_delay(&self, delay: Duration) {
+        self.lamp().ack_delay = delay;
+    }
+
+    /// Delays only the answers to `cmd` by `delay`.
+    pub fn delay_ack(&self, cmd: Command, delay: Duration) {
+        self.lamp().slow_acks.push((cmd, delay));
+    }
+
+    /// Makes reads and writes fail, and opening the lamp again find
+    /// nothing.
+    pub fn unplug(&self) {
+        self.lamp().unplugged = true;
+    }
+
+    /// Plugs the lamp back in after [`FakeLitra::unplug`].
+    pub fn plug_in(&self) {
+        self.lamp().unplugged = false;
+    }
+
+    /// A handle on the lamp, as opening it finds it: not found while
+    /// unplugged.
+    pub fn open(&self) -> Result<Self, Error> {
+        if self.lamp().unplugged {
+            return Err(Error::DeviceNotFound(self.serial.clone()));
+        }
+        Ok(self.clone())
+    }
+
+    /// The answer a real lamp gives to `cmd`, applying set commands first.
+    fn answer(&self, lamp: &mut FakeLamp, cmd: Command) -> Option<Response> {
+        let name = self.model.name().as_bytes();
+        match cmd {
+            Command::SetPower(on) => lamp.power = on,
+            Command::SetBrightness(level) => lamp.brightness = level,
+            Command::SetTemperature(kelvin) => lamp.temperature = kelvin,
+            Command::GetPower => return Some(Response::Power(lamp.power, Trigger::Reply)),
+            Command::GetBrightness => {
+                return Some(Response::Brightness(lamp.brightness, Trigger::Reply));
+            }
+            Command::GetTemperature => {
+                return Some(Response::Temperature(lamp.temperature, Trigger::Reply));
+            }
+            Command::GetFeatureIndex(feature) => {
+                return Some(Response::FeatureIndex(match feature {
+                    crate::protocol::DEVICE_INFORMATION => Self::INFORMATION_INDEX,
+                    crate::protocol::DEVICE_NAME => Self::NAME_INDEX,
+                    _ => 0,
+                }));
+            }
+            Command::GetFirmware(Self::INFORMATION_INDEX) => {
+                return Some(Response::Firmware(Self::FIRMWARE));
+            }
+            Command::GetSerial(Self::INFORMATION_INDEX) => {
+                let mut serial = [0; 12];
+                let text = self.serial.as_deref().unwrap_or_default().as_bytes();
+                let len = text.len().min(serial.len());
+                serial[..len].copy_from_slice(&text[..len]);
+                return Some(Response::Serial(serial));
+            }
+            Command::GetNameLength(Self::NAME_INDEX) => {
+                return Some(Response::NameLength(name.len() as u8));
+            }
+            Command::GetName(Self::NAME_INDEX, offset) => {
+                let mut part = [0; crate::protocol::NAME_PART_LEN];
+                let rest = name.get(usize::from(offset)..).unwrap_or_default();
+                let len = rest.len().min(part.len());
+                part[..len].copy_from_slice(&rest[..len]);
+                return Some(Response::NamePart(part));
+            }
+            Command::GetFirmware(_)
+            | Command::GetSerial(_)
+            | Command::GetNameLength(_)
+            | Command::GetName(..) => {}
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+impl Transport for FakeLitra {
+    fn write(&self, data: &[u8]) -> Result<usize, Error> {
+        let mut lamp = self.lamp();
+        if lamp.unplugged {
+            return Err(Error::Disconnected);
+        }
+        let Some(cmd) = Command::from_bytes(data) else {
+            return Ok(data.len());
+        };
+        lamp.written.push(cmd);
+        if let Some(reply) = self.answer(&mut lamp, cmd)
+            && !lamp.silent
+        {
+            let slow = lamp.slow_acks.iter().find(|(slow, _)| *slow == cmd);
+            let delay = slow.map_or(lamp.ack_delay, |&(_, delay)| delay);
+            let at = std::time::Instant::now() + delay;
+            lamp.replies.push_back((at, reply.to_bytes(self.model)));
+        }
+        Ok(data.len())
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, Error> {
+        let timeout = Duration::from_millis(timeout_ms.max(0) as u64);
+        let now = std::time::Instant::now();
+        let (reply, wait) = {
+            let mut lamp = self.lamp();
+            if lamp.unplugged {
+                return Err(Error::Disconnected);
+            }
+            // A slow answer holds back only itself, not those after it.
+            match lamp.replies.iter().position(|&(at, _)| at <= now) {
+                Some(ready) => (lamp.replies.remove(ready), Duration::ZERO),
+                None => {
+                    let next = lamp.replies.iter().map(|&(at, _)| at - now).min();
+                    (None, next.unwrap_or(timeout).min(timeout))
+                }
+            }
+        };
+        let Some((_, reply)) = reply else {
+            thread::sleep(wait);
+            return Ok(0);
+        };
+        let len = reply.len().min(buf.len());
+        buf[..len].copy_from_slice(&reply[..len]);
+        Ok(len)
+    }
+
+    fn serial_number(&self) -> Option<String> {
+        self.serial.clone()
+    }
+
+    fn model(&self) -> Model {
+        self.model
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interfaces_of_one_lamp_share_a_port() {
+        assert_eq!(usb_port("1-2:1.0"), "1-2");
+        assert_eq!(usb_port("1-2:1.1"), "1-2");
+        assert_eq!(usb_port("3-1.4.2:1.2"), "3-1.4.2");
+        assert_eq!(
+            usb_port(r"\\?\HID#VID_046D&PID_C900&MI_02#7&1a2b&0&0000#{4d1e55b2}"),
+            r"\\?\HID#VID_046D&PID_C900"
+        );
+        assert_ne!(usb_port("1-2:1.0"), usb_port("1-3:1.0"));
+    }
+
+    #[test]
+    fn other_paths_are_kept_whole() {
+        for path in [
+            "/dev/hidraw3",
+            "DevSrvsID:4294969365",
+            "IOService:/AppleACPI",
+        ] {
+            assert_eq!(usb_port(path), path);
+        }
+    }
+
+    #[test]
+    fn the_built_in_backend_is_the_only_one_accepted() {
+        assert_eq!(HidBackend::Hidraw.check_setting(""), Ok(()));
+        assert_eq!(HidBackend::Hidraw.check_setting("HIDRAW"), Ok(()));
+        assert_eq!(HidBackend::Libusb.check_setting(" libusb "), Ok(()));
+
+        let error = HidBackend::Hidraw.check_setting("libusb").unwrap_err();
+        assert!(error.contains("this build uses hidraw"), "{error}");
+        assert!(error.contains("--features tray,linux-libusb"), "{error}");
+        let error = HidBackend::Libusb.check_setting("hidraw").unwrap_err();
+        assert!(error.contains("--features tray,linux-hidraw"), "{error}");
+
+        let error = HidBackend::Native.check_setting("libusb").unwrap_err();
+        assert!(error.contains("can only use native"), "{error}");
+        let error = HidBackend::Hidraw.check_setting("winusb").unwrap_err();
+        assert!(error.contains("can only use hidraw"), "{error}");
+    }
+}
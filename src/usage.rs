@@ -0,0 +1,49 @@
+use std::time::{Duration, Instant, SystemTime};
+
+/// Adds up how long the lamp is on from periodic samples.
+///
+/// Each interval is measured on both clocks and the shorter one counts. The
+/// monotonic clock stops during suspend on some systems and the wall clock
+/// jumps with NTP or manual changes, so taking the smaller of the two never
+/// counts time that did not pass, and a wall clock going backwards adds
+/// nothing.
+#[derive(Debug, Default)]
+pub struct OnTimer {
+    last: Option<(Instant, SystemTime)>,
+    pending: Duration,
+}
+
+impl OnTimer {
+    /// Records a sample. Time since the previous sample counts only when
+    /// the lamp was on for both.
+    pub fn sample(&mut self, on: bool, now: Instant, wall: SystemTime) {
+        if !on {
+            self.last = None;
+            return;
+        }
+        if let Some((last, last_wall)) = self.last {
+            let elapsed = now.duration_since(last);
+            let wall_elapsed = wall.duration_since(last_wall).unwrap_or_default();
+            self.pending += elapsed.min(wall_elapsed);
+        }
+        self.last = Some((now, wall));
+    }
+
+    /// Stops counting until the next sample, as after a disconnect.
+    pub fn pause(&mut self) {
+        self.last = None;
+    }
+
+    /// Takes the whole seconds counted since the last call. The fraction is
+    /// kept for later.
+    pub fn take_seconds(&mut self) -> u64 {
+        let seconds = self.pending.as_secs();
+        self.pending -= Duration::from_secs(seconds);
+        seconds
+    }
+}
+
+/// `seconds` of on-time in hours, rounded to one decimal.
+pub fn hours(seconds: u64) -> f64 {
+    (seconds as f64 / 360.0).round() / 10.0
+}
@@ -1,73 +1,930 @@
-use hidapi::{HidApi, HidDevice};
-use log::info;
+use std::cell::Cell;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-use crate::protocol::{Command, PRODUCT_ID, Response, VENDOR_ID};
+use tracing::{Level, debug, enabled, info, warn};
+
+use crate::i18n::msg;
+use crate::protocol::{
+    Command, DEVICE_INFORMATION, DEVICE_NAME, Model, ProtocolError, Response, Trigger,
+};
+use crate::throttle::LogThrottle;
+use crate::trace::{self, ReplayTransport, TracingTransport};
+use crate::transport::{self, DryRunTransport, HidBackend, HidTransport, Transport};
+
+/// Exit code of a CLI run given arguments it cannot act on: ones the
+/// parser rejects, or values outside the lamp's range.
+pub const INVALID_INPUT_EXIT_CODE: i32 = 8;
 
 #[derive(Debug)]
 pub enum Error {
-    DeviceNotFound,
+    /// No lamp, or none with the requested serial number.
+    DeviceNotFound(Option<String>),
+    /// Lamps are connected, but none is the one `--device` asks for.
+    NoMatch(String),
+    /// This user may not open the lamp at this path.
+    PermissionDenied(String, Model),
+    /// The lamp was unplugged or its handle stopped working.
+    Disconnected,
+    /// The lamp did not answer a query in time.
+    ReadTimeout,
+    /// A power toggle could not read the state to flip (`None`), or the
+    /// lamp did not confirm the new one.
+    ToggleFailed(Option<bool>),
+    /// Fewer bytes were written than the frame holds.
+    WriteShort {
+        expected: usize,
+        written: usize,
+    },
     Hid(hidapi::HidError),
+    Io(std::io::Error),
+    Trace(String),
+    /// A command carried a value the lamp does not accept.
+    Protocol(ProtocolError),
 }
 
 impl From<hidapi::HidError> for Error {
     fn from(e: hidapi::HidError) -> Self {
-        Error::Hid(e)
+        match e {
+            hidapi::HidError::IncompleteSendError { sent, all } => Error::WriteShort {
+                expected: all,
+                written: sent,
+            },
+            hidapi::HidError::HidApiError { ref message } if is_disconnect(message) => {
+                Error::Disconnected
+            }
+            e => Error::Hid(e),
+        }
+    }
+}
+
+/// Whether a hidapi error message means the device is gone. hidapi only
+/// reports the platform's wording.
+fn is_disconnect(message: &str) -> bool {
+    let message = message.to_lowercase();
+    ["no such device", "not connected", "input/output error"]
+        .iter()
+        .any(|wording| message.contains(wording))
+}
+
+/// Which counter of replies owed `cmd` adds to: power, brightness or
+/// temperature. Other commands are answered within the call that sends them.
+fn reply_slot(cmd: Command) -> Option<usize> {
+    match cmd.query() {
+        Command::GetPower => Some(0),
+        Command::GetBrightness => Some(1),
+        Command::GetTemperature => Some(2),
+        _ => None,
+    }
+}
+
+impl Error {
+    /// Whether the connection to the lamp is lost. Timeouts and short writes
+    /// leave it usable.
+    pub fn is_fatal(&self) -> bool {
+        !matches!(
+            self,
+            Error::ReadTimeout
+                | Error::ToggleFailed(_)
+                | Error::WriteShort { .. }
+                | Error::Protocol(_)
+        )
+    }
+
+    /// Short name of this kind of error in JSON error reports.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::DeviceNotFound(_) => "device-not-found",
+            Error::NoMatch(_) => "no-match",
+            Error::PermissionDenied(..) => "permission-denied",
+            Error::Disconnected => "disconnected",
+            Error::ReadTimeout => "read-timeout",
+            Error::ToggleFailed(_) => "toggle-failed",
+            Error::WriteShort { .. } => "write-short",
+            Error::Hid(_) => "hid",
+            Error::Io(_) => "io",
+            Error::Trace(_) => "trace",
+            Error::Protocol(_) => "out-of-range",
+        }
+    }
+
+    /// Exit code of a CLI run that failed with this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::DeviceNotFound(_) => 2,
+            Error::NoMatch(_) => 7,
+            Error::PermissionDenied(..) => 3,
+            Error::Disconnected => 4,
+            Error::ReadTimeout | Error::ToggleFailed(_) => 5,
+            Error::WriteShort { .. } => 6,
+            Error::Protocol(_) => INVALID_INPUT_EXIT_CODE,
+            Error::Hid(_) | Error::Io(_) | Error::Trace(_) => 1,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<ProtocolError> for Error {
+    fn from(e: ProtocolError) -> Self {
+        Error::Protocol(e)
     }
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Error::DeviceNotFound => write!(f, "Litra device not found"),
-            Error::Hid(e) => write!(f, "HID error: {e}"),
+            Error::DeviceNotFound(None) => write!(f, "{}", msg!("error-device-not-found")),
+            Error::DeviceNotFound(Some(serial)) => write!(
+                f,
+                "{}",
+                msg!("error-serial-not-found", serial = serial.as_str())
+            ),
+            Error::NoMatch(selector) => write!(
+                f,
+                "{}",
+                msg!("error-no-match", selector = selector.as_str())
+            ),
+            Error::PermissionDenied(path, model) => write!(
+                f,
+                "{}",
+                msg!(
+                    "error-permission-denied",
+                    path = path.as_str(),
+                    rule = HidBackend::current().udev_rule(*model)
+                )
+            ),
+            Error::Disconnected => write!(f, "{}", msg!("device-disconnected")),
+            Error::ReadTimeout => write!(f, "{}", msg!("error-read-timeout")),
+            Error::ToggleFailed(None) => write!(f, "{}", msg!("error-toggle-unread")),
+            Error::ToggleFailed(Some(on)) => write!(
+                f,
+                "{}",
+                msg!(
+                    "error-toggle-unconfirmed",
+                    on = if *on { "yes" } else { "no" }
+                )
+            ),
+            Error::WriteShort { expected, written } => write!(
+                f,
+                "{}",
+                msg!(
+                    "error-write-short",
+                    expected = *expected,
+                    written = *written
+                )
+            ),
+            Error::Hid(e) => write!(f, "{}", msg!("error-hid", error = e.to_string())),
+            Error::Io(e) => write!(f, "{}", msg!("error-io", error = e.to_string())),
+            Error::Trace(message) => {
+                write!(f, "{}", msg!("error-trace", error = message.as_str()))
+            }
+            Error::Protocol(ProtocolError::Brightness { level, model }) => write!(
+                f,
+                "{}",
+                msg!(
+                    "error-brightness-range",
+                    level = *level,
+                    model = model.name(),
+                    min = model.min_brightness(),
+                    max = model.max_brightness()
+                )
+            ),
+            Error::Protocol(ProtocolError::Temperature { kelvin, model }) => {
+                let capabilities = model.capabilities();
+                write!(
+                    f,
+                    "{}",
+                    msg!(
+                        "error-temperature-range",
+                        kelvin = *kelvin,
+                        model = model.name(),
+                        min = capabilities.min_temperature,
+                        max = capabilities.max_temperature
+                    )
+                )
+            }
         }
     }
 }
 
 impl std::error::Error for Error {}
 
+/// Where device traffic goes, chosen once at startup.
+#[derive(Debug, Clone, Default)]
+pub enum TransportKind {
+    /// The real lamp over HID.
+    #[default]
+    Hid,
+    /// Inbound frames scripted by a recorded trace.
+    Replay(PathBuf),
+    /// A simulated lamp that logs the frames it would have written.
+    DryRun,
+    /// A scripted lamp the test keeps a handle on.
+    #[cfg(test)]
+    Fake(transport::FakeLitra),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DeviceOptions {
+    pub transport: TransportKind,
+    /// Open the lamp with this serial number rather than the first found.
+    pub serial: Option<String>,
+    /// Open the lamp at this HID path, as shown by `devices`. Takes
+    /// precedence over `serial`.
+    pub path: Option<String>,
+    /// Open the lamp this picks out of those `devices` lists: its serial
+    /// number, HID path or index. Looked up each time a lamp is opened, and
+    /// takes precedence over `path` and `serial`.
+    pub selector: Option<String>,
+    pub trace_out: Option<PathBuf>,
+}
+
+/// What [`LitraDevice::open_all`] found.
+pub struct OpenedLamps {
+    pub opened: Vec<LitraDevice>,
+    /// Lamps that failed to open, named by serial number or path.
+    pub failed: Vec<(String, Error)>,
+}
+
+/// A connected lamp, as found by [`LitraDevice::list`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub model: Model,
+    /// Platform HID path, usable with [`LitraDevice::open_path`].
+    pub path: String,
+    /// Serial number, when the lamp reports one.
+    pub serial: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+}
+
+/// What the lamp reports about itself. Values it did not report in time
+/// are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Identity {
+    pub serial: Option<String>,
+    pub firmware: Option<String>,
+    /// Marketing name from the HID++ device name feature, e.g. `Litra Glow`.
+    pub name: Option<String>,
+}
+
+const LOG_SUMMARY_WINDOW: Duration = Duration::from_secs(2);
+/// How long draining waits for another frame before calling the queue quiet.
+const DRAIN_QUIET: Duration = Duration::from_millis(10);
+/// Longest the reader thread holds the transport per read, which bounds
+/// how long a write waits for it.
+const READ_POLL_MS: i32 = 10;
+/// How long the reader thread stays off the transport for a waiting write.
+const WRITE_YIELD: Duration = Duration::from_millis(1);
+/// Writes tried before a failure is reported. The first write after USB
+/// autosuspend can fail while the next one goes through.
+const WRITE_ATTEMPTS: u32 = 3;
+const WRITE_RETRY_DELAY: Duration = Duration::from_millis(5);
+/// Power queries tried before a toggle gives up. A slow lamp can miss the
+/// first one.
+const TOGGLE_ATTEMPTS: u32 = 3;
+/// Least time between two writes. The lamp can drop a command written
+/// right after another.
+const WRITE_SPACING: Duration = Duration::from_millis(20);
+
+/// A raw frame from the reader thread, or the read error that ended it.
+type Frame = Result<Vec<u8>, Error>;
+
+/// State shared between a `LitraDevice` and its reader thread.
+struct Shared {
+    transport: Mutex<Box<dyn Transport>>,
+    /// Set while a write waits for the transport, so the reader lets go.
+    writing: AtomicBool,
+    stop: AtomicBool,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Box<dyn Transport>> {
+        self.transport.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Reads frames off the transport as they arrive and queues them, until
+/// stopped or a read fails. The failure is queued too and ends the thread.
+fn read_frames(shared: Arc<Shared>, frames: mpsc::Sender<Frame>) {
+    let mut buf = [0u8; 64];
+    while !shared.stop.load(Ordering::Acquire) {
+        if shared.writing.load(Ordering::Acquire) {
+            thread::sleep(WRITE_YIELD);
+            continue;
+        }
+        let frame = match shared.lock().read_timeout(&mut buf, READ_POLL_MS) {
+            Ok(0) => continue,
+            Ok(len) => Ok(buf[..len].to_vec()),
+            Err(e) => Err(e),
+        };
+        let failed = frame.is_err();
+        if frames.send(frame).is_err() || failed {
+            break;
+        }
+    }
+    debug!("Reader thread stopped");
+}
+
 pub struct LitraDevice {
-    device: HidDevice,
+    shared: Arc<Shared>,
+    frames: mpsc::Receiver<Frame>,
+    reader: Option<JoinHandle<()>>,
+    serial: Option<String>,
+    model: Model,
+    device_info: Option<DeviceInfo>,
+    last_write: Cell<Option<Instant>>,
+    /// Replies still owed for power, brightness and temperature: one per
+    /// command sent, taken off as replies are read.
+    owed: Cell<[u16; 3]>,
+    sent_log: LogThrottle,
+    read_log: LogThrottle,
 }
 
 impl LitraDevice {
-    pub fn open() -> Result<Self, Error> {
-        info!("Initializing HID API...");
-        let api = HidApi::new()?;
+    pub fn open(options: &DeviceOptions) -> Result<Self, Error> {
+        let mut transport: Box<dyn Transport> = match &options.transport {
+            TransportKind::Hid => {
+                let path = match &options.selector {
+                    Some(selector) => Some(transport::select(selector)?),
+                    None => options.path.clone(),
+                };
+                Box::new(HidTransport::open(
+                    options.serial.as_deref(),
+                    path.as_deref(),
+                )?)
+            }
+            TransportKind::Replay(path) => Box::new(ReplayTransport::load(path)?),
+            TransportKind::DryRun => Box::new(DryRunTransport::new()),
+            #[cfg(test)]
+            TransportKind::Fake(fake) => Box::new(fake.open()?),
+        };
+        if let Some(path) = &options.trace_out {
+            transport = Box::new(TracingTransport::new(transport, path)?);
+        }
+        Self::start(transport)
+    }
 
-        info!(
-            "Looking for device VID={:04x} PID={:04x}",
-            VENDOR_ID, PRODUCT_ID
-        );
-        let device = api
-            .open(VENDOR_ID, PRODUCT_ID)
-            .map_err(|_| Error::DeviceNotFound)?;
+    /// Takes over `transport` and starts the thread reading from it.
+    fn start(transport: Box<dyn Transport>) -> Result<Self, Error> {
+        let serial = transport.serial_number();
+        let model = transport.model();
+        let device_info = transport.info();
+        let shared = Arc::new(Shared {
+            transport: Mutex::new(transport),
+            writing: AtomicBool::new(false),
+            stop: AtomicBool::new(false),
+        });
+        let (tx, frames) = mpsc::channel();
+        let reader = thread::Builder::new().name("litra-reader".into()).spawn({
+            let shared = Arc::clone(&shared);
+            move || read_frames(shared, tx)
+        })?;
+        Ok(Self {
+            shared,
+            frames,
+            reader: Some(reader),
+            serial,
+            model,
+            device_info,
+            last_write: Cell::new(None),
+            owed: Cell::new([0; 3]),
+            sent_log: LogThrottle::new(LOG_SUMMARY_WINDOW),
+            read_log: LogThrottle::new(LOG_SUMMARY_WINDOW),
+        })
+    }
+
+    /// Every connected lamp, in a stable order.
+    pub fn list() -> Result<Vec<DeviceInfo>, Error> {
+        HidTransport::list()
+    }
 
-        info!("Device opened successfully");
-        device.set_blocking_mode(false)?;
+    /// Opens every connected lamp with `options`, in the order of
+    /// [`list`](Self::list). Lamps that fail to open are skipped and
+    /// returned with their error.
+    pub fn open_all(options: &DeviceOptions) -> Result<OpenedLamps, Error> {
+        let mut opened = Vec::new();
+        let mut failed = Vec::new();
+        // A scripted lamp is the only one connected, while plugged in.
+        #[cfg(test)]
+        if let TransportKind::Fake(fake) = &options.transport {
+            opened.extend(fake.open().ok().map(|_| Self::open(options)).transpose()?);
+            return Ok(OpenedLamps { opened, failed });
+        }
+        for (index, lamp) in Self::list()?.into_iter().enumerate() {
+            let tag = lamp.serial.clone().unwrap_or_else(|| index.to_string());
+            let options = DeviceOptions {
+                serial: None,
+                path: Some(lamp.path.clone()),
+                selector: None,
+                trace_out: options
+                    .trace_out
+                    .as_deref()
+                    .map(|path| trace::lamp_path(path, &tag)),
+                ..options.clone()
+            };
+            match Self::open(&options) {
+                Ok(device) => opened.push(device),
+                Err(e) => failed.push((lamp.serial.unwrap_or(lamp.path), e)),
+            }
+        }
+        Ok(OpenedLamps { opened, failed })
+    }
+
+    /// Opens the lamp at `path`, one of those returned by [`list`](Self::list).
+    pub fn open_path(path: &str) -> Result<Self, Error> {
+        Self::open(&DeviceOptions {
+            path: Some(path.to_string()),
+            ..DeviceOptions::default()
+        })
+    }
+
+    /// Opens the lamp with `serial`, or one reporting no serial whose path
+    /// is `serial`.
+    pub fn open_serial(serial: &str) -> Result<Self, Error> {
+        Self::open(&DeviceOptions {
+            serial: Some(serial.to_string()),
+            ..DeviceOptions::default()
+        })
+    }
+
+    pub fn serial_number(&self) -> Option<String> {
+        self.serial.clone()
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// What the HID layer reported about the lamp when it was opened. None
+    /// for simulated and replayed lamps.
+    pub fn device_info(&self) -> Option<&DeviceInfo> {
+        self.device_info.as_ref()
+    }
+
+    /// Asks the lamp for its serial number and firmware version through the
+    /// HID++ device information feature, waiting up to `timeout` for each
+    /// answer. The serial falls back to the one from the USB descriptor.
+    pub fn info(&self, timeout: Duration) -> Result<Identity, Error> {
+        let mut identity = Identity {
+            serial: self.serial_number(),
+            ..Identity::default()
+        };
+        let index = match self.query(Command::GetFeatureIndex(DEVICE_INFORMATION), timeout)? {
+            Some(Response::FeatureIndex(index)) if index != 0 => index,
+            _ => {
+                debug!("Lamp has no device information feature");
+                return Ok(identity);
+            }
+        };
+        if let Some(Response::Firmware(firmware)) =
+            self.query(Command::GetFirmware(index), timeout)?
+        {
+            identity.firmware = Some(firmware.to_string());
+        }
+        if let Some(Response::Serial(serial)) = self.query(Command::GetSerial(index), timeout)? {
+            let serial = String::from_utf8_lossy(&serial)
+                .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+                .to_string();
+            if !serial.is_empty() {
+                identity.serial = Some(serial);
+            }
+        }
+        Ok(identity)
+    }
 
-        Ok(Self { device })
+    /// Asks the lamp for its name through the HID++ device name feature,
+    /// waiting up to `timeout` for each answer. `None` when the lamp lacks
+    /// the feature or any part of the name did not arrive.
+    pub fn name(&self, timeout: Duration) -> Result<Option<String>, Error> {
+        let index = match self.query(Command::GetFeatureIndex(DEVICE_NAME), timeout)? {
+            Some(Response::FeatureIndex(index)) if index != 0 => index,
+            _ => {
+                debug!("Lamp has no device name feature");
+                return Ok(None);
+            }
+        };
+        let Some(Response::NameLength(length)) =
+            self.query(Command::GetNameLength(index), timeout)?
+        else {
+            return Ok(None);
+        };
+        let length = usize::from(length);
+        let mut name = Vec::with_capacity(length);
+        while name.len() < length {
+            let Some(Response::NamePart(part)) =
+                self.query(Command::GetName(index, name.len() as u8), timeout)?
+            else {
+                debug!(
+                    "Lamp name incomplete after {} of {} bytes",
+                    name.len(),
+                    length
+                );
+                return Ok(None);
+            };
+            let take = (length - name.len()).min(part.len());
+            name.extend_from_slice(&part[..take]);
+        }
+        let name = String::from_utf8_lossy(&name)
+            .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+            .to_string();
+        Ok(Some(name).filter(|name| !name.is_empty()))
     }
 
     pub fn send(&self, cmd: Command) -> Result<(), Error> {
-        let data = cmd.to_bytes();
-        info!("Sending {:?}: {:02x?}", cmd, &data[..8]);
-        let written = self.device.write(&data)?;
-        info!("Wrote {} bytes", written);
+        let data = cmd.to_bytes(self.model())?;
+        debug!("Sending {:?}: {:02x?}", cmd, &data[..8]);
+        let written = self.write(&data)?;
+        debug!("Wrote {} bytes", written);
+        if let Some(slot) = reply_slot(cmd) {
+            let mut owed = self.owed.get();
+            owed[slot] = owed[slot].saturating_add(1);
+            self.owed.set(owed);
+        }
+        if enabled!(Level::INFO)
+            && let Some(count) = self.sent_log.hit(Instant::now())
+        {
+            info!(
+                "Sent {} frames in the last {:?}, last {:?}",
+                count,
+                self.sent_log.window(),
+                cmd
+            );
+        }
         Ok(())
     }
 
+    /// Writes `data` at least `WRITE_SPACING` after the previous write,
+    /// retrying failed writes up to `WRITE_ATTEMPTS` times.
+    fn write(&self, data: &[u8]) -> Result<usize, Error> {
+        if let Some(last) = self.last_write.get() {
+            thread::sleep(WRITE_SPACING.saturating_sub(last.elapsed()));
+        }
+        let written = self.write_now(data);
+        self.last_write.set(Some(Instant::now()));
+        written
+    }
+
+    fn write_now(&self, data: &[u8]) -> Result<usize, Error> {
+        self.shared.writing.store(true, Ordering::Release);
+        let transport = self.shared.lock();
+        self.shared.writing.store(false, Ordering::Release);
+        let mut attempt = 1;
+        loop {
+            let written = transport.write(data).and_then(|written| {
+                if written < data.len() {
+                    Err(Error::WriteShort {
+                        expected: data.len(),
+                        written,
+                    })
+                } else {
+                    Ok(written)
+                }
+            });
+            match written {
+                Ok(written) => return Ok(written),
+                Err(e) if attempt < WRITE_ATTEMPTS => {
+                    debug!("Write attempt {} failed, retrying: {}", attempt, e);
+                    attempt += 1;
+                    thread::sleep(WRITE_RETRY_DELAY);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Waits up to `timeout` for the next frame the reader thread queued.
+    fn next_frame(&self, timeout: Duration) -> Result<Option<Vec<u8>>, Error> {
+        match self.frames.recv_timeout(timeout) {
+            Ok(frame) => frame.map(Some),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(Error::Disconnected),
+        }
+    }
+
+    /// Waits up to `timeout` for the next frame and parses it.
+    fn read(&self, timeout: Duration) -> Result<Option<Response>, Error> {
+        let response = self
+            .next_frame(timeout)?
+            .and_then(|frame| self.parse(&frame));
+        if let Some(response) = &response {
+            self.settle(response);
+        }
+        Ok(response)
+    }
+
+    /// Takes a reply read with [`read_blocking`](Self::read_blocking) off
+    /// the replies owed. Returns false for a reply to a setting nothing was
+    /// sent for, which means the lamp and the app are out of step. Button
+    /// reports and other responses are always expected.
+    pub fn settle(&self, response: &Response) -> bool {
+        let query = match *response {
+            Response::Power(_, Trigger::Reply) => Command::GetPower,
+            Response::Brightness(_, Trigger::Reply) => Command::GetBrightness,
+            Response::Temperature(_, Trigger::Reply) => Command::GetTemperature,
+            _ => return true,
+        };
+        let Some(slot) = reply_slot(query) else {
+            return true;
+        };
+        let mut owed = self.owed.get();
+        let Some(left) = owed[slot].checked_sub(1) else {
+            return false;
+        };
+        owed[slot] = left;
+        self.owed.set(owed);
+        true
+    }
+
+    fn parse(&self, frame: &[u8]) -> Option<Response> {
+        debug!(
+            "Read {} bytes: {:02x?}",
+            frame.len(),
+            &frame[..frame.len().min(16)]
+        );
+        let response = Response::from_bytes(frame);
+        debug!("Parsed response: {:?}", response);
+        if enabled!(Level::INFO)
+            && let Some(count) = self.read_log.hit(Instant::now())
+        {
+            info!(
+                "Read {} frames in the last {:?}, last {:?}",
+                count,
+                self.read_log.window(),
+                response
+            );
+        }
+        response
+    }
+
+    /// The next report already read from the lamp, without waiting. Frames
+    /// that do not parse are skipped.
     pub fn try_read(&self) -> Result<Option<Response>, Error> {
-        let mut buf = [0u8; 64];
-        match self.device.read_timeout(&mut buf, 50) {
-            Ok(0) => Ok(None),
-            Ok(len) => {
-                info!("Read {} bytes: {:02x?}", len, &buf[..len.min(16)]);
-                let response = Response::from_bytes(&buf[..len]);
-                info!("Parsed response: {:?}", response);
-                Ok(response)
+        self.read_blocking(Duration::ZERO)
+    }
+
+    /// Waits up to `timeout` for the next report from the lamp. Frames that
+    /// do not parse are skipped. Replies are not taken off the replies owed
+    /// until the caller passes them to [`settle`](Self::settle).
+    pub fn read_blocking(&self, timeout: Duration) -> Result<Option<Response>, Error> {
+        let deadline = Instant::now() + timeout;
+        while let Some(frame) =
+            self.next_frame(deadline.saturating_duration_since(Instant::now()))?
+        {
+            if let Some(response) = self.parse(&frame) {
+                return Ok(Some(response));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Discards raw frames, parseable or not, until the queue is quiet or
+    /// `timeout` elapses. Replies still owed after that are written off.
+    pub fn drain(&self, timeout: Duration) -> Result<usize, Error> {
+        self.owed.set([0; 3]);
+        let deadline = Instant::now() + timeout;
+        let mut dropped = 0;
+        while Instant::now() < deadline {
+            let Some(frame) = self.next_frame(DRAIN_QUIET)? else {
+                break;
+            };
+            debug!(
+                "Drained {} bytes: {:02x?}",
+                frame.len(),
+                &frame[..frame.len().min(16)]
+            );
+            dropped += 1;
+        }
+        Ok(dropped)
+    }
+
+    /// Sends the read-back query for `cmd` and waits for the matching
+    /// response, discarding unrelated reports until `timeout` elapses.
+    pub fn query(&self, cmd: Command, timeout: Duration) -> Result<Option<Response>, Error> {
+        let query = cmd.query();
+        let deadline = Instant::now() + timeout;
+        self.send(query)?;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            if let Some(response) = self.read(remaining)?
+                && response.matches(&query)
+            {
+                return Ok(Some(response));
+            }
+        }
+    }
+
+    /// Sends `cmds` in order. With `confirm`, each waits up to that long to
+    /// be confirmed, as with [`set_confirmed`](Self::set_confirmed), before
+    /// the next goes out. Returns the commands that were not confirmed.
+    pub fn send_all(
+        &self,
+        cmds: &[Command],
+        confirm: Option<Duration>,
+    ) -> Result<Vec<Command>, Error> {
+        let mut failed = Vec::new();
+        for &cmd in cmds {
+            match confirm {
+                Some(timeout) => {
+                    if !self.set_confirmed(cmd, timeout)? {
+                        failed.push(cmd);
+                    }
+                }
+                None => self.send(cmd)?,
             }
-            Err(e) => Err(e.into()),
         }
+        Ok(failed)
+    }
+
+    /// Reads the power state, waiting up to `timeout` for each of a few
+    /// attempts, and writes the opposite, confirmed like
+    /// [`set_confirmed`](Self::set_confirmed). Returns the new state.
+    /// Reports about other settings that arrive while waiting are skipped
+    /// rather than taken for the answer.
+    pub fn toggle_power(&self, timeout: Duration) -> Result<bool, Error> {
+        let mut current = None;
+        for attempt in 1..=TOGGLE_ATTEMPTS {
+            if let Some(Response::Power(on, _)) = self.query(Command::GetPower, timeout)? {
+                current = Some(on);
+                break;
+            }
+            debug!("No power state on attempt {}", attempt);
+        }
+        let on = !current.ok_or(Error::ToggleFailed(None))?;
+        if !self.set_confirmed(Command::SetPower(on), timeout)? {
+            return Err(Error::ToggleFailed(Some(on)));
+        }
+        Ok(on)
+    }
+
+    /// Sends a set command and waits until the device reports the new value,
+    /// resending once if it was not applied.
+    pub fn set_confirmed(&self, cmd: Command, timeout: Duration) -> Result<bool, Error> {
+        for _ in 0..2 {
+            self.send(cmd)?;
+            if let Some(response) = self.query(cmd, timeout)?
+                && response.matches(&cmd)
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl Drop for LitraDevice {
+    /// Stops the reader thread. It holds the transport for at most one
+    /// short read, so this returns quickly.
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Release);
+        if let Some(reader) = self.reader.take()
+            && reader.join().is_err()
+        {
+            warn!("Reader thread panicked");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{MAX_TEMPERATURE, Trigger};
+    use crate::transport::FakeLitra;
+
+    const TIMEOUT: Duration = Duration::from_millis(500);
+
+    fn open(fake: &FakeLitra) -> LitraDevice {
+        LitraDevice::open(&DeviceOptions {
+            transport: TransportKind::Fake(fake.clone()),
+            ..DeviceOptions::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn every_query_is_answered() {
+        for model in Model::ALL {
+            let fake = FakeLitra::new(model);
+            fake.set_state(true, model.max_brightness(), MAX_TEMPERATURE);
+            let device = open(&fake);
+            assert_eq!(device.model(), model);
+            assert_eq!(
+                device.query(Command::GetPower, TIMEOUT).unwrap(),
+                Some(Response::Power(true, Trigger::Reply))
+            );
+            assert_eq!(
+                device.query(Command::GetBrightness, TIMEOUT).unwrap(),
+                Some(Response::Brightness(model.max_brightness(), Trigger::Reply))
+            );
+            assert_eq!(
+                device.query(Command::GetTemperature, TIMEOUT).unwrap(),
+                Some(Response::Temperature(MAX_TEMPERATURE, Trigger::Reply))
+            );
+        }
+    }
+
+    #[test]
+    fn set_commands_reach_the_lamp() {
+        let fake = FakeLitra::new(Model::Beam);
+        let device = open(&fake);
+        let cmds = [
+            Command::SetPower(true),
+            Command::SetBrightness(123),
+            Command::SetTemperature(4000),
+        ];
+        assert_eq!(device.send_all(&cmds, Some(TIMEOUT)).unwrap(), []);
+        assert_eq!(fake.state(), (true, 123, 4000));
+        let written: Vec<_> = fake
+            .written()
+            .into_iter()
+            .filter(|cmd| cmd.query() != *cmd)
+            .collect();
+        assert_eq!(written, cmds);
+    }
+
+    #[test]
+    fn out_of_range_commands_are_not_written() {
+        let fake = FakeLitra::new(Model::Glow);
+        let device = open(&fake);
+        assert!(matches!(
+            device.send(Command::SetBrightness(400)),
+            Err(Error::Protocol(_))
+        ));
+        assert_eq!(fake.written(), []);
+    }
+
+    #[test]
+    fn identity_and_name_are_read() {
+        let fake = FakeLitra::new(Model::BeamLx).with_serial("2233ABC");
+        let device = open(&fake);
+        let identity = device.info(TIMEOUT).unwrap();
+        assert_eq!(identity.serial.as_deref(), Some("2233ABC"));
+        assert_eq!(identity.firmware.as_deref(), Some("RQM 40.00.B0012"));
+        assert_eq!(
+            device.name(TIMEOUT).unwrap().as_deref(),
+            Some("Litra Beam LX")
+        );
+    }
+
+    #[test]
+    fn button_reports_are_not_taken_for_answers() {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.push(Response::Brightness(100, Trigger::Button));
+        let device = open(&fake);
+        assert_eq!(
+            device.query(Command::GetPower, TIMEOUT).unwrap(),
+            Some(Response::Power(false, Trigger::Reply))
+        );
+    }
+
+    #[test]
+    fn toggle_flips_and_confirms() {
+        let fake = FakeLitra::new(Model::Glow);
+        let device = open(&fake);
+        assert!(device.toggle_power(TIMEOUT).unwrap());
+        assert!(fake.state().0);
+        assert!(!device.toggle_power(TIMEOUT).unwrap());
+        assert!(!fake.state().0);
+    }
+
+    #[test]
+    fn silent_lamp_times_out() {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.set_silent(true);
+        let device = open(&fake);
+        let timeout = Duration::from_millis(50);
+        assert_eq!(device.query(Command::GetPower, timeout).unwrap(), None);
+        assert!(matches!(
+            device.toggle_power(timeout),
+            Err(Error::ToggleFailed(None))
+        ));
+        assert!(
+            !device
+                .set_confirmed(Command::SetPower(true), timeout)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn unplugged_lamp_is_disconnected() {
+        let fake = FakeLitra::new(Model::Glow);
+        let device = open(&fake);
+        fake.unplug();
+        let error = device.query(Command::GetPower, TIMEOUT).unwrap_err();
+        assert!(matches!(error, Error::Disconnected));
+        assert!(error.is_fatal());
+        assert_eq!(error.exit_code(), 4);
     }
 }
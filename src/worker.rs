@@ -0,0 +1,2836 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::mpsc::{RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{DateTime, Local};
+use tracing::{debug, debug_span, error, info, info_span, trace, warn};
+
+use crate::alarm::{self, AlarmConfig, Firing};
+use crate::arbiter::{Arbiter, ArbiterConfig, Pause, Setting, Source, Verdict};
+use crate::channel::{self, SendError};
+use crate::config::{ConfigStore, DeviceConfig};
+use crate::controller::{CONFIRM_TIMEOUT, Controller, Snapshot};
+use crate::convert::{brightness_to_percent, percent_to_brightness, snap_temperature};
+use crate::curve::TemperatureCurve;
+use crate::cycle::CycleConfig;
+use crate::focus::FocusConfig;
+use crate::history::{Entry, History, HistoryConfig, Origin};
+use crate::hotplug::Hotplug;
+use crate::i18n::msg;
+use crate::journal::{Intent, Journal};
+use crate::power_source::{self, BatteryConfig, PowerSource};
+use crate::protocol::{Command, MIN_TEMPERATURE, Model, Response, Trigger};
+use crate::scenes::{SceneConfig, SceneStore};
+use crate::transition::Transition;
+use crate::usage::OnTimer;
+use crate::usb::{self, DeviceInfo, Identity, LitraDevice};
+
+const PENDING_TIMEOUT: Duration = Duration::from_millis(300);
+/// Delay before the first retry of a failed open; it doubles with each
+/// failure up to `RECONNECT_MAX_DELAY`.
+const RECONNECT_MIN_DELAY: Duration = Duration::from_millis(500);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+/// How often the hotplug watcher refreshes the device list.
+const HOTPLUG_INTERVAL: Duration = Duration::from_millis(500);
+/// Loop cadence while a fade runs, and the default wait for lamp reports
+/// otherwise.
+const POLL_INTERVAL: Duration = Duration::from_millis(30);
+/// Shortest configurable wait for lamp reports, so the loop never spins.
+const MIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+const RESYNC_DRAIN_TIMEOUT: Duration = Duration::from_millis(100);
+const RESYNC_TIMEOUT: Duration = Duration::from_millis(500);
+/// A wall-clock gap this long between polls means the machine was asleep.
+const RESUME_GAP: Duration = Duration::from_secs(5);
+const ALARM_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const CURVE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const REFRESH_TIMEOUT: Duration = Duration::from_millis(500);
+const KEEPALIVE_TIMEOUT: Duration = Duration::from_millis(500);
+const POWER_SOURCE_INTERVAL: Duration = Duration::from_secs(30);
+const BATTERY_FADE: Duration = Duration::from_secs(2);
+/// How often counted on-time is saved, bounding what a crash loses.
+const ON_TIME_SAVE_INTERVAL: Duration = Duration::from_secs(300);
+
+#[derive(Debug)]
+pub enum DeviceCommand {
+    Retry,
+    SetPower(bool),
+    /// Switch power to the opposite of the last known state.
+    TogglePower,
+    SetBrightness(u16),
+    SetTemperature(u16),
+    /// Fade brightness and temperature together to a stored scene.
+    #[cfg_attr(not(feature = "tray"), allow(dead_code))]
+    ApplyScene {
+        name: String,
+        duration: Duration,
+    },
+    /// Step brightness to the next value in the cycle list.
+    #[cfg_attr(not(feature = "tray"), allow(dead_code))]
+    CycleBrightness,
+    /// Step temperature to the next value in the cycle list.
+    #[cfg_attr(not(feature = "tray"), allow(dead_code))]
+    CycleTemperature,
+    /// Replace the wake-up alarm schedule. Disarming stops a running ramp.
+    SetAlarm(AlarmConfig),
+    /// Stop a running wake-up ramp, leaving the lamp where it got to.
+    #[cfg_attr(not(feature = "tray"), allow(dead_code))]
+    DismissAlarm,
+    /// Pause all automation rules, or resume them with `None`.
+    #[cfg_attr(not(feature = "tray"), allow(dead_code))]
+    PauseAutomations(Option<Pause>),
+    /// A listed app gained (`true`) or lost focus.
+    FocusChanged(bool),
+    /// Turn focus follow on or off.
+    #[cfg_attr(not(feature = "tray"), allow(dead_code))]
+    SetFocusFollow(bool),
+    /// Drive every other connected lamp along with this one, or stop.
+    #[cfg_attr(not(feature = "tray"), allow(dead_code))]
+    SetSync(bool),
+    /// Report the changes recorded over the given span as
+    /// `DeviceEvent::History`.
+    QueryHistory(Duration),
+    /// Run the exit actions, acknowledge with `DeviceEvent::ShutdownComplete`
+    /// and stop the worker.
+    Shutdown {
+        power_off: bool,
+    },
+}
+
+impl DeviceCommand {
+    /// Whether `self` is a setter that `newer` makes pointless to send.
+    pub fn replaced_by(&self, newer: &DeviceCommand) -> bool {
+        matches!(
+            (self, newer),
+            (DeviceCommand::Retry, DeviceCommand::Retry)
+                | (DeviceCommand::SetPower(_), DeviceCommand::SetPower(_))
+                | (
+                    DeviceCommand::SetBrightness(_),
+                    DeviceCommand::SetBrightness(_)
+                )
+                | (
+                    DeviceCommand::SetTemperature(_),
+                    DeviceCommand::SetTemperature(_)
+                )
+                | (
+                    DeviceCommand::ApplyScene { .. },
+                    DeviceCommand::ApplyScene { .. }
+                )
+                | (DeviceCommand::SetAlarm(_), DeviceCommand::SetAlarm(_))
+                | (
+                    DeviceCommand::FocusChanged(_),
+                    DeviceCommand::FocusChanged(_)
+                )
+                | (
+                    DeviceCommand::SetFocusFollow(_),
+                    DeviceCommand::SetFocusFollow(_)
+                )
+                | (DeviceCommand::SetSync(_), DeviceCommand::SetSync(_))
+                | (
+                    DeviceCommand::QueryHistory(_),
+                    DeviceCommand::QueryHistory(_)
+                )
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum DeviceEvent {
+    /// A lamp of this model was opened.
+    Connected(Model),
+    /// What the HID layer reported about the opened lamp. Follows
+    /// `Connected` for real lamps.
+    Info(DeviceInfo),
+    /// The lamp was unplugged or stopped answering.
+    Disconnected,
+    Power(bool),
+    Brightness(u16),
+    Temperature(u16),
+    Error(String),
+    /// Something the user should notice that is not a connection error.
+    Warning(String),
+    /// The setting was just changed with the lamp's own buttons. Follows
+    /// the event carrying the new value.
+    ButtonPressed(Setting),
+    /// Automations were paused, resumed, or their pause ran out.
+    AutomationsPaused(Option<Pause>),
+    /// Recorded changes, oldest first, in answer to `QueryHistory`.
+    History(Vec<Entry>),
+    /// Total seconds the connected lamp has been on, after connecting and
+    /// each time the count is saved.
+    OnTime(u64),
+    /// The connected lamp's serial number, firmware version and name.
+    Identity(Identity),
+    ShutdownComplete,
+}
+
+impl DeviceEvent {
+    /// Whether this event makes `previous` obsolete: both report the same
+    /// setting, or a button press on it, so only the latest matters.
+    fn supersedes(&self, previous: &DeviceEvent) -> bool {
+        match (previous, self) {
+            (DeviceEvent::ButtonPressed(before), DeviceEvent::ButtonPressed(now)) => before == now,
+            _ => matches!(
+                (previous, self),
+                (DeviceEvent::Power(_), DeviceEvent::Power(_))
+                    | (DeviceEvent::Brightness(_), DeviceEvent::Brightness(_))
+                    | (DeviceEvent::Temperature(_), DeviceEvent::Temperature(_))
+                    | (DeviceEvent::History(_), DeviceEvent::History(_))
+                    | (DeviceEvent::OnTime(_), DeviceEvent::OnTime(_))
+            ),
+        }
+    }
+
+    /// Whether this event only carries a value, so a later value of another
+    /// setting may be moved ahead of it when coalescing.
+    fn is_value(&self) -> bool {
+        matches!(
+            self,
+            DeviceEvent::Power(_)
+                | DeviceEvent::Brightness(_)
+                | DeviceEvent::Temperature(_)
+                | DeviceEvent::ButtonPressed(_)
+                | DeviceEvent::History(_)
+                | DeviceEvent::OnTime(_)
+        )
+    }
+
+    /// Whether this event reports the state of one lamp, as opposed to the
+    /// worker's own state or a message for the user.
+    pub fn is_about_lamp(&self) -> bool {
+        matches!(
+            self,
+            DeviceEvent::Connected(_)
+                | DeviceEvent::Info(_)
+                | DeviceEvent::Disconnected
+                | DeviceEvent::Power(_)
+                | DeviceEvent::Brightness(_)
+                | DeviceEvent::Temperature(_)
+                | DeviceEvent::ButtonPressed(_)
+                | DeviceEvent::OnTime(_)
+                | DeviceEvent::Identity(_)
+        )
+    }
+}
+
+/// Names a lamp across reconnects: its serial number, else its HID path.
+/// Lamps reporting neither, such as the dry-run lamp, share the default id.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct DeviceId(String);
+
+impl DeviceId {
+    pub fn of(controller: &Controller) -> Self {
+        controller
+            .serial_number()
+            .or_else(|| controller.device_info().map(|info| info.path.clone()))
+            .map(Self)
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            f.write_str("default lamp")
+        } else {
+            f.write_str(&self.0)
+        }
+    }
+}
+
+/// An event with the lamp it is about. Events not tied to a lamp, such as
+/// history or shutdown, carry the id of the current or last opened one.
+#[derive(Debug)]
+pub struct Routed {
+    pub device: DeviceId,
+    pub event: DeviceEvent,
+}
+
+impl Routed {
+    fn supersedes(&self, previous: &Routed) -> bool {
+        self.device == previous.device && self.event.supersedes(&previous.event)
+    }
+}
+
+/// Collapses same-setting events from the same lamp to their final value,
+/// as long as only other values lie between them: a button held on the lamp
+/// alternates values and `ButtonPressed`. Each kept event sits where its last
+/// occurrence arrived, so a value still comes before its `ButtonPressed`.
+/// Connection, error, warning and shutdown events are always kept, and no
+/// value moves past them.
+pub fn coalesce_events(events: impl IntoIterator<Item = Routed>) -> Vec<Routed> {
+    let mut coalesced: Vec<Routed> = Vec::new();
+    for event in events {
+        let superseded = coalesced
+            .iter()
+            .rev()
+            .take_while(|queued| queued.event.is_value())
+            .position(|queued| event.supersedes(queued));
+        if let Some(back) = superseded {
+            coalesced.remove(coalesced.len() - 1 - back);
+        }
+        coalesced.push(event);
+    }
+    coalesced
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceState {
+    pub power: bool,
+    pub brightness: u16,
+    pub temperature: u16,
+    pub pending_brightness: Option<Instant>,
+    pub pending_temperature: Option<Instant>,
+}
+
+/// Source of time for the worker, so timing logic can be driven without sleeping.
+pub trait Clock: Send {
+    fn now(&self) -> Instant;
+    /// Wall-clock time, which unlike `now` keeps running while suspended.
+    fn wall_now(&self) -> SystemTime;
+    fn sleep(&self, duration: Duration);
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wall_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+pub type Connector = Box<dyn FnMut() -> Result<LitraDevice, usb::Error> + Send>;
+
+/// Owns the device connection on the worker thread: applies UI commands,
+/// suppresses stale echoes of pending writes, and reports state as events.
+pub struct DeviceWorker {
+    connect: Connector,
+    clock: Box<dyn Clock>,
+    events: channel::Sender<Routed>,
+    device: Option<Controller>,
+    /// Lamp the events are about: the connected one, or the last one while
+    /// disconnected.
+    device_id: DeviceId,
+    state: DeviceState,
+    /// Last known state of each lamp opened before, restored when it comes
+    /// back so a lamp never starts from another one's values.
+    states: HashMap<DeviceId, DeviceState>,
+    last_error: Option<String>,
+    journal: Option<Journal>,
+    intent: Intent,
+    serial: Option<String>,
+    /// Model of the connected lamp, or of the last one while disconnected.
+    model: Model,
+    devices: BTreeMap<String, DeviceConfig>,
+    /// Lowest brightness written to any lamp, in percent of its range.
+    floor: u8,
+    /// Whether other connected lamps follow the one opened.
+    sync: bool,
+    usage_store: Option<ConfigStore>,
+    on_timer: OnTimer,
+    on_time_saved: Option<Instant>,
+    last_poll: Option<SystemTime>,
+    resyncs: u32,
+    scenes: Option<SceneStore>,
+    scene_config: SceneConfig,
+    transition: Option<Transition>,
+    last_step: Option<Instant>,
+    alarm: AlarmConfig,
+    alarm_checked: Option<DateTime<Local>>,
+    alarm_ramp: bool,
+    arbiter: Arbiter,
+    cycle: CycleConfig,
+    curve: TemperatureCurve,
+    curve_checked: Option<Instant>,
+    refresh_interval: Duration,
+    last_refresh: Option<Instant>,
+    keepalive_interval: Duration,
+    /// Longest wait for a lamp report between loop iterations while idle.
+    poll_interval: Duration,
+    /// Whether to write the last known state back to a lamp that comes back
+    /// from sleep or a reconnect, instead of adopting what it reports.
+    restore_state: bool,
+    /// Whether `state` holds values learned from the current lamp, so there
+    /// is something to restore.
+    synced: bool,
+    /// Last time anything was written to or read from the lamp.
+    last_exchange: Option<Instant>,
+    battery: BatteryConfig,
+    power_source: Option<PowerSource>,
+    power_source_checked: Option<Instant>,
+    hotplug: Option<Hotplug>,
+    hotplug_checked: Option<Instant>,
+    /// When the next open may be tried, and the delay after that one.
+    next_attempt: Option<Instant>,
+    reconnect_delay: Duration,
+    /// Whether the user asked to retry now, skipping backoff and hotplug.
+    retry_now: bool,
+    /// When battery dimming was applied, and the brightness and temperature
+    /// to restore on AC.
+    battery_saved: Option<(Instant, u16, u16)>,
+    focus: FocusConfig,
+    /// When to turn the lamp off after a listed app lost focus.
+    focus_off_at: Option<Instant>,
+    /// Whether the lamp was already on when a listed app got focus.
+    focus_was_on: bool,
+    history: History,
+    /// Whether the state changed because the lamp reported it since the
+    /// last history entry.
+    reported: bool,
+}
+
+impl DeviceWorker {
+    pub fn new(
+        connect: Connector,
+        clock: Box<dyn Clock>,
+        events: channel::Sender<Routed>,
+        state: DeviceState,
+    ) -> Self {
+        Self {
+            connect,
+            clock,
+            events,
+            device: None,
+            device_id: DeviceId::default(),
+            state,
+            states: HashMap::new(),
+            last_error: None,
+            journal: None,
+            intent: Intent::default(),
+            serial: None,
+            model: Model::default(),
+            devices: BTreeMap::new(),
+            floor: 0,
+            sync: false,
+            usage_store: None,
+            on_timer: OnTimer::default(),
+            on_time_saved: None,
+            last_poll: None,
+            resyncs: 0,
+            scenes: None,
+            scene_config: SceneConfig::default(),
+            transition: None,
+            last_step: None,
+            alarm: AlarmConfig::default(),
+            alarm_checked: None,
+            alarm_ramp: false,
+            arbiter: Arbiter::new(ArbiterConfig::default()),
+            cycle: CycleConfig::default(),
+            curve: TemperatureCurve::default(),
+            curve_checked: None,
+            refresh_interval: Duration::ZERO,
+            keepalive_interval: Duration::ZERO,
+            poll_interval: POLL_INTERVAL,
+            restore_state: false,
+            synced: false,
+            last_exchange: None,
+            last_refresh: None,
+            battery: BatteryConfig::default(),
+            power_source: None,
+            power_source_checked: None,
+            hotplug: None,
+            hotplug_checked: None,
+            next_attempt: None,
+            reconnect_delay: RECONNECT_MIN_DELAY,
+            retry_now: false,
+            battery_saved: None,
+            focus: FocusConfig::default(),
+            focus_off_at: None,
+            focus_was_on: false,
+            history: History::new(&HistoryConfig::default()),
+            reported: false,
+        }
+    }
+
+    /// Re-reads the lamp every `interval` while idle to catch changes made
+    /// by other software. Zero disables it.
+    pub fn with_refresh(mut self, interval: Duration) -> Self {
+        self.refresh_interval = interval;
+        self
+    }
+
+    /// Asks the lamp for its power state after `interval` without any
+    /// exchange, so a lamp lost while idle is noticed. Zero disables it.
+    pub fn with_keepalive(mut self, interval: Duration) -> Self {
+        self.keepalive_interval = interval;
+        self
+    }
+
+    /// How long the loop waits for a lamp report while idle, at least
+    /// `MIN_POLL_INTERVAL`. Longer waits mean fewer wakeups, but commands
+    /// from the window may wait as long.
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval.max(MIN_POLL_INTERVAL);
+        self
+    }
+
+    /// Writes the last known state back after resume or a reconnect, as the
+    /// lamp may have fallen back to its power-on defaults.
+    pub fn with_restore(mut self, restore: bool) -> Self {
+        self.restore_state = restore;
+        self
+    }
+
+    /// Per-lamp settings by serial number. Calibration offsets are applied
+    /// to every value written to the matching lamp.
+    pub fn with_devices(mut self, devices: BTreeMap<String, DeviceConfig>) -> Self {
+        self.devices = devices;
+        self
+    }
+
+    /// Raises every brightness written below `percent` of a lamp's range
+    /// to it.
+    pub fn with_brightness_floor(mut self, percent: u8) -> Self {
+        self.floor = percent;
+        self
+    }
+
+    /// Drives every other connected lamp along with the opened one.
+    pub fn with_sync(mut self, enabled: bool) -> Self {
+        self.sync = enabled;
+        self
+    }
+
+    /// Adds the time the lamp is on to its entry in the `devices` section
+    /// of `store` every few minutes and on disconnect and shutdown.
+    pub fn with_usage_store(mut self, store: ConfigStore) -> Self {
+        self.usage_store = Some(store);
+        self
+    }
+
+    /// Reconnects only once `hotplug` sees the lamp, and disconnects as soon
+    /// as it no longer does, instead of waiting for a failed read.
+    pub fn with_hotplug(mut self, hotplug: Hotplug) -> Self {
+        self.hotplug = Some(hotplug);
+        self
+    }
+
+    pub fn with_history(mut self, config: HistoryConfig) -> Self {
+        self.history = History::new(&config);
+        self
+    }
+
+    pub fn with_focus(mut self, focus: FocusConfig) -> Self {
+        self.focus = focus;
+        self
+    }
+
+    pub fn with_battery(mut self, battery: BatteryConfig) -> Self {
+        self.battery = battery;
+        self
+    }
+
+    pub fn with_curve(mut self, curve: TemperatureCurve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    pub fn with_cycle(mut self, cycle: CycleConfig) -> Self {
+        self.cycle = cycle;
+        self
+    }
+
+    pub fn with_arbiter(mut self, config: ArbiterConfig) -> Self {
+        self.arbiter = Arbiter::new(config);
+        self
+    }
+
+    pub fn with_alarm(mut self, alarm: AlarmConfig) -> Self {
+        self.alarm = alarm;
+        self
+    }
+
+    /// Looks up scenes for `DeviceCommand::ApplyScene` in `scenes`.
+    pub fn with_scenes(mut self, scenes: SceneStore) -> Self {
+        self.scenes = Some(scenes);
+        self
+    }
+
+    pub fn with_scene_config(mut self, config: SceneConfig) -> Self {
+        self.scene_config = config;
+        self
+    }
+
+    /// Persists settings requested while disconnected to `journal` and
+    /// replays them on the next connect, including after a restart.
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.intent = journal.load();
+        self.serial = self.intent.serial.clone();
+        self.journal = Some(journal);
+        self
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.device.is_some()
+    }
+
+    /// Opens the device and queries its state. Connection errors are reported
+    /// once per distinct message.
+    pub fn try_connect(&mut self) -> bool {
+        let _span = info_span!("connect").entered();
+        debug!("Trying to open device...");
+        match (self.connect)() {
+            Ok(dev) => {
+                info!("Device connected, querying state...");
+                let controller = Controller::new(dev);
+                self.serial = controller.serial_number();
+                self.model = controller.model();
+                let device = self
+                    .serial
+                    .as_ref()
+                    .and_then(|serial| self.devices.get(serial))
+                    .copied()
+                    .unwrap_or_default();
+                let calibration = device.calibration;
+                if !calibration.is_identity() {
+                    info!("Calibrating lamp: {}", calibration);
+                }
+                let controller = controller
+                    .with_calibration(calibration)
+                    .with_floor(self.floor);
+                let id = DeviceId::of(&controller);
+                if id != self.device_id {
+                    debug!("Switching from {} to {}", self.device_id, id);
+                    let previous = std::mem::replace(&mut self.device_id, id);
+                    self.states.insert(previous, self.state);
+                    self.synced = false;
+                    if let Some(state) = self.states.get(&self.device_id) {
+                        self.state = *state;
+                        self.synced = true;
+                    }
+                }
+                self.replay_intent(&controller);
+                self.device = Some(controller);
+                self.last_error = None;
+                self.last_poll = None;
+                self.emit(DeviceEvent::Connected(self.model));
+                if let Some(info) = self.device.as_ref().and_then(Controller::device_info) {
+                    self.emit(DeviceEvent::Info(info.clone()));
+                }
+                self.recover("connect");
+                if self.sync {
+                    self.open_followers();
+                }
+                self.on_time_saved = Some(self.clock.now());
+                self.emit(DeviceEvent::OnTime(device.on_seconds));
+                self.query_identity();
+                self.is_connected()
+            }
+            Err(err) => {
+                self.report_error(err.to_string());
+                false
+            }
+        }
+    }
+
+    /// Opens the lamp, backing off after each failure. With a hotplug
+    /// watcher, nothing is tried until the lamp is plugged in, so a missing
+    /// lamp costs a device list refresh rather than an open attempt. A lamp
+    /// missing at startup is reported; after a disconnect, that message
+    /// stays. A retry asked for by the user skips both.
+    fn reconnect(&mut self) -> bool {
+        let forced = std::mem::take(&mut self.retry_now);
+        if !forced && let Some(hotplug) = self.hotplug.as_mut() {
+            match hotplug.check() {
+                Ok(()) => {}
+                Err(err @ (usb::Error::DeviceNotFound(_) | usb::Error::NoMatch(_))) => {
+                    self.reset_backoff();
+                    if self.last_error.is_none() {
+                        self.report_error(err.to_string());
+                    }
+                    return false;
+                }
+                Err(err) => debug!("Hotplug check failed: {}", err),
+            }
+        }
+        let now = self.clock.now();
+        if !forced && self.next_attempt.is_some_and(|at| now < at) {
+            return false;
+        }
+        if self.try_connect() {
+            self.reset_backoff();
+            return true;
+        }
+        let delay = self.reconnect_delay;
+        self.next_attempt = Some(now + delay);
+        self.reconnect_delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+        if let Some(error) = self.last_error.clone() {
+            let seconds = delay.as_secs_f32().ceil() as u64;
+            debug!("Next open attempt in {:?}", delay);
+            self.emit(DeviceEvent::Error(msg!(
+                "error-retrying",
+                error = error,
+                seconds = seconds
+            )));
+        }
+        false
+    }
+
+    fn reset_backoff(&mut self) {
+        self.next_attempt = None;
+        self.reconnect_delay = RECONNECT_MIN_DELAY;
+    }
+
+    /// How long the loop may wait for a command before trying to reconnect.
+    fn reconnect_wait(&self) -> Duration {
+        let backoff = self.next_attempt.map_or(Duration::ZERO, |at| {
+            at.saturating_duration_since(self.clock.now())
+        });
+        match self.hotplug {
+            Some(_) => backoff.clamp(HOTPLUG_INTERVAL, RECONNECT_MAX_DELAY),
+            None => backoff.max(POLL_INTERVAL),
+        }
+    }
+
+    /// Whether the hotplug watcher saw the lamp go away since the last check.
+    fn unplugged(&mut self) -> bool {
+        let Some(hotplug) = self.hotplug.as_mut() else {
+            return false;
+        };
+        let now = self.clock.now();
+        if self
+            .hotplug_checked
+            .is_some_and(|checked| now.duration_since(checked) < HOTPLUG_INTERVAL)
+        {
+            return false;
+        }
+        self.hotplug_checked = Some(now);
+        matches!(
+            hotplug.check(),
+            Err(usb::Error::DeviceNotFound(_) | usb::Error::NoMatch(_))
+        )
+    }
+
+    /// Reports a connection error once per distinct message.
+    fn report_error(&mut self, message: String) {
+        if self.last_error.as_deref() != Some(&message) {
+            warn!("Device error: {}", message);
+            self.emit(DeviceEvent::Error(message.clone()));
+            self.last_error = Some(message);
+        }
+    }
+
+    /// Records the command in the local state and forwards it to the device
+    /// when connected. Setters that do not reach the device are journaled.
+    pub fn handle_command(&mut self, cmd: DeviceCommand) -> Result<(), usb::Error> {
+        self.handle_command_from(cmd, &Source::Manual)
+    }
+
+    /// Like [`handle_command`](Self::handle_command), with the setters
+    /// checked with the arbiter as changes by `source`. Ones it turns down
+    /// are dropped.
+    fn handle_command_from(
+        &mut self,
+        cmd: DeviceCommand,
+        source: &Source,
+    ) -> Result<(), usb::Error> {
+        let _span = debug_span!("command", command = ?cmd).entered();
+        let now = self.clock.now();
+        if self.device.is_some() {
+            self.last_exchange = Some(now);
+        }
+        let state = &mut self.state;
+        let device = self.device.as_ref();
+        let result = match cmd {
+            DeviceCommand::Retry | DeviceCommand::Shutdown { .. } => return Ok(()),
+            DeviceCommand::ApplyScene { ref name, duration } => {
+                return self.start_scene(name, duration, source);
+            }
+            DeviceCommand::CycleBrightness => return self.cycle(Setting::Brightness),
+            DeviceCommand::CycleTemperature => return self.cycle(Setting::Temperature),
+            DeviceCommand::SetAlarm(ref alarm) => {
+                if !alarm.armed {
+                    self.dismiss_alarm();
+                }
+                self.alarm = alarm.clone();
+                return Ok(());
+            }
+            DeviceCommand::DismissAlarm => {
+                self.dismiss_alarm();
+                return Ok(());
+            }
+            DeviceCommand::PauseAutomations(pause) => {
+                info!("Automations paused: {:?}", pause);
+                self.arbiter.set_pause(pause);
+                if pause.is_some() {
+                    self.dismiss_alarm();
+                    self.focus_off_at = None;
+                }
+                self.emit(DeviceEvent::AutomationsPaused(pause));
+                return Ok(());
+            }
+            DeviceCommand::QueryHistory(span) => {
+                let now: DateTime<Local> = self.clock.wall_now().into();
+                let since = now - chrono::Duration::from_std(span).unwrap_or_default();
+                self.emit(DeviceEvent::History(self.history.since(since)));
+                return Ok(());
+            }
+            DeviceCommand::FocusChanged(focused) => return self.focus_changed(focused),
+            DeviceCommand::SetFocusFollow(enabled) => {
+                self.focus.enabled = enabled;
+                self.focus_off_at = None;
+                return Ok(());
+            }
+            DeviceCommand::SetSync(enabled) => {
+                info!("Lamp sync enabled: {}", enabled);
+                self.sync = enabled;
+                if enabled {
+                    self.open_followers();
+                } else if let Some(dev) = self.device.as_ref() {
+                    dev.set_followers(Vec::new());
+                }
+                return Ok(());
+            }
+            DeviceCommand::TogglePower => {
+                // Flip what the lamp reports, not what was last seen, in
+                // case a report went missing.
+                let on = match device {
+                    Some(dev) => dev.toggle_power()?,
+                    None => !state.power,
+                };
+                self.arbiter.check(&Source::Manual, Setting::Power, now);
+                self.state.power = on;
+                self.emit(DeviceEvent::Power(on));
+                return Ok(());
+            }
+            DeviceCommand::SetPower(on) => {
+                if !allowed(&mut self.arbiter, source, Setting::Power, now) {
+                    return Ok(());
+                }
+                state.power = on;
+                device.map_or(Ok(()), |dev| dev.send(Command::SetPower(on)))
+            }
+            DeviceCommand::SetBrightness(level) => {
+                if !allowed(&mut self.arbiter, source, Setting::Brightness, now) {
+                    return Ok(());
+                }
+                state.brightness = level;
+                state.pending_brightness = Some(now);
+                if let Some(transition) = &mut self.transition {
+                    transition.drop_brightness();
+                }
+                device.map_or(Ok(()), |dev| dev.send(Command::SetBrightness(level)))
+            }
+            DeviceCommand::SetTemperature(level) => {
+                if !allowed(&mut self.arbiter, source, Setting::Temperature, now) {
+                    return Ok(());
+                }
+                state.temperature = level;
+                state.pending_temperature = Some(now);
+                if let Some(transition) = &mut self.transition {
+                    transition.drop_temperature();
+                }
+                device.map_or(Ok(()), |dev| dev.send(Command::SetTemperature(level)))
+            }
+        };
+
+        if device.is_none() || result.is_err() {
+            self.record_intent(&cmd);
+        }
+        result
+    }
+
+    /// Starts fading to the scene called `name`, as a change by `source`. A
+    /// fade already running is replaced from wherever both axes got to, so a
+    /// new scene never leaves one axis stranded half-way.
+    fn start_scene(
+        &mut self,
+        name: &str,
+        duration: Duration,
+        source: &Source,
+    ) -> Result<(), usb::Error> {
+        let scene = match self.scenes.as_ref().map(|store| store.get(name)) {
+            Some(Ok(Some(scene))) => scene,
+            Some(Ok(None)) | None => {
+                warn!("Unknown scene {:?}", name);
+                return Ok(());
+            }
+            Some(Err(e)) => {
+                warn!("Failed to read scenes: {}", e);
+                return Ok(());
+            }
+        };
+        let brightness = percent_to_brightness(scene.brightness, self.model);
+        let temperature = snap_temperature(scene.temperature);
+        info!("Applying scene {:?} over {:?}", name, duration);
+        let power = scene
+            .power
+            .or((self.scene_config.power_on && !self.state.power).then_some(true));
+        if let Some(on) = power
+            && on != self.state.power
+        {
+            self.handle_command_from(DeviceCommand::SetPower(on), source)?;
+            if self.state.power == on {
+                self.emit(DeviceEvent::Power(on));
+            }
+        }
+
+        self.alarm_ramp = false;
+        if !self.is_connected() || duration.is_zero() {
+            self.transition = None;
+            self.handle_command_from(DeviceCommand::SetBrightness(brightness), source)?;
+            return self.handle_command_from(DeviceCommand::SetTemperature(temperature), source);
+        }
+        let now = self.clock.now();
+        let mut transition = Transition::new(
+            now,
+            duration,
+            (self.state.brightness, brightness),
+            (self.state.temperature, temperature),
+        );
+        if !allowed(&mut self.arbiter, source, Setting::Brightness, now) {
+            transition.drop_brightness();
+        }
+        if !allowed(&mut self.arbiter, source, Setting::Temperature, now) {
+            transition.drop_temperature();
+        }
+        self.transition = Some(transition);
+        self.last_step = None;
+        Ok(())
+    }
+
+    /// Moves brightness or temperature to the entry after the current one in
+    /// its cycle list. The lamp is asked for its value first, so the cycle
+    /// follows changes made on the lamp itself.
+    fn cycle(&mut self, setting: Setting) -> Result<(), usb::Error> {
+        let query = match setting {
+            Setting::Power => return Ok(()),
+            Setting::Brightness => Command::GetBrightness,
+            Setting::Temperature => Command::GetTemperature,
+        };
+        if let Some(dev) = self.device.as_ref() {
+            match dev.query(query, CONFIRM_TIMEOUT)? {
+                Some(Response::Brightness(level, _)) => self.state.brightness = level,
+                Some(Response::Temperature(level, _)) => self.state.temperature = level,
+                _ => {}
+            }
+        }
+        let (brightness, temperature) = (self.state.brightness, self.state.temperature);
+        let next = match setting {
+            Setting::Brightness => self.cycle.next_brightness(brightness, self.model),
+            _ => self.cycle.next_temperature(temperature),
+        };
+        let Some(next) = next else {
+            return Ok(());
+        };
+        debug!("Cycling {:?} to {}", setting, next);
+
+        let fade = Duration::from_millis(self.cycle.fade_ms);
+        if self.is_connected() && !fade.is_zero() {
+            let now = self.clock.now();
+            self.arbiter.check(&Source::Manual, setting, now);
+            self.alarm_ramp = false;
+            let transition = match setting {
+                Setting::Brightness => {
+                    let mut transition =
+                        Transition::new(now, fade, (brightness, next), (temperature, temperature));
+                    transition.drop_temperature();
+                    transition
+                }
+                _ => {
+                    let mut transition =
+                        Transition::new(now, fade, (brightness, brightness), (temperature, next));
+                    transition.drop_brightness();
+                    transition
+                }
+            };
+            self.transition = Some(transition);
+            self.last_step = None;
+            return Ok(());
+        }
+
+        match setting {
+            Setting::Brightness => {
+                self.handle_command(DeviceCommand::SetBrightness(next))?;
+                self.emit(DeviceEvent::Brightness(next));
+            }
+            _ => {
+                self.handle_command(DeviceCommand::SetTemperature(next))?;
+                self.emit(DeviceEvent::Temperature(next));
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the next step of the running fade, at most once per step
+    /// interval. Brightness and temperature are sent together each step.
+    fn step_transition(&mut self) -> Result<(), usb::Error> {
+        let Some(transition) = self.transition else {
+            return Ok(());
+        };
+        let now = self.clock.now();
+        if self
+            .last_step
+            .is_some_and(|last| now.duration_since(last) < transition.interval())
+        {
+            return Ok(());
+        }
+        self.last_step = Some(now);
+        let Some(dev) = self.device.as_ref() else {
+            return Ok(());
+        };
+
+        let step = transition.step(now);
+        if let Some(level) = step.brightness
+            && level != self.state.brightness
+        {
+            dev.send(Command::SetBrightness(level))?;
+            self.state.brightness = level;
+            self.emit(DeviceEvent::Brightness(level));
+        }
+        if let Some(level) = step.temperature
+            && level != self.state.temperature
+        {
+            dev.send(Command::SetTemperature(level))?;
+            self.state.temperature = level;
+            self.emit(DeviceEvent::Temperature(level));
+        }
+        if transition.is_done(now) {
+            debug!("Transition finished");
+            self.transition = None;
+            self.alarm_ramp = false;
+        }
+        Ok(())
+    }
+
+    /// Lifts a timed automation pause once it has run out. Rules pick up
+    /// from the current state; nothing missed meanwhile is replayed.
+    fn check_pause(&mut self) {
+        if self.arbiter.expire_pause(self.clock.wall_now().into()) {
+            info!("Automation pause ended");
+            self.emit(DeviceEvent::AutomationsPaused(None));
+        }
+    }
+
+    /// Starts the wake-up ramp when its start time has passed since the last
+    /// check. The wall clock is used so a start missed during suspend is
+    /// noticed on resume.
+    fn check_alarm(&mut self) -> Result<(), usb::Error> {
+        let now: DateTime<Local> = self.clock.wall_now().into();
+        let Some(last) = self.alarm_checked else {
+            self.alarm_checked = Some(now);
+            return Ok(());
+        };
+        if (now - last)
+            .to_std()
+            .is_ok_and(|elapsed| elapsed < ALARM_CHECK_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.alarm_checked = Some(now);
+        match self.alarm.due(last, now) {
+            Some(firing) => self.start_alarm(firing),
+            None => Ok(()),
+        }
+    }
+
+    /// Fades the temperature towards the curve once a minute while the lamp
+    /// is on. Waits while another fade runs, and for the hold time after a
+    /// manual temperature change. Brightness is left alone.
+    fn check_curve(&mut self) {
+        if self.curve.is_empty()
+            || self.device.is_none()
+            || !self.state.power
+            || self.transition.is_some()
+        {
+            return;
+        }
+        let now = self.clock.now();
+        if self
+            .curve_checked
+            .is_some_and(|last| now.duration_since(last) < CURVE_CHECK_INTERVAL)
+        {
+            return;
+        }
+        self.curve_checked = Some(now);
+        if self
+            .arbiter
+            .last_manual(Setting::Temperature)
+            .is_some_and(|at| now.duration_since(at) < self.curve.hold())
+        {
+            debug!("Temperature curve held after a manual change");
+            return;
+        }
+
+        let local: DateTime<Local> = self.clock.wall_now().into();
+        let Some(target) = self.curve.at(local.time()) else {
+            return;
+        };
+        let current = self.state.temperature;
+        if target == current {
+            return;
+        }
+        let verdict = self
+            .arbiter
+            .check(&Source::automation("curve"), Setting::Temperature, now);
+        if verdict != Verdict::Apply {
+            debug!("Temperature curve not applied: {:?}", verdict);
+            return;
+        }
+        debug!("Temperature curve: {} -> {}", current, target);
+        let brightness = self.state.brightness;
+        let mut transition = Transition::new(
+            now,
+            self.curve.fade(),
+            (brightness, brightness),
+            (current, target),
+        );
+        transition.drop_brightness();
+        self.transition = Some(transition);
+        self.last_step = None;
+    }
+
+    /// Polls the power source and dims the lamp when the machine switches to
+    /// battery. Only switches count, so starting up on battery changes nothing.
+    fn check_power_source(&mut self) -> Result<(), usb::Error> {
+        if !self.battery.enabled {
+            return Ok(());
+        }
+        let now = self.clock.now();
+        if self
+            .power_source_checked
+            .is_some_and(|last| now.duration_since(last) < POWER_SOURCE_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.power_source_checked = Some(now);
+        let source = power_source::current();
+        let previous = std::mem::replace(&mut self.power_source, source);
+        match (previous, source) {
+            (Some(PowerSource::Ac), Some(PowerSource::Battery)) => self.enter_battery(now),
+            (Some(PowerSource::Battery), Some(PowerSource::Ac)) => {
+                self.leave_battery(now);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Applies the battery scene, or caps brightness when there is none, and
+    /// remembers what to restore.
+    fn enter_battery(&mut self, now: Instant) -> Result<(), usb::Error> {
+        info!("Running on battery");
+        if self.device.is_none() || !self.state.power {
+            return Ok(());
+        }
+        let verdict = self
+            .arbiter
+            .check(&Source::automation("battery"), Setting::Brightness, now);
+        if verdict != Verdict::Apply {
+            info!("Battery dimming not applied: {:?}", verdict);
+            return Ok(());
+        }
+        self.battery_saved = Some((now, self.state.brightness, self.state.temperature));
+
+        let name = self.battery.scene.clone();
+        if let Some(Ok(Some(_))) = self.scenes.as_ref().map(|store| store.get(&name)) {
+            return self.start_scene(&name, BATTERY_FADE, &Source::automation("battery"));
+        }
+        let cap = percent_to_brightness(self.battery.brightness, self.model);
+        let (brightness, temperature) = (self.state.brightness, self.state.temperature);
+        if brightness > cap {
+            let mut transition = Transition::new(
+                now,
+                BATTERY_FADE,
+                (brightness, cap),
+                (temperature, temperature),
+            );
+            transition.drop_temperature();
+            self.transition = Some(transition);
+            self.last_step = None;
+        }
+        Ok(())
+    }
+
+    /// Fades back to the values from before battery dimming, unless they
+    /// were changed by hand since.
+    fn leave_battery(&mut self, now: Instant) {
+        info!("Running on AC power");
+        let Some((applied, brightness, temperature)) = self.battery_saved.take() else {
+            return;
+        };
+        if self.device.is_none() {
+            return;
+        }
+        let changed_by_hand =
+            [Setting::Brightness, Setting::Temperature]
+                .into_iter()
+                .any(|setting| {
+                    self.arbiter
+                        .last_manual(setting)
+                        .is_some_and(|at| at > applied)
+                });
+        if changed_by_hand {
+            info!("Not restoring after battery: changed by hand");
+            return;
+        }
+        let verdict = self
+            .arbiter
+            .check(&Source::automation("battery"), Setting::Brightness, now);
+        if verdict != Verdict::Apply {
+            info!("Restore after battery not applied: {:?}", verdict);
+            return;
+        }
+        self.transition = Some(Transition::new(
+            now,
+            BATTERY_FADE,
+            (self.state.brightness, brightness),
+            (self.state.temperature, temperature),
+        ));
+        self.last_step = None;
+    }
+
+    /// Turns the lamp on when a listed app gains focus. Losing focus only
+    /// schedules the turn-off, so quick alt-tabbing does not flicker it.
+    fn focus_changed(&mut self, focused: bool) -> Result<(), usb::Error> {
+        if !self.focus.enabled {
+            return Ok(());
+        }
+        if !focused {
+            self.focus_off_at = Some(self.clock.now() + self.focus.off_delay());
+            return Ok(());
+        }
+        if self.focus_off_at.take().is_some() {
+            return Ok(());
+        }
+        self.focus_was_on = self.state.power;
+        if self.state.power {
+            return Ok(());
+        }
+        self.automated_power("focus", true)
+    }
+
+    /// Turns the lamp off once the delay after losing focus has passed,
+    /// unless it was on before the app got focus and should stay so.
+    fn check_focus(&mut self) -> Result<(), usb::Error> {
+        let Some(at) = self.focus_off_at else {
+            return Ok(());
+        };
+        if self.clock.now() < at {
+            return Ok(());
+        }
+        self.focus_off_at = None;
+        if !self.state.power || (self.focus.restore_previous && self.focus_was_on) {
+            return Ok(());
+        }
+        self.automated_power("focus", false)
+    }
+
+    /// Switches power on behalf of the automation `rule`, if the arbiter
+    /// lets it.
+    fn automated_power(&mut self, rule: &str, on: bool) -> Result<(), usb::Error> {
+        let verdict =
+            self.arbiter
+                .check(&Source::automation(rule), Setting::Power, self.clock.now());
+        if let Some(oscillation) = self.arbiter.take_warning() {
+            warn!("{}", oscillation);
+            self.emit(DeviceEvent::Warning(oscillation.to_string()));
+        }
+        if verdict != Verdict::Apply {
+            info!("Power change by {} not applied: {:?}", rule, verdict);
+            return Ok(());
+        }
+        let Some(dev) = self.device.as_ref() else {
+            return Ok(());
+        };
+        info!("Power {} by {}", if on { "on" } else { "off" }, rule);
+        dev.send(Command::SetPower(on))?;
+        self.state.power = on;
+        self.emit(DeviceEvent::Power(on));
+        Ok(())
+    }
+
+    /// Turns the lamp on at its dimmest, warmest setting and starts a coarse
+    /// ramp to the alarm's target.
+    fn start_alarm(&mut self, firing: Firing) -> Result<(), usb::Error> {
+        if self.device.is_none() {
+            return Ok(());
+        }
+        let verdict = self.arbiter.check(
+            &Source::automation("alarm"),
+            Setting::Power,
+            self.clock.now(),
+        );
+        if let Some(oscillation) = self.arbiter.take_warning() {
+            warn!("{}", oscillation);
+            self.emit(DeviceEvent::Warning(oscillation.to_string()));
+        }
+        if verdict != Verdict::Apply {
+            info!("Wake-up alarm not started: {:?}", verdict);
+            return Ok(());
+        }
+        let Some(dev) = self.device.as_ref() else {
+            return Ok(());
+        };
+        info!(
+            "Wake-up alarm for {} starting, ramp {:?}",
+            firing.scheduled, firing.duration
+        );
+        let min_brightness = self.model.min_brightness();
+        dev.send(Command::SetBrightness(min_brightness))?;
+        dev.send(Command::SetTemperature(MIN_TEMPERATURE))?;
+        dev.send(Command::SetPower(true))?;
+        self.state.power = true;
+        self.state.brightness = min_brightness;
+        self.state.temperature = MIN_TEMPERATURE;
+        self.emit(DeviceEvent::Power(true));
+        self.emit(DeviceEvent::Brightness(min_brightness));
+        self.emit(DeviceEvent::Temperature(MIN_TEMPERATURE));
+
+        let target = (
+            percent_to_brightness(self.alarm.brightness, self.model),
+            snap_temperature(self.alarm.temperature),
+        );
+        self.transition = Some(
+            Transition::new(
+                self.clock.now(),
+                firing.duration,
+                (min_brightness, target.0),
+                (MIN_TEMPERATURE, target.1),
+            )
+            .with_interval(alarm::RAMP_STEP_INTERVAL),
+        );
+        self.last_step = None;
+        self.alarm_ramp = true;
+        Ok(())
+    }
+
+    fn dismiss_alarm(&mut self) {
+        if self.alarm_ramp {
+            info!("Wake-up ramp dismissed");
+            self.transition = None;
+            self.alarm_ramp = false;
+        }
+    }
+
+    fn record_intent(&mut self, cmd: &DeviceCommand) {
+        let Some(journal) = &self.journal else {
+            return;
+        };
+        match *cmd {
+            DeviceCommand::SetPower(on) => self.intent.power = Some(on),
+            DeviceCommand::SetBrightness(level) => self.intent.brightness = Some(level),
+            DeviceCommand::SetTemperature(level) => self.intent.temperature = Some(level),
+            DeviceCommand::Retry
+            | DeviceCommand::TogglePower
+            | DeviceCommand::ApplyScene { .. }
+            | DeviceCommand::CycleBrightness
+            | DeviceCommand::CycleTemperature
+            | DeviceCommand::SetAlarm(_)
+            | DeviceCommand::DismissAlarm
+            | DeviceCommand::PauseAutomations(_)
+            | DeviceCommand::FocusChanged(_)
+            | DeviceCommand::SetFocusFollow(_)
+            | DeviceCommand::SetSync(_)
+            | DeviceCommand::QueryHistory(_)
+            | DeviceCommand::Shutdown { .. } => return,
+        }
+        if self.serial.is_some() {
+            self.intent.serial = self.serial.clone();
+        }
+        if let Err(e) = journal.store(&self.intent) {
+            warn!("Failed to write command journal: {}", e);
+        }
+    }
+
+    /// Applies settings journaled while disconnected, then clears the
+    /// journal. Intents recorded for a different lamp are dropped. When the
+    /// state is about to be restored anyway, the intent only joins it.
+    fn replay_intent(&mut self, controller: &Controller) {
+        let Some(journal) = &self.journal else {
+            return;
+        };
+        if self.intent.is_empty() {
+            return;
+        }
+        if self.intent.applies_to(self.serial.as_deref()) {
+            if self.restore_state && self.synced {
+                debug!("Journaled settings are written with the restored state");
+            } else {
+                info!("Replaying settings requested while disconnected");
+                for cmd in self.intent.commands() {
+                    if let Err(e) = controller.send(cmd) {
+                        warn!("Failed to replay {:?}: {}", cmd, e);
+                        return;
+                    }
+                }
+            }
+            self.state.power = self.intent.power.unwrap_or(self.state.power);
+            self.state.brightness = self.intent.brightness.unwrap_or(self.state.brightness);
+            self.state.temperature = self.intent.temperature.unwrap_or(self.state.temperature);
+        } else {
+            info!("Discarding settings journaled for another lamp");
+        }
+        self.intent = Intent::default();
+        if let Err(e) = journal.clear() {
+            warn!("Failed to clear command journal: {}", e);
+        }
+    }
+
+    /// Applies a device report. Replies and acknowledgements that arrive
+    /// while a write of the same setting is pending are dropped so the UI
+    /// does not jump back, unless they echo the latest write; hardware
+    /// button reports always win. Holding a button at a limit repeats the
+    /// same report many times; those repeats are dropped.
+    pub fn handle_response(&mut self, response: Response) {
+        if response.is_hardware() && self.repeats_state(response) {
+            trace!("Suppressed repeated {:?}", response);
+            return;
+        }
+        debug!("Received response: {:?}", response);
+        let now = self.clock.now();
+        let setting = match response {
+            Response::Power(on, _) => {
+                self.state.power = on;
+                info!("Sending power event to UI: {}", on);
+                self.emit(DeviceEvent::Power(on));
+                Setting::Power
+            }
+            Response::Brightness(level, trigger) => {
+                if trigger == Trigger::Button
+                    || accept_pending(
+                        &mut self.state.pending_brightness,
+                        now,
+                        level == self.state.brightness,
+                    )
+                {
+                    self.state.brightness = level;
+                    self.emit(DeviceEvent::Brightness(level));
+                }
+                Setting::Brightness
+            }
+            Response::Temperature(level, trigger) => {
+                if trigger == Trigger::Button
+                    || accept_pending(
+                        &mut self.state.pending_temperature,
+                        now,
+                        level == self.state.temperature,
+                    )
+                {
+                    self.state.temperature = level;
+                    self.emit(DeviceEvent::Temperature(level));
+                }
+                Setting::Temperature
+            }
+            Response::FeatureIndex(_)
+            | Response::Firmware(_)
+            | Response::Serial(_)
+            | Response::NameLength(_)
+            | Response::NamePart(_) => return,
+            Response::Unknown { report } => {
+                debug!("Unknown report: {:02x?}", report);
+                return;
+            }
+        };
+        if response.is_hardware() {
+            self.reported = true;
+            self.emit(DeviceEvent::ButtonPressed(setting));
+        }
+    }
+
+    /// Whether `response` reports the value the app already has.
+    fn repeats_state(&self, response: Response) -> bool {
+        match response {
+            Response::Power(on, _) => on == self.state.power,
+            Response::Brightness(level, _) => level == self.state.brightness,
+            Response::Temperature(kelvin, _) => kelvin == self.state.temperature,
+            _ => false,
+        }
+    }
+
+    /// Whether `error` means the lamp is gone. Errors that leave the
+    /// connection usable are shown as a warning and the loop carries on.
+    fn fatal(&mut self, what: &str, error: usb::Error) -> bool {
+        if error.is_fatal() {
+            error!("{} failed: {}", what, error);
+            return true;
+        }
+        warn!("{} failed: {}", what, error);
+        self.emit(DeviceEvent::Warning(error.to_string()));
+        false
+    }
+
+    /// Handles every report the reader thread has queued. A reply to a
+    /// setting the lamp owes no reply for is stale, and like an
+    /// out-of-range value it triggers a resync instead of being applied.
+    pub fn tick(&mut self) {
+        while let Some(dev) = self.device.as_ref() {
+            let read = dev.try_read();
+            if !self.handle_read(read) {
+                return;
+            }
+        }
+    }
+
+    /// Waits for the next loop iteration. While a lamp is connected and no
+    /// fade runs, waits on the lamp instead of sleeping, so a button press
+    /// is handled as soon as it arrives.
+    fn wait(&mut self) {
+        let Some(dev) = self.device.as_ref().filter(|_| self.transition.is_none()) else {
+            self.clock.sleep(POLL_INTERVAL);
+            return;
+        };
+        let read = dev.read_blocking(self.poll_interval);
+        self.handle_read(read);
+    }
+
+    /// Handles the outcome of reading from the lamp: button reports, and
+    /// acknowledgements of unconfirmed writes or late answers to queries.
+    /// Returns whether a report was handled and the connection is still in
+    /// step.
+    fn handle_read(&mut self, read: Result<Option<Response>, usb::Error>) -> bool {
+        match read {
+            Ok(Some(response)) => {
+                self.last_exchange = Some(self.clock.now());
+                let owed = self.device.as_ref().is_none_or(|dev| dev.settle(&response));
+                if !response.is_plausible(self.model) {
+                    warn!("Unexpected response {:?}", response);
+                    self.resync("unexpected response");
+                    false
+                } else if !owed {
+                    warn!("Unsolicited response {:?}", response);
+                    self.resync("unsolicited response");
+                    false
+                } else {
+                    self.handle_response(response);
+                    true
+                }
+            }
+            Ok(None) => false,
+            Err(e) => {
+                error!("Read error: {:?}", e);
+                self.disconnect();
+                false
+            }
+        }
+    }
+
+    /// Opens every other connected lamp as a follower of the open one and
+    /// brings it to the same state. Only lamps opened over HID can lead,
+    /// since the others cannot tell which lamp they are.
+    fn open_followers(&mut self) {
+        let Some(controller) = self.device.as_ref() else {
+            return;
+        };
+        let Some(primary) = controller.device_info() else {
+            debug!("Lamp sync needs a HID lamp");
+            return;
+        };
+        let mut lamps = match LitraDevice::list() {
+            Ok(lamps) => lamps,
+            Err(e) => {
+                warn!("Failed to list lamps to sync: {}", e);
+                return;
+            }
+        };
+        lamps.retain(|lamp| {
+            lamp.path != primary.path && (lamp.serial.is_none() || lamp.serial != primary.serial)
+        });
+        let followers: Vec<Controller> = lamps
+            .iter()
+            .filter_map(|lamp| match LitraDevice::open_path(&lamp.path) {
+                Ok(device) => {
+                    let calibration = lamp
+                        .serial
+                        .as_ref()
+                        .and_then(|serial| self.devices.get(serial))
+                        .map(|device| device.calibration)
+                        .unwrap_or_default();
+                    Some(
+                        Controller::new(device)
+                            .with_calibration(calibration)
+                            .with_floor(self.floor),
+                    )
+                }
+                Err(e) => {
+                    warn!("Failed to open {} to sync: {}", lamp.path, e);
+                    None
+                }
+            })
+            .collect();
+        info!("Syncing {} other lamps", followers.len());
+        controller.set_followers(followers);
+        controller.align_followers(Snapshot {
+            power: Some(self.state.power),
+            brightness: Some(self.state.brightness),
+            temperature: Some(self.state.temperature),
+        });
+    }
+
+    /// Reports followers dropped after a failed write. The open lamp and the
+    /// remaining followers carry on.
+    fn check_followers(&mut self) {
+        let lost = self
+            .device
+            .as_ref()
+            .map(Controller::take_lost)
+            .unwrap_or_default();
+        for (lamp, e) in lost {
+            warn!("Dropped {} from sync: {}", lamp, e);
+            self.emit(DeviceEvent::Warning(msg!(
+                "sync-lamp-lost",
+                lamp = lamp.as_str(),
+                error = e.to_string()
+            )));
+        }
+    }
+
+    /// Asks the lamp for its serial number, firmware version and name. A
+    /// lamp that does not answer is reported without them.
+    fn query_identity(&mut self) {
+        let Some(controller) = self.device.as_ref() else {
+            return;
+        };
+        let mut identity = controller.info().unwrap_or_else(|e| {
+            debug!("Failed to read lamp identity: {}", e);
+            Identity::default()
+        });
+        identity.name = controller.name().unwrap_or_else(|e| {
+            debug!("Failed to read lamp name: {}", e);
+            None
+        });
+        debug!("Lamp identity: {:?}", identity);
+        self.emit(DeviceEvent::Identity(identity));
+    }
+
+    /// Drops everything queued by the device, re-queries power, brightness
+    /// and temperature, and takes state only from those fresh answers.
+    pub fn resync(&mut self, reason: &str) {
+        let Some(dev) = self.device.as_ref() else {
+            return;
+        };
+        self.resyncs += 1;
+        let _span = info_span!("resync", reason, count = self.resyncs).entered();
+        let snapshot = match dev.drain(RESYNC_DRAIN_TIMEOUT).and_then(|dropped| {
+            debug!("Discarded {} queued frames", dropped);
+            dev.read_state(RESYNC_TIMEOUT)
+        }) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                error!("Resync failed: {}", e);
+                self.disconnect();
+                return;
+            }
+        };
+        info!("Resynchronized device state: {:?}", snapshot);
+        self.last_refresh = Some(self.clock.now());
+        self.last_exchange = self.last_refresh;
+        self.reported = true;
+
+        self.synced = true;
+        self.state.pending_brightness = None;
+        self.state.pending_temperature = None;
+        if let Some(on) = snapshot.power {
+            self.state.power = on;
+            self.emit(DeviceEvent::Power(on));
+        }
+        if let Some(level) = snapshot.brightness {
+            self.state.brightness = level;
+            self.emit(DeviceEvent::Brightness(level));
+        }
+        if let Some(level) = snapshot.temperature {
+            self.state.temperature = level;
+            self.emit(DeviceEvent::Temperature(level));
+        }
+    }
+
+    /// Brings a lamp that may have lost its state back in line: writes the
+    /// last known state when restoring is on and it is known, else re-reads
+    /// the lamp.
+    fn recover(&mut self, reason: &str) {
+        if self.restore_state && self.synced {
+            self.restore(reason);
+        } else {
+            self.resync(reason);
+        }
+    }
+
+    /// Writes power, brightness and temperature from `state` to the lamp.
+    /// Brightness and temperature are marked pending so stale reports
+    /// arriving right after do not move the sliders back.
+    fn restore(&mut self, reason: &str) {
+        let Some(dev) = self.device.as_ref() else {
+            return;
+        };
+        let _span = info_span!("restore", reason).entered();
+        let state = self.state;
+        let cmds = [
+            Command::SetPower(state.power),
+            Command::SetBrightness(state.brightness),
+            Command::SetTemperature(state.temperature),
+        ];
+        let result = dev.drain(RESYNC_DRAIN_TIMEOUT).and_then(|dropped| {
+            debug!("Discarded {} queued frames", dropped);
+            dev.apply_all(&cmds)
+        });
+        match result {
+            Ok(unconfirmed) => {
+                for cmd in unconfirmed {
+                    warn!("Lamp did not confirm {:?}", cmd);
+                }
+            }
+            Err(e) => {
+                error!("Restore failed: {}", e);
+                self.disconnect();
+                return;
+            }
+        }
+        info!("Restored device state: {:?}", state);
+        let now = self.clock.now();
+        self.last_refresh = Some(now);
+        self.last_exchange = Some(now);
+        self.state.pending_brightness = Some(now);
+        self.state.pending_temperature = Some(now);
+        self.emit(DeviceEvent::Power(state.power));
+        self.emit(DeviceEvent::Brightness(state.brightness));
+        self.emit(DeviceEvent::Temperature(state.temperature));
+    }
+
+    /// Queries the lamp once the refresh interval has passed and adopts any
+    /// values that differ from ours, logging them so interfering software
+    /// can be spotted. Skipped while a fade runs or a write awaits its echo.
+    fn refresh(&mut self) {
+        let now = self.clock.now();
+        let pending =
+            |at: Option<Instant>| at.is_some_and(|at| now.duration_since(at) < PENDING_TIMEOUT);
+        if self.refresh_interval.is_zero()
+            || self.transition.is_some()
+            || pending(self.state.pending_brightness)
+            || pending(self.state.pending_temperature)
+            || self
+                .last_refresh
+                .is_some_and(|last| now.duration_since(last) < self.refresh_interval)
+        {
+            return;
+        }
+        self.last_refresh = Some(now);
+        self.last_exchange = Some(now);
+        let Some(dev) = self.device.as_ref() else {
+            return;
+        };
+        let _span = debug_span!("refresh").entered();
+        // Anything adopted below was changed outside the app.
+        self.reported = true;
+        let snapshot = match dev.read_state(REFRESH_TIMEOUT) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                error!("Refresh failed: {}", e);
+                self.disconnect();
+                return;
+            }
+        };
+
+        if let Some(on) = snapshot.power
+            && on != self.state.power
+        {
+            info!(
+                "Power changed outside the app: {} -> {}",
+                self.state.power, on
+            );
+            self.state.power = on;
+            self.emit(DeviceEvent::Power(on));
+        }
+        if let Some(level) = snapshot.brightness
+            && level != self.state.brightness
+        {
+            info!(
+                "Brightness changed outside the app: {} -> {}",
+                self.state.brightness, level
+            );
+            self.state.brightness = level;
+            self.emit(DeviceEvent::Brightness(level));
+        }
+        if let Some(level) = snapshot.temperature
+            && level != self.state.temperature
+        {
+            info!(
+                "Temperature changed outside the app: {} -> {}",
+                self.state.temperature, level
+            );
+            self.state.temperature = level;
+            self.emit(DeviceEvent::Temperature(level));
+        }
+    }
+
+    /// Whether the wall clock jumped since the last poll, meaning the system
+    /// was suspended in between.
+    fn resumed(&mut self) -> bool {
+        let now = self.clock.wall_now();
+        let gap = self
+            .last_poll
+            .and_then(|last| now.duration_since(last).ok())
+            .unwrap_or_default();
+        self.last_poll = Some(now);
+        gap >= RESUME_GAP
+    }
+
+    /// Asks the lamp for its power state once the keepalive interval passes
+    /// without any exchange. Only power is adopted, so brightness and
+    /// temperature writes awaiting their echo are left alone. A failed write
+    /// or a missing answer counts as a disconnect.
+    fn keepalive(&mut self) {
+        let now = self.clock.now();
+        if self.keepalive_interval.is_zero()
+            || self.transition.is_some()
+            || self
+                .last_exchange
+                .is_some_and(|last| now.duration_since(last) < self.keepalive_interval)
+        {
+            return;
+        }
+        self.last_exchange = Some(now);
+        let Some(dev) = self.device.as_ref() else {
+            return;
+        };
+        let _span = debug_span!("keepalive").entered();
+        match dev.query(Command::GetPower, KEEPALIVE_TIMEOUT) {
+            Ok(Some(Response::Power(on, _))) => {
+                if on != self.state.power {
+                    info!(
+                        "Power changed outside the app: {} -> {}",
+                        self.state.power, on
+                    );
+                    self.reported = true;
+                    self.state.power = on;
+                    self.emit(DeviceEvent::Power(on));
+                }
+            }
+            Ok(_) => {
+                warn!("Lamp did not answer the keepalive");
+                self.disconnect();
+            }
+            Err(e) => {
+                error!("Keepalive failed: {}", e);
+                self.disconnect();
+            }
+        }
+    }
+
+    pub fn disconnect(&mut self) {
+        warn!("Device disconnected");
+        self.save_on_time();
+        self.on_timer.pause();
+        self.device = None;
+        self.transition = None;
+        self.alarm_ramp = false;
+        self.hotplug_checked = None;
+        self.reset_backoff();
+        self.last_error = Some(msg!("device-disconnected"));
+        self.emit(DeviceEvent::Disconnected);
+    }
+
+    /// Performs the exit actions and acknowledges the shutdown. Safe to call
+    /// while disconnected; the power-off is then skipped.
+    pub fn shutdown(&mut self, power_off: bool) {
+        let _span = info_span!("shutdown", power_off).entered();
+        info!("Shutting down device worker");
+        if power_off && let Some(dev) = self.device.as_ref() {
+            match dev.apply(Command::SetPower(false)) {
+                Ok(true) => self.state.power = false,
+                Ok(false) => warn!("Power-off on exit was not confirmed"),
+                Err(e) => warn!("Failed to power off on exit: {}", e),
+            }
+        }
+        if self.is_connected() {
+            self.record_history();
+            self.count_on_time();
+            self.save_on_time();
+        }
+        self.history.flush();
+        self.device = None;
+        self.emit(DeviceEvent::ShutdownComplete);
+    }
+
+    /// Adds the current state to the history if it changed. The origin is
+    /// the lamp for reported changes, otherwise whoever the arbiter last
+    /// let change the setting.
+    fn record_history(&mut self) {
+        let reported = std::mem::take(&mut self.reported);
+        let state = &self.state;
+        let brightness = brightness_to_percent(state.brightness, self.model);
+        let setting = match self.history.last() {
+            Some(last) if last.power != state.power => Setting::Power,
+            Some(last) if last.brightness != brightness => Setting::Brightness,
+            Some(last) if last.temperature != state.temperature => Setting::Temperature,
+            Some(_) => return,
+            None => Setting::Power,
+        };
+        let origin = if reported {
+            Origin::Hardware
+        } else {
+            match self.arbiter.last_source(setting) {
+                Some(Source::Automation(_)) => Origin::Automation,
+                Some(Source::Manual) | None => Origin::Manual,
+            }
+        };
+        self.history.record(Entry {
+            at: self.clock.wall_now().into(),
+            power: state.power,
+            brightness,
+            temperature: state.temperature,
+            origin,
+        });
+    }
+
+    /// Counts the time since the last call while the lamp is on, and saves
+    /// the count once the save interval has passed.
+    fn count_on_time(&mut self) {
+        let now = self.clock.now();
+        self.on_timer
+            .sample(self.state.power, now, self.clock.wall_now());
+        if self
+            .on_time_saved
+            .is_some_and(|last| now.duration_since(last) >= ON_TIME_SAVE_INTERVAL)
+        {
+            self.save_on_time();
+        }
+    }
+
+    /// Adds the counted on-time to the lamp's stored total. The file is
+    /// re-read first, so a reset made meanwhile from the CLI is kept. Time
+    /// on a lamp without a serial number cannot be attributed and is
+    /// dropped.
+    fn save_on_time(&mut self) {
+        self.on_time_saved = Some(self.clock.now());
+        let seconds = self.on_timer.take_seconds();
+        let Some(serial) = self.serial.clone() else {
+            return;
+        };
+        if seconds == 0 {
+            return;
+        }
+        let device = self.devices.entry(serial.clone()).or_default();
+        device.on_seconds += seconds;
+        if let Some(store) = &self.usage_store {
+            match store.update(|config| {
+                config.devices.entry(serial.clone()).or_default().on_seconds += seconds
+            }) {
+                Ok(config) => device.on_seconds = config.on_seconds(Some(&serial)),
+                Err(e) => warn!("Failed to save lamp on-time: {}", e),
+            }
+        }
+        debug!("Lamp {} on for {}s in total", serial, device.on_seconds);
+        let total = device.on_seconds;
+        self.emit(DeviceEvent::OnTime(total));
+    }
+
+    fn emit(&self, event: DeviceEvent) {
+        let routed = Routed {
+            device: self.device_id.clone(),
+            event,
+        };
+        if let Err(SendError::Full(event)) = self
+            .events
+            .send_collapsing(routed, |queued, routed| routed.supersedes(queued))
+        {
+            warn!("UI event queue is full, dropping {:?}", event);
+        }
+    }
+
+    pub fn run(mut self, commands: channel::Receiver<DeviceCommand>) {
+        info!("Device loop started");
+        loop {
+            if !self.is_connected() && !self.reconnect() {
+                match commands.recv_timeout(self.reconnect_wait()) {
+                    Ok(DeviceCommand::Shutdown { power_off }) => {
+                        self.shutdown(power_off);
+                        return;
+                    }
+                    Ok(DeviceCommand::Retry) => {
+                        info!("Retrying to connect now");
+                        self.reset_backoff();
+                        self.retry_now = true;
+                    }
+                    Ok(cmd) => {
+                        debug!("Received command while disconnected: {:?}", cmd);
+                        let _ = self.handle_command(cmd);
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        info!("Command channel closed");
+                        self.shutdown(false);
+                        return;
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                }
+                continue;
+            }
+
+            let mut failed = false;
+            loop {
+                let cmd = match commands.try_recv() {
+                    Ok(cmd) => cmd,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        info!("Command channel closed");
+                        self.shutdown(false);
+                        return;
+                    }
+                };
+                debug!("Received command: {:?}", cmd);
+                if let DeviceCommand::Shutdown { power_off } = cmd {
+                    self.shutdown(power_off);
+                    return;
+                }
+                if let Err(e) = self.handle_command(cmd)
+                    && self.fatal("Command", e)
+                {
+                    failed = true;
+                    break;
+                }
+            }
+
+            self.check_pause();
+            if !failed && let Err(e) = self.check_alarm() {
+                failed = self.fatal("Wake-up alarm", e);
+            }
+            if !failed && let Err(e) = self.check_focus() {
+                failed = self.fatal("Focus follow", e);
+            }
+            if !failed && let Err(e) = self.check_power_source() {
+                failed = self.fatal("Battery dimming", e);
+            }
+            if !failed {
+                self.check_curve();
+            }
+            if !failed && self.unplugged() {
+                info!("Lamp unplugged");
+                failed = true;
+            }
+            if !failed && let Err(e) = self.step_transition() {
+                failed = self.fatal("Transition step", e);
+            }
+
+            if failed {
+                self.disconnect();
+            } else if self.resumed() {
+                info!("System resumed from sleep");
+                self.recover("resume");
+            } else {
+                self.refresh();
+                self.keepalive();
+                self.tick();
+            }
+            if self.is_connected() {
+                self.check_followers();
+                self.record_history();
+                self.count_on_time();
+            }
+
+            self.wait();
+        }
+    }
+}
+
+/// Checks a change of `setting` by `source` with `arbiter`, logging one it
+/// turns down. Returns whether to apply it.
+fn allowed(arbiter: &mut Arbiter, source: &Source, setting: Setting, now: Instant) -> bool {
+    let verdict = arbiter.check(source, setting, now);
+    if verdict != Verdict::Apply {
+        info!(
+            "{:?} change by {:?} not applied: {:?}",
+            setting, source, verdict
+        );
+    }
+    verdict == Verdict::Apply
+}
+
+/// Whether a reply may overwrite a setting with a write in flight. A reply
+/// carrying the value last written confirms the write and clears the
+/// pending mark right away; any other reply waits out `PENDING_TIMEOUT`.
+fn accept_pending(pending: &mut Option<Instant>, now: Instant, confirmed: bool) -> bool {
+    match *pending {
+        Some(_) if confirmed => {
+            *pending = None;
+            true
+        }
+        Some(t) if now.duration_since(t) < PENDING_TIMEOUT => false,
+        Some(_) => {
+            *pending = None;
+            true
+        }
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenes::Scene;
+    use crate::transport::FakeLitra;
+    use crate::usb::{DeviceOptions, TransportKind};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// A clock that only moves when told to, or when the worker sleeps.
+    #[derive(Clone)]
+    struct FakeClock(Arc<Mutex<(Instant, SystemTime)>>);
+
+    impl FakeClock {
+        fn new() -> Self {
+            Self(Arc::new(Mutex::new((Instant::now(), SystemTime::now()))))
+        }
+
+        fn advance(&self, duration: Duration) {
+            let mut now = self.0.lock().unwrap();
+            now.0 += duration;
+            now.1 += duration;
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Instant {
+            self.0.lock().unwrap().0
+        }
+
+        fn wall_now(&self) -> SystemTime {
+            self.0.lock().unwrap().1
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.advance(duration);
+        }
+    }
+
+    struct Harness {
+        worker: DeviceWorker,
+        clock: FakeClock,
+        events: channel::Receiver<Routed>,
+        opens: Arc<AtomicUsize>,
+    }
+
+    impl Harness {
+        fn new(fake: &FakeLitra) -> Self {
+            Self::with(fake, |worker| worker)
+        }
+
+        /// A harness whose worker is set up by `configure`.
+        fn with(fake: &FakeLitra, configure: impl FnOnce(DeviceWorker) -> DeviceWorker) -> Self {
+            let clock = FakeClock::new();
+            let (tx, events) = channel::bounded(64);
+            let opens = Arc::new(AtomicUsize::new(0));
+            let connect = {
+                let fake = fake.clone();
+                let opens = opens.clone();
+                Box::new(move || {
+                    opens.fetch_add(1, Ordering::SeqCst);
+                    LitraDevice::open(&DeviceOptions {
+                        transport: TransportKind::Fake(fake.clone()),
+                        ..DeviceOptions::default()
+                    })
+                })
+            };
+            let state = DeviceState {
+                power: false,
+                brightness: Model::Glow.min_brightness(),
+                temperature: MIN_TEMPERATURE,
+                pending_brightness: None,
+                pending_temperature: None,
+            };
+            let worker = configure(DeviceWorker::new(
+                connect,
+                Box::new(clock.clone()),
+                tx,
+                state,
+            ));
+            Self {
+                worker,
+                clock,
+                events,
+                opens,
+            }
+        }
+
+        /// Unplugs `fake` and waits for the worker to notice, which it does
+        /// on the reader thread's next read.
+        fn unplug(&mut self, fake: &FakeLitra) {
+            fake.unplug();
+            for _ in 0..100 {
+                if !self.worker.is_connected() {
+                    break;
+                }
+                self.worker.wait();
+            }
+            assert!(!self.worker.is_connected());
+        }
+
+        fn events(&self) -> Vec<DeviceEvent> {
+            self.events.try_iter().map(|routed| routed.event).collect()
+        }
+
+        fn brightness_events(&self) -> Vec<u16> {
+            self.events()
+                .into_iter()
+                .filter_map(|event| match event {
+                    DeviceEvent::Brightness(level) => Some(level),
+                    _ => None,
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn connecting_reads_the_lamp_state() {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.set_state(true, 120, 4000);
+        let mut harness = Harness::new(&fake);
+        assert!(harness.worker.try_connect());
+
+        let events = harness.events();
+        assert!(matches!(events[0], DeviceEvent::Connected(Model::Glow)));
+        assert!(events.iter().any(|e| matches!(e, DeviceEvent::Power(true))));
+        assert!(
+            events
+                .iter()
+                .any(|e| matches!(e, DeviceEvent::Brightness(120)))
+        );
+        assert!(
+            events
+                .iter()
+                .any(|e| matches!(e, DeviceEvent::Temperature(4000)))
+        );
+        let state = harness.worker.state;
+        assert_eq!(
+            (state.power, state.brightness, state.temperature),
+            (true, 120, 4000)
+        );
+
+        let written = fake.written();
+        for query in [
+            Command::GetPower,
+            Command::GetBrightness,
+            Command::GetTemperature,
+        ] {
+            assert!(written.contains(&query), "{written:?}");
+        }
+        assert!(!written.iter().any(|cmd| matches!(
+            cmd,
+            Command::SetPower(_) | Command::SetBrightness(_) | Command::SetTemperature(_)
+        )));
+    }
+
+    #[test]
+    fn stale_reports_are_dropped_while_a_write_is_pending() {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.set_state(true, 100, 4000);
+        let mut harness = Harness::new(&fake);
+        assert!(harness.worker.try_connect());
+        harness.events();
+
+        harness
+            .worker
+            .handle_command(DeviceCommand::SetBrightness(200))
+            .unwrap();
+        assert_eq!(fake.written().last(), Some(&Command::SetBrightness(200)));
+        harness
+            .worker
+            .handle_response(Response::Brightness(100, Trigger::Reply));
+        assert!(harness.brightness_events().is_empty());
+        assert_eq!(harness.worker.state.brightness, 200);
+
+        harness.clock.advance(PENDING_TIMEOUT);
+        harness
+            .worker
+            .handle_response(Response::Brightness(100, Trigger::Reply));
+        assert_eq!(harness.brightness_events(), [100]);
+        assert_eq!(harness.worker.state.pending_brightness, None);
+    }
+
+    #[test]
+    fn the_echo_of_a_write_ends_the_pending_window() {
+        let fake = FakeLitra::new(Model::Glow);
+        let mut harness = Harness::new(&fake);
+        assert!(harness.worker.try_connect());
+        harness.events();
+
+        harness
+            .worker
+            .handle_command(DeviceCommand::SetBrightness(200))
+            .unwrap();
+        harness
+            .worker
+            .handle_response(Response::Brightness(200, Trigger::Reply));
+        assert_eq!(harness.brightness_events(), [200]);
+        assert_eq!(harness.worker.state.pending_brightness, None);
+        harness
+            .worker
+            .handle_response(Response::Brightness(150, Trigger::Reply));
+        assert_eq!(harness.brightness_events(), [150]);
+    }
+
+    #[test]
+    fn button_reports_win_over_a_pending_write() {
+        let fake = FakeLitra::new(Model::Glow);
+        let mut harness = Harness::new(&fake);
+        assert!(harness.worker.try_connect());
+        harness.events();
+
+        harness
+            .worker
+            .handle_command(DeviceCommand::SetBrightness(200))
+            .unwrap();
+        harness
+            .worker
+            .handle_response(Response::Brightness(180, Trigger::Button));
+        let events = harness.events();
+        assert!(
+            matches!(
+                events[..],
+                [
+                    DeviceEvent::Brightness(180),
+                    DeviceEvent::ButtonPressed(Setting::Brightness)
+                ]
+            ),
+            "{events:?}"
+        );
+        assert_eq!(harness.worker.state.brightness, 180);
+    }
+
+    #[test]
+    fn a_repeated_button_report_is_shown_once() {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.set_state(true, 100, 4000);
+        let mut harness = Harness::new(&fake);
+        assert!(harness.worker.try_connect());
+        harness.events();
+
+        harness
+            .worker
+            .handle_response(Response::Brightness(150, Trigger::Button));
+        harness
+            .worker
+            .handle_response(Response::Brightness(150, Trigger::Button));
+
+        assert_eq!(harness.brightness_events(), [150]);
+    }
+
+    #[test]
+    fn a_new_button_value_is_shown_right_away() {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.set_state(true, 100, 4000);
+        let mut harness = Harness::new(&fake);
+        assert!(harness.worker.try_connect());
+        harness.events();
+
+        harness
+            .worker
+            .handle_response(Response::Brightness(150, Trigger::Button));
+        assert_eq!(harness.brightness_events(), [150]);
+        harness
+            .worker
+            .handle_response(Response::Brightness(160, Trigger::Button));
+
+        assert_eq!(harness.brightness_events(), [160]);
+    }
+
+    #[test]
+    fn a_burst_of_button_reports_coalesces_to_a_handful_of_events() {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.set_state(true, 100, 4000);
+        let mut harness = Harness::new(&fake);
+        assert!(harness.worker.try_connect());
+        harness.events();
+
+        let levels: Vec<u16> = (0..100).map(|step| 101 + step).collect();
+        for &level in &levels {
+            fake.push(Response::Brightness(level, Trigger::Button));
+        }
+        for _ in 0..1000 {
+            if harness.worker.state.brightness == 200 {
+                break;
+            }
+            harness.worker.wait();
+        }
+        assert_eq!(harness.worker.state.brightness, 200);
+
+        let events: Vec<DeviceEvent> = coalesce_events(harness.events.try_iter())
+            .into_iter()
+            .map(|routed| routed.event)
+            .collect();
+        assert!(events.len() <= 4, "{events:?}");
+        let value = events
+            .iter()
+            .position(|e| matches!(e, DeviceEvent::Brightness(200)));
+        let pressed = events
+            .iter()
+            .position(|e| matches!(e, DeviceEvent::ButtonPressed(Setting::Brightness)));
+        assert!(value.is_some() && value < pressed, "{events:?}");
+        assert_eq!(
+            events
+                .iter()
+                .filter(|e| matches!(e, DeviceEvent::Brightness(_)))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn coalescing_keeps_connection_events_in_place() {
+        let device = DeviceId::default();
+        let routed = |event| Routed {
+            device: device.clone(),
+            event,
+        };
+        let events = coalesce_events([
+            routed(DeviceEvent::Brightness(100)),
+            routed(DeviceEvent::Disconnected),
+            routed(DeviceEvent::Connected(Model::Glow)),
+            routed(DeviceEvent::Brightness(150)),
+            routed(DeviceEvent::Error("gone".into())),
+            routed(DeviceEvent::Brightness(200)),
+        ]);
+
+        let events: Vec<DeviceEvent> = events.into_iter().map(|routed| routed.event).collect();
+        assert!(
+            matches!(
+                events.as_slice(),
+                [
+                    DeviceEvent::Brightness(100),
+                    DeviceEvent::Disconnected,
+                    DeviceEvent::Connected(Model::Glow),
+                    DeviceEvent::Brightness(150),
+                    DeviceEvent::Error(_),
+                    DeviceEvent::Brightness(200),
+                ]
+            ),
+            "{events:?}"
+        );
+    }
+
+    #[test]
+    fn unplugging_disconnects_and_reconnects_with_backoff() {
+        let fake = FakeLitra::new(Model::Glow);
+        let mut harness = Harness::new(&fake);
+        assert!(harness.worker.try_connect());
+        harness.events();
+
+        harness.unplug(&fake);
+        assert!(matches!(harness.events()[..], [DeviceEvent::Disconnected]));
+
+        let opens = harness.opens.load(Ordering::SeqCst);
+        assert!(!harness.worker.reconnect());
+        assert_eq!(harness.opens.load(Ordering::SeqCst), opens + 1);
+        assert!(!harness.worker.reconnect());
+        assert_eq!(harness.opens.load(Ordering::SeqCst), opens + 1);
+
+        harness.clock.advance(RECONNECT_MIN_DELAY);
+        assert!(!harness.worker.reconnect());
+        assert_eq!(harness.opens.load(Ordering::SeqCst), opens + 2);
+        assert_eq!(harness.worker.reconnect_delay, RECONNECT_MIN_DELAY * 4);
+
+        fake.plug_in();
+        harness.clock.advance(RECONNECT_MIN_DELAY * 2);
+        assert!(harness.worker.reconnect());
+        assert!(harness.worker.is_connected());
+        assert_eq!(harness.worker.reconnect_delay, RECONNECT_MIN_DELAY);
+    }
+
+    #[test]
+    fn setters_while_disconnected_only_change_the_state() {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.unplug();
+        let mut harness = Harness::new(&fake);
+        assert!(!harness.worker.try_connect());
+        harness
+            .worker
+            .handle_command(DeviceCommand::SetTemperature(5000))
+            .unwrap();
+        assert_eq!(harness.worker.state.temperature, 5000);
+        assert!(fake.written().is_empty());
+    }
+
+    /// A journal of its own for each test, starting empty.
+    fn journal(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "litra-glow-journal-{}-{}.json",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// The set commands `fake` received after the first `skip` writes.
+    fn sets_after(fake: &FakeLitra, skip: usize) -> Vec<Command> {
+        let mut written = fake.written().split_off(skip);
+        written.retain(|cmd| {
+            matches!(
+                cmd,
+                Command::SetPower(_) | Command::SetBrightness(_) | Command::SetTemperature(_)
+            )
+        });
+        written
+    }
+
+    #[test]
+    fn journaled_settings_survive_a_restart() {
+        let path = journal("restart");
+        let fake = FakeLitra::new(Model::Glow).with_serial("DESK01");
+        fake.set_state(true, 100, 4000);
+        let mut harness = Harness::with(&fake, |worker| {
+            worker.with_journal(Journal::new(path.clone()))
+        });
+        assert!(harness.worker.try_connect());
+        harness.unplug(&fake);
+        for cmd in [
+            DeviceCommand::SetBrightness(200),
+            DeviceCommand::SetPower(false),
+        ] {
+            harness.worker.handle_command(cmd).unwrap();
+        }
+        assert_eq!(
+            Journal::new(path.clone()).load(),
+            Intent {
+                serial: Some("DESK01".to_string()),
+                power: Some(false),
+                brightness: Some(200),
+                temperature: None,
+            }
+        );
+        drop(harness);
+
+        fake.plug_in();
+        let before = fake.written().len();
+        let mut harness = Harness::with(&fake, |worker| {
+            worker.with_journal(Journal::new(path.clone()))
+        });
+        assert!(harness.worker.try_connect());
+        assert_eq!(
+            sets_after(&fake, before),
+            [Command::SetBrightness(200), Command::SetPower(false)]
+        );
+        assert_eq!(fake.state(), (false, 200, 4000));
+        assert_eq!(harness.worker.state.brightness, 200);
+        assert!(!path.exists());
+
+        let before = fake.written().len();
+        harness.unplug(&fake);
+        fake.plug_in();
+        assert!(harness.worker.try_connect());
+        assert!(sets_after(&fake, before).is_empty());
+    }
+
+    #[test]
+    fn settings_journaled_for_another_lamp_are_dropped() {
+        let path = journal("other-lamp");
+        let intent = Intent {
+            serial: Some("OTHER".to_string()),
+            brightness: Some(200),
+            ..Intent::default()
+        };
+        Journal::new(path.clone()).store(&intent).unwrap();
+        let fake = FakeLitra::new(Model::Glow).with_serial("DESK01");
+        fake.set_state(true, 100, 4000);
+        let mut harness = Harness::with(&fake, |worker| {
+            worker.with_journal(Journal::new(path.clone()))
+        });
+        assert!(harness.worker.try_connect());
+        assert!(sets_after(&fake, 0).is_empty());
+        assert_eq!(harness.worker.state.brightness, 100);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn restoring_on_connect_writes_journaled_settings_once() {
+        let path = journal("restore");
+        let fake = FakeLitra::new(Model::Glow).with_serial("DESK01");
+        fake.set_state(true, 100, 4000);
+        let mut harness = Harness::with(&fake, |worker| {
+            worker
+                .with_restore(true)
+                .with_journal(Journal::new(path.clone()))
+        });
+        assert!(harness.worker.try_connect());
+        harness.unplug(&fake);
+        harness
+            .worker
+            .handle_command(DeviceCommand::SetBrightness(200))
+            .unwrap();
+        assert!(path.exists());
+
+        fake.plug_in();
+        let before = fake.written().len();
+        assert!(harness.worker.try_connect());
+        let sets = sets_after(&fake, before);
+        let brightness = sets
+            .iter()
+            .filter(|cmd| **cmd == Command::SetBrightness(200))
+            .count();
+        assert_eq!(brightness, 1, "{sets:?}");
+        assert_eq!(fake.state(), (true, 200, 4000));
+        assert!(!path.exists());
+    }
+
+    /// The last value each setting was reported with, as the window ends up
+    /// showing it.
+    fn shown(events: &[DeviceEvent]) -> (Option<bool>, Option<u16>, Option<u16>) {
+        let mut shown = (None, None, None);
+        for event in events {
+            match *event {
+                DeviceEvent::Power(on) => shown.0 = Some(on),
+                DeviceEvent::Brightness(level) => shown.1 = Some(level),
+                DeviceEvent::Temperature(kelvin) => shown.2 = Some(kelvin),
+                _ => {}
+            }
+        }
+        shown
+    }
+
+    /// A scene store at a fresh temporary path holding the scene `name`.
+    fn scenes(name: &str) -> SceneStore {
+        let store = SceneStore::new(journal(&format!("scenes-{name}")));
+        let scene = Scene {
+            brightness: 30,
+            temperature: 3000,
+            power: None,
+        };
+        store.set(name, scene).unwrap();
+        store
+    }
+
+    #[test]
+    fn a_battery_scene_does_not_hold_off_the_next_automation() {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.set_state(true, 200, 4000);
+        let mut harness = Harness::with(&fake, |worker| worker.with_scenes(scenes("battery")));
+        assert!(harness.worker.try_connect());
+
+        let now = harness.clock.now();
+        harness.worker.enter_battery(now).unwrap();
+        assert!(harness.worker.transition.is_some());
+        let arbiter = &mut harness.worker.arbiter;
+        for setting in [Setting::Brightness, Setting::Temperature] {
+            assert_eq!(
+                arbiter.last_source(setting),
+                Some(&Source::automation("battery"))
+            );
+            assert_eq!(arbiter.last_manual(setting), None);
+        }
+        // The alarm ranks above battery dimming, so it need not wait.
+        assert_eq!(
+            arbiter.check(&Source::automation("alarm"), Setting::Brightness, now),
+            Verdict::Apply
+        );
+    }
+
+    #[test]
+    fn an_instant_scene_is_credited_to_whoever_applied_it() {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.set_state(true, 200, 4000);
+        let mut harness = Harness::with(&fake, |worker| worker.with_scenes(scenes("instant")));
+        assert!(harness.worker.try_connect());
+        let now = harness.clock.now();
+
+        harness
+            .worker
+            .start_scene("instant", Duration::ZERO, &Source::automation("battery"))
+            .unwrap();
+        let state = harness.worker.state;
+        assert_eq!(
+            (state.brightness, state.temperature),
+            (percent_to_brightness(30, Model::Glow), 3000)
+        );
+        let arbiter = &mut harness.worker.arbiter;
+        assert_eq!(arbiter.last_manual(Setting::Brightness), None);
+        assert_eq!(
+            arbiter.check(&Source::automation("alarm"), Setting::Brightness, now),
+            Verdict::Apply
+        );
+
+        harness
+            .worker
+            .handle_command(DeviceCommand::ApplyScene {
+                name: "instant".to_string(),
+                duration: Duration::ZERO,
+            })
+            .unwrap();
+        let arbiter = &mut harness.worker.arbiter;
+        assert_eq!(arbiter.last_manual(Setting::Brightness), Some(now));
+        assert_eq!(
+            arbiter.check(&Source::automation("alarm"), Setting::Brightness, now),
+            Verdict::Outranked
+        );
+    }
+
+    #[test]
+    fn a_garbage_report_resyncs_from_fresh_answers() {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.set_state(true, 100, 4000);
+        let mut harness = Harness::new(&fake);
+        assert!(harness.worker.try_connect());
+        harness.events();
+        let resyncs = harness.worker.resyncs;
+
+        // The lamp moved on without telling, and its queue holds junk.
+        fake.set_state(false, 150, 5000);
+        fake.push(Response::Brightness(9999, Trigger::Reply));
+        fake.push(Response::Temperature(3000, Trigger::Reply));
+        fake.push(Response::Power(true, Trigger::Reply));
+        for _ in 0..100 {
+            if harness.worker.resyncs > resyncs {
+                break;
+            }
+            harness.worker.wait();
+        }
+        assert_eq!(harness.worker.resyncs, resyncs + 1);
+        for _ in 0..5 {
+            harness.worker.tick();
+        }
+
+        let state = harness.worker.state;
+        assert_eq!(
+            (state.power, state.brightness, state.temperature),
+            (false, 150, 5000)
+        );
+        assert_eq!(
+            shown(&harness.events()),
+            (Some(false), Some(150), Some(5000))
+        );
+    }
+
+    #[test]
+    fn an_unsolicited_reply_resyncs() {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.set_state(true, 100, 4000);
+        let mut harness = Harness::new(&fake);
+        assert!(harness.worker.try_connect());
+        harness.events();
+        let resyncs = harness.worker.resyncs;
+
+        // Nothing asked for a brightness since the connect read it.
+        fake.push(Response::Brightness(120, Trigger::Reply));
+        for _ in 0..100 {
+            if harness.worker.resyncs > resyncs {
+                break;
+            }
+            harness.worker.wait();
+        }
+        assert_eq!(harness.worker.resyncs, resyncs + 1);
+        assert_eq!(harness.worker.state.brightness, 100);
+        assert_eq!(shown(&harness.events()).1, Some(100));
+    }
+
+    #[test]
+    fn the_acknowledgement_of_a_write_does_not_resync() {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.set_state(true, 100, 4000);
+        let mut harness = Harness::new(&fake);
+        assert!(harness.worker.try_connect());
+        let resyncs = harness.worker.resyncs;
+
+        harness
+            .worker
+            .handle_command(DeviceCommand::SetBrightness(150))
+            .unwrap();
+        for _ in 0..5 {
+            harness.worker.wait();
+        }
+
+        assert_eq!(harness.worker.resyncs, resyncs);
+        assert_eq!(harness.worker.state.brightness, 150);
+    }
+
+    #[test]
+    fn resuming_drops_answers_from_before_sleep() {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.set_state(true, 100, 4000);
+        let mut harness = Harness::new(&fake);
+        assert!(harness.worker.try_connect());
+        assert!(!harness.worker.resumed());
+        harness.events();
+
+        fake.push(Response::Brightness(120, Trigger::Reply));
+        fake.push(Response::Temperature(3000, Trigger::Reply));
+        fake.set_state(true, 150, 5000);
+        harness.clock.advance(RESUME_GAP);
+        assert!(harness.worker.resumed());
+        harness.worker.recover("resume");
+        for _ in 0..5 {
+            harness.worker.tick();
+        }
+
+        let state = harness.worker.state;
+        assert_eq!(
+            (state.power, state.brightness, state.temperature),
+            (true, 150, 5000)
+        );
+        assert_eq!(
+            shown(&harness.events()),
+            (Some(true), Some(150), Some(5000))
+        );
+    }
+
+    /// Runs the worker loop on its own thread until it returns, and counts
+    /// the `ShutdownComplete` events it sent on the way out.
+    fn run_until_stopped(
+        harness: Harness,
+        drive: impl FnOnce(channel::Sender<DeviceCommand>),
+    ) -> usize {
+        let Harness { worker, events, .. } = harness;
+        let (tx, rx) = channel::bounded(8);
+        let handle = std::thread::spawn(move || worker.run(rx));
+        drive(tx);
+        handle.join().unwrap();
+        events
+            .try_iter()
+            .filter(|routed| matches!(routed.event, DeviceEvent::ShutdownComplete))
+            .count()
+    }
+
+    #[test]
+    fn a_shutdown_racing_an_unplug_runs_the_exit_actions_once() {
+        for _ in 0..20 {
+            let fake = FakeLitra::new(Model::Glow);
+            fake.set_state(true, 100, 4000);
+            let mut harness = Harness::new(&fake);
+            assert!(harness.worker.try_connect());
+            harness.events();
+
+            let completed = run_until_stopped(harness, |tx| {
+                fake.unplug();
+                tx.send_timeout(
+                    DeviceCommand::Shutdown { power_off: true },
+                    Duration::from_secs(1),
+                )
+                .unwrap();
+            });
+            assert_eq!(completed, 1);
+            let power_offs = fake
+                .written()
+                .into_iter()
+                .filter(|cmd| *cmd == Command::SetPower(false))
+                .count();
+            assert!(power_offs <= 1, "{power_offs} power-off writes");
+        }
+    }
+
+    #[test]
+    fn dropping_the_commands_shuts_down_a_connected_worker() {
+        let fake = FakeLitra::new(Model::Glow);
+        fake.set_state(true, 100, 4000);
+        let mut harness = Harness::new(&fake);
+        assert!(harness.worker.try_connect());
+
+        assert_eq!(run_until_stopped(harness, drop), 1);
+        assert!(!fake.written().contains(&Command::SetPower(false)));
+    }
+
+    #[test]
+    fn dropping_the_commands_shuts_down_a_disconnected_worker() {
+        let fake = FakeLitra::new(Model::Glow);
+        let mut harness = Harness::new(&fake);
+        assert!(harness.worker.try_connect());
+        harness.unplug(&fake);
+
+        assert_eq!(run_until_stopped(harness, drop), 1);
+    }
+}
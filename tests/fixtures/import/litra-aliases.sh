@@ -0,0 +1,7 @@
+# Lamp shortcuts
+alias desk='litra brightness --percentage 70 && litra temperature --value 4000'
+alias beam="litra brightness --device-type beam --value 200; litra temperature --value 6500"
+alias lamp-off='litra off'
+alias warm='litra temperature --value 2700'
+alias ll='ls -l'
+export PATH="$HOME/bin:$PATH"